@@ -1,26 +1,159 @@
-use std::{collections::BTreeMap, sync::Mutex};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
 
+use log::{trace, warn};
 use sdl2::{mixer::{self, Channel, InitFlag, Sdl2MixerContext, Chunk, MAX_VOLUME}};
+use thiserror::Error;
 
 use crate::game::WorldType;
 
 const MAX_CHANNELS: i32 = 16;
+/// Sound effects are played on the channels in this group, so that they never replace the music
 const GROUP_ID: i32 = 1;
 const LIGHT_MUSIC_CHANNEL: Channel = Channel(0);
 const DARK_MUSIC_CHANNEL: Channel = Channel(1);
+/// Volume of the music at full music volume in the settings
 const MUSIC_VOLUME: i32 = MAX_VOLUME / 4;
+/// Number of ticks it takes for the music of one world to fade into the other one
+const CROSSFADE_TICKS: f32 = 30.0;
 
 lazy_static::lazy_static! {
-    static ref AUDIO_INSTANCE: Mutex<GameAudio> = Mutex::new(GameAudio::new().expect("Failed to init audio"));
+    /// `None` until `init()` succeeded. All functions in this module do nothing meanwhile,
+    /// e.g. when the game is simulated headless or no audio device is available.
+    static ref AUDIO_INSTANCE: Mutex<Option<GameAudio>> = Mutex::new(None);
+}
+
+/// Set while sound effects are suppressed, see `muted()`
+static MUTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Error)]
+pub enum AudioError {
+    #[error("SDL_mixer error: {0}")]
+    Mixer(String),
+}
+
+/// Open the audio device and start the music. If this fails, the game runs without sound.
+pub fn init() -> Result<(), AudioError> {
+    let mut instance = instance();
+    if instance.is_none() {
+        *instance = Some(GameAudio::new().map_err(AudioError::Mixer)?);
+    }
+    Ok(())
+}
+
+/// A panic while holding the lock can't leave `GameAudio` in an invalid state, so it's
+/// still used afterwards
+fn instance() -> MutexGuard<'static, Option<GameAudio>> {
+    AUDIO_INSTANCE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Advance the crossfade between the worlds' music, called once per game tick
+pub fn tick() {
+    if let Some(audio) = &mut *instance() {
+        audio.tick();
+    }
 }
 
 pub fn set_world(world_type: WorldType) {
-    AUDIO_INSTANCE.lock().expect("Poisoned `GameAudio` mutex").set_world(world_type);
+    if let Some(audio) = &mut *instance() {
+        audio.set_world(world_type);
+    }
+}
+
+/// Set the volume of a category from the settings, from 0 to 1
+pub fn set_volume(category: SoundCategory, volume: f32) {
+    if let Some(audio) = &mut *instance() {
+        audio.set_volume(category, volume);
+    }
+}
+
+pub fn play_sound(sound: SoundId) {
+    if MUTED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(audio) = &*instance() {
+        audio.play_sound(sound);
+    }
+}
+
+/// Run `f` without playing sound effects, e.g. while the player is simulated off-screen
+pub fn muted<T>(f: impl FnOnce() -> T) -> T {
+    let was_muted = MUTED.swap(true, Ordering::Relaxed);
+    let result = f();
+    MUTED.store(was_muted, Ordering::Relaxed);
+    result
+}
+
+/// Categories with separate volumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCategory {
+    Music,
+    Effects,
+}
+
+/// Sound effects, loaded from `assets/sounds` when the audio is initialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SoundId {
+    Jump,
+    Dash,
+    WallJump,
+    Death,
+    KeyPickup,
+    DoorOpen,
+    WorldSwitch,
+    Goal,
+    Switch,
+}
+
+impl SoundId {
+    const ALL: [Self; 9] = [
+        SoundId::Jump,
+        SoundId::Dash,
+        SoundId::WallJump,
+        SoundId::Death,
+        SoundId::KeyPickup,
+        SoundId::DoorOpen,
+        SoundId::WorldSwitch,
+        SoundId::Goal,
+        SoundId::Switch,
+    ];
+
+    fn path(self) -> &'static str {
+        match self {
+            // Wall jumps use the same sound as regular jumps
+            SoundId::Jump | SoundId::WallJump => "assets/sounds/jump.ogg",
+            SoundId::Dash => "assets/sounds/dash.ogg",
+            SoundId::Death => "assets/sounds/death.ogg",
+            SoundId::KeyPickup => "assets/sounds/collect.ogg",
+            SoundId::DoorOpen => "assets/sounds/door.ogg",
+            // Switch objects use the world switch sound
+            SoundId::WorldSwitch | SoundId::Switch => "assets/sounds/switch.ogg",
+            SoundId::Goal => "assets/sounds/teleport.ogg",
+        }
+    }
+
+    fn category(self) -> SoundCategory {
+        SoundCategory::Effects
+    }
 }
 
 struct GameAudio {
     mixer_context: Sdl2MixerContext,
-    chunks_by_channel: BTreeMap<i32, Chunk>
+    chunks_by_channel: BTreeMap<i32, Chunk>,
+    /// Sounds that failed to load are missing and stay silent
+    sounds: BTreeMap<SoundId, Chunk>,
+    /// The music fades towards this world, the other one is muted
+    world_type: WorldType,
+    /// How much of the light world's music is audible, from 0 to 1. The dark world's music
+    /// plays at the remaining volume.
+    light_mix: f32,
+    music_volume: i32,
+    effects_volume: i32,
 }
 
 // The `Chunk` type contains a pointer, so we need to manually
@@ -49,22 +182,89 @@ impl GameAudio {
         chunks_by_channel.insert(LIGHT_MUSIC_CHANNEL.0, light_chunk);
         chunks_by_channel.insert(DARK_MUSIC_CHANNEL.0, dark_chunk);
 
-        Ok(GameAudio { mixer_context, chunks_by_channel })
+        let mut sounds = BTreeMap::new();
+        for sound in SoundId::ALL {
+            match Chunk::from_file(sound.path()) {
+                Ok(chunk) => {
+                    sounds.insert(sound, chunk);
+                }
+                Err(err) => warn!("Failed to load sound {sound:?}: {err}"),
+            }
+        }
+
+        Ok(GameAudio {
+            mixer_context,
+            chunks_by_channel,
+            sounds,
+            world_type: WorldType::Light,
+            light_mix: 1.0,
+            music_volume: MUSIC_VOLUME,
+            effects_volume: MAX_VOLUME,
+        })
     }
 
     fn channel_finished(channel: Channel) {
-        println!("finished: {}", channel.0);
+        trace!("Channel {} finished", channel.0);
     }
 
-    fn get_music_channel(world_type: WorldType) -> Channel {
-        match world_type {
-            WorldType::Light => LIGHT_MUSIC_CHANNEL,
-            WorldType::Dark => DARK_MUSIC_CHANNEL,
+    fn tick(&mut self) {
+        let target = match self.world_type {
+            WorldType::Light => 1.0,
+            WorldType::Dark => 0.0,
+        };
+        if self.light_mix == target {
+            return;
         }
+        let step = 1.0 / CROSSFADE_TICKS;
+        self.light_mix = if self.light_mix < target {
+            (self.light_mix + step).min(target)
+        } else {
+            (self.light_mix - step).max(target)
+        };
+        self.apply_music_volume();
+    }
+
+    fn set_world(&mut self, world_type: WorldType) {
+        self.world_type = world_type;
     }
 
-    fn set_world(&self, world_type: WorldType) {
-        GameAudio::get_music_channel(world_type).set_volume(MUSIC_VOLUME);
-        GameAudio::get_music_channel(world_type.inverse()).set_volume(0);
+    fn apply_music_volume(&self) {
+        let volume = self.music_volume as f32;
+        LIGHT_MUSIC_CHANNEL.set_volume((volume * self.light_mix) as i32);
+        DARK_MUSIC_CHANNEL.set_volume((volume * (1.0 - self.light_mix)) as i32);
+    }
+
+    fn set_volume(&mut self, category: SoundCategory, volume: f32) {
+        let volume = volume.clamp(0.0, 1.0);
+        match category {
+            SoundCategory::Music => {
+                self.music_volume = (MUSIC_VOLUME as f32 * volume) as i32;
+                self.apply_music_volume();
+            }
+            SoundCategory::Effects => self.effects_volume = (MAX_VOLUME as f32 * volume) as i32,
+        }
+    }
+
+    /// Play a sound on a free effect channel. If all of them are busy, the sound that has
+    /// been playing the longest is cut off.
+    fn play_sound(&self, sound: SoundId) {
+        let chunk = match self.sounds.get(&sound) {
+            Some(chunk) => chunk,
+            None => return,
+        };
+        let group = mixer::Group(GROUP_ID);
+        let channel = match group.find_available().or_else(|| group.find_oldest()) {
+            Some(channel) => channel,
+            None => return,
+        };
+
+        let volume = match sound.category() {
+            SoundCategory::Music => self.music_volume,
+            SoundCategory::Effects => self.effects_volume,
+        };
+        channel.set_volume(volume);
+        if let Err(err) = channel.play(chunk, 0) {
+            warn!("Failed to play sound {sound:?}: {err}");
+        }
     }
 }
@@ -0,0 +1,208 @@
+//! Local debug server that lets external tools (test runners, TAS tools, bots) query the game
+//! state and inject inputs. Only compiled with the `automation` feature.
+//!
+//! The protocol is line-based JSON over TCP: every request is a single line like
+//! `{"command": "Press", "button": "Jump"}` and is answered with a single line like
+//! `{"status": "ok"}`. See `Request` for all commands.
+
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{Game, WorldType},
+    input::{ButtonType, Input},
+    math::FVec2,
+    player::PlayerState,
+};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:7878";
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command")]
+enum Request {
+    GetState,
+    /// Hold a button until it's released or the client disconnects
+    Press { button: ButtonType },
+    Release { button: ButtonType },
+    LoadLevel { name: String },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Ok {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        state: Option<StateSnapshot>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct StateSnapshot {
+    tick: u64,
+    level: String,
+    world_type: WorldType,
+    player: PlayerSnapshot,
+}
+
+#[derive(Debug, Serialize)]
+struct PlayerSnapshot {
+    position: FVec2,
+    dead: bool,
+    grounded: bool,
+    state: PlayerState,
+}
+
+struct Client {
+    stream: TcpStream,
+    /// Received data that doesn't form a complete line yet
+    buffer: Vec<u8>,
+    /// Buttons pressed by this client, released when it disconnects
+    pressed_buttons: Vec<ButtonType>,
+}
+
+pub struct AutomationServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+}
+
+impl AutomationServer {
+    /// Listen on the address in `COMPLEMENTARY_AUTOMATION_ADDR`, or on `DEFAULT_ADDRESS`
+    pub fn bind_from_env() -> io::Result<Self> {
+        let address = env::var("COMPLEMENTARY_AUTOMATION_ADDR")
+            .unwrap_or_else(|_| String::from(DEFAULT_ADDRESS));
+        let listener = TcpListener::bind(&address)?;
+        listener.set_nonblocking(true)?;
+        info!("Automation server listening on {address}");
+
+        Ok(AutomationServer {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Accept new clients and handle all pending requests. Never blocks.
+    pub fn poll(&mut self, game: &mut Game, input: &mut Input) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => {
+                    if let Err(err) = stream.set_nonblocking(true) {
+                        warn!("Failed to set up automation client {address}: {err}");
+                        continue;
+                    }
+                    info!("Automation client connected: {address}");
+                    self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                        pressed_buttons: Vec::new(),
+                    });
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Failed to accept automation client: {err}");
+                    break;
+                }
+            }
+        }
+
+        self.clients.retain_mut(|client| {
+            let connected = AutomationServer::poll_client(client, game, input);
+            if !connected {
+                info!("Automation client disconnected");
+                for button in client.pressed_buttons.drain(..) {
+                    input.set_button_released(button);
+                }
+            }
+            connected
+        });
+    }
+
+    /// Returns `false` if the client disconnected
+    fn poll_client(
+        client: &mut Client,
+        game: &mut Game,
+        input: &mut Input,
+    ) -> bool {
+        let mut chunk = [0; 1024];
+        loop {
+            match client.stream.read(&mut chunk) {
+                Ok(0) => return false,
+                Ok(len) => client.buffer.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => return false,
+            }
+        }
+
+        while let Some(end) = client.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = client.buffer.drain(..=end).collect();
+            let response = match serde_json::from_slice::<Request>(&line) {
+                Ok(request) => AutomationServer::handle_request(request, client, game, input),
+                Err(err) => Response::Error {
+                    message: format!("invalid request: {err}"),
+                },
+            };
+
+            let mut message = match serde_json::to_vec(&response) {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!("Failed to serialize automation response: {err}");
+                    continue;
+                }
+            };
+            message.push(b'\n');
+            if client.stream.write_all(&message).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn handle_request(
+        request: Request,
+        client: &mut Client,
+        game: &mut Game,
+        input: &mut Input,
+    ) -> Response {
+        match request {
+            Request::GetState => Response::Ok {
+                state: Some(StateSnapshot {
+                    tick: game.tick_count(),
+                    level: game.level_name().to_owned(),
+                    world_type: game.world_type(),
+                    player: PlayerSnapshot {
+                        position: game.player().position(),
+                        dead: game.player().dead(),
+                        grounded: game.player().grounded(),
+                        state: game.player().state(),
+                    },
+                }),
+            },
+            Request::Press { button } => {
+                input.set_button_pressed(button);
+                if !client.pressed_buttons.contains(&button) {
+                    client.pressed_buttons.push(button);
+                }
+                Response::Ok { state: None }
+            }
+            Request::Release { button } => {
+                input.set_button_released(button);
+                client.pressed_buttons.retain(|pressed| *pressed != button);
+                Response::Ok { state: None }
+            }
+            Request::LoadLevel { name } => match game.load_level(&name) {
+                Ok(()) => Response::Ok { state: None },
+                Err(err) => Response::Error {
+                    message: err.to_string(),
+                },
+            },
+        }
+    }
+}
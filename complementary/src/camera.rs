@@ -0,0 +1,237 @@
+use cgmath::Zero;
+use serde::Deserialize;
+
+use crate::{
+    imgui_helpers::ImGui,
+    math::{FMat4, FVec2, FVec3},
+    rendering::DrawState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CameraMode {
+    /// Show the whole level
+    Fit,
+    /// Show part of the level around the player
+    Follow,
+}
+
+/// Camera settings of a level, see `LevelMetadata::camera`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct CameraSettings {
+    pub mode: CameraMode,
+    /// Magnification compared to showing the whole level, only used when following the player
+    #[serde(default)]
+    pub zoom: Option<f32>,
+}
+
+/// Camera path shown the first time a level is entered, see `LevelMetadata::intro`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CameraIntro {
+    /// Points in tiles that the center of the view pans through, in order
+    pub path: Vec<FVec2>,
+    /// Length of the whole flyover
+    #[serde(default = "CameraIntro::default_ticks")]
+    pub ticks: u32,
+}
+
+impl CameraIntro {
+    fn default_ticks() -> u32 {
+        180
+    }
+}
+
+/// Decides which part of the level is visible
+pub struct Camera {
+    mode: CameraMode,
+    zoom: f32,
+    /// Smoothed position of the player, the center of the view in follow mode
+    center: FVec2,
+    shake_intensity: f32,
+    shake_duration: i32,
+    shake_ticks: i32,
+    /// Extra zoom that fades out over `punch_duration` ticks, see `zoom_punch()`
+    punch_amount: f32,
+    punch_duration: i32,
+    punch_ticks: i32,
+    elapsed_ticks: u32,
+    /// Intro that's currently playing and the ticks since it started
+    flyover: Option<(CameraIntro, u32)>,
+}
+
+impl Camera {
+    const DEFAULT_ZOOM: f32 = 2.0;
+    /// Fraction of the distance to the player that the camera moves every tick
+    const SMOOTHING: f32 = 0.08;
+
+    pub fn new() -> Self {
+        Camera {
+            mode: CameraMode::Fit,
+            zoom: Camera::DEFAULT_ZOOM,
+            center: FVec2::zero(),
+            shake_intensity: 0.0,
+            shake_duration: 0,
+            shake_ticks: 0,
+            punch_amount: 0.0,
+            punch_duration: 0,
+            punch_ticks: 0,
+            elapsed_ticks: 0,
+            flyover: None,
+        }
+    }
+
+    /// Apply a level's settings and jump to `target` without smoothing, e.g. after loading a level
+    pub fn reset(&mut self, settings: Option<CameraSettings>, target: FVec2) {
+        let settings = settings.unwrap_or(CameraSettings { mode: CameraMode::Fit, zoom: None });
+        self.mode = settings.mode;
+        self.zoom = settings.zoom.unwrap_or(Camera::DEFAULT_ZOOM);
+        self.center = target;
+        self.shake_ticks = 0;
+        self.punch_ticks = 0;
+        self.flyover = None;
+    }
+
+    /// Pan along the intro's path, ignoring the level's camera mode until it's over
+    pub fn start_flyover(&mut self, intro: CameraIntro) {
+        if let Some(&start) = intro.path.first() {
+            self.center = start;
+            self.flyover = Some((intro, 0));
+        }
+    }
+
+    pub fn flyover_active(&self) -> bool {
+        self.flyover.is_some()
+    }
+
+    /// Stop the intro where it is, the camera then moves back to the player as usual
+    pub fn skip_flyover(&mut self) {
+        self.flyover = None;
+    }
+
+    /// Shake the view for the given number of ticks. `intensity` is the initial offset in tiles.
+    pub fn shake(&mut self, intensity: f32, ticks: i32) {
+        self.shake_intensity = intensity;
+        self.shake_duration = ticks;
+        self.shake_ticks = ticks;
+    }
+
+    /// Zoom in towards the center of the screen and back out over the given number of ticks.
+    /// `amount` is the initial zoom relative to the current one, e.g. 0.1 for 10% closer.
+    pub fn zoom_punch(&mut self, amount: f32, ticks: i32) {
+        self.punch_amount = amount;
+        self.punch_duration = ticks;
+        self.punch_ticks = ticks;
+    }
+
+    /// Move towards `target`, usually the player's center
+    pub fn tick(&mut self, target: FVec2) {
+        if let Some((intro, ticks)) = &mut self.flyover {
+            *ticks += 1;
+            self.center = flyover_position(&intro.path, *ticks as f32 / intro.ticks.max(1) as f32);
+            if *ticks >= intro.ticks {
+                self.flyover = None;
+            }
+        } else {
+            self.center += (target - self.center) * Camera::SMOOTHING;
+        }
+        self.tick_effects();
+    }
+
+    /// Advance shakes and zoom punches without moving the camera, e.g. while the game is
+    /// frozen by a hitstop
+    pub fn tick_effects(&mut self) {
+        self.shake_ticks = (self.shake_ticks - 1).max(0);
+        self.punch_ticks = (self.punch_ticks - 1).max(0);
+        self.elapsed_ticks = self.elapsed_ticks.wrapping_add(1);
+    }
+
+    fn punch_scale(&self) -> f32 {
+        if self.punch_ticks == 0 {
+            return 1.0;
+        }
+        1.0 + self.punch_amount * self.punch_ticks as f32 / self.punch_duration as f32
+    }
+
+    /// Offset of the view while shaking. It's not random so that replays look the same.
+    fn shake_offset(&self) -> FVec2 {
+        if self.shake_ticks == 0 {
+            return FVec2::zero();
+        }
+        let strength = self.shake_intensity * self.shake_ticks as f32 / self.shake_duration as f32;
+        let time = self.elapsed_ticks as f32;
+        FVec2::new((time * 1.7).sin(), (time * 2.3).cos()) * strength
+    }
+
+    pub fn update_draw_state(
+        &self,
+        state: &mut DrawState,
+        window_width: f32,
+        window_height: f32,
+        tilemap_width: f32,
+        tilemap_height: f32,
+    ) {
+        // Intros need to zoom in to show movement, even in levels that fit on the screen
+        let mode = if self.flyover.is_some() { CameraMode::Follow } else { self.mode };
+        match mode {
+            CameraMode::Fit => {
+                state.update_view_matrix(window_width, window_height, tilemap_width, tilemap_height)
+            }
+            CameraMode::Follow => {
+                let pixels_per_tile =
+                    f32::min(window_width / tilemap_width, window_height / tilemap_height) * self.zoom;
+                let center = FVec2::new(
+                    clamp_to_level(self.center.x, window_width / pixels_per_tile, tilemap_width),
+                    clamp_to_level(self.center.y, window_height / pixels_per_tile, tilemap_height),
+                );
+                state.update_view_matrix_centered(window_width, window_height, center, pixels_per_tile);
+            }
+        }
+
+        let offset = self.shake_offset();
+        // Scaling in clip space zooms towards the center of the screen in both camera modes
+        let scale = self.punch_scale();
+        state.view_matrix = FMat4::from_nonuniform_scale(scale, scale, 1.0)
+            * state.view_matrix
+            * FMat4::from_translation(FVec3::new(-offset.x, -offset.y, 0.0));
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui) {
+        if gui.radio_button_bool("Fit", self.mode == CameraMode::Fit) {
+            self.mode = CameraMode::Fit;
+        }
+        gui.same_line();
+        if gui.radio_button_bool("Follow", self.mode == CameraMode::Follow) {
+            self.mode = CameraMode::Follow;
+        }
+        self.zoom.draw_gui("Zoom", gui);
+        self.zoom = self.zoom.max(1.0);
+        if gui.button("Shake") {
+            self.shake(0.3, 30);
+        }
+        gui.same_line();
+        if gui.button("Zoom punch") {
+            self.zoom_punch(0.1, 20);
+        }
+    }
+}
+
+/// Position along a path of points with equal time spent on each segment, `progress` is in 0..=1
+fn flyover_position(path: &[FVec2], progress: f32) -> FVec2 {
+    if path.len() < 2 {
+        return path.first().copied().unwrap_or_else(FVec2::zero);
+    }
+    let position = progress.clamp(0.0, 1.0) * (path.len() - 1) as f32;
+    let index = (position as usize).min(path.len() - 2);
+    // Ease in and out of every point so that the view doesn't jerk when changing direction
+    let t = position - index as f32;
+    let t = t * t * (3.0 - 2.0 * t);
+    path[index] + (path[index + 1] - path[index]) * t
+}
+
+/// Keep the visible part of one axis inside of the level, or center it if the level is smaller
+fn clamp_to_level(center: f32, visible_size: f32, level_size: f32) -> f32 {
+    if visible_size >= level_size {
+        level_size / 2.0
+    } else {
+        center.clamp(visible_size / 2.0, level_size - visible_size / 2.0)
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{
+    game::WorldType,
+    math::{Bounds, Direction},
+    objects::ObjectSet,
+    player::CollisionType,
+    tilemap::Tilemap,
+};
+
+/// Everything that blocks the player's movement in one world: solid tiles, slopes, the edges of
+/// the tile map and solid objects like platforms, doors and ability blocks.
+#[derive(Clone, Copy)]
+pub struct CollisionWorld<'a> {
+    pub tilemap: &'a Tilemap,
+    pub objects: &'a ObjectSet,
+    pub world_type: WorldType,
+}
+
+impl<'a> CollisionWorld<'a> {
+    pub fn new(tilemap: &'a Tilemap, objects: &'a ObjectSet, world_type: WorldType) -> Self {
+        Self { tilemap, objects, world_type }
+    }
+
+    /// Whether `bounds` overlap a tile or an object that's solid in this world
+    pub fn overlaps_solid(&self, bounds: Bounds) -> bool {
+        self.overlaps_tilemap(bounds) || self.overlaps_solid_object(bounds)
+    }
+
+    /// Whether `bounds` overlap a solid tile or a slope or are outside of the tile map. Unlike
+    /// objects, touching a tile's left or top edge already counts as overlapping it.
+    pub fn overlaps_tilemap(&self, bounds: Bounds) -> bool {
+        self.tilemap.overlaps_solid(bounds, self.world_type)
+    }
+
+    pub fn overlaps_solid_object(&self, bounds: Bounds) -> bool {
+        matches!(
+            self.objects.check_collision(&bounds, self.world_type),
+            Some(CollisionType::Solid | CollisionType::Wall)
+        )
+    }
+
+    /// How far `bounds` can move in `direction`, up to `distance`, before touching a solid tile
+    /// or object. `None` if nothing is in the way. Slopes and one-way platforms are ignored.
+    pub fn sweep(&self, bounds: Bounds, direction: Direction, distance: f32) -> Option<f32> {
+        self.objects
+            .solid_bounds(self.world_type)
+            .iter()
+            .filter_map(|obstacle| bounds.sweep_distance(direction, obstacle))
+            .chain(self.tilemap.sweep(bounds, direction, distance, self.world_type))
+            .filter(|&contact| contact <= distance)
+            .reduce(f32::min)
+    }
+
+    /// Whether something whose bottom edge moved down from `old_bottom` to `bounds.max.y`
+    /// landed on a one-way tile or platform
+    pub fn lands_on_one_way_platform(&self, old_bottom: f32, bounds: Bounds) -> bool {
+        self.tilemap.lands_on_one_way_platform(old_bottom, bounds)
+            || self.objects.lands_on_one_way_platform(old_bottom, &bounds, self.world_type)
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use num_traits::FromPrimitive;
+use sdl2::{event::Event, keyboard::Keycode};
+
+use crate::input::{ButtonType, Input, KeyBindings, KeyBindingsError, KEY_BINDINGS_PATH};
+use crate::toast;
+
+/// Binding presets shipped with the game, e.g. for other keyboard layouts
+const SHIPPED_PRESETS_DIR: &str = "assets/controls";
+/// Presets exported from the Controls panel
+const USER_PRESETS_DIR: &str = "control_presets";
+/// Tells controller buttons apart from keys with the same name, e.g. the A button
+const PAD_LABEL_PREFIX: &str = "Pad ";
+
+/// ImGui panel for rebinding keys and controller buttons at runtime
+#[derive(Debug, Default)]
+pub struct ControlsPanel {
+    /// Button that the next pressed key or controller button will be bound to
+    capturing: Option<ButtonType>,
+    /// Path entered in the import field
+    import_path: String,
+    /// Set when the bindings were changed, see `take_bindings_changed()`
+    bindings_changed: bool,
+}
+
+impl ControlsPanel {
+    /// Bind the pressed key or controller button while capturing. Returns `true` if the event was consumed and
+    /// shouldn't be handled by the game.
+    pub fn handle_event(&mut self, event: &Event, input: &mut Input) -> bool {
+        let button = match self.capturing {
+            Some(button) => button,
+            None => return false,
+        };
+
+        match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => {
+                self.capturing = None;
+                true
+            }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                repeat: false,
+                ..
+            } => {
+                let mut bindings = input.bindings().clone();
+                bindings.bind(*keycode, button);
+                input.set_bindings(bindings);
+                self.capturing = None;
+                self.bindings_changed = true;
+                true
+            }
+            Event::ControllerButtonDown {
+                button: pad_button, ..
+            } => {
+                let mut bindings = input.bindings().clone();
+                bindings.bind_pad(*pad_button, button);
+                input.set_bindings(bindings);
+                self.capturing = None;
+                self.bindings_changed = true;
+                true
+            }
+            Event::KeyDown { .. }
+            | Event::KeyUp { .. }
+            | Event::ControllerButtonUp { .. }
+            | Event::ControllerAxisMotion { .. } => true,
+            _ => false,
+        }
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, input: &mut Input) {
+        let _token = match imgui::Window::new("Controls")
+            .size([350.0, 400.0], imgui::Condition::FirstUseEver)
+            .collapsed(true, imgui::Condition::FirstUseEver)
+            .begin(gui)
+        {
+            Some(token) => token,
+            None => return,
+        };
+
+        let mut bindings = input.bindings().clone();
+        let mut changed = false;
+
+        for index in 0..ButtonType::COUNT {
+            let button = ButtonType::from_usize(index).unwrap();
+            let _id = gui.push_id(index as i32);
+
+            gui.text(format!("{:?}", button));
+            for keycode in bindings.keycodes(button) {
+                gui.same_line();
+                // Clicking a key removes it
+                if gui.small_button(keycode.name()) {
+                    bindings.unbind(keycode, button);
+                    changed = true;
+                }
+            }
+            for pad_button in bindings.pad_buttons(button) {
+                gui.same_line();
+                if gui.small_button(format!("{PAD_LABEL_PREFIX}{}", pad_button.string())) {
+                    bindings.unbind_pad(pad_button, button);
+                    changed = true;
+                }
+            }
+
+            gui.same_line();
+            if self.capturing == Some(button) {
+                gui.text_disabled("Press a key or controller button (Escape to cancel)");
+            } else if gui.small_button("+") {
+                self.capturing = Some(button);
+            }
+        }
+
+        gui.separator();
+        if gui.button("Reset to defaults") {
+            bindings = KeyBindings::default();
+            changed = true;
+        }
+        gui.same_line();
+        if gui.button("Save") {
+            match bindings.save(Path::new(KEY_BINDINGS_PATH)) {
+                Ok(()) => toast::show(format!("Saved key bindings to {KEY_BINDINGS_PATH}")),
+                Err(err) => {
+                    error!("Failed to save key bindings: {err}");
+                    toast::show("Failed to save key bindings");
+                }
+            }
+        }
+
+        if gui.collapsing_header("Presets", imgui::TreeNodeFlags::empty()) {
+            for path in ControlsPanel::presets() {
+                let name = path
+                    .file_stem()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if gui.button(&name) {
+                    if let Some(preset) = ControlsPanel::load_preset(&path) {
+                        bindings = preset;
+                        changed = true;
+                    }
+                }
+            }
+
+            gui.input_text("##import_path", &mut self.import_path).build();
+            gui.same_line();
+            if gui.button("Import") {
+                if let Some(preset) = ControlsPanel::load_preset(Path::new(&self.import_path)) {
+                    bindings = preset;
+                    changed = true;
+                }
+            }
+            if gui.button("Export current bindings") {
+                ControlsPanel::export_preset(&bindings);
+            }
+        }
+
+        if changed {
+            input.set_bindings(bindings);
+            self.bindings_changed = true;
+        }
+    }
+
+    /// Returns true if the bindings were changed since the last call, so that everything
+    /// showing them can be updated
+    pub fn take_bindings_changed(&mut self) -> bool {
+        std::mem::take(&mut self.bindings_changed)
+    }
+
+    /// Paths of all shipped and exported presets
+    fn presets() -> Vec<PathBuf> {
+        let mut presets = Vec::new();
+        for dir in [SHIPPED_PRESETS_DIR, USER_PRESETS_DIR] {
+            let mut paths: Vec<_> = fs::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| matches!(path.extension().and_then(|ext| ext.to_str()), Some("json")))
+                .collect();
+            paths.sort();
+            presets.extend(paths);
+        }
+        presets
+    }
+
+    fn load_preset(path: &Path) -> Option<KeyBindings> {
+        match KeyBindings::load(path) {
+            Ok(bindings) => {
+                toast::show(format!("Loaded controls from {}", path.display()));
+                Some(bindings)
+            }
+            Err(err) => {
+                error!("Failed to load controls from {}: {err}", path.display());
+                toast::show("Failed to load controls");
+                None
+            }
+        }
+    }
+
+    fn export_preset(bindings: &KeyBindings) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let path: PathBuf = [USER_PRESETS_DIR, &format!("preset-{timestamp}.json")]
+            .iter()
+            .collect();
+
+        let result = fs::create_dir_all(USER_PRESETS_DIR)
+            .map_err(KeyBindingsError::from)
+            .and_then(|()| bindings.save(&path));
+        match result {
+            Ok(()) => toast::show(format!("Exported controls to {}", path.display())),
+            Err(err) => {
+                error!("Failed to export controls: {err}");
+                toast::show("Failed to export controls");
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{
+    level::Level,
+    math::{Bounds, FVec2},
+    objects::ObjectVisibility,
+    player::Player,
+    rendering::DrawState,
+    tilemap::Tile,
+};
+
+/// How the scene is rendered, for debugging
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugDrawMode {
+    Normal,
+    /// Draw the scene with outlines of all bounds on top
+    Wireframe,
+    /// Only draw the outlines of all bounds
+    BoundsOnly,
+}
+
+impl DebugDrawMode {
+    const ALL: [Self; 3] = [
+        DebugDrawMode::Normal,
+        DebugDrawMode::Wireframe,
+        DebugDrawMode::BoundsOnly,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            DebugDrawMode::Normal => "Normal",
+            DebugDrawMode::Wireframe => "Wireframe",
+            DebugDrawMode::BoundsOnly => "Bounds only",
+        }
+    }
+}
+
+/// DevGUI settings to hide parts of the scene
+#[derive(Debug, Clone)]
+pub struct DebugView {
+    pub tilemap: bool,
+    pub player: bool,
+    pub objects: ObjectVisibility,
+    pub mode: DebugDrawMode,
+}
+
+impl Default for DebugView {
+    fn default() -> Self {
+        Self {
+            tilemap: true,
+            player: true,
+            objects: ObjectVisibility::default(),
+            mode: DebugDrawMode::Normal,
+        }
+    }
+}
+
+impl DebugView {
+    const OBJECT_OUTLINE_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+    const PLAYER_OUTLINE_COLOR: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+    const TILE_OUTLINE_COLOR: [f32; 4] = [0.0, 0.6, 1.0, 0.6];
+
+    /// Whether the regular renderers should run
+    pub fn draw_scene(&self) -> bool {
+        self.mode != DebugDrawMode::BoundsOnly
+    }
+
+    pub fn draw_tilemap(&self) -> bool {
+        self.draw_scene() && self.tilemap
+    }
+
+    pub fn draw_player(&self) -> bool {
+        self.draw_scene() && self.player
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui) {
+        for mode in DebugDrawMode::ALL {
+            if gui.radio_button_bool(mode.label(), self.mode == mode) {
+                self.mode = mode;
+            }
+        }
+        gui.separator();
+        gui.checkbox("tilemap", &mut self.tilemap);
+        gui.checkbox("player", &mut self.player);
+        self.objects.draw_gui(gui);
+    }
+
+    /// Draw outlines of everything visible on top of the scene
+    pub fn draw_outlines(&self, gui: &imgui::Ui, state: &DrawState, level: &Level, player: &Player) {
+        if self.mode == DebugDrawMode::Normal {
+            return;
+        }
+
+        let screen_size = gui.io().display_size;
+        let draw_list = gui.get_background_draw_list();
+        let draw_bounds = |bounds: Bounds, color: [f32; 4]| {
+            let min = state.world_to_screen(bounds.min, screen_size);
+            let max = state.world_to_screen(bounds.max, screen_size);
+            draw_list.add_rect(min, max, color).build();
+        };
+
+        if self.tilemap {
+            let tilemap = &level.tilemap;
+            for y in 0..tilemap.height() {
+                for x in 0..tilemap.width() {
+                    let tile = tilemap.get_tile(x, y);
+                    let min = FVec2::new(x as f32, y as f32);
+                    if tile.is_solid() || tile.world_type().is_some() {
+                        draw_bounds(Bounds::new(min, min + FVec2::new(1.0, 1.0)), Self::TILE_OUTLINE_COLOR);
+                    } else if matches!(tile, Tile::OneWayUp) {
+                        let max = min + FVec2::new(1.0, Tile::ONE_WAY_THICKNESS);
+                        draw_bounds(Bounds::new(min, max), Self::TILE_OUTLINE_COLOR);
+                    }
+                }
+            }
+        }
+        for bounds in level.objects.objects.debug_bounds(&self.objects) {
+            draw_bounds(bounds, Self::OBJECT_OUTLINE_COLOR);
+        }
+        if self.player {
+            draw_bounds(player.bounds(), Self::PLAYER_OUTLINE_COLOR);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use sdl2::rect::Rect;
+use sdl2::video::{FullscreenType, Window as SdlWindow};
+use sdl2::VideoSubsystem;
+
+use crate::settings::{FullscreenMode, PresentMode, Settings};
+
+/// A display connected to the system, as reported by SDL
+#[derive(Debug, Clone)]
+pub struct DisplayInfo {
+    pub index: i32,
+    pub name: String,
+    pub bounds: Rect,
+    /// Refresh rate in Hz, zero if unknown
+    pub refresh_rate: i32,
+    /// Diagonal, horizontal and vertical DPI
+    pub dpi: Option<(f32, f32, f32)>,
+}
+
+impl DisplayInfo {
+    pub fn query(video_subsystem: &VideoSubsystem, index: i32) -> Result<Self, String> {
+        Ok(Self {
+            index,
+            name: video_subsystem.display_name(index)?,
+            bounds: video_subsystem.display_bounds(index)?,
+            refresh_rate: video_subsystem
+                .current_display_mode(index)
+                .map(|mode| mode.refresh_rate)
+                .unwrap_or(0),
+            dpi: video_subsystem.display_dpi(index).ok(),
+        })
+    }
+
+    /// Get all connected displays, skipping the ones that couldn't be queried
+    pub fn all(video_subsystem: &VideoSubsystem) -> Vec<Self> {
+        let count = video_subsystem.num_video_displays().unwrap_or(0);
+        (0..count)
+            .filter_map(|index| DisplayInfo::query(video_subsystem, index).ok())
+            .collect()
+    }
+
+    /// Find a connected display by its name
+    pub fn find(video_subsystem: &VideoSubsystem, name: &str) -> Option<Self> {
+        DisplayInfo::all(video_subsystem)
+            .into_iter()
+            .find(|display| display.name == name)
+    }
+
+    /// Top left corner of a window with the given size centered on this display
+    pub fn centered_position(&self, width: u32, height: u32) -> (i32, i32) {
+        (
+            self.bounds.x() + (self.bounds.width() as i32 - width as i32) / 2,
+            self.bounds.y() + (self.bounds.height() as i32 - height as i32) / 2,
+        )
+    }
+}
+
+/// Changes to the window requested in the `DisplayPanel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayRequest {
+    /// Move the window to the display with the given index
+    MoveTo(i32),
+    SetFullscreen(bool),
+    SetFullscreenMode(FullscreenMode),
+    SetPresentMode(PresentMode),
+}
+
+/// DevGUI section showing the display the window is on, and allowing to move it to another one
+#[derive(Debug, Default)]
+pub struct DisplayPanel;
+
+impl DisplayPanel {
+    pub fn draw_gui(
+        &mut self,
+        gui: &imgui::Ui,
+        sdl_window: &SdlWindow,
+        settings: &Settings,
+    ) -> Option<DisplayRequest> {
+        // Adds a section to the game's DevGUI window
+        let _token = imgui::Window::new("DevGUI").begin(gui)?;
+        if !gui.collapsing_header("Display", imgui::TreeNodeFlags::empty()) {
+            return None;
+        }
+
+        let displays = DisplayInfo::all(sdl_window.subsystem());
+        let current_index = sdl_window.display_index().ok();
+        let mut request = None;
+
+        gui.indent();
+        match displays.iter().find(|display| Some(display.index) == current_index) {
+            Some(display) => {
+                gui.text(format!("Display: {} ({})", display.name, display.index));
+                gui.text(format!(
+                    "Bounds: {}x{} at {}, {}",
+                    display.bounds.width(),
+                    display.bounds.height(),
+                    display.bounds.x(),
+                    display.bounds.y()
+                ));
+                gui.text(format!("Refresh rate: {} Hz", display.refresh_rate));
+                match display.dpi {
+                    Some((diagonal, horizontal, vertical)) => gui.text(format!(
+                        "DPI: {diagonal:.0} (horizontal {horizontal:.0}, vertical {vertical:.0})"
+                    )),
+                    None => gui.text("DPI: unknown"),
+                }
+            }
+            None => gui.text("Display: unknown"),
+        }
+
+        let names: Vec<_> = displays.iter().map(|display| display.name.as_str()).collect();
+        let mut selected = displays
+            .iter()
+            .position(|display| Some(display.index) == current_index)
+            .unwrap_or(0);
+        if gui.combo_simple_string("Move to", &mut selected, &names) {
+            if let Some(display) = displays.get(selected) {
+                request = Some(DisplayRequest::MoveTo(display.index));
+            }
+        }
+
+        let mut fullscreen = sdl_window.fullscreen_state() != FullscreenType::Off;
+        if gui.checkbox("Fullscreen", &mut fullscreen) {
+            request = Some(DisplayRequest::SetFullscreen(fullscreen));
+        }
+
+        let names: Vec<_> = FullscreenMode::ALL.iter().map(|mode| mode.name()).collect();
+        let mut selected = FullscreenMode::ALL
+            .iter()
+            .position(|mode| *mode == settings.fullscreen_mode)
+            .unwrap_or(0);
+        if gui.combo_simple_string("Fullscreen mode", &mut selected, &names) {
+            request = Some(DisplayRequest::SetFullscreenMode(FullscreenMode::ALL[selected]));
+        }
+
+        let names: Vec<_> = PresentMode::ALL.iter().map(|mode| mode.name()).collect();
+        let mut selected = PresentMode::ALL
+            .iter()
+            .position(|mode| *mode == settings.present_mode)
+            .unwrap_or(0);
+        if gui.combo_simple_string("Present mode", &mut selected, &names) {
+            request = Some(DisplayRequest::SetPresentMode(PresentMode::ALL[selected]));
+        }
+        gui.unindent();
+
+        request
+    }
+}
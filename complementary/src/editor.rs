@@ -0,0 +1,356 @@
+use std::fs;
+
+use bytemuck::Contiguous;
+use log::error;
+use serde_json::Value;
+
+use crate::{
+    level::Level,
+    math::{Bounds, FVec2},
+    objects::ObjectSet,
+    rendering::DrawState,
+    tilemap::Tile,
+    toast,
+};
+
+/// Objects are moved in steps of this size (in tiles)
+const GRID_SNAP: f32 = 0.5;
+/// Objects are copied with this offset, so that the copy isn't hidden behind the original
+const DUPLICATE_OFFSET: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditorTool {
+    Tiles,
+    Objects,
+}
+
+/// Paints tiles and moves objects of the current level with the mouse while the game is frozen.
+///
+/// Objects are edited as the JSON values of the object map, so that fields the game doesn't
+/// know about and the order of the objects are kept when saving.
+pub struct LevelEditor {
+    active: bool,
+    tool: EditorTool,
+    brush: Tile,
+    /// Name of the level `objects` were loaded from
+    level_name: String,
+    objects: Vec<Value>,
+    selected: Option<usize>,
+    /// Offset between the mouse and the selected object while it's dragged
+    drag_offset: Option<FVec2>,
+    /// JSON of the selected object, can be edited in the DevGUI
+    selected_json: String,
+}
+
+impl LevelEditor {
+    pub fn new() -> Self {
+        LevelEditor {
+            active: false,
+            tool: EditorTool::Tiles,
+            brush: Tile::Solid,
+            level_name: String::new(),
+            objects: Vec::new(),
+            selected: None,
+            drag_offset: None,
+            selected_json: String::new(),
+        }
+    }
+
+    /// The game isn't ticked while the editor is active
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, state: &DrawState, level: &mut Level) {
+        if self.active {
+            if self.level_name != level.name {
+                // The level was changed, e.g. from the level list
+                self.load_objects(level);
+            }
+            self.handle_mouse(gui, state, level);
+            self.draw_overlay(gui, state, level);
+        }
+
+        // Adds a section to the game's DevGUI window
+        let _token = match imgui::Window::new("DevGUI").begin(gui) {
+            Some(token) => token,
+            None => return,
+        };
+        if !gui.collapsing_header("Editor", imgui::TreeNodeFlags::empty()) {
+            return;
+        }
+
+        gui.indent();
+        if gui.checkbox("Active", &mut self.active) && self.active {
+            self.load_objects(level);
+        }
+        if self.active {
+            self.draw_tools_gui(gui, level);
+        }
+        gui.unindent();
+    }
+
+    fn draw_tools_gui(&mut self, gui: &imgui::Ui, level: &mut Level) {
+        if gui.radio_button_bool("Tiles", self.tool == EditorTool::Tiles) {
+            self.tool = EditorTool::Tiles;
+        }
+        gui.same_line();
+        if gui.radio_button_bool("Objects", self.tool == EditorTool::Objects) {
+            self.tool = EditorTool::Objects;
+        }
+
+        match self.tool {
+            EditorTool::Tiles => {
+                let tiles: Vec<Tile> = Tile::all().collect();
+                let names: Vec<String> = tiles.iter().map(|tile| format!("{tile:?}")).collect();
+                let mut index = tiles
+                    .iter()
+                    .position(|tile| tile.into_integer() == self.brush.into_integer())
+                    .unwrap_or_default();
+                if gui.combo_simple_string("Brush", &mut index, &names) {
+                    self.brush = tiles[index];
+                }
+                gui.text("Left click to paint, right click to erase");
+            }
+            EditorTool::Objects => match self.selected {
+                Some(index) => {
+                    gui.input_text_multiline("##object", &mut self.selected_json, [0.0, 150.0])
+                        .build();
+                    if gui.button("Apply") {
+                        match serde_json::from_str(&self.selected_json) {
+                            Ok(value) => {
+                                self.objects[index] = value;
+                                self.rebuild_objects(level);
+                            }
+                            Err(err) => toast::show(format!("Invalid object: {err}")),
+                        }
+                    }
+                    gui.same_line();
+                    if gui.button("Duplicate") {
+                        self.duplicate_object(index, level);
+                    }
+                    gui.same_line();
+                    if gui.button("Delete") {
+                        self.delete_object(index, level);
+                    }
+                }
+                None => gui.text("Click an object to select it"),
+            },
+        }
+
+        if gui.button("Save") {
+            self.save(level);
+        }
+        gui.same_line();
+        if gui.button("Revert") {
+            match Level::load_with_difficulty(&self.level_name, level.difficulty) {
+                Ok(loaded) => {
+                    *level = loaded;
+                    self.load_objects(level);
+                }
+                Err(err) => error!("Failed to reload level {}: {err}", self.level_name),
+            }
+        }
+    }
+
+    fn handle_mouse(&mut self, gui: &imgui::Ui, state: &DrawState, level: &mut Level) {
+        let io = gui.io();
+        let position = state.screen_to_world(io.mouse_pos, io.display_size);
+
+        match self.tool {
+            EditorTool::Tiles => {
+                if io.want_capture_mouse {
+                    return;
+                }
+                let tile = if gui.is_mouse_down(imgui::MouseButton::Left) {
+                    self.brush
+                } else if gui.is_mouse_down(imgui::MouseButton::Right) {
+                    Tile::Air
+                } else {
+                    return;
+                };
+                let (x, y) = (position.x.floor() as i32, position.y.floor() as i32);
+                if level.tilemap.contains_tile(x, y)
+                    && level.tilemap.get_tile(x, y).into_integer() != tile.into_integer()
+                {
+                    level.set_tile(x, y, tile);
+                }
+            }
+            EditorTool::Objects => {
+                if gui.is_mouse_clicked(imgui::MouseButton::Left) && !io.want_capture_mouse {
+                    self.select(self.object_at(position));
+                    self.drag_offset = self
+                        .selected
+                        .and_then(|index| object_position(&self.objects[index]))
+                        .map(|object_position| position - object_position);
+                }
+                if let (Some(index), Some(offset)) = (self.selected, self.drag_offset) {
+                    let target = position - offset;
+                    let snapped = FVec2::new(
+                        (target.x / GRID_SNAP).round() * GRID_SNAP,
+                        (target.y / GRID_SNAP).round() * GRID_SNAP,
+                    );
+                    self.objects[index]["position"] = serde_json::json!({ "x": snapped.x, "y": snapped.y });
+                    if gui.is_mouse_released(imgui::MouseButton::Left) {
+                        self.drag_offset = None;
+                        self.select(Some(index));
+                        self.rebuild_objects(level);
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_overlay(&self, gui: &imgui::Ui, state: &DrawState, level: &Level) {
+        let io = gui.io();
+        let draw_list = gui.get_background_draw_list();
+        let to_screen = |bounds: Bounds| {
+            (
+                state.world_to_screen(bounds.min, io.display_size),
+                state.world_to_screen(bounds.max, io.display_size),
+            )
+        };
+
+        if self.tool == EditorTool::Objects {
+            for (index, object) in self.objects.iter().enumerate() {
+                if let Some(bounds) = object_bounds(object) {
+                    let (min, max) = to_screen(bounds);
+                    let color = if self.selected == Some(index) {
+                        [1.0, 0.8, 0.0, 1.0]
+                    } else {
+                        [0.2, 0.8, 1.0, 0.8]
+                    };
+                    draw_list.add_rect(min, max, color).thickness(2.0).build();
+                }
+            }
+        }
+
+        if !io.want_capture_mouse {
+            let position = state.screen_to_world(io.mouse_pos, io.display_size);
+            let tile = FVec2::new(position.x.floor(), position.y.floor());
+            if self.tool == EditorTool::Tiles
+                && level.tilemap.contains_tile(tile.x as i32, tile.y as i32)
+            {
+                let (min, max) = to_screen(Bounds::new(tile, tile + FVec2::new(1.0, 1.0)));
+                draw_list.add_rect(min, max, [1.0, 1.0, 1.0, 0.8]).build();
+            }
+        }
+    }
+
+    fn load_objects(&mut self, level: &Level) {
+        self.level_name = level.name.clone();
+        self.select(None);
+        self.drag_offset = None;
+        self.objects = match fs::read_to_string(Level::object_map_path(&level.name))
+            .map_err(|err| err.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+        {
+            Ok(objects) => objects,
+            Err(err) => {
+                error!("Failed to load objects of {}: {err}", level.name);
+                Vec::new()
+            }
+        };
+    }
+
+    /// Replace the level's objects with the edited ones
+    fn rebuild_objects(&mut self, level: &mut Level) {
+        match ObjectSet::from_json(&self.objects, level.difficulty) {
+            Ok(objects) => level.set_objects(objects),
+            Err(err) => toast::show(format!("Invalid objects: {err}")),
+        }
+    }
+
+    fn select(&mut self, index: Option<usize>) {
+        self.selected = index;
+        self.selected_json = index
+            .and_then(|index| serde_json::to_string_pretty(&self.objects[index]).ok())
+            .unwrap_or_default();
+    }
+
+    /// Topmost object containing the given position
+    fn object_at(&self, position: FVec2) -> Option<usize> {
+        self.objects.iter().rposition(|object| {
+            object_bounds(object).map_or(false, |bounds| bounds.contains_point(position))
+        })
+    }
+
+    fn duplicate_object(&mut self, index: usize, level: &mut Level) {
+        let mut object = self.objects[index].clone();
+        object["id"] = Value::from(self.unused_id(&object));
+        if let Some(position) = object_position(&object) {
+            let position = position + FVec2::new(DUPLICATE_OFFSET, DUPLICATE_OFFSET);
+            object["position"] = serde_json::json!({ "x": position.x, "y": position.y });
+        }
+        self.objects.push(object);
+        self.select(Some(self.objects.len() - 1));
+        self.rebuild_objects(level);
+    }
+
+    /// Id for a new copy of `object`, numbered like the ones from the converter
+    fn unused_id(&self, object: &Value) -> String {
+        let name = object["type"].as_str().unwrap_or("Object");
+        (0..)
+            .map(|n| format!("{name}_{n}"))
+            .find(|id| !self.objects.iter().any(|other| other["id"] == id.as_str()))
+            .unwrap()
+    }
+
+    fn delete_object(&mut self, index: usize, level: &mut Level) {
+        self.objects.remove(index);
+        // Parents are referenced by their index in the list
+        for object in &mut self.objects {
+            let fields = match object.as_object_mut() {
+                Some(fields) => fields,
+                None => continue,
+            };
+            match fields.get("parent").and_then(Value::as_u64).map(|parent| parent as usize) {
+                Some(parent) if parent == index => {
+                    fields.remove("parent");
+                }
+                Some(parent) if parent > index => {
+                    fields.insert(String::from("parent"), Value::from(parent - 1));
+                }
+                _ => {}
+            }
+        }
+        self.select(None);
+        self.rebuild_objects(level);
+    }
+
+    fn save(&self, level: &Level) {
+        let result = level
+            .tilemap
+            .save_to_file(Level::tilemap_path(&level.name))
+            .and_then(|()| {
+                let json = serde_json::to_string_pretty(&self.objects)?;
+                fs::write(Level::object_map_path(&level.name), json)
+            });
+        match result {
+            Ok(()) => toast::show(format!("Saved {}", level.name)),
+            Err(err) => {
+                error!("Failed to save level {}: {err}", level.name);
+                toast::show("Failed to save level");
+            }
+        }
+    }
+}
+
+fn object_position(object: &Value) -> Option<FVec2> {
+    let position = &object["position"];
+    Some(FVec2::new(
+        position["x"].as_f64()? as f32,
+        position["y"].as_f64()? as f32,
+    ))
+}
+
+/// Area of an object that can be clicked. Objects without a size get a handle of one tile.
+fn object_bounds(object: &Value) -> Option<Bounds> {
+    let position = object_position(object)?;
+    let size = &object["data"]["size"];
+    let size = match (size["x"].as_f64(), size["y"].as_f64()) {
+        (Some(x), Some(y)) => FVec2::new(x as f32, y as f32),
+        _ => FVec2::new(1.0, 1.0),
+    };
+    Some(Bounds::new(position, position + size))
+}
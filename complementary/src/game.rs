@@ -1,18 +1,45 @@
-use std::time::{Duration, SystemTime};
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
 
 use crate::{
+    camera::Camera,
+    collision::CollisionWorld,
+    debug_view::DebugView,
+    editor::LevelEditor,
+    gpu_memory,
     imgui_helpers::ImGui,
     input::{ButtonType, Input},
-    level::{self, Level, LevelLoadError, LevelState},
-    objects::{ObjectSet, Tickable},
-    player::Player,
-    rendering::DrawState,
+    level::{self, Difficulty, Level, LevelLoadError, LevelState},
+    level_validator::{self, MovementReach, Reachability},
+    menu::{PauseMenu, PauseMenuItem},
+    mods::{ModList, ModLoadError, ModTickState, TickHook},
+    objects::{
+        particle_system::{ParticleLayer, ParticleSystemObject, ParticleSystemRenderer},
+        ObjectSet, Tickable,
+    },
+    player::{Ability, AbilityPair, Player, PlayerState},
+    player_tuning::{PlayerTuning, PLAYER_TUNING_PATH},
+    post_processing::SpeedLines,
+    profiler::TickBudget,
+    randomizer::Randomizer,
+    replay::ReplaySetup,
+    rendering::{DrawState, FadeRenderer, RenderLayer},
+    save::{self, SaveData},
+    settings::Settings,
+    telegraph,
+    thumbnail::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH},
     tilemap::{Tilemap, TilemapRenderer},
-    window::DrawContext, math::Color, audio,
+    user_levels::UserLevels,
+    toast,
+    ui_layout::{anchored_position, anchored_text_position, Anchor},
+    window::DrawContext, math::Color, audio::{self, SoundId}, localization,
 };
-use log::error;
+use complementary_macros::JsonSchema;
+use log::{error, warn};
 use rand_xoshiro::{rand_core::SeedableRng, Xoshiro256PlusPlus};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 pub struct Game {
     rng: Xoshiro256PlusPlus,
@@ -20,8 +47,100 @@ pub struct Game {
     level: Level,
     level_index: usize,
     world_type: WorldType,
+    /// Whether the other world is currently previewed (see `ButtonType::Peek`)
+    peeking: bool,
+    show_map: bool,
+    state: GameState,
+    pause_menu: PauseMenu,
+    /// Set when the player chose to quit the game in the pause menu
+    quit_requested: bool,
+    /// Set when the progress was saved, so that the window captures a thumbnail
+    thumbnail_requested: bool,
+    /// Thumbnail of the last save, shown on the title screen
+    title_thumbnail: Option<imgui::TextureId>,
+    /// Number of ticks simulated since the game started
+    tick_count: u64,
+    /// Return to the title screen after the player was idle for this long
+    idle_timeout: Option<Duration>,
+    /// Levels played in order, `level_index` points into this list
+    playlist: Vec<String>,
+    kiosk: bool,
+    /// Progress that's saved when a level is completed. Not persisted in kiosk mode.
+    save_data: SaveData,
+    debug_view: DebugView,
+    editor: LevelEditor,
+    mods: ModList,
+    user_levels: UserLevels,
+    randomizer: Randomizer,
+    /// Reach of the player tuning it was measured with, see `Game::movement_reach()`
+    movement_reach: Option<(PlayerTuning, MovementReach)>,
+    /// Ignore gameplay input entirely while the DevGUI window is focused, not only while
+    /// ImGui is capturing the keyboard
+    lock_input_when_gui_focused: bool,
+    dev_gui_focused: bool,
+    tick_budget: TickBudget,
+    death_transition: Option<DeathTransition>,
+    /// Ticks left to retry a `SwitchAndAbility` switch that was blocked by an object in the other world
+    switch_buffer_ticks: i32,
+    /// Ticks left of the flash after completing a level
+    completion_flash_ticks: i32,
+    /// Ticks left that the simulation is frozen for, see `hitstop()`
+    hitstop_ticks: i32,
+    /// See `Settings::hitstop`
+    hitstop_enabled: bool,
+    /// See `Settings::reduce_motion`
+    reduce_motion: bool,
+    /// See `Settings::show_timer`
+    show_timer: bool,
+    /// See `Settings::reduce_flashing`
+    reduce_flashing: bool,
+    /// Particle systems that aren't part of the level, like the burst when the player dies
+    effects: Vec<ParticleSystemObject>,
 
     draw_state: DrawState,
+    camera: Camera,
+    /// Renderers are created when the game is first drawn, so that it can be simulated
+    /// without a GPU
+    effects_renderer: Option<ParticleSystemRenderer>,
+    fade_renderer: Option<FadeRenderer>,
+}
+
+/// Top-level state of the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameState {
+    Title,
+    Playing,
+    /// The game doesn't tick while the pause menu is open
+    Paused,
+    /// The player was idle for too long, fade out to the title screen
+    FadingToTitle { ticks: i32 },
+}
+
+/// Played between the player's death and their respawn: the player bursts into particles and
+/// the game freezes for a moment, then the screen fades out and back in
+#[derive(Debug, Clone, Copy)]
+struct DeathTransition {
+    ticks: i32,
+}
+
+impl DeathTransition {
+    const FREEZE_TICKS: i32 = 40;
+    const FADE_TICKS: i32 = 20;
+    /// The player is respawned while the screen is completely faded out
+    const RESPAWN_TICK: i32 = DeathTransition::FREEZE_TICKS + DeathTransition::FADE_TICKS;
+    const DURATION: i32 = DeathTransition::RESPAWN_TICK + DeathTransition::FADE_TICKS;
+
+    /// Opacity of the fade, from 0 to 1
+    fn fade_alpha(&self) -> f32 {
+        let fade_ticks = self.ticks - DeathTransition::FREEZE_TICKS;
+        if fade_ticks <= 0 {
+            0.0
+        } else if fade_ticks <= DeathTransition::FADE_TICKS {
+            fade_ticks as f32 / DeathTransition::FADE_TICKS as f32
+        } else {
+            1.0 - (fade_ticks - DeathTransition::FADE_TICKS) as f32 / DeathTransition::FADE_TICKS as f32
+        }
+    }
 }
 
 pub struct PlayerTickState<'a> {
@@ -34,13 +153,18 @@ pub struct PlayerTickState<'a> {
 
 pub struct ObjectTickState<'a> {
     pub input: &'a Input,
+    pub rng: &'a mut Xoshiro256PlusPlus,
     pub tilemap: &'a mut Tilemap,
     pub player: &'a mut Player,
     pub level_state: &'a mut LevelState,
     pub world_type: WorldType,
+    /// Set if ticks take too long, expensive effects should be limited
+    pub reduced_simulation: bool,
+    /// Particle effects spawned by objects in this tick, e.g. splashes
+    pub spawned_effects: Vec<ParticleSystemObject>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema)]
 pub enum WorldType {
     Light,
     Dark,
@@ -60,6 +184,52 @@ impl WorldType {
             WorldType::Dark => Color::WHITE,
         }
     }
+
+    /// Everything is drawn using the colors of the Light world and inverted in post-processing
+    /// for the Dark world. Colors that should look the same in both worlds are inverted
+    /// beforehand to cancel that out.
+    pub fn uninverted_color(self, color: Color) -> Color {
+        match self {
+            WorldType::Light => color,
+            WorldType::Dark => color.inverted(),
+        }
+    }
+}
+
+/// Speed modifier for practice or challenge runs, in percent of the normal speed. Only
+/// changes how many ticks are run per second, so the simulation itself stays deterministic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GameSpeed(u32);
+
+impl GameSpeed {
+    pub const NORMAL: GameSpeed = GameSpeed(100);
+    pub const MIN_PERCENT: u32 = 50;
+    pub const MAX_PERCENT: u32 = 150;
+
+    pub fn new(percent: u32) -> Self {
+        GameSpeed(percent.clamp(GameSpeed::MIN_PERCENT, GameSpeed::MAX_PERCENT))
+    }
+
+    pub fn percent(self) -> u32 {
+        self.0
+    }
+
+    /// Runs at other speeds can't be compared with normal ones, so they don't set records
+    pub fn is_normal(self) -> bool {
+        self == GameSpeed::NORMAL
+    }
+
+    /// Game time passed in `real_time`
+    pub fn scale(self, real_time: Duration) -> Duration {
+        real_time * self.0 / 100
+    }
+}
+
+impl Default for GameSpeed {
+    fn default() -> Self {
+        GameSpeed::NORMAL
+    }
 }
 
 lazy_static::lazy_static! {
@@ -70,29 +240,161 @@ lazy_static::lazy_static! {
 impl Game {
     // Tick 100 times per second
     pub const TICK_DURATION: Duration = Duration::new(0, 10000000);
-    // Skip 5 frames max. between rendering
+    // Skip 5 frames max. between rendering by default, see `Settings::max_ticks_per_frame`
     pub const MAX_TICKS_PER_FRAME: i32 = 5;
+    /// Duration of the fade to the title screen in ticks
+    const TITLE_FADE_TICKS: i32 = 100;
+    /// Duration of the flash after a level was completed in ticks
+    const COMPLETION_FLASH_TICKS: i32 = 30;
+    /// How long a blocked `SwitchAndAbility` switch is retried in ticks
+    const MAX_SWITCH_BUFFER_TICKS: i32 = 6;
+    const DEATH_HITSTOP_TICKS: i32 = 8;
+    const DASH_IMPACT_HITSTOP_TICKS: i32 = 4;
+    /// The zoom punch recovers for this many ticks after the hitstop is over
+    const ZOOM_PUNCH_RECOVERY_TICKS: i32 = 12;
+
+    /// Create the game and load the first level. No GPU resources are created until the game
+    /// is drawn, so it can also be ticked headless.
+    pub fn new(settings: &Settings) -> Result<Self, GameLoadError> {
+        // Every visitor starts from the beginning in kiosk mode
+        let save_data = if settings.kiosk {
+            SaveData::default()
+        } else {
+            SaveData::load_or_default()
+        };
+        Game::with_save_data(settings, save_data, ModList::discover())
+    }
+
+    /// Like `new()`, but with a fresh save and without mods, so that tests don't depend on the
+    /// files of whoever runs them
+    #[cfg(test)]
+    pub(crate) fn new_for_tests(settings: &Settings) -> Result<Self, GameLoadError> {
+        Game::with_save_data(settings, SaveData::default(), ModList::default())
+    }
 
-    pub fn new(device: &wgpu::Device) -> Result<Self, GameLoadError> {
+    fn with_save_data(
+        settings: &Settings,
+        save_data: SaveData,
+        mods: ModList,
+    ) -> Result<Self, GameLoadError> {
         let seed = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap_or(Duration::default())
             .as_secs();
+        let playlist = match &settings.playlist {
+            Some(playlist) => playlist.clone(),
+            // Levels from mods are played after the main levels
+            None => MAIN_LEVELS
+                .iter()
+                .map(|level| level.to_string())
+                .chain(mods.levels())
+                .collect(),
+        };
+        let level_index = save_data.level_index.min(playlist.len().saturating_sub(1));
+        let mut level = Level::load_with_difficulty(
+            playlist.get(level_index).expect("No levels loaded"),
+            save_data.difficulty(),
+        )?;
+        if save_data.mirror_mode {
+            level.mirror();
+        }
+
+        let mut player = Player::new();
+        player.set_tuning(PlayerTuning::load_or_default());
+        player.set_forgiving_spikes(settings.forgiving_spikes);
 
         let mut game = Game {
             rng: Xoshiro256PlusPlus::seed_from_u64(seed),
-            player: Player::new(device),
+            player,
             world_type: WorldType::Light,
-            level: Level::load(device, MAIN_LEVELS.first().expect("No levels loaded"))?,
-            level_index: 0,
+            level,
+            level_index,
+            peeking: false,
+            show_map: false,
+            // Wait for someone to walk up to the game at events
+            state: if settings.kiosk {
+                GameState::Title
+            } else {
+                GameState::Playing
+            },
+            pause_menu: PauseMenu::new(!settings.kiosk),
+            quit_requested: false,
+            thumbnail_requested: false,
+            title_thumbnail: None,
+            tick_count: 0,
+            idle_timeout: settings.idle_timeout,
+            playlist,
+            kiosk: settings.kiosk,
+            save_data,
+            debug_view: DebugView::default(),
+            editor: LevelEditor::new(),
+            mods,
+            user_levels: UserLevels::new(),
+            randomizer: Randomizer::new(),
+            movement_reach: None,
+            lock_input_when_gui_focused: false,
+            dev_gui_focused: false,
+            tick_budget: TickBudget::new(Game::TICK_DURATION, settings.auto_slow_mode),
+            death_transition: None,
+            switch_buffer_ticks: 0,
+            completion_flash_ticks: 0,
+            hitstop_ticks: 0,
+            hitstop_enabled: settings.hitstop,
+            reduce_motion: settings.reduce_motion,
+            show_timer: settings.show_timer,
+            reduce_flashing: settings.reduce_flashing,
+            effects: Vec::new(),
             draw_state: DrawState::new(),
+            camera: Camera::new(),
+            effects_renderer: None,
+            fade_renderer: None,
         };
 
+        game.player.set_abilities(game.save_data.abilities);
         game.spawn_player();
+        game.reset_camera();
+        game.start_intro();
         Ok(game)
     }
 
-    pub fn draw_gui(&mut self, gui: &imgui::Ui, input: &mut Input, device: &wgpu::Device) {
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, input: &mut Input) {
+        if self.show_map {
+            self.draw_map(gui);
+        }
+        self.debug_view
+            .draw_outlines(gui, &self.draw_state, &self.level, &self.player);
+        match self.state {
+            GameState::Title => self.draw_title(gui),
+            GameState::FadingToTitle { ticks } => {
+                Game::draw_fade(gui, ticks as f32 / Game::TITLE_FADE_TICKS as f32)
+            }
+            GameState::Paused => {
+                Game::draw_fade(gui, 0.5);
+                self.pause_menu.draw_gui(gui);
+                if let Some(seed) = self.randomizer.seed() {
+                    Game::draw_randomizer_seed(gui, seed);
+                }
+            }
+            GameState::Playing => {
+                let hazards = self.level.objects.hazard_bounds(self.world_type);
+                telegraph::draw_hazard_warnings(gui, &self.draw_state, &hazards);
+                if self.show_timer {
+                    self.draw_timer(gui);
+                }
+                if self.level.state.total_shard_count() > 0 {
+                    self.draw_shard_counter(gui);
+                }
+                if self.player.active_ability(self.world_type) == Ability::WallJump {
+                    self.draw_wall_jump_limits(gui);
+                }
+            }
+        }
+
+        self.dev_gui_focused = false;
+        if self.kiosk {
+            return;
+        }
+
         let _token = match imgui::Window::new("DevGUI")
             .size([400.0, 250.0], imgui::Condition::FirstUseEver)
             .begin(&gui)
@@ -100,6 +402,10 @@ impl Game {
             Some(token) => token,
             None => return,
         };
+        self.dev_gui_focused =
+            gui.is_window_focused_with_flags(imgui::WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS);
+
+        gui.checkbox("Lock input while focused", &mut self.lock_input_when_gui_focused);
 
         if gui.button("Change ability") {
             self.player.set_ability(
@@ -111,28 +417,422 @@ impl Game {
         if gui.collapsing_header("Levels", imgui::TreeNodeFlags::empty()) {
             gui.indent();
             for level_name in &*ALL_LEVELS {
-                if gui.button(level_name) {
-                    if let Err(err) = self.load_level(device, level_name) {
+                let label = format!("{} ({level_name})", localization::tr(&format!("level.{level_name}")));
+                if gui.button(label) {
+                    if let Err(err) = self.load_level(level_name) {
+                        error!("{err}");
+                    }
+                }
+            }
+            for level_name in self.mods.levels() {
+                if gui.button(&level_name) {
+                    if let Err(err) = self.load_level(&level_name) {
                         error!("{err}");
                     }
                 }
             }
             gui.unindent();
         }
+        self.user_levels.poll();
+        if gui.collapsing_header("User levels", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            if let Some(level_name) = self.user_levels.draw_gui(gui) {
+                if let Err(err) = self.load_level(&level_name) {
+                    error!("{err}");
+                }
+            }
+            gui.unindent();
+        }
+        if gui.collapsing_header("Randomizer", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            if self.randomizer.draw_gui(gui) {
+                if let Err(err) = self.restart_level() {
+                    error!("{err}");
+                }
+            }
+            gui.unindent();
+        }
+        if gui.collapsing_header("Modifiers", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            // Saved with the rest of the progress, so every save file keeps its own modifiers
+            let mirror_changed = gui.checkbox("Mirror mode", &mut self.save_data.mirror_mode);
+            let hard_mode_changed = gui.checkbox("Hard mode", &mut self.save_data.hard_mode);
+            if mirror_changed || hard_mode_changed {
+                self.save_progress();
+                if let Err(err) = self.restart_level() {
+                    error!("{err}");
+                }
+            }
+            let mut speed = self.save_data.game_speed.percent();
+            if imgui::Slider::new("Game speed (%)", GameSpeed::MIN_PERCENT, GameSpeed::MAX_PERCENT)
+                .build(gui, &mut speed)
+            {
+                self.save_data.game_speed = GameSpeed::new(speed);
+            }
+            if !self.save_data.game_speed.is_normal() {
+                gui.text_disabled("Best times aren't recorded at this speed");
+            }
+            gui.unindent();
+        }
+        if gui.collapsing_header("Mods", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            self.mods.draw_gui(gui);
+            gui.unindent();
+        }
+        if gui.collapsing_header("Language", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            let current_language = localization::current_language();
+            for language in localization::available_languages().unwrap_or_default() {
+                let label = if language == current_language {
+                    format!("{language} (active)")
+                } else {
+                    language.clone()
+                };
+                if gui.button(label) {
+                    if let Err(err) = localization::set_language(&language) {
+                        error!("{err}");
+                    }
+                }
+            }
+            gui.unindent();
+        }
+        if gui.collapsing_header("Level state", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            self.level.draw_state_gui(gui);
+            gui.unindent();
+        }
+        if gui.collapsing_header("Camera", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            self.camera.draw_gui(gui);
+            gui.unindent();
+        }
+        if gui.collapsing_header("GPU memory", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            gpu_memory::draw_gui(gui);
+            gui.unindent();
+        }
+        if gui.collapsing_header("View", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            self.debug_view.draw_gui(gui);
+            gui.unindent();
+        }
+        gui.text(format!(
+            "Tick: {:.2} ms{}",
+            self.tick_budget.last_tick_duration().as_secs_f64() * 1000.0,
+            if self.tick_budget.reduced_simulation() { " (reduced simulation)" } else { "" },
+        ));
         input.draw_gui("Input", gui);
         self.player.draw_gui("Player", gui);
+        if gui.collapsing_header("Player tuning", imgui::TreeNodeFlags::empty()) {
+            gui.indent();
+            if gui.button("Save") {
+                match self.player.tuning().save(Path::new(PLAYER_TUNING_PATH)) {
+                    Ok(()) => toast::show(format!("Saved {PLAYER_TUNING_PATH}")),
+                    Err(err) => error!("Failed to save player tuning: {err}"),
+                }
+            }
+            gui.same_line();
+            if gui.button("Reload") {
+                self.player.set_tuning(PlayerTuning::load_or_default());
+            }
+            gui.same_line();
+            if gui.button("Reset to defaults") {
+                self.player.set_tuning(PlayerTuning::default());
+            }
+            self.player.tuning_mut().draw_gui("Values", gui);
+            gui.unindent();
+        }
+        _token.end();
+
+        self.editor.draw_gui(gui, &self.draw_state, &mut self.level);
     }
 
-    pub fn tick(&mut self, input: &Input, device: &wgpu::Device) {
-        if input.get_button(ButtonType::Switch).pressed_first_frame()
-            || input
-                .get_button(ButtonType::SwitchAndAbility)
-                .pressed_first_frame()
-        {
-            if !self.player.is_colliding_with_solid_objects(&self.level.objects, self.world_type.inverse()) {
-                // Only allow switching if the player is not colliding with an object
-                // in the other world to avoid getting stuck
-                self.world_type = self.world_type.inverse();
+    fn draw_map(&self, gui: &imgui::Ui) {
+        const TILE_SIZE: f32 = 3.0;
+        const MARGIN: f32 = 10.0;
+
+        let tilemap = &self.level.tilemap;
+        let origin = anchored_position(
+            Anchor::TOP_RIGHT,
+            [tilemap.width() as f32 * TILE_SIZE, tilemap.height() as f32 * TILE_SIZE],
+            [MARGIN, MARGIN],
+            gui.io().display_size,
+        );
+        let to_screen = |x: f32, y: f32| [origin[0] + x * TILE_SIZE, origin[1] + y * TILE_SIZE];
+
+        let background = self.world_type.inverse().foreground_color();
+        let foreground = self.world_type.foreground_color();
+
+        let draw_list = gui.get_foreground_draw_list();
+        draw_list
+            .add_rect(
+                to_screen(0.0, 0.0),
+                to_screen(tilemap.width() as f32, tilemap.height() as f32),
+                [background.r, background.g, background.b, 0.75],
+            )
+            .filled(true)
+            .build();
+
+        for y in 0..tilemap.height() {
+            for x in 0..tilemap.width() {
+                if tilemap.get_tile(x, y).is_solid_in(self.world_type) {
+                    draw_list
+                        .add_rect(
+                            to_screen(x as f32, y as f32),
+                            to_screen(x as f32 + 1.0, y as f32 + 1.0),
+                            [foreground.r, foreground.g, foreground.b, 0.75],
+                        )
+                        .filled(true)
+                        .build();
+                }
+            }
+        }
+
+        let player_bounds = self.player.bounds();
+        let player_color = self.player.active_ability(self.world_type).color();
+        draw_list
+            .add_rect(
+                to_screen(player_bounds.min.x, player_bounds.min.y),
+                to_screen(player_bounds.max.x, player_bounds.max.y),
+                [player_color.r, player_color.g, player_color.b, 1.0],
+            )
+            .filled(true)
+            .build();
+    }
+
+    fn draw_fade(gui: &imgui::Ui, alpha: f32) {
+        gui.get_background_draw_list()
+            .add_rect([0.0, 0.0], gui.io().display_size, [0.0, 0.0, 0.0, alpha])
+            .filled(true)
+            .build();
+    }
+
+    fn draw_title(&self, gui: &imgui::Ui) {
+        Game::draw_fade(gui, 0.8);
+
+        let draw_list = gui.get_background_draw_list();
+        if let Some(thumbnail) = self.title_thumbnail {
+            // Centered above the text, which starts at the center of the screen
+            let [display_width, display_height] = gui.io().display_size;
+            let width = display_width * 0.25;
+            let height = width * THUMBNAIL_HEIGHT as f32 / THUMBNAIL_WIDTH as f32;
+            let x = (display_width - width) / 2.0;
+            let y = display_height / 2.0 - height - gui.text_line_height() * 2.0;
+            draw_list.add_image(thumbnail, [x, y], [x + width, y + height]).build();
+        }
+        let lines = [localization::tr("title.name"), localization::tr("title.start")];
+        for (index, line) in lines.iter().enumerate() {
+            let [text_width, text_height] = gui.calc_text_size(line);
+            // The lines start at the center of the screen
+            let [x, y] = anchored_position(Anchor::CENTER, [text_width, 0.0], [0.0, 0.0], gui.io().display_size);
+            draw_list.add_text([x, y + index as f32 * text_height * 2.0], [1.0, 1.0, 1.0, 1.0], line);
+        }
+    }
+
+    /// Show the seed at the bottom of the screen, so that players can share it
+    fn draw_randomizer_seed(gui: &imgui::Ui, seed: u64) {
+        let text = format!("{}: {seed}", localization::tr("pause.randomizer_seed"));
+        let text_height = gui.calc_text_size(&text)[1];
+        let position = anchored_text_position(gui, &text, Anchor::BOTTOM, [0.0, text_height * 2.0]);
+        gui.get_background_draw_list().add_text(position, [0.7, 0.7, 0.7, 1.0], text);
+    }
+
+    /// Show the time spent in the current level in the top right corner
+    fn draw_timer(&self, gui: &imgui::Ui) {
+        let time = Game::TICK_DURATION * self.level.state.elapsed_ticks();
+        let text = format!("{:.2}", time.as_secs_f32());
+        let text_height = gui.calc_text_size(&text)[1];
+        let position = anchored_text_position(gui, &text, Anchor::TOP_RIGHT, [text_height, text_height]);
+        gui.get_background_draw_list().add_text(position, [0.7, 0.7, 0.7, 1.0], text);
+    }
+
+    /// Show the collected shards of the current level in the top left corner
+    fn draw_shard_counter(&self, gui: &imgui::Ui) {
+        let state = &self.level.state;
+        let text = format!(
+            "{}: {} / {}",
+            localization::tr("hud.shards"),
+            state.collected_shard_count(),
+            state.total_shard_count(),
+        );
+        let text_height = gui.calc_text_size(&text)[1];
+        let position = anchored_text_position(gui, &text, Anchor::TOP_LEFT, [text_height, text_height]);
+        gui.get_background_draw_list().add_text(position, [0.7, 0.7, 0.7, 1.0], text);
+    }
+
+    /// Show the remaining wall jumps and stamina in the bottom left corner, if the player tuning
+    /// limits them
+    fn draw_wall_jump_limits(&self, gui: &imgui::Ui) {
+        const BAR_SIZE: [f32; 2] = [120.0, 8.0];
+        const COLOR: [f32; 4] = [0.7, 0.7, 0.7, 1.0];
+
+        let draw_list = gui.get_background_draw_list();
+        let line_height = gui.text_line_height();
+        let mut margin = [line_height, line_height];
+        if let Some(stamina) = self.player.wall_stamina() {
+            let [x, y] = anchored_position(Anchor::BOTTOM_LEFT, BAR_SIZE, margin, gui.io().display_size);
+            draw_list.add_rect([x, y], [x + BAR_SIZE[0], y + BAR_SIZE[1]], COLOR).build();
+            draw_list
+                .add_rect([x, y], [x + BAR_SIZE[0] * stamina, y + BAR_SIZE[1]], COLOR)
+                .filled(true)
+                .build();
+            margin[1] += BAR_SIZE[1] + line_height * 0.5;
+        }
+        if let Some(remaining) = self.player.remaining_chained_wall_jumps() {
+            let text = format!("{}: {remaining}", localization::tr("hud.wall_jumps"));
+            let position = anchored_text_position(gui, &text, Anchor::BOTTOM_LEFT, margin);
+            draw_list.add_text(position, COLOR, text);
+        }
+    }
+
+    /// Apply settings changed while the game is running
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.show_timer = settings.show_timer;
+        self.reduce_flashing = settings.reduce_flashing;
+        self.hitstop_enabled = settings.hitstop;
+        self.reduce_motion = settings.reduce_motion;
+        self.player.set_forgiving_spikes(settings.forgiving_spikes);
+    }
+
+    pub fn state(&self) -> GameState {
+        self.state
+    }
+
+    pub fn speed(&self) -> GameSpeed {
+        self.save_data.game_speed
+    }
+
+    /// Modifiers and tuning the current level is played with, see `Replay`
+    pub fn replay_setup(&self) -> ReplaySetup {
+        ReplaySetup {
+            mirror_mode: self.save_data.mirror_mode,
+            difficulty: self.save_data.difficulty(),
+            randomizer_seed: self.randomizer.seed(),
+            tuning: self.player.tuning().clone(),
+            forgiving_spikes: self.player.forgiving_spikes(),
+        }
+    }
+
+    /// Play with the setup of a replay from the next time a level is loaded. The modifiers stay
+    /// changed afterwards, like when they're changed in the DevGUI, and the assists until the
+    /// settings are applied again.
+    pub fn apply_replay_setup(&mut self, setup: &ReplaySetup) {
+        self.save_data.mirror_mode = setup.mirror_mode;
+        self.save_data.hard_mode = setup.difficulty == Difficulty::Hard;
+        self.randomizer.set_seed(setup.randomizer_seed);
+        self.player.set_tuning(setup.tuning.clone());
+        self.player.set_forgiving_spikes(setup.forgiving_spikes);
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn level_name(&self) -> &str {
+        &self.level.name
+    }
+
+    pub fn world_type(&self) -> WorldType {
+        self.world_type
+    }
+
+    pub fn tick(&mut self, input: &Input) {
+        let start = Instant::now();
+        self.tick_simulation(input);
+        self.tick_budget.finish_tick(start.elapsed());
+    }
+
+    fn tick_simulation(&mut self, input: &Input) {
+        // Levels are edited while the game is frozen
+        if self.editor.active() {
+            return;
+        }
+        self.tick_count += 1;
+        if matches!(self.state, GameState::Playing | GameState::FadingToTitle { .. }) {
+            self.save_data.playtime += Game::TICK_DURATION;
+        }
+        if self.state == GameState::Playing && !self.camera.flyover_active() {
+            self.level.state.tick();
+        }
+
+        match self.state {
+            GameState::Title => {
+                if input.any_button_pressed_first_frame() {
+                    self.state = GameState::Playing;
+                }
+                return;
+            }
+            GameState::FadingToTitle { ticks } => {
+                if input.idle_ticks() == 0 {
+                    // The player came back in time
+                    self.state = GameState::Playing;
+                } else if ticks >= Game::TITLE_FADE_TICKS {
+                    self.return_to_title();
+                    return;
+                } else {
+                    self.state = GameState::FadingToTitle { ticks: ticks + 1 };
+                }
+            }
+            GameState::Paused => {
+                self.tick_pause_menu(input);
+                return;
+            }
+            GameState::Playing => {
+                if input.get_button(ButtonType::Pause).pressed_first_frame() {
+                    self.pause_menu.reset();
+                    self.state = GameState::Paused;
+                    return;
+                }
+                if self.idle_timed_out(input) {
+                    self.state = GameState::FadingToTitle { ticks: 0 };
+                }
+            }
+        }
+
+        // The player gets control once the intro is over
+        if self.camera.flyover_active() {
+            if input.any_button_pressed_first_frame() {
+                self.camera.skip_flyover();
+            }
+            self.camera.tick(self.player.bounds().center());
+            return;
+        }
+
+        // Ticks are held rather than skipped so that the fixed timestep and replays aren't
+        // affected. The game keeps drawing, and the camera keeps shaking and punching in.
+        if self.hitstop_ticks > 0 {
+            self.hitstop_ticks -= 1;
+            self.camera.tick_effects();
+            return;
+        }
+
+        if input.get_button(ButtonType::Restart).pressed_first_frame() {
+            if let Err(error) = self.restart_level() {
+                error!("Failed to restart level: {}", error);
+            }
+        }
+        if input.get_button(ButtonType::Map).pressed_first_frame() {
+            self.show_map = !self.show_map;
+        }
+        self.peeking = input.get_button(ButtonType::Peek).pressed();
+
+        if input.get_button(ButtonType::Switch).pressed_first_frame() {
+            self.try_switch_world();
+        }
+        // Switch and use the new world's ability on the same tick. The switch is buffered like
+        // jumps so that the combo still works if the player clears an object a few ticks later.
+        if input.get_button(ButtonType::SwitchAndAbility).pressed_first_frame() {
+            self.switch_buffer_ticks = Game::MAX_SWITCH_BUFFER_TICKS;
+        }
+        if self.switch_buffer_ticks > 0 {
+            self.switch_buffer_ticks -= 1;
+            if self.try_switch_world() {
+                self.switch_buffer_ticks = 0;
+                self.player.buffer_ability();
             }
         }
 
@@ -146,35 +846,259 @@ impl Game {
             world_type: self.world_type,
         };
 
-        self.player.tick(&mut state);
+        // The player is frozen while the death transition plays
+        if self.death_transition.is_none() {
+            let start = Instant::now();
+            self.player.tick(&mut state);
+            self.tick_budget.profiler().record("player", start.elapsed());
+            if self.player.dash_impacted() {
+                self.hitstop(Game::DASH_IMPACT_HITSTOP_TICKS, Some(0.05));
+            }
+        }
 
         let mut state = ObjectTickState {
             input,
+            rng: &mut self.rng,
             tilemap: &mut self.level.tilemap,
             player: &mut self.player,
             level_state: &mut self.level.state,
             world_type: self.world_type,
+            reduced_simulation: self.tick_budget.reduced_simulation(),
+            spawned_effects: Vec::new(),
         };
 
+        let start = Instant::now();
         self.level.objects.tick(&mut state);
+        for effect in &mut self.effects {
+            effect.tick(&mut state);
+        }
+        self.effects.append(&mut state.spawned_effects);
+        self.effects.retain(|effect| !effect.finished());
+        self.tick_budget.profiler().record("objects", start.elapsed());
 
+        self.mods.run_tick_hooks(&mut ModTickState {
+            input,
+            player: &mut self.player,
+            level: &mut self.level,
+            world_type: self.world_type,
+            tick_count: self.tick_count,
+        });
+
+        // Objects may have moved into the player, e.g. a platform of the world that was just
+        // switched to that was pushed past the player or pushed them into another object.
+        // Resolve that on the same tick, so that the player never stays inside of them.
+        if self.death_transition.is_none() && self.player.allowed_to_move() {
+            self.player.depenetrate(&CollisionWorld::new(&self.level.tilemap, &self.level.objects, self.world_type));
+        }
+
+        self.handle_player_state_changes();
+
+        let start = Instant::now();
+        self.completion_flash_ticks = (self.completion_flash_ticks - 1).max(0);
+        if self.player.touched_goal() {
+            self.complete_level();
+        }
         if self.player.touched_goal() {
-            if let Err(error) = self.next_level(device) {
-                error!("Failed to load level: {}", error);
+            self.respawn_player();
+        }
+        self.tick_death_transition();
+        self.camera.tick(self.player.bounds().center());
+        self.tick_budget.profiler().record("level", start.elapsed());
+    }
+
+    /// Run `hook` once per simulated tick while the mod `mod_id` is enabled
+    pub fn register_tick_hook(&mut self, mod_id: &str, hook: Box<dyn TickHook>) -> Result<(), ModLoadError> {
+        self.mods.register_tick_hook(mod_id, hook)
+    }
+
+    /// Freeze the simulation for `ticks` to emphasize an impact. `zoom_punch` briefly zooms
+    /// in by that fraction, unless motion is reduced. Does nothing if hitstops are disabled.
+    pub fn hitstop(&mut self, ticks: i32, zoom_punch: Option<f32>) {
+        if !self.hitstop_enabled {
+            return;
+        }
+        self.hitstop_ticks = self.hitstop_ticks.max(ticks);
+        if let Some(amount) = zoom_punch.filter(|_| !self.reduce_motion) {
+            self.camera.zoom_punch(amount, ticks + Game::ZOOM_PUNCH_RECOVERY_TICKS);
+        }
+    }
+
+    /// Switch to the other world, returns whether that was possible
+    fn try_switch_world(&mut self) -> bool {
+        // Only allow switching if the player is not colliding with an object or a tile
+        // in the other world to avoid getting stuck
+        let other_world = self.world_type.inverse();
+        if self.player.is_colliding_solid(&CollisionWorld::new(&self.level.tilemap, &self.level.objects, other_world)) {
+            return false;
+        }
+        self.world_type = self.world_type.inverse();
+        audio::play_sound(SoundId::WorldSwitch);
+        true
+    }
+
+    fn idle_timed_out(&self, input: &Input) -> bool {
+        match self.idle_timeout {
+            Some(timeout) => Game::TICK_DURATION * input.idle_ticks() as u32 >= timeout,
+            None => false,
+        }
+    }
+
+    fn tick_pause_menu(&mut self, input: &Input) {
+        if input.get_button(ButtonType::Pause).pressed_first_frame() {
+            self.state = GameState::Playing;
+            return;
+        }
+        if self.idle_timed_out(input) {
+            self.state = GameState::FadingToTitle { ticks: 0 };
+            return;
+        }
+
+        match self.pause_menu.tick(input) {
+            Some(PauseMenuItem::Resume) => self.state = GameState::Playing,
+            Some(PauseMenuItem::RestartLevel) => {
+                self.state = GameState::Playing;
+                if let Err(error) = self.restart_level() {
+                    error!("Failed to restart level: {}", error);
+                }
             }
+            Some(PauseMenuItem::Quit) => self.quit_requested = true,
+            None => {}
+        }
+    }
+
+    /// Whether key presses should be kept from the game because the DevGUI is focused
+    pub fn gameplay_input_locked(&self) -> bool {
+        self.lock_input_when_gui_focused && self.dev_gui_focused
+    }
+
+    /// Whether the player chose to quit in the pause menu
+    pub fn quit_requested(&self) -> bool {
+        self.quit_requested
+    }
+
+    /// Called when the player reached a goal. Loads the next level, starts the completion
+    /// animation and saves the progress.
+    fn complete_level(&mut self) {
+        audio::play_sound(SoundId::Goal);
+        let keys = self.level.state.collected_key_count();
+        let shards = self.level.state.collected_shard_count();
+        let time = Game::TICK_DURATION * self.level.state.elapsed_ticks();
+        let recorded_time = self.save_data.game_speed.is_normal().then_some(time);
+        if self.save_data.record_completion(&self.level.name, self.level.difficulty, keys, shards, recorded_time) {
+            toast::show(format!("{}: {:.2}s", localization::tr("toast.best_time"), time.as_secs_f32()));
         }
-        if self.player.touched_goal() || self.player.dead() {
-            let pos = self
-                .level
-                .tilemap
-                .get_spawn_point()
-                .unwrap_or(self.player.position());
-            self.player.reset(pos);
+
+        // Goals inside a level tag (e.g. in the level select) lead to the tagged level
+        let result = match self.level.state.level_tag() {
+            Some(level_id) => match self.load_tagged_level(level_id) {
+                // The kiosk playlist continues with its next level instead
+                Err(LevelLoadError::NotInPlaylist(_)) => self.next_level(),
+                result => result,
+            },
+            None => self.next_level(),
+        };
+        if let Err(error) = result {
+            error!("Failed to load level: {}", error);
+            return;
         }
+        self.completion_flash_ticks = Game::COMPLETION_FLASH_TICKS;
+        self.save_progress();
+    }
+
+    /// Write the current progress to disk
+    pub fn save_progress(&mut self) {
+        if self.kiosk {
+            return;
+        }
+        self.save_data.level_index = self.level_index;
+        self.save_data.abilities = self.player.abilities();
+        match self.save_data.save(&save::save_path()) {
+            Ok(()) => {
+                toast::show(localization::tr("toast.progress_saved"));
+                self.thumbnail_requested = true;
+            }
+            Err(err) => error!("Failed to save progress: {err}"),
+        }
+    }
+
+    /// Whether a thumbnail should be captured for the last save, see `thumbnail`
+    pub fn take_thumbnail_request(&mut self) -> bool {
+        std::mem::take(&mut self.thumbnail_requested)
+    }
+
+    pub fn set_title_thumbnail(&mut self, thumbnail: Option<imgui::TextureId>) {
+        self.title_thumbnail = thumbnail;
+    }
+
+    /// React to what the player did during this tick
+    fn handle_player_state_changes(&mut self) {
+        for change in self.player.take_state_changes() {
+            if change.to == PlayerState::Dead {
+                match ParticleSystemObject::load_prefab("death", self.player.bounds().center()) {
+                    Ok(burst) => self.effects.push(burst),
+                    Err(err) => warn!("Failed to load death particles: {err}"),
+                }
+            }
+        }
+    }
+
+    /// Start the death transition once the player died and respawn them when the screen is faded out
+    fn tick_death_transition(&mut self) {
+        let ticks = match self.death_transition {
+            Some(transition) => transition.ticks + 1,
+            None if self.player.state() == PlayerState::Dead => {
+                self.save_data.death_count += 1;
+                self.camera.shake(0.2, DeathTransition::FREEZE_TICKS);
+                self.hitstop(Game::DEATH_HITSTOP_TICKS, Some(0.1));
+                0
+            }
+            None => return,
+        };
+
+        if ticks == DeathTransition::RESPAWN_TICK {
+            self.respawn_player();
+        }
+        self.death_transition = if ticks < DeathTransition::DURATION {
+            Some(DeathTransition { ticks })
+        } else {
+            None
+        };
+    }
+
+    /// The world that is drawn. Peeking only changes what's drawn, the player still
+    /// interacts with the current world.
+    fn visible_world_type(&self) -> WorldType {
+        if self.peeking {
+            self.world_type.inverse()
+        } else {
+            self.world_type
+        }
+    }
+
+    /// Amount of color inversion applied in post-processing
+    pub fn inversion(&self) -> f32 {
+        match self.visible_world_type() {
+            WorldType::Light => 0.0,
+            WorldType::Dark => 1.0,
+        }
+    }
+
+    /// Speed lines shown while the player is dashing
+    pub fn speed_lines(&self) -> Option<SpeedLines> {
+        self.player
+            .dash_progress()
+            .map(|(direction, progress)| SpeedLines {
+                direction: direction.as_vec(),
+                // Strongest right after the dash started
+                intensity: 1.0 - progress,
+            })
     }
 
     pub fn draw(&mut self, context: &mut DrawContext) {
-        self.draw_state.update_view_matrix(
+        let world_type = self.visible_world_type();
+
+        self.camera.update_draw_state(
+            &mut self.draw_state,
             context.window_width as f32,
             context.window_height as f32,
             self.level.tilemap.width() as f32,
@@ -182,30 +1106,254 @@ impl Game {
         );
 
         self.level
-            .tilemap_renderer
-            .draw(context, &self.draw_state, self.world_type);
-        self.player.draw(context, &self.draw_state, self.world_type);
-        self.level
-            .objects
-            .draw(context, &self.draw_state, self.world_type);
+            .tilemap_renderer(context, world_type)
+            .draw_background(context, &self.draw_state);
+        if !self.debug_view.draw_scene() {
+            return;
+        }
+
+        let device = context.device;
+        let format = context.format;
+        let effects_renderer = self
+            .effects_renderer
+            .get_or_insert_with(|| ParticleSystemRenderer::new(device, format));
+        let visibility = &self.debug_view.objects;
+        self.level.objects.draw(
+            context,
+            &self.draw_state,
+            world_type,
+            RenderLayer::BehindTilemap,
+            visibility,
+        );
+        effects_renderer.draw_layer(
+            &self.effects,
+            context,
+            &self.draw_state,
+            world_type,
+            ParticleLayer::BehindTilemap,
+        );
+        if self.debug_view.draw_tilemap() {
+            self.level
+                .tilemap_renderer(context, world_type)
+                .draw(context, &self.draw_state);
+        }
+        self.level.objects.draw(
+            context,
+            &self.draw_state,
+            world_type,
+            RenderLayer::Opaque,
+            visibility,
+        );
+        if self.debug_view.draw_player() && !self.player.dead() {
+            self.player.draw(context, &self.draw_state, world_type);
+        }
+        self.level.objects.draw(
+            context,
+            &self.draw_state,
+            world_type,
+            RenderLayer::Transparent,
+            visibility,
+        );
+        effects_renderer.draw_layer(
+            &self.effects,
+            context,
+            &self.draw_state,
+            world_type,
+            ParticleLayer::OverTilemap,
+        );
+
+        if let Some(color) = self.overlay_color(world_type) {
+            self.fade_renderer
+                .get_or_insert_with(|| FadeRenderer::new(device, format))
+                .draw(context, color);
+        }
+    }
+
+    /// Color drawn over the whole scene during transitions. Only one overlay is drawn per
+    /// frame since they share a uniform buffer.
+    fn overlay_color(&self, world_type: WorldType) -> Option<Color> {
+        if let Some(transition) = self.death_transition {
+            let color = world_type.uninverted_color(Color::BLACK);
+            Some(color.with_alpha(transition.fade_alpha()))
+        } else if self.completion_flash_ticks > 0 {
+            let color = world_type.uninverted_color(Color::WHITE);
+            let alpha = self.completion_flash_ticks as f32 / Game::COMPLETION_FLASH_TICKS as f32;
+            let max_alpha = if self.reduce_flashing { 0.2 } else { 0.8 };
+            Some(color.with_alpha(alpha * max_alpha))
+        } else {
+            None
+        }
     }
 
-    pub fn load_level(&mut self, device: &wgpu::Device, name: &str) -> Result<(), LevelLoadError> {
-        let level = Level::load(device, name)?;
+    /// Draw objects which should glow. `context.output` must be the bloom target.
+    pub fn draw_emissive(&mut self, context: &mut DrawContext) {
+        let world_type = self.visible_world_type();
+
+        if !self.debug_view.draw_scene() {
+            return;
+        }
+
+        if self.debug_view.draw_tilemap() {
+            self.level
+                .tilemap_renderer(context, world_type)
+                .draw_emissive(context, &self.draw_state);
+        }
+        self.level.objects.draw_emissive(
+            context,
+            &self.draw_state,
+            world_type,
+            &self.debug_view.objects,
+        );
+        if self.debug_view.draw_player() && !self.player.dead() {
+            self.player.draw(context, &self.draw_state, world_type);
+        }
+    }
+
+    pub fn load_level(&mut self, name: &str) -> Result<(), LevelLoadError> {
+        let mut level = Level::load_with_difficulty(name, self.save_data.difficulty())?;
+        if cfg!(debug_assertions) {
+            for error in level_validator::check_object_map(name) {
+                warn!("Object map of level \"{name}\" doesn't match the schema: {error}");
+            }
+        }
+        let abilities = level.metadata.abilities.unwrap_or(self.player.abilities());
+        // The reachability analysis floods the whole level, so release builds only run it for
+        // the randomizer
+        let reach = (self.randomizer.enabled() || cfg!(debug_assertions)).then(|| self.movement_reach());
+        if let Some(reach) = &reach {
+            self.randomizer.apply(&mut level, abilities, reach);
+        }
+        if self.save_data.mirror_mode {
+            level.mirror();
+        }
+        if let Some(reach) = &reach {
+            let reachability = Reachability::of_level(&level, abilities, reach);
+            if !reachability.completable() {
+                warn!(
+                    "No goal of level \"{name}\" seems to be reachable with {abilities:?}, goal tiles: {:?}",
+                    reachability.unreachable_goals
+                );
+            }
+        }
         self.level = level;
+        // The new level hasn't been drawn yet, so everything still alive belonged to the old one
+        gpu_memory::check_scope_released(level::LEVEL_SCOPE);
+        self.death_transition = None;
+        self.switch_buffer_ticks = 0;
+        self.hitstop_ticks = 0;
+        self.effects.clear();
         self.spawn_player();
+        self.reset_camera();
+        self.start_intro();
         Ok(())
     }
 
-    pub fn next_level(&mut self, device: &wgpu::Device) -> Result<(), LevelLoadError> {
+    /// How far the player can move with the current tuning. Measuring simulates the player, so
+    /// it's only done again after the tuning was changed.
+    fn movement_reach(&mut self) -> MovementReach {
+        match &self.movement_reach {
+            Some((tuning, reach)) if tuning == self.player.tuning() => *reach,
+            _ => {
+                let reach = MovementReach::measure(self.player.tuning());
+                self.movement_reach = Some((self.player.tuning().clone(), reach));
+                reach
+            }
+        }
+    }
+
+    fn reset_camera(&mut self) {
+        self.camera
+            .reset(self.level.metadata.camera, self.player.bounds().center());
+    }
+
+    /// Play the level's intro flyover if it wasn't seen before. It's marked as seen right away
+    /// so that restarting doesn't show it again.
+    fn start_intro(&mut self) {
+        if let Some(intro) = &self.level.metadata.intro {
+            if self.save_data.seen_levels.insert(self.level.name.clone()) {
+                self.camera.start_flyover(intro.clone());
+            }
+        }
+    }
+
+    pub fn restart_level(&mut self) -> Result<(), LevelLoadError> {
+        let name = self.level.name.clone();
+        self.load_level(&name)
+    }
+
+    pub fn next_level(&mut self) -> Result<(), LevelLoadError> {
         self.level_index += 1;
-        self.level_index %= MAIN_LEVELS.len();
-        self.load_level(device, MAIN_LEVELS[self.level_index])
+        self.level_index %= self.playlist.len();
+        let name = self.playlist[self.level_index].clone();
+        self.load_level(&name)
+    }
+
+    /// Restart the current level as if it was entered for the first time, so that the
+    /// same inputs always lead to the same result
+    pub fn restart_from_beginning(&mut self) -> Result<(), LevelLoadError> {
+        let name = self.level.name.clone();
+        self.load_level_from_beginning(&name)
+    }
+
+    /// Load a level as if it was entered for the first time, see `restart_from_beginning()`
+    pub fn load_level_from_beginning(&mut self, name: &str) -> Result<(), LevelLoadError> {
+        self.world_type = WorldType::Light;
+        self.state = GameState::Playing;
+        self.player.set_abilities(AbilityPair::default());
+        self.load_level(name)
+    }
+
+    /// Load the main level with the given index, as referenced by level tags. Kiosk mode
+    /// refuses levels that aren't in its playlist.
+    pub fn load_tagged_level(&mut self, level_id: i32) -> Result<(), LevelLoadError> {
+        let name = usize::try_from(level_id)
+            .ok()
+            .and_then(|index| MAIN_LEVELS.get(index))
+            .ok_or(LevelLoadError::InvalidLevelTag(level_id))?
+            .to_string();
+        match self.playlist.iter().position(|level| *level == name) {
+            Some(index) => self.level_index = index,
+            None if self.kiosk => return Err(LevelLoadError::NotInPlaylist(name)),
+            None => {}
+        }
+        self.load_level(&name)
     }
 
+    /// Show the title screen and reset the current level. The level progress is kept,
+    /// except in kiosk mode where the next player starts from the first level.
+    pub fn return_to_title(&mut self) {
+        self.state = GameState::Title;
+        self.world_type = WorldType::Light;
+
+        let result = if self.kiosk {
+            self.level_index = 0;
+            let name = self.playlist[0].clone();
+            self.load_level(&name)
+        } else {
+            self.restart_level()
+        };
+        if let Err(error) = result {
+            error!("Failed to reset level: {}", error);
+        }
+    }
+
+    /// Spawn the player at the start of a level and give them the abilities from the level's metadata
     pub fn spawn_player(&mut self) {
-        if let Some(spawn_point) = self.level.tilemap.get_spawn_point() {
-            self.player.set_position(spawn_point);
+        if let Some(abilities) = self.level.metadata.abilities {
+            self.player.set_abilities(abilities);
+        }
+        self.respawn_player();
+    }
+
+    /// Move the player back to the spawn point after dying, keeping their abilities
+    pub fn respawn_player(&mut self) {
+        let position = self
+            .level
+            .spawn_point(self.player.position())
+            .unwrap_or(self.player.position());
+        self.player.reset(position);
+        if let Some(facing) = self.level.metadata.facing {
+            self.player.set_facing(facing);
         }
     }
 }
@@ -215,3 +1363,261 @@ pub enum GameLoadError {
     #[error("failed to load level: {0}")]
     Level(#[from] LevelLoadError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::FVec2,
+        player_tuning::DashTuning,
+        test_support::{self, test_mod},
+        tilemap::Tile,
+    };
+    use std::{cell::Cell, rc::Rc};
+
+    const FLOOR_Y: i32 = 15;
+
+    /// Create a game with an empty room that only has a floor, with the player standing on it
+    fn game_on_floor(ability: Ability) -> Game {
+        test_support::use_repository_root();
+        let settings = Settings {
+            hitstop: false,
+            ..Settings::default()
+        };
+        let mut game = Game::new_for_tests(&settings).unwrap();
+        let mut tilemap = Tilemap::new(64, FLOOR_Y + 1);
+        for x in 0..tilemap.width() {
+            tilemap.set_tile(x, FLOOR_Y, Tile::Solid);
+        }
+        game.level.tilemap = tilemap;
+        game.level.set_objects(ObjectSet::from_json(&[], Difficulty::Normal).unwrap());
+        game.camera.skip_flyover();
+        game.player.set_tuning(PlayerTuning::default());
+        game.player.set_ability(WorldType::Light, ability);
+        game.player.set_ability(WorldType::Dark, ability);
+        game.player.reset(FVec2::new(8.0, FLOOR_Y as f32 - Player::SIZE.y));
+        let mut input = Input::new();
+        for _ in 0..2 {
+            tick_with(&mut game, &mut input, &[]);
+        }
+        assert_eq!(game.player.state(), PlayerState::Grounded);
+        game
+    }
+
+    /// Hold `buttons` for a tick and release all others
+    fn tick_with(game: &mut Game, input: &mut Input, buttons: &[ButtonType]) {
+        let mask = buttons.iter().fold(0, |mask, button| mask | (1 << *button as u32));
+        input.set_pressed_buttons(mask);
+        input.tick();
+        game.tick(input);
+    }
+
+    /// Dash to the right and press Jump `jump_tick` ticks into the dash, if any. Returns the game
+    /// and the player's state and velocity after every tick.
+    fn dash_jump(dash: DashTuning, jump_tick: Option<i32>) -> (Game, Vec<(PlayerState, FVec2)>) {
+        let mut game = game_on_floor(Ability::Dash);
+        game.player.tuning_mut().dash = dash;
+        let mut input = Input::new();
+        tick_with(&mut game, &mut input, &[ButtonType::Right, ButtonType::Ability]);
+        let mut ticks = vec![(game.player.state(), game.player.velocity())];
+        for tick in 1..60 {
+            let buttons: &[ButtonType] = if Some(tick) == jump_tick { &[ButtonType::Jump] } else { &[] };
+            tick_with(&mut game, &mut input, buttons);
+            ticks.push((game.player.state(), game.player.velocity()));
+        }
+        (game, ticks)
+    }
+
+    fn dashing_ticks(ticks: &[(PlayerState, FVec2)]) -> usize {
+        ticks.iter().filter(|(state, _)| *state == PlayerState::Dashing).count()
+    }
+
+    #[test]
+    fn dash_jump_inside_window_keeps_momentum() {
+        let dash = DashTuning {
+            dash_jump_window: 4,
+            ..DashTuning::default()
+        };
+        let (_, full) = dash_jump(dash.clone(), Some(2));
+        let (_, half) = dash_jump(
+            DashTuning {
+                dash_jump_momentum_scale: 0.5,
+                ..dash.clone()
+            },
+            Some(2),
+        );
+
+        let (state, velocity) = full[2];
+        assert_eq!(state, PlayerState::Airborne);
+        assert!(velocity.y < 0.0, "the player should jump, velocity: {velocity:?}");
+        // Far more than a jump from standing still, which has no horizontal velocity
+        assert!(velocity.x > dash.dash_force * 0.5, "velocity: {velocity:?}");
+        assert!((half[2].1.x - velocity.x * 0.5).abs() < 1e-4);
+        // The jump ended the dash
+        assert_eq!(dashing_ticks(&full), 2);
+    }
+
+    #[test]
+    fn jump_after_dash_jump_window_is_normal_jump() {
+        let dash = DashTuning {
+            dash_jump_window: 4,
+            ..DashTuning::default()
+        };
+        let (_, without_jump) = dash_jump(dash.clone(), None);
+        let dash_ticks = dashing_ticks(&without_jump);
+        // Pressed shortly before the dash ends, so the buffered jump starts once it's over
+        let (_, ticks) = dash_jump(dash, Some(dash_ticks as i32 - 2));
+
+        assert_eq!(dashing_ticks(&ticks), dash_ticks);
+        let jump_tick = ticks
+            .iter()
+            .position(|(_, velocity)| velocity.y < 0.0)
+            .expect("the buffered jump should start after the dash");
+        assert!(jump_tick >= dash_ticks);
+        // Only what's left of the dash's decaying velocity, nothing was converted
+        assert!(ticks[jump_tick].1.x.abs() < 0.05, "velocity: {:?}", ticks[jump_tick].1);
+    }
+
+    #[test]
+    fn dash_jump_window_of_zero_disables_dash_jumps() {
+        let dash = DashTuning {
+            dash_jump_window: 0,
+            ..DashTuning::default()
+        };
+        let (_, without_jump) = dash_jump(dash.clone(), None);
+        let (game, ticks) = dash_jump(dash, Some(1));
+
+        assert_eq!(dashing_ticks(&ticks), dashing_ticks(&without_jump));
+        assert!(ticks.iter().all(|(_, velocity)| velocity.y >= 0.0));
+        assert!(game.player.grounded());
+    }
+
+    #[test]
+    fn wall_jump_chain_limit_and_stamina_reset_on_landing() {
+        let mut game = game_on_floor(Ability::WallJump);
+        for y in 0..FLOOR_Y {
+            game.level.set_tile(2, y, Tile::Solid);
+        }
+        let wall_jump = &mut game.player.tuning_mut().wall_jump;
+        wall_jump.max_chained_wall_jumps = 2;
+        wall_jump.max_stamina_ticks = 120;
+        wall_jump.wall_jump_stamina_cost = 20;
+        game.player.reset(FVec2::new(3.0, FLOOR_Y as f32 - Player::SIZE.y));
+        let mut input = Input::new();
+        for _ in 0..2 {
+            tick_with(&mut game, &mut input, &[]);
+        }
+
+        // Hold towards the wall and keep tapping Jump, which chains wall jumps off it
+        let mut wall_jumps = 0;
+        let mut last_state = game.player.state();
+        let mut count_wall_jumps = |game: &Game| {
+            let state = game.player.state();
+            if state == PlayerState::WallJumping && last_state != PlayerState::WallJumping {
+                wall_jumps += 1;
+            }
+            last_state = state;
+        };
+        for tick in 0..600 {
+            if game.player.remaining_chained_wall_jumps() == Some(0) {
+                break;
+            }
+            let buttons: &[ButtonType] = if tick % 2 == 0 {
+                &[ButtonType::Left, ButtonType::Jump]
+            } else {
+                &[ButtonType::Left]
+            };
+            tick_with(&mut game, &mut input, buttons);
+            count_wall_jumps(&game);
+        }
+        assert_eq!(game.player.remaining_chained_wall_jumps(), Some(0));
+
+        // Back on the wall, where another wall jump would be possible without the limit
+        for _ in 0..600 {
+            if game.player.state() == PlayerState::WallSliding {
+                break;
+            }
+            tick_with(&mut game, &mut input, &[ButtonType::Left]);
+            count_wall_jumps(&game);
+        }
+        assert_eq!(game.player.state(), PlayerState::WallSliding);
+        assert!(game.player.wall_stamina().unwrap() < 1.0);
+        tick_with(&mut game, &mut input, &[ButtonType::Left, ButtonType::Jump]);
+        count_wall_jumps(&game);
+
+        // Landing restores the limits. A jump that was buffered before landing may still happen.
+        for _ in 0..600 {
+            if game.player.state() == PlayerState::Grounded {
+                break;
+            }
+            tick_with(&mut game, &mut input, &[ButtonType::Left]);
+            count_wall_jumps(&game);
+        }
+        assert_eq!(wall_jumps, 2);
+        assert_eq!(game.player.state(), PlayerState::Grounded);
+        assert_eq!(game.player.remaining_chained_wall_jumps(), Some(2));
+        assert_eq!(game.player.wall_stamina(), Some(1.0));
+    }
+
+    #[test]
+    fn kiosk_mode_refuses_tagged_levels_outside_the_playlist() {
+        let mut game = game_on_floor(Ability::None);
+        game.kiosk = true;
+        game.playlist = vec![MAIN_LEVELS[0].to_string()];
+        assert!(matches!(game.load_tagged_level(1), Err(LevelLoadError::NotInPlaylist(_))));
+        assert!(game.load_tagged_level(0).is_ok());
+        assert_eq!(game.level_name(), MAIN_LEVELS[0].as_str());
+    }
+
+    #[test]
+    fn player_falls_onto_the_floor_of_a_shipped_level() {
+        // The spawn point of the first level is a few tiles above the floor in row 16
+        const LEVEL: &str = "map001_intro_SWITCH";
+        const LEVEL_FLOOR_Y: f32 = 16.0;
+
+        test_support::use_repository_root();
+        let mut game = Game::new_for_tests(&Settings::default()).unwrap();
+        game.load_level(LEVEL).unwrap();
+        game.camera.skip_flyover();
+        let spawn = game.player.position();
+        assert!(spawn.y + Player::SIZE.y < LEVEL_FLOOR_Y);
+
+        let mut input = Input::new();
+        for _ in 0..120 {
+            tick_with(&mut game, &mut input, &[]);
+        }
+        assert_eq!(game.player.state(), PlayerState::Grounded);
+        let position = game.player.position();
+        assert_eq!(position.x, spawn.x);
+        assert!(
+            (position.y + Player::SIZE.y - LEVEL_FLOOR_Y).abs() < 1e-3,
+            "player stopped at {position:?}"
+        );
+    }
+
+    /// Counts the ticks it was called on
+    struct CountTicks(Rc<Cell<u64>>);
+
+    impl TickHook for CountTicks {
+        fn tick(&mut self, _state: &mut ModTickState) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn tick_hooks_run_once_per_tick_for_enabled_mods() {
+        let mut game = game_on_floor(Ability::None);
+        game.mods = ModList::with_mods(vec![test_mod("enabled", true), test_mod("disabled", false)]);
+        let enabled_ticks = Rc::new(Cell::new(0));
+        let disabled_ticks = Rc::new(Cell::new(0));
+        game.register_tick_hook("enabled", Box::new(CountTicks(enabled_ticks.clone()))).unwrap();
+        game.register_tick_hook("disabled", Box::new(CountTicks(disabled_ticks.clone()))).unwrap();
+
+        let mut input = Input::new();
+        for _ in 0..5 {
+            tick_with(&mut game, &mut input, &[]);
+        }
+        assert_eq!(enabled_ticks.get(), 5);
+        assert_eq!(disabled_ticks.get(), 0);
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Deref,
+    sync::Mutex,
+};
+
+use lazy_static::lazy_static;
+use log::warn;
+use wgpu::util::DeviceExt;
+
+/// Number and total size of the live resources of a subsystem
+#[derive(Debug, Default, Clone, Copy)]
+struct SubsystemUsage {
+    count: usize,
+    bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    /// Subsystems are named after the first part of the resource labels, e.g. "tilemap" for
+    /// "tilemap_vertex_buffer"
+    subsystems: BTreeMap<String, SubsystemUsage>,
+    /// Set while resources with a shorter lifetime than the game are created, see `enter_scope()`
+    current_scope: Option<&'static str>,
+    counts_by_scope: HashMap<&'static str, usize>,
+}
+
+lazy_static! {
+    static ref USAGE: Mutex<Usage> = Mutex::new(Usage::default());
+}
+
+/// GPU resource which is counted in the memory usage until it's dropped
+pub struct Tracked<T> {
+    resource: T,
+    subsystem: String,
+    scope: Option<&'static str>,
+    bytes: u64,
+}
+
+pub type TrackedBuffer = Tracked<wgpu::Buffer>;
+pub type TrackedTexture = Tracked<wgpu::Texture>;
+
+impl<T> Tracked<T> {
+    fn new(resource: T, label: Option<&str>, bytes: u64) -> Self {
+        let subsystem = label
+            .and_then(|label| label.split('_').next())
+            .unwrap_or("unlabeled")
+            .to_owned();
+
+        let mut usage = USAGE.lock().unwrap();
+        let entry = usage.subsystems.entry(subsystem.clone()).or_default();
+        entry.count += 1;
+        entry.bytes += bytes;
+        let scope = usage.current_scope;
+        if let Some(scope) = scope {
+            *usage.counts_by_scope.entry(scope).or_default() += 1;
+        }
+
+        Tracked { resource, subsystem, scope, bytes }
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        let mut usage = USAGE.lock().unwrap();
+        if let Some(entry) = usage.subsystems.get_mut(&self.subsystem) {
+            entry.count -= 1;
+            entry.bytes -= self.bytes;
+        }
+        if let Some(scope) = self.scope {
+            if let Some(count) = usage.counts_by_scope.get_mut(scope) {
+                *count -= 1;
+            }
+        }
+    }
+}
+
+pub fn create_buffer(device: &wgpu::Device, descriptor: &wgpu::BufferDescriptor) -> TrackedBuffer {
+    Tracked::new(device.create_buffer(descriptor), descriptor.label, descriptor.size)
+}
+
+pub fn create_buffer_init(
+    device: &wgpu::Device,
+    descriptor: &wgpu::util::BufferInitDescriptor,
+) -> TrackedBuffer {
+    Tracked::new(
+        device.create_buffer_init(descriptor),
+        descriptor.label,
+        descriptor.contents.len() as u64,
+    )
+}
+
+pub fn create_texture(device: &wgpu::Device, descriptor: &wgpu::TextureDescriptor) -> TrackedTexture {
+    let size = descriptor.size;
+    let bytes = size.width as u64
+        * size.height as u64
+        * size.depth_or_array_layers as u64
+        * descriptor.format.describe().block_size as u64;
+    Tracked::new(device.create_texture(descriptor), descriptor.label, bytes)
+}
+
+/// Resources created until the returned guard is dropped belong to the scope, so that
+/// `check_scope_released()` can find the ones that outlive it
+pub fn enter_scope(scope: &'static str) -> ScopeGuard {
+    let mut usage = USAGE.lock().unwrap();
+    let previous = usage.current_scope.replace(scope);
+    ScopeGuard { previous }
+}
+
+pub struct ScopeGuard {
+    previous: Option<&'static str>,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        USAGE.lock().unwrap().current_scope = self.previous;
+    }
+}
+
+/// Warn if resources of a scope are still alive although everything owning them was dropped
+pub fn check_scope_released(scope: &'static str) {
+    let usage = USAGE.lock().unwrap();
+    let count = usage.counts_by_scope.get(scope).copied().unwrap_or_default();
+    if count > 0 {
+        warn!("{count} GPU resources created for the {scope} are still alive and might have leaked");
+    }
+}
+
+pub fn draw_gui(gui: &imgui::Ui) {
+    let usage = USAGE.lock().unwrap();
+    let mut total = SubsystemUsage::default();
+    for (subsystem, entry) in &usage.subsystems {
+        if entry.count == 0 {
+            continue;
+        }
+        gui.text(format!(
+            "{subsystem}: {} resources, {:.1} KiB",
+            entry.count,
+            entry.bytes as f64 / 1024.0
+        ));
+        total.count += entry.count;
+        total.bytes += entry.bytes;
+    }
+    gui.separator();
+    gui.text(format!(
+        "Total: {} resources, {:.1} MiB",
+        total.count,
+        total.bytes as f64 / (1024.0 * 1024.0)
+    ));
+}
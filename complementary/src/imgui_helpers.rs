@@ -39,6 +39,19 @@ impl ImGui for i32 {
     }
 }
 
+/// Edited as an `i32`, since ImGui has no unsigned input. Negative values are clamped to zero.
+impl ImGui for usize {
+    fn draw_gui_with_settings(&mut self, label: &str, gui: &imgui::Ui, settings: &ImGuiSettings) {
+        let mut value = i32::try_from(*self).unwrap_or(i32::MAX);
+        if gui.input_int(label, &mut value)
+            .read_only(settings.read_only)
+            .build()
+        {
+            *self = value.max(0) as usize;
+        }
+    }
+}
+
 impl ImGui for bool {
     fn draw_gui_with_settings(&mut self, label: &str, gui: &imgui::Ui, _settings: &ImGuiSettings) {
         gui.checkbox(label, self);
@@ -95,7 +95,7 @@ fn to_sdl_cursor(cursor: MouseCursor) -> SystemCursor {
 /// Returns `true` if the provided event is associated with the provided window.
 ///
 /// # Example
-/// ```rust,no_run
+/// ```rust,ignore
 /// # let mut event_pump: sdl2::EventPump = unimplemented!();
 /// # let window: sdl2::video::Window = unimplemented!();
 /// # let mut imgui = imgui::Context::create();
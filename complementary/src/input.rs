@@ -1,12 +1,23 @@
 use complementary_macros::EnumCount;
 use imgui::TreeNodeFlags;
+use log::warn;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use std::fmt::Debug;
+use sdl2::{
+    controller::{Axis, Button as PadButton},
+    keyboard::Keycode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    fs, io,
+    path::Path,
+};
 
 use crate::imgui_helpers::ImGui;
 
-#[derive(Clone, Copy, Debug, EnumCount, FromPrimitive)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumCount, FromPrimitive, Serialize, Deserialize)]
 pub enum ButtonType {
     Jump,
     Switch,
@@ -19,6 +30,256 @@ pub enum ButtonType {
 
     Pause,
     Confirm,
+
+    Restart,
+    /// Preview the other world while held without switching
+    Peek,
+    /// Toggle the minimap overlay
+    Map,
+}
+
+/// Keys and the buttons they trigger when no custom bindings are set
+pub const DEFAULT_KEYMAP: &[(Keycode, &[ButtonType])] = &[
+    (Keycode::Space, &[ButtonType::Jump, ButtonType::Confirm]),
+    (Keycode::Return, &[ButtonType::Switch, ButtonType::Confirm]),
+    (Keycode::RShift, &[ButtonType::SwitchAndAbility]),
+    (Keycode::RCtrl, &[ButtonType::Ability]),
+    (Keycode::RAlt, &[ButtonType::Ability]),
+    (Keycode::LCtrl, &[ButtonType::Ability]),
+    (Keycode::Left, &[ButtonType::Left]),
+    (Keycode::A, &[ButtonType::Left]),
+    (Keycode::Right, &[ButtonType::Right]),
+    (Keycode::D, &[ButtonType::Right]),
+    (Keycode::Up, &[ButtonType::Up, ButtonType::Jump]),
+    (Keycode::W, &[ButtonType::Up, ButtonType::Jump]),
+    (Keycode::Down, &[ButtonType::Down]),
+    (Keycode::S, &[ButtonType::Down]),
+    (Keycode::Escape, &[ButtonType::Pause]),
+    (Keycode::P, &[ButtonType::Pause]),
+    (Keycode::R, &[ButtonType::Restart]),
+    (Keycode::Tab, &[ButtonType::Peek]),
+    (Keycode::M, &[ButtonType::Map]),
+];
+
+/// Controller buttons and the buttons they trigger when no custom bindings are set. The left
+/// stick acts like the D-pad, see `Input::set_stick_axis()`.
+pub const DEFAULT_PAD_MAP: &[(PadButton, &[ButtonType])] = &[
+    (PadButton::A, &[ButtonType::Jump, ButtonType::Confirm]),
+    (PadButton::Y, &[ButtonType::Switch]),
+    (PadButton::X, &[ButtonType::Ability]),
+    (PadButton::RightShoulder, &[ButtonType::SwitchAndAbility]),
+    (PadButton::LeftShoulder, &[ButtonType::Peek]),
+    (PadButton::B, &[ButtonType::Map]),
+    (PadButton::DPadLeft, &[ButtonType::Left]),
+    (PadButton::DPadRight, &[ButtonType::Right]),
+    (PadButton::DPadUp, &[ButtonType::Up]),
+    (PadButton::DPadDown, &[ButtonType::Down]),
+    (PadButton::Start, &[ButtonType::Pause]),
+    (PadButton::Back, &[ButtonType::Restart]),
+];
+
+/// Controller bindings are saved next to the keys, with this prefix before SDL's name of the
+/// button, e.g. "Pad a"
+const PAD_BINDING_PREFIX: &str = "Pad ";
+
+/// How far the left stick has to be tilted before it presses a direction, out of `i16::MAX`
+const STICK_DEADZONE: i16 = 12_000;
+
+/// Custom key bindings saved from the Controls panel
+pub const KEY_BINDINGS_PATH: &str = "controls.json";
+
+/// Maps keyboard keys and controller buttons to the buttons they trigger. A key can trigger
+/// multiple buttons and a button can be triggered by multiple keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "BTreeMap<String, Vec<ButtonType>>")]
+#[serde(try_from = "BTreeMap<String, Vec<ButtonType>>")]
+pub struct KeyBindings {
+    buttons_by_keycode: HashMap<Keycode, Vec<ButtonType>>,
+    buttons_by_pad_button: HashMap<PadButton, Vec<ButtonType>>,
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        KeyBindings {
+            buttons_by_keycode: HashMap::new(),
+            buttons_by_pad_button: HashMap::new(),
+        }
+    }
+
+    pub fn from_keymap(
+        keymap: &[(Keycode, &[ButtonType])],
+        pad_map: &[(PadButton, &[ButtonType])],
+    ) -> Self {
+        let mut bindings = KeyBindings::new();
+        for (keycode, buttons) in keymap {
+            for button in *buttons {
+                bindings.bind(*keycode, *button);
+            }
+        }
+        for (pad_button, buttons) in pad_map {
+            for button in *buttons {
+                bindings.bind_pad(*pad_button, *button);
+            }
+        }
+        bindings
+    }
+
+    pub fn bind(&mut self, keycode: Keycode, button: ButtonType) {
+        let buttons = self.buttons_by_keycode.entry(keycode).or_default();
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+    }
+
+    pub fn unbind(&mut self, keycode: Keycode, button: ButtonType) {
+        if let Some(buttons) = self.buttons_by_keycode.get_mut(&keycode) {
+            buttons.retain(|bound| *bound != button);
+            if buttons.is_empty() {
+                self.buttons_by_keycode.remove(&keycode);
+            }
+        }
+    }
+
+    pub fn bind_pad(&mut self, pad_button: PadButton, button: ButtonType) {
+        let buttons = self.buttons_by_pad_button.entry(pad_button).or_default();
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+    }
+
+    pub fn unbind_pad(&mut self, pad_button: PadButton, button: ButtonType) {
+        if let Some(buttons) = self.buttons_by_pad_button.get_mut(&pad_button) {
+            buttons.retain(|bound| *bound != button);
+            if buttons.is_empty() {
+                self.buttons_by_pad_button.remove(&pad_button);
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, KeyBindingsError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), KeyBindingsError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Load the custom bindings from `KEY_BINDINGS_PATH`, or use the default ones if none were saved
+    pub fn load_or_default() -> Self {
+        match KeyBindings::load(Path::new(KEY_BINDINGS_PATH)) {
+            Ok(bindings) => bindings,
+            Err(KeyBindingsError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                KeyBindings::default()
+            }
+            Err(err) => {
+                warn!("Failed to load key bindings, using defaults: {err}");
+                KeyBindings::default()
+            }
+        }
+    }
+
+    pub fn buttons(&self, keycode: Keycode) -> &[ButtonType] {
+        self.buttons_by_keycode
+            .get(&keycode)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Get all keys bound to a button, sorted by name
+    pub fn keycodes(&self, button: ButtonType) -> Vec<Keycode> {
+        let mut keycodes: Vec<_> = self
+            .buttons_by_keycode
+            .iter()
+            .filter(|(_, buttons)| buttons.contains(&button))
+            .map(|(keycode, _)| *keycode)
+            .collect();
+        keycodes.sort_by_key(|keycode| keycode.name());
+        keycodes
+    }
+
+    pub fn buttons_for_pad(&self, pad_button: PadButton) -> &[ButtonType] {
+        self.buttons_by_pad_button
+            .get(&pad_button)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Get all controller buttons bound to a button, in the order of `DEFAULT_PAD_MAP` so that
+    /// the face buttons come first
+    pub fn pad_buttons(&self, button: ButtonType) -> Vec<PadButton> {
+        let mut pad_buttons: Vec<_> = self
+            .buttons_by_pad_button
+            .iter()
+            .filter(|(_, buttons)| buttons.contains(&button))
+            .map(|(pad_button, _)| *pad_button)
+            .collect();
+        pad_buttons.sort_by_key(|pad_button| {
+            DEFAULT_PAD_MAP
+                .iter()
+                .position(|(default, _)| default == pad_button)
+                .unwrap_or(DEFAULT_PAD_MAP.len())
+        });
+        pad_buttons
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::from_keymap(DEFAULT_KEYMAP, DEFAULT_PAD_MAP)
+    }
+}
+
+impl From<KeyBindings> for BTreeMap<String, Vec<ButtonType>> {
+    fn from(bindings: KeyBindings) -> Self {
+        bindings
+            .buttons_by_keycode
+            .into_iter()
+            .map(|(keycode, buttons)| (keycode.name(), buttons))
+            .chain(
+                bindings
+                    .buttons_by_pad_button
+                    .into_iter()
+                    .map(|(pad_button, buttons)| {
+                        (format!("{PAD_BINDING_PREFIX}{}", pad_button.string()), buttons)
+                    }),
+            )
+            .collect()
+    }
+}
+
+impl TryFrom<BTreeMap<String, Vec<ButtonType>>> for KeyBindings {
+    type Error = String;
+
+    fn try_from(map: BTreeMap<String, Vec<ButtonType>>) -> Result<Self, Self::Error> {
+        let mut bindings = KeyBindings::new();
+        for (name, buttons) in map {
+            if let Some(pad_name) = name.strip_prefix(PAD_BINDING_PREFIX) {
+                let pad_button = PadButton::from_string(pad_name)
+                    .ok_or_else(|| format!("unknown controller button \"{pad_name}\""))?;
+                for button in buttons {
+                    bindings.bind_pad(pad_button, button);
+                }
+                continue;
+            }
+
+            let keycode =
+                Keycode::from_name(&name).ok_or_else(|| format!("unknown key \"{name}\""))?;
+            for button in buttons {
+                bindings.bind(keycode, button);
+            }
+        }
+        Ok(bindings)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyBindingsError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid key bindings: {0}")]
+    InvalidData(#[from] serde_json::Error),
 }
 
 #[derive(Clone, Copy)]
@@ -59,12 +320,26 @@ impl Button {
 #[derive(Debug)]
 pub struct Input {
     buttons: [Button; ButtonType::COUNT],
+    bindings: KeyBindings,
+    /// D-pad directions currently pressed by the left stick's X and Y axes
+    stick_directions: [Option<PadButton>; 2],
+    /// Number of ticks since the last button was pressed
+    idle_ticks: i32,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::new()
+    }
 }
 
 impl Input {
     pub fn new() -> Self {
         Input {
             buttons: [Button::new(); ButtonType::COUNT],
+            bindings: KeyBindings::default(),
+            stick_directions: [None; 2],
+            idle_ticks: 0,
         }
     }
 
@@ -74,27 +349,116 @@ impl Input {
                 button.pressed_ticks = Some(pressed_ticks + 1);
             }
         }
+
+        if self.buttons.iter().any(Button::pressed) {
+            self.idle_ticks = 0;
+        } else {
+            self.idle_ticks = self.idle_ticks.saturating_add(1);
+        }
     }
 
     pub fn set_button_pressed(&mut self, typ: ButtonType) {
         if !self.buttons[typ as usize].pressed_ticks.is_some() {
             self.buttons[typ as usize].pressed_ticks = Some(0);
         }
+        self.idle_ticks = 0;
     }
 
     pub fn set_button_released(&mut self, typ: ButtonType) {
         self.buttons[typ as usize].pressed_ticks = None;
     }
 
+    pub fn set_key_pressed(&mut self, keycode: Keycode) {
+        for typ in self.bindings.buttons(keycode).to_vec() {
+            self.set_button_pressed(typ);
+        }
+    }
+
+    pub fn set_key_released(&mut self, keycode: Keycode) {
+        for typ in self.bindings.buttons(keycode).to_vec() {
+            self.set_button_released(typ);
+        }
+    }
+
+    pub fn set_pad_button_pressed(&mut self, pad_button: PadButton) {
+        for typ in self.bindings.buttons_for_pad(pad_button).to_vec() {
+            self.set_button_pressed(typ);
+        }
+    }
+
+    pub fn set_pad_button_released(&mut self, pad_button: PadButton) {
+        for typ in self.bindings.buttons_for_pad(pad_button).to_vec() {
+            self.set_button_released(typ);
+        }
+    }
+
+    /// Tilting the left stick past the deadzone presses the D-pad direction it points to, so it
+    /// follows the D-pad's bindings
+    pub fn set_stick_axis(&mut self, axis: Axis, value: i16) {
+        let (index, negative, positive) = match axis {
+            Axis::LeftX => (0, PadButton::DPadLeft, PadButton::DPadRight),
+            Axis::LeftY => (1, PadButton::DPadUp, PadButton::DPadDown),
+            _ => return,
+        };
+        let direction = if value < -STICK_DEADZONE {
+            Some(negative)
+        } else if value > STICK_DEADZONE {
+            Some(positive)
+        } else {
+            None
+        };
+
+        let previous = std::mem::replace(&mut self.stick_directions[index], direction);
+        if previous != direction {
+            if let Some(previous) = previous {
+                self.set_pad_button_released(previous);
+            }
+            if let Some(direction) = direction {
+                self.set_pad_button_pressed(direction);
+            }
+        }
+    }
+
+    pub fn bindings(&self) -> &KeyBindings {
+        &self.bindings
+    }
+
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
     pub fn get_button(&self, typ: ButtonType) -> &Button {
         &self.buttons[typ as usize]
     }
 
-    pub fn ability_button_pressed_first_frame(&self) -> bool {
-        self.get_button(ButtonType::Ability).pressed_first_frame()
-            || self
-                .get_button(ButtonType::SwitchAndAbility)
-                .pressed_first_frame()
+    /// Get a bitmask of all pressed buttons, with bits in the order of `ButtonType`
+    pub fn pressed_buttons(&self) -> u32 {
+        self.buttons
+            .iter()
+            .enumerate()
+            .filter(|(_, button)| button.pressed())
+            .fold(0, |mask, (index, _)| mask | (1 << index))
+    }
+
+    /// Press and release buttons to match a bitmask from `pressed_buttons()`
+    pub fn set_pressed_buttons(&mut self, mask: u32) {
+        for index in 0..ButtonType::COUNT {
+            let typ = ButtonType::from_usize(index).unwrap();
+            if mask & (1 << index) != 0 {
+                self.set_button_pressed(typ);
+            } else {
+                self.set_button_released(typ);
+            }
+        }
+    }
+
+    pub fn any_button_pressed_first_frame(&self) -> bool {
+        self.buttons.iter().any(Button::pressed_first_frame)
+    }
+
+    /// Number of ticks since the last button was pressed. Held buttons count as activity.
+    pub fn idle_ticks(&self) -> i32 {
+        self.idle_ticks
     }
 
     pub fn ability_button_pressed(&self) -> bool {
@@ -111,8 +475,10 @@ impl ImGui for Input {
         _settings: &crate::imgui_helpers::ImGuiSettings,
     ) {
         if gui.collapsing_header(label, TreeNodeFlags::empty()) {
+            gui.text(format!("Idle for {} ticks", self.idle_ticks));
             for (index, button) in self.buttons.iter().enumerate() {
-                gui.text(format!("{:?}", ButtonType::from_usize(index).unwrap()));
+                let typ = ButtonType::from_usize(index).unwrap();
+                gui.text(format!("{:?}", typ));
                 gui.same_line();
 
                 let _token = gui.begin_disabled(true);
@@ -129,7 +495,37 @@ impl ImGui for Input {
                     gui.same_line();
                     gui.text(button.pressed_ticks().unwrap().to_string());
                 }
+
+                let key_names: Vec<_> = self
+                    .bindings
+                    .keycodes(typ)
+                    .iter()
+                    .map(|keycode| keycode.name())
+                    .chain(
+                        self.bindings
+                            .pad_buttons(typ)
+                            .iter()
+                            .map(|pad_button| pad_button.string()),
+                    )
+                    .collect();
+                gui.same_line();
+                gui.text_disabled(key_names.join(", "));
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controller_only_preset_binds_every_action_to_the_controller() {
+        let bindings: KeyBindings =
+            serde_json::from_str(include_str!("../../assets/controls/controller_only.json")).unwrap();
+        for index in 0..ButtonType::COUNT {
+            let button = ButtonType::from_usize(index).unwrap();
+            assert!(!bindings.pad_buttons(button).is_empty(), "{button:?} is unbound");
+        }
+    }
+}
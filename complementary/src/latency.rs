@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use sdl2::event::Event;
+
+use crate::settings::PresentMode;
+
+/// Stages of a single input's way to the screen
+#[derive(Debug, Clone, Copy)]
+struct Measurement {
+    /// When SDL received the event, derived from its timestamp
+    input: Instant,
+    /// The end of the first tick that processed the input
+    tick: Option<Instant>,
+    /// When the frame showing the result of that tick was submitted
+    render: Option<Instant>,
+}
+
+/// Durations between the stages of a finished measurement
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    input_to_tick: Duration,
+    tick_to_render: Duration,
+    render_to_present: Duration,
+}
+
+impl Sample {
+    fn total(&self) -> Duration {
+        self.input_to_tick + self.tick_to_render + self.render_to_present
+    }
+}
+
+/// Measures the time from pressing a button until the resulting frame is presented, to compare
+/// the frame pacing and present mode options.
+///
+/// Only one input is followed at a time, presses in the meantime are ignored. The present time
+/// is when `wgpu` accepted the frame, the display may show it later depending on the present mode.
+#[derive(Debug, Default)]
+pub struct LatencyMeter {
+    enabled: bool,
+    current: Option<Measurement>,
+    /// Finished measurements, newest last
+    samples: VecDeque<Sample>,
+    /// Samples are only comparable within the same present mode
+    present_mode: Option<PresentMode>,
+}
+
+impl LatencyMeter {
+    const HISTORY_LENGTH: usize = 120;
+
+    /// Start a measurement for button presses. `sdl_ticks` is the current value of SDL's
+    /// millisecond timer, which event timestamps are based on.
+    pub fn handle_event(&mut self, event: &Event, sdl_ticks: u32) {
+        if !self.enabled || self.current.is_some() {
+            return;
+        }
+        let timestamp = match *event {
+            Event::KeyDown { timestamp, repeat: false, .. }
+            | Event::ControllerButtonDown { timestamp, .. }
+            | Event::FingerDown { timestamp, .. } => timestamp,
+            _ => return,
+        };
+        let age = Duration::from_millis(sdl_ticks.saturating_sub(timestamp) as u64);
+        self.current = Some(Measurement {
+            input: Instant::now() - age,
+            tick: None,
+            render: None,
+        });
+    }
+
+    /// Called after every game tick
+    pub fn tick_finished(&mut self) {
+        if let Some(measurement) = self.current.as_mut().filter(|m| m.tick.is_none()) {
+            measurement.tick = Some(Instant::now());
+        }
+    }
+
+    /// Called once the frame's commands were submitted
+    pub fn frame_rendered(&mut self) {
+        if let Some(measurement) = self.current.as_mut().filter(|m| m.tick.is_some() && m.render.is_none()) {
+            measurement.render = Some(Instant::now());
+        }
+    }
+
+    /// Called after presenting the frame, finishes the measurement if the frame showed its input
+    pub fn frame_presented(&mut self) {
+        let (input, tick, render) = match self.current {
+            Some(Measurement { input, tick: Some(tick), render: Some(render) }) => (input, tick, render),
+            _ => return,
+        };
+        self.current = None;
+        if self.samples.len() == LatencyMeter::HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            input_to_tick: tick.saturating_duration_since(input),
+            tick_to_render: render - tick,
+            render_to_present: render.elapsed(),
+        });
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, present_mode: PresentMode) {
+        if self.present_mode != Some(present_mode) {
+            self.present_mode = Some(present_mode);
+            self.samples.clear();
+        }
+
+        // Adds a section to the game's DevGUI window
+        let _token = match imgui::Window::new("DevGUI").begin(gui) {
+            Some(token) => token,
+            None => return,
+        };
+        if !gui.collapsing_header("Input latency", imgui::TreeNodeFlags::empty()) {
+            return;
+        }
+
+        gui.indent();
+        if gui.checkbox("Measure", &mut self.enabled) && !self.enabled {
+            self.current = None;
+        }
+        gui.same_line();
+        if gui.button("Clear") {
+            self.samples.clear();
+        }
+        gui.text(format!("Present mode: {}", present_mode.name()));
+        if self.samples.is_empty() {
+            gui.text("Press a button to measure");
+        } else {
+            let count = self.samples.len() as u32;
+            let average = |stage: fn(&Sample) -> Duration| {
+                self.samples.iter().map(stage).sum::<Duration>().as_secs_f32() * 1000.0 / count as f32
+            };
+            gui.text(format!("Samples: {count}"));
+            gui.text(format!("Input to tick: {:.2} ms avg", average(|s| s.input_to_tick)));
+            gui.text(format!("Tick to render: {:.2} ms avg", average(|s| s.tick_to_render)));
+            gui.text(format!("Render to present: {:.2} ms avg", average(|s| s.render_to_present)));
+
+            let totals: Vec<f32> = self
+                .samples
+                .iter()
+                .map(|sample| sample.total().as_secs_f32() * 1000.0)
+                .collect();
+            let max = totals.iter().cloned().fold(0.0, f32::max);
+            gui.text(format!("Total: {:.2} ms avg, {max:.2} ms max", average(Sample::total)));
+            gui.plot_lines("Latency", &totals)
+                .scale_min(0.0)
+                .graph_size([0.0, 60.0])
+                .build();
+        }
+        gui.unindent();
+    }
+}
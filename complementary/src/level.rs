@@ -1,10 +1,22 @@
-use std::{fs, io, path::PathBuf, collections::HashMap};
+use std::{fs, io, path::{Path, PathBuf}, collections::{HashMap, HashSet}};
 
+use cgmath::InnerSpace;
+use complementary_macros::{ImGui, JsonSchema};
 use log::debug;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    objects::{ObjectSet, ObjectSetLoadError},
-    tilemap::{Tilemap, TilemapLoadError, TilemapRenderer},
+    camera::{CameraIntro, CameraSettings},
+    game::WorldType,
+    gpu_memory,
+    imgui_helpers::ImGui,
+    math::{Direction, FVec2},
+    mods,
+    objects::{ObjectRef, ObjectSet, ObjectSetLoadError, Positional},
+    player::AbilityPair,
+    tilemap::{Tile, Tilemap, TilemapLoadError, TilemapRenderer},
+    user_levels,
+    window::DrawContext,
 };
 
 pub fn get_all_levels() -> Result<Vec<String>, io::Error> {
@@ -25,60 +37,405 @@ pub fn get_all_levels() -> Result<Vec<String>, io::Error> {
     Ok(levels)
 }
 
+/// GPU resources owned by the current level are created in this scope, see `gpu_memory`
+pub const LEVEL_SCOPE: &str = "level";
+
 pub struct Level {
+    pub name: String,
+    /// Always `Difficulty::Normal` for levels without a hard mode
+    pub difficulty: Difficulty,
     pub tilemap: Tilemap,
     pub objects: ObjectSet,
     pub state: LevelState,
+    pub metadata: LevelMetadata,
+
+    /// Created when the level is first drawn, see `tilemap_renderer()`
+    tilemap_renderer: Option<TilemapRenderer>,
+    /// Tiles changed since the tile map was last drawn
+    changed_tiles: Vec<(i32, i32)>,
+}
+
+/// Objects can be limited to some difficulties with their `modes` field, and tiles can be
+/// replaced in hard mode with `LevelMetadata::hard_tiles`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Difficulty {
+    Normal,
+    Hard,
+}
+
+/// Tile replaced when a level is played in hard mode
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct HardTile {
+    pub x: i32,
+    pub y: i32,
+    pub tile: Tile,
+}
 
-    pub tilemap_renderer: TilemapRenderer,
+/// Optional settings for a level, loaded from `assets/maps/<name>.meta.json`
+#[derive(Debug, Default, Deserialize)]
+pub struct LevelMetadata {
+    /// Abilities given to the player when spawning, e.g. for tutorial levels
+    #[serde(default)]
+    pub abilities: Option<AbilityPair>,
+    /// Direction the player faces (and dashes to) when spawning
+    #[serde(default)]
+    pub facing: Option<Direction>,
+    /// Index of the `SpawnPoint` tile (in reading order) where the player starts.
+    /// The first one is used by default.
+    #[serde(default)]
+    pub spawn_point: Option<usize>,
+    /// Levels show the whole tile map unless configured otherwise
+    #[serde(default)]
+    pub camera: Option<CameraSettings>,
+    /// Camera flyover shown before the player gets control, only the first time the level is entered
+    #[serde(default)]
+    pub intro: Option<CameraIntro>,
+    /// Shown next to user levels in the level list
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Whether the level can be played in hard mode. Otherwise it's always loaded with
+    /// `Difficulty::Normal`, so that its records aren't split between difficulties.
+    #[serde(default)]
+    pub hard_mode: bool,
+    #[serde(default)]
+    pub hard_tiles: Vec<HardTile>,
+}
+
+impl LevelMetadata {
+    /// Load the metadata of a level. Levels without a metadata file use the defaults.
+    pub fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self, LevelMetadataLoadError> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(LevelMetadata::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
 }
 
 pub struct LevelState {
-    keys_by_group: HashMap<i32, CollectedKeys>
+    keys_by_group: HashMap<i32, CollectedKeys>,
+    /// Number of `ShardObject`s in the level and how many of them were collected
+    total_shard_count: usize,
+    collected_shard_count: usize,
+    /// Groups of `SwitchObject`s that are on. Switches start off.
+    switches_on: HashSet<i32>,
+    /// Id of the last checkpoint the player reached
+    checkpoint: Option<i32>,
+    /// Ticks spent playing the level, including deaths
+    elapsed_ticks: u32,
+    /// Level id and spawn position of the last level tag the player entered
+    level_tag: Option<(i32, FVec2)>,
 }
 
-#[derive(Default, Copy, Clone)]
+/// Keys of one group, which open the doors of the same group once all of them are collected
+#[derive(Default, Copy, Clone, ImGui)]
 pub struct CollectedKeys {
     total_key_count: usize,
     collected_key_count: usize,
 }
 
 impl Level {
-    pub fn load<'a, T: AsRef<str> + ?Sized>(
-        device: &'a wgpu::Device,
-        name: &'a T,
-    ) -> Result<Level, LevelLoadError> {
-        let tilemap_path: PathBuf = ["assets", "maps", &format!("{}.cmtm", name.as_ref())]
-            .iter()
-            .collect();
-        let object_map_path = tilemap_path.with_extension("json");
-        debug!("Loaded level: {}", &object_map_path.display());
-        let tilemap = Tilemap::load_from_file(tilemap_path)?;
-        let mut objects = ObjectSet::load_from_file(object_map_path, &device)?;
+    /// Load a level without creating any GPU resources, so that it can be simulated headless
+    pub fn load<T: AsRef<str> + ?Sized>(name: &T) -> Result<Level, LevelLoadError> {
+        Level::load_with_difficulty(name, Difficulty::Normal)
+    }
 
-        let mut keys_by_group: HashMap<i32, CollectedKeys> = HashMap::new();
-        for key in &mut objects.objects.keys {
-            let entry = keys_by_group.entry(key.group()).or_default();
-            entry.total_key_count += 1;
+    /// Load a level with the objects and tiles of a difficulty. Falls back to
+    /// `Difficulty::Normal` if the level has no hard mode.
+    pub fn load_with_difficulty<T: AsRef<str> + ?Sized>(name: &T, difficulty: Difficulty) -> Result<Level, LevelLoadError> {
+        let tilemap_path = Level::tilemap_path(name.as_ref());
+        let object_map_path = Level::object_map_path(name.as_ref());
+        let metadata_path = tilemap_path.with_extension("meta.json");
+        debug!("Loaded level: {}", &object_map_path.display());
+        let metadata = LevelMetadata::load_from_file(metadata_path)?;
+        let difficulty = if metadata.hard_mode { difficulty } else { Difficulty::Normal };
+        let mut tilemap = Tilemap::load_from_file(tilemap_path)?;
+        if difficulty == Difficulty::Hard {
+            for hard_tile in &metadata.hard_tiles {
+                if tilemap.contains_tile(hard_tile.x, hard_tile.y) {
+                    tilemap.set_tile(hard_tile.x, hard_tile.y, hard_tile.tile);
+                }
+            }
         }
+        let objects = ObjectSet::load_from_file(object_map_path, difficulty)?;
+        let state = LevelState::new(&objects);
 
-        let state = LevelState { keys_by_group };
-
-        let tilemap_renderer = TilemapRenderer::new(device, &tilemap);
         Ok(Level {
+            name: name.as_ref().to_owned(),
+            difficulty,
             tilemap,
             objects,
             state,
-            tilemap_renderer,
+            metadata,
+            tilemap_renderer: None,
+            changed_tiles: Vec::new(),
         })
     }
+
+    /// Levels from mods and user levels are loaded from their own directories, see
+    /// `mods::level_name()` and `user_levels::level_name()`
+    pub fn tilemap_path(name: &str) -> PathBuf {
+        if let Some(file_name) = user_levels::file_name(name) {
+            return [user_levels::USER_LEVELS_PATH, &format!("{file_name}.cmtm")].iter().collect();
+        }
+        match mods::split_level_name(name) {
+            Some((mod_id, level)) => [mods::MODS_PATH, mod_id, "maps", &format!("{level}.cmtm")].iter().collect(),
+            None => ["assets", "maps", &format!("{name}.cmtm")].iter().collect(),
+        }
+    }
+
+    pub fn object_map_path(name: &str) -> PathBuf {
+        Level::tilemap_path(name).with_extension("json")
+    }
+
+    /// Get the renderer for the tile map, creating it if the level wasn't drawn before.
+    /// Tiles changed with `set_tile()` are updated first, and tiles that only exist in one
+    /// world are faded unless `world_type` is visible.
+    pub fn tilemap_renderer(&mut self, context: &DrawContext, world_type: WorldType) -> &mut TilemapRenderer {
+        let changed_tiles = std::mem::take(&mut self.changed_tiles);
+        if self.tilemap_renderer.is_none() {
+            let _scope = gpu_memory::enter_scope(LEVEL_SCOPE);
+            return self.tilemap_renderer.insert(TilemapRenderer::new(
+                context.device,
+                context.format,
+                &self.tilemap,
+                world_type,
+            ));
+        }
+        let renderer = self.tilemap_renderer.as_mut().unwrap();
+        for (x, y) in changed_tiles {
+            renderer.update_tile(context.queue, &self.tilemap, x, y);
+        }
+        renderer.set_world_type(context.queue, &self.tilemap, world_type);
+        renderer
+    }
+
+    /// Change a tile, e.g. in the level editor. The tile map is drawn with the new tile
+    /// from the next frame on.
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: Tile) {
+        self.tilemap.set_tile(x, y, tile);
+        self.changed_tiles.push((x, y));
+    }
+
+    /// Flip the level horizontally for mirror mode. Has to be called before the level is
+    /// played or drawn, since the objects are reset to their initial state.
+    pub fn mirror(&mut self) {
+        let width = self.tilemap.width();
+        // Spawn points are numbered in reading order, which changes within each row
+        let spawn_point = self
+            .metadata
+            .spawn_point
+            .and_then(|index| self.tilemap.spawn_points().get(index).copied())
+            .map(|position| FVec2::new((width - 1) as f32 - position.x, position.y));
+
+        self.tilemap.mirror();
+        self.objects.mirror(width as f32);
+
+        if let Some(spawn_point) = spawn_point {
+            self.metadata.spawn_point = self.tilemap.spawn_points().iter().position(|p| *p == spawn_point);
+        }
+        // Players face right unless the level says otherwise
+        let facing = self.metadata.facing.unwrap_or(Direction::Right);
+        self.metadata.facing = Some(facing.mirrored());
+        if let Some(intro) = &mut self.metadata.intro {
+            for point in &mut intro.path {
+                point.x = width as f32 - point.x;
+            }
+        }
+    }
+
+    /// Replace all objects, resetting the level's progress
+    pub fn set_objects(&mut self, objects: ObjectSet) {
+        self.state = LevelState::new(&objects);
+        self.objects = objects;
+    }
+}
+
+impl Level {
+    /// Where the player (re)spawns: at the last entered level tag, at the last reached
+    /// checkpoint nearest to `near`, or at the level's spawn point if neither was reached yet
+    pub fn spawn_point(&self, near: FVec2) -> Option<FVec2> {
+        if let Some((_, position)) = self.state.level_tag {
+            return Some(position);
+        }
+
+        if let Some(id) = self.state.checkpoint {
+            let nearest_checkpoint = self
+                .objects
+                .objects
+                .checkpoints
+                .iter()
+                .filter(|checkpoint| checkpoint.id() == id)
+                .map(|checkpoint| checkpoint.spawn_position())
+                .min_by(|a, b| (*a - near).magnitude2().total_cmp(&(*b - near).magnitude2()));
+            if nearest_checkpoint.is_some() {
+                return nearest_checkpoint;
+            }
+        }
+
+        let spawn_points = self.tilemap.spawn_points();
+        self.metadata
+            .spawn_point
+            .and_then(|index| spawn_points.get(index).copied())
+            .or_else(|| spawn_points.first().copied())
+    }
+
+    /// DevGUI section with the collected keys of every group and the doors they open. Keys can
+    /// be granted or cleared and switches flipped to test locked doors without reaching them.
+    pub fn draw_state_gui(&mut self, gui: &imgui::Ui) {
+        gui.text(format!("Elapsed ticks: {}", self.state.elapsed_ticks));
+        gui.text(format!("Checkpoint: {:?}", self.state.checkpoint));
+        gui.text(format!("Shards: {} / {}", self.state.collected_shard_count, self.state.total_shard_count));
+
+        let mut groups: Vec<i32> = self.state.keys_by_group.keys().copied().collect();
+        groups.sort_unstable();
+        if groups.is_empty() {
+            gui.text_disabled("No keys in this level");
+        }
+        for group in groups {
+            let _id = gui.push_id(group);
+            if let Some(keys) = self.state.keys_by_group.get_mut(&group) {
+                keys.draw_gui(&format!("Group {group}"), gui);
+            }
+            if gui.button("Grant keys") {
+                for key in self.objects.objects.keys.iter_mut().filter(|key| key.group() == group) {
+                    key.collect(&mut self.state);
+                }
+            }
+            gui.same_line();
+            if gui.button("Clear keys") {
+                for key in self.objects.objects.keys.iter_mut().filter(|key| key.group() == group) {
+                    key.reset();
+                }
+                self.state.clear_collected_keys(group);
+            }
+            // Doors update on the next tick
+            let objects = &self.objects.objects;
+            for (index, door) in objects.doors.iter().enumerate().filter(|(_, door)| door.group() == group) {
+                let position = door.position();
+                let id = objects.id_of(ObjectRef::Door(index)).map(ToString::to_string).unwrap_or_default();
+                gui.text(format!(
+                    "Door {id} at ({:.1}, {:.1}): {:.0}% open",
+                    position.x,
+                    position.y,
+                    door.key_collected_percentage() * 100.0,
+                ));
+            }
+        }
+
+        let mut switch_groups: Vec<i32> = self.objects.objects.switches.iter().map(|switch| switch.group()).collect();
+        switch_groups.sort_unstable();
+        switch_groups.dedup();
+        for group in switch_groups {
+            // Switches, doors and platforms update on the next tick
+            let mut on = self.state.switch_on(group);
+            if gui.checkbox(format!("Switch group {group}"), &mut on) {
+                self.state.toggle_switch(group);
+            }
+        }
+    }
 }
 
 impl LevelState {
+    /// State of a level that was just entered
+    pub fn new(objects: &ObjectSet) -> Self {
+        let mut keys_by_group: HashMap<i32, CollectedKeys> = HashMap::new();
+        for key in &objects.objects.keys {
+            let entry = keys_by_group.entry(key.group()).or_default();
+            entry.total_key_count += 1;
+        }
+        // Doors that only depend on a switch don't have any keys
+        for door in &objects.objects.doors {
+            keys_by_group.entry(door.group()).or_default();
+        }
+
+        LevelState {
+            keys_by_group,
+            total_shard_count: objects.objects.shards.len(),
+            collected_shard_count: 0,
+            switches_on: HashSet::new(),
+            checkpoint: None,
+            level_tag: None,
+            elapsed_ticks: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.elapsed_ticks += 1;
+    }
+
+    pub fn elapsed_ticks(&self) -> u32 {
+        self.elapsed_ticks
+    }
+
+    /// Set the checkpoint where the player respawns. Returns `false` if a later
+    /// checkpoint was already reached.
+    pub fn activate_checkpoint(&mut self, id: i32) -> bool {
+        match self.checkpoint {
+            Some(current) if current >= id => false,
+            _ => {
+                self.checkpoint = Some(id);
+                true
+            }
+        }
+    }
+
+    pub fn checkpoint(&self) -> Option<i32> {
+        self.checkpoint
+    }
+
+    pub fn enter_level_tag(&mut self, level_id: i32, spawn_position: FVec2) {
+        if self.level_tag.map(|(id, _)| id) != Some(level_id) {
+            debug!("Entered level tag {level_id}");
+        }
+        self.level_tag = Some((level_id, spawn_position));
+    }
+
+    /// Id of the level whose tag the player entered last
+    pub fn level_tag(&self) -> Option<i32> {
+        self.level_tag.map(|(id, _)| id)
+    }
+
+    pub fn add_collected_shard(&mut self) {
+        self.collected_shard_count += 1;
+    }
+
+    pub fn collected_shard_count(&self) -> usize {
+        self.collected_shard_count
+    }
+
+    pub fn total_shard_count(&self) -> usize {
+        self.total_shard_count
+    }
+
+    /// Flip all switches of a group
+    pub fn toggle_switch(&mut self, group: i32) {
+        if !self.switches_on.remove(&group) {
+            self.switches_on.insert(group);
+        }
+        debug!("Switch group {group} is {}", if self.switch_on(group) { "on" } else { "off" });
+    }
+
+    pub fn switch_on(&self, group: i32) -> bool {
+        self.switches_on.contains(&group)
+    }
+
     pub fn add_collected_key(&mut self, group: i32) {
         self.keys_by_group.entry(group).or_default().collected_key_count += 1;
     }
 
+    pub fn clear_collected_keys(&mut self, group: i32) {
+        self.keys_by_group.entry(group).or_default().collected_key_count = 0;
+    }
+
+    /// Number of keys collected in all groups
+    pub fn collected_key_count(&self) -> usize {
+        self.keys_by_group
+            .values()
+            .map(|keys| keys.collected_key_count)
+            .sum()
+    }
+
     pub fn key_collected_percentage(&self, group: i32) -> f32 {
         let entry = self.keys_by_group.get(&group).expect("Invalid key group");
         if entry.total_key_count == 0 {
@@ -100,4 +457,18 @@ pub enum LevelLoadError {
     Tilemap(#[from] TilemapLoadError),
     #[error("failed to load objects: {0}")]
     ObjectSet(#[from] ObjectSetLoadError),
+    #[error("failed to load metadata: {0}")]
+    Metadata(#[from] LevelMetadataLoadError),
+    #[error("no level with id {0}")]
+    InvalidLevelTag(i32),
+    #[error("level \"{0}\" isn't in the kiosk playlist")]
+    NotInPlaylist(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LevelMetadataLoadError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid data: {0}")]
+    InvalidData(#[from] serde_json::Error),
 }
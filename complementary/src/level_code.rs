@@ -0,0 +1,480 @@
+use std::{
+    fs, io,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use log::error;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    game::Game,
+    level::{Difficulty, Level, LevelMetadata},
+    objects::{ObjectSet, ObjectSetLoadError},
+    toast, user_levels,
+};
+
+const MAGIC: &[u8; 4] = b"CMLC";
+/// Codes with a different version are rejected. Increment when the format changes.
+const VERSION: u8 = 1;
+/// Only small levels can be shared, so that codes are short enough to paste into a chat
+const MAX_WIDTH: i32 = 64;
+const MAX_HEIGHT: i32 = 36;
+const MAX_CODE_LENGTH: usize = 16 * 1024;
+
+/// Level that can be shared as a text code instead of exchanging files
+#[derive(Debug, Clone)]
+pub struct LevelCode {
+    name: String,
+    width: i32,
+    height: i32,
+    tiles: Vec<u8>,
+    objects: Vec<Value>,
+    metadata: Option<Value>,
+}
+
+impl LevelCode {
+    /// Read a level as it was last saved. Changes in the level editor must be saved first.
+    pub fn from_level(name: &str) -> Result<Self, LevelCodeError> {
+        let tilemap_path = Level::tilemap_path(name);
+        let bytes = fs::read(&tilemap_path)?;
+        if bytes.len() < 12 || &bytes[..4] != b"CMTM" {
+            return Err(LevelCodeError::InvalidData(String::from("invalid tile map")));
+        }
+        let width = i32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let height = i32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        check_size(width, height)?;
+        if bytes.len() - 12 != (width * height) as usize {
+            return Err(LevelCodeError::InvalidData(String::from("wrong number of tiles")));
+        }
+
+        let objects = serde_json::from_str(&fs::read_to_string(Level::object_map_path(name))?)?;
+        let metadata = match fs::read_to_string(tilemap_path.with_extension("meta.json")) {
+            Ok(contents) => Some(serde_json::from_str(&contents)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+
+        // Levels from mods or user levels are shared under the name of their files
+        let name = tilemap_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| name.to_owned());
+        Ok(LevelCode {
+            name,
+            width,
+            height,
+            tiles: bytes[12..].to_vec(),
+            objects,
+            metadata,
+        })
+    }
+
+    /// Encode the level as URL-safe base64 of a binary format: the magic `CMLC`, the version,
+    /// the name, the tile map size and its tiles as runs of equal tiles, followed by the
+    /// objects and metadata as compact JSON. All integers are little endian.
+    pub fn encode(&self) -> Result<String, LevelCodeError> {
+        let mut bytes = Vec::new();
+        bytes.write_all(MAGIC)?;
+        bytes.write_all(&[VERSION])?;
+        write_string(&mut bytes, &self.name)?;
+        bytes.write_all(&self.width.to_le_bytes())?;
+        bytes.write_all(&self.height.to_le_bytes())?;
+
+        let runs = run_length_encode(&self.tiles);
+        bytes.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (length, tile) in runs {
+            bytes.write_all(&[length, tile])?;
+        }
+
+        write_string(&mut bytes, &serde_json::to_string(&self.objects)?)?;
+        let metadata = match &self.metadata {
+            Some(metadata) => serde_json::to_string(metadata)?,
+            None => String::new(),
+        };
+        write_string(&mut bytes, &metadata)?;
+
+        let code = encode_base64(&bytes);
+        if code.len() > MAX_CODE_LENGTH {
+            return Err(LevelCodeError::TooLarge(format!(
+                "the code would have {} characters, at most {MAX_CODE_LENGTH} are allowed",
+                code.len()
+            )));
+        }
+        Ok(code)
+    }
+
+    /// Decode a code from `encode()`. Whitespace is ignored, since pasted codes are often
+    /// wrapped.
+    pub fn decode(code: &str) -> Result<Self, LevelCodeError> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() > MAX_CODE_LENGTH {
+            return Err(LevelCodeError::TooLarge(format!("the code has {} characters", code.len())));
+        }
+        let bytes = decode_base64(&code)
+            .ok_or_else(|| LevelCodeError::InvalidData(String::from("not a level code")))?;
+        let mut reader = &bytes[..];
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(LevelCodeError::InvalidData(String::from("not a level code")));
+        }
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != VERSION {
+            return Err(LevelCodeError::UnsupportedVersion(version[0]));
+        }
+
+        let name = read_string(&mut reader)?;
+        let width = read_u32(&mut reader)? as i32;
+        let height = read_u32(&mut reader)? as i32;
+        check_size(width, height)?;
+
+        let run_count = read_u32(&mut reader)?;
+        let mut tiles = Vec::with_capacity((width * height) as usize);
+        for _ in 0..run_count {
+            let mut run = [0u8; 2];
+            reader.read_exact(&mut run)?;
+            tiles.extend(std::iter::repeat(run[1]).take(run[0] as usize));
+            if tiles.len() > (width * height) as usize {
+                break;
+            }
+        }
+        if tiles.len() != (width * height) as usize {
+            return Err(LevelCodeError::InvalidData(String::from("wrong number of tiles")));
+        }
+
+        let objects: Vec<Value> = serde_json::from_str(&read_string(&mut reader)?)?;
+        // Make sure the objects can be loaded before the level is installed
+        ObjectSet::from_json(&objects, Difficulty::Normal)?;
+        let metadata = match read_string(&mut reader)? {
+            metadata if metadata.is_empty() => None,
+            metadata => {
+                let metadata: Value = serde_json::from_str(&metadata)?;
+                // Same for the metadata, which is kept as JSON so that it's installed unchanged
+                LevelMetadata::deserialize(&metadata)?;
+                Some(metadata)
+            }
+        };
+
+        Ok(LevelCode {
+            name,
+            width,
+            height,
+            tiles,
+            objects,
+            metadata,
+        })
+    }
+
+    /// Write the level's files to the user levels directory, next to the levels made by the
+    /// player. Returns the name to load it with.
+    pub fn install(&self) -> Result<String, LevelCodeError> {
+        let directory = Path::new(user_levels::USER_LEVELS_PATH);
+        fs::create_dir_all(directory)?;
+
+        // Never overwrite another level
+        let base_name = sanitize_file_name(&self.name);
+        let mut file_name = base_name.clone();
+        let mut suffix = 2;
+        while user_tilemap_path(directory, &file_name).exists() {
+            file_name = format!("{base_name}_{suffix}");
+            suffix += 1;
+        }
+
+        let tilemap_path = user_tilemap_path(directory, &file_name);
+        let mut tilemap = Vec::with_capacity(12 + self.tiles.len());
+        tilemap.write_all(b"CMTM")?;
+        tilemap.write_all(&self.width.to_le_bytes())?;
+        tilemap.write_all(&self.height.to_le_bytes())?;
+        tilemap.write_all(&self.tiles)?;
+        fs::write(&tilemap_path, tilemap)?;
+        fs::write(
+            tilemap_path.with_extension("json"),
+            serde_json::to_string_pretty(&self.objects)?,
+        )?;
+        if let Some(metadata) = &self.metadata {
+            fs::write(
+                tilemap_path.with_extension("meta.json"),
+                serde_json::to_string_pretty(metadata)?,
+            )?;
+        }
+
+        Ok(user_levels::level_name(&file_name))
+    }
+}
+
+/// Adds a section to the DevGUI for sharing the current level through the clipboard
+#[derive(Default)]
+pub struct LevelCodePanel;
+
+impl LevelCodePanel {
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, game: &mut Game, clipboard: &sdl2::clipboard::ClipboardUtil) {
+        let _token = match imgui::Window::new("DevGUI").begin(gui) {
+            Some(token) => token,
+            None => return,
+        };
+        if !gui.collapsing_header("Level codes", imgui::TreeNodeFlags::empty()) {
+            return;
+        }
+
+        gui.indent();
+        if gui.button("Copy code of current level") {
+            let result = LevelCode::from_level(game.level_name()).and_then(|level| level.encode());
+            match result {
+                Ok(code) => match clipboard.set_clipboard_text(&code) {
+                    Ok(()) => toast::show(format!("Copied level code ({} characters)", code.len())),
+                    Err(err) => error!("Failed to copy level code: {err}"),
+                },
+                Err(err) => toast::show(format!("Failed to create level code: {err}")),
+            }
+        }
+        if gui.button("Import code from clipboard") {
+            let result = clipboard
+                .clipboard_text()
+                .map_err(|err| LevelCodeError::Io(io::Error::new(io::ErrorKind::Other, err)))
+                .and_then(|code| LevelCode::decode(&code))
+                .and_then(|level| level.install());
+            match result {
+                Ok(name) => {
+                    toast::show(format!("Imported level {name}"));
+                    if let Err(err) = game.load_level(&name) {
+                        error!("{err}");
+                    }
+                }
+                Err(err) => toast::show(format!("Failed to import level code: {err}")),
+            }
+        }
+        gui.unindent();
+    }
+}
+
+fn check_size(width: i32, height: i32) -> Result<(), LevelCodeError> {
+    if width <= 0 || height <= 0 || width > MAX_WIDTH || height > MAX_HEIGHT {
+        return Err(LevelCodeError::TooLarge(format!(
+            "the level is {width}x{height} tiles, at most {MAX_WIDTH}x{MAX_HEIGHT} are allowed"
+        )));
+    }
+    Ok(())
+}
+
+fn user_tilemap_path(directory: &Path, file_name: &str) -> PathBuf {
+    directory.join(format!("{file_name}.cmtm"))
+}
+
+/// Names in codes come from other players, so only keep characters that are safe in paths
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .take(64)
+        .collect();
+    if name.is_empty() {
+        String::from("shared_level")
+    } else {
+        name
+    }
+}
+
+/// Runs of up to 255 equal tiles. Most of a tile map is air or solid, so this shrinks it a lot.
+fn run_length_encode(tiles: &[u8]) -> Vec<(u8, u8)> {
+    let mut runs: Vec<(u8, u8)> = Vec::new();
+    for &tile in tiles {
+        match runs.last_mut() {
+            Some((length, last)) if *last == tile && *length < u8::MAX => *length += 1,
+            _ => runs.push((1, tile)),
+        }
+    }
+    runs
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+    writer.write_all(&(string.len() as u32).to_le_bytes())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_string(reader: &mut &[u8]) -> Result<String, LevelCodeError> {
+    let length = read_u32(&mut *reader)? as usize;
+    // The length comes from the code, so check it before allocating
+    if length > reader.len() {
+        return Err(LevelCodeError::InvalidData(String::from("truncated code")));
+    }
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| LevelCodeError::InvalidData(String::from("string isn't valid UTF-8")))
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// URL-safe base64 without padding, so that codes survive being pasted into links
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity((bytes.len() * 4 + 2) / 3);
+    for chunk in bytes.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (i, &byte)| value | ((byte as u32) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            result.push(BASE64_ALPHABET[((value >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    result
+}
+
+fn decode_base64(code: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(code.len() * 3 / 4);
+    for chunk in code.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut value = 0u32;
+        for (i, &c) in chunk.iter().enumerate() {
+            let index = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            value |= index << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            result.push((value >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(result)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LevelCodeError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid data: {0}")]
+    InvalidData(String),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid objects: {0}")]
+    Objects(#[from] ObjectSetLoadError),
+    #[error("unsupported level code version {0}")]
+    UnsupportedVersion(u8),
+    #[error("level too large: {0}")]
+    TooLarge(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NAME: &str = "shared";
+    /// Offsets in the binary format of `level_code()`
+    const VERSION_OFFSET: usize = 4;
+    const WIDTH_OFFSET: usize = 9 + NAME.len();
+    const HEIGHT_OFFSET: usize = WIDTH_OFFSET + 4;
+    const FIRST_RUN_OFFSET: usize = HEIGHT_OFFSET + 8;
+
+    fn level_code() -> LevelCode {
+        let mut tiles = vec![0; 10 * 4];
+        tiles[35] = 1;
+        LevelCode {
+            name: String::from(NAME),
+            width: 10,
+            height: 4,
+            tiles,
+            objects: Vec::new(),
+            metadata: Some(json!({ "author": "someone" })),
+        }
+    }
+
+    /// The binary format inside the code, to build codes that `encode()` wouldn't produce
+    fn to_bytes(level_code: &LevelCode) -> Vec<u8> {
+        decode_base64(&level_code.encode().unwrap()).unwrap()
+    }
+
+    fn decode_bytes(bytes: &[u8]) -> Result<LevelCode, LevelCodeError> {
+        LevelCode::decode(&encode_base64(bytes))
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        // Lengths with every remainder when split into groups of three bytes
+        for length in 0..=6 {
+            let bytes: Vec<u8> = (0..length).map(|i| (i * 97 + 200) as u8).collect();
+            let code = encode_base64(&bytes);
+            assert_eq!(code.len(), (length * 4 + 2) / 3);
+            assert_eq!(decode_base64(&code), Some(bytes));
+        }
+        assert_eq!(decode_base64("A"), None);
+        assert_eq!(decode_base64("AA=A"), None);
+    }
+
+    #[test]
+    fn round_trip_keeps_the_level() {
+        let level_code = level_code();
+        // Pasted codes are often wrapped
+        let code: String = level_code
+            .encode()
+            .unwrap()
+            .chars()
+            .enumerate()
+            .flat_map(|(i, c)| (i % 8 == 7).then_some('\n').into_iter().chain([c]))
+            .collect();
+        let decoded = LevelCode::decode(&code).unwrap();
+        assert_eq!(decoded.name, level_code.name);
+        assert_eq!((decoded.width, decoded.height), (level_code.width, level_code.height));
+        assert_eq!(decoded.tiles, level_code.tiles);
+        assert_eq!(decoded.objects, level_code.objects);
+        assert_eq!(decoded.metadata, level_code.metadata);
+    }
+
+    #[test]
+    fn untrusted_codes_are_checked() {
+        let bytes = to_bytes(&level_code());
+        for length in [0, VERSION_OFFSET, WIDTH_OFFSET + 2, FIRST_RUN_OFFSET + 1, bytes.len() - 1] {
+            assert!(decode_bytes(&bytes[..length]).is_err(), "truncated to {length} bytes");
+        }
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(decode_bytes(&wrong_magic), Err(LevelCodeError::InvalidData(_))));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[VERSION_OFFSET] = VERSION + 1;
+        assert!(matches!(
+            decode_bytes(&wrong_version),
+            Err(LevelCodeError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+
+        for (offset, size) in [
+            (WIDTH_OFFSET, MAX_WIDTH as u32 + 1),
+            (HEIGHT_OFFSET, MAX_HEIGHT as u32 + 1),
+            (WIDTH_OFFSET, u32::MAX),
+        ] {
+            let mut too_large = bytes.clone();
+            too_large[offset..offset + 4].copy_from_slice(&size.to_le_bytes());
+            assert!(matches!(decode_bytes(&too_large), Err(LevelCodeError::TooLarge(_))));
+        }
+
+        // The runs add up to one tile less or more than the tile map has
+        for length in [34, 36] {
+            let mut wrong_tile_count = bytes.clone();
+            wrong_tile_count[FIRST_RUN_OFFSET] = length;
+            assert!(matches!(decode_bytes(&wrong_tile_count), Err(LevelCodeError::InvalidData(_))));
+        }
+
+        let too_long = "A".repeat(MAX_CODE_LENGTH + 1);
+        assert!(matches!(LevelCode::decode(&too_long), Err(LevelCodeError::TooLarge(_))));
+    }
+
+    #[test]
+    fn invalid_metadata_is_rejected() {
+        let level_code = LevelCode {
+            metadata: Some(json!({ "spawn_point": "first" })),
+            ..level_code()
+        };
+        let code = level_code.encode().unwrap();
+        assert!(matches!(LevelCode::decode(&code), Err(LevelCodeError::Json(_))));
+    }
+}
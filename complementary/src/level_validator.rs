@@ -0,0 +1,720 @@
+//! Reachability analysis that finds goals the player can't get to, e.g. because the
+//! `Randomizer` moved the ability that's needed to reach them behind them.
+//!
+//! The level is reduced to a grid in which the player occupies a single tile. How far the
+//! player can jump, dash and drift is measured by simulating them with the current tuning and
+//! rounded to whole tiles, see `MovementReach`. Switching worlds and the abilities granted by
+//! ability blocks are part of the search, as is wind, which carries the player a tile at a
+//! time. Moving platforms count as ground along their whole path, and other objects like doors,
+//! water and enemies are ignored. That makes the analysis optimistic, so it catches levels that
+//! are clearly broken rather than proving that a level can be finished.
+//!
+//! Object maps are also checked against their JSON Schema, see `check_object_map()`.
+
+use std::{collections::VecDeque, fs};
+
+use cgmath::Zero;
+use serde_json::Value;
+
+use crate::{
+    audio,
+    game::{PlayerTickState, WorldType},
+    input::{ButtonType, Input},
+    level::{Difficulty, Level, LevelState},
+    math::{Bounds, FVec2},
+    objects::{self, ObjectSet, PositionalWithSize},
+    player::{Ability, AbilityPair, Player, PlayerState},
+    player_tuning::PlayerTuning,
+    schema,
+    tilemap::{Tile, Tilemap},
+};
+
+lazy_static::lazy_static! {
+    /// Generating the schema walks every object type, so it's only done once
+    static ref OBJECT_MAP_SCHEMA: Value = objects::object_map_schema();
+}
+
+/// Check a level's object map against `objects::object_map_schema()`. The loader ignores unknown
+/// fields and uses defaults for missing ones, so this finds typos in hand-edited levels.
+/// Returns a message for every mismatch.
+pub fn check_object_map(name: &str) -> Vec<String> {
+    let path = Level::object_map_path(name);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => return vec![format!("failed to read {}: {err}", path.display())],
+    };
+    match serde_json::from_str(&contents) {
+        Ok(objects) => object_map_errors(&objects),
+        Err(err) => vec![format!("invalid JSON in {}: {err}", path.display())],
+    }
+}
+
+/// Check the contents of an object map file, see `check_object_map()`
+pub fn object_map_errors(objects: &Value) -> Vec<String> {
+    schema::validate(objects, &OBJECT_MAP_SCHEMA)
+}
+
+/// How far the player can move, in whole tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovementReach {
+    /// Tiles a jump rises. Double jumps and wall jumps are assumed to rise as high.
+    pub jump_height: i32,
+    /// Tiles a running jump moves sideways until the player is back at the height they jumped
+    /// from. Falling further lets them drift further.
+    pub jump_distance: i32,
+    /// Tiles a dash moves sideways
+    pub dash_distance: i32,
+    /// Tiles the player can move sideways for every tile they fall, without and with the glider
+    pub fall_drift: i32,
+    pub glide_drift: i32,
+}
+
+impl MovementReach {
+    /// Ticks of running before a jump or dash is measured, so that the player is at top speed
+    const RUN_UP_TICKS: i32 = 60;
+    /// Measurements stop after this many ticks in case the player never lands
+    const MAX_TICKS: i32 = 600;
+
+    /// Measure the player's movement with `tuning` in an empty room
+    pub fn measure(tuning: &PlayerTuning) -> Self {
+        audio::muted(|| {
+            let (jump_height, jump_distance) = MovementReach::measure_jump(tuning);
+            MovementReach {
+                // The top of the player reaches further up than their feet
+                jump_height: (jump_height + Player::SIZE.y).floor() as i32,
+                jump_distance: gap_tiles(jump_distance),
+                dash_distance: gap_tiles(MovementReach::measure_dash(tuning)),
+                fall_drift: MovementReach::measure_drift(tuning, Ability::None, &[ButtonType::Right]),
+                glide_drift: MovementReach::measure_drift(
+                    tuning,
+                    Ability::Glider,
+                    &[ButtonType::Right, ButtonType::Ability],
+                ),
+            }
+        })
+    }
+
+    /// Returns how high a running jump rises and how far it moves until the player lands
+    fn measure_jump(tuning: &PlayerTuning) -> (f32, f32) {
+        let mut room = EmptyRoom::new(tuning, Ability::None, false);
+        for _ in 0..MovementReach::RUN_UP_TICKS {
+            room.tick(&[ButtonType::Right]);
+        }
+        let start = room.player.position();
+        let mut height: f32 = 0.0;
+        for tick in 0..MovementReach::MAX_TICKS {
+            room.tick(&[ButtonType::Right, ButtonType::Jump]);
+            height = height.max(start.y - room.player.position().y);
+            if tick > 0 && room.player.grounded() {
+                break;
+            }
+        }
+        (height, room.player.position().x - start.x)
+    }
+
+    /// Returns how far a dash moves the player
+    fn measure_dash(tuning: &PlayerTuning) -> f32 {
+        let mut room = EmptyRoom::new(tuning, Ability::Dash, false);
+        for _ in 0..MovementReach::RUN_UP_TICKS {
+            room.tick(&[ButtonType::Right]);
+        }
+        let start = room.player.position();
+        room.tick(&[ButtonType::Right, ButtonType::Ability]);
+        for _ in 0..MovementReach::MAX_TICKS {
+            if room.player.state() != PlayerState::Dashing {
+                break;
+            }
+            room.tick(&[ButtonType::Right]);
+        }
+        room.player.position().x - start.x
+    }
+
+    /// Returns how many tiles the player moves sideways per tile fallen at top speed while
+    /// holding `buttons`, at least one
+    fn measure_drift(tuning: &PlayerTuning, ability: Ability, buttons: &[ButtonType]) -> i32 {
+        let mut room = EmptyRoom::new(tuning, ability, true);
+        for _ in 0..MovementReach::RUN_UP_TICKS {
+            room.tick(buttons);
+        }
+        let start = room.player.position();
+        for _ in 0..MovementReach::RUN_UP_TICKS {
+            room.tick(buttons);
+        }
+        let delta = room.player.position() - start;
+        ((delta.x / delta.y).round() as i32).max(1)
+    }
+}
+
+/// Tiles the player moves sideways to cross a gap they can cross by moving `distance`. The
+/// player can start with most of their body over the gap and land with little of it on the
+/// other side.
+fn gap_tiles(distance: f32) -> i32 {
+    (distance + Player::SIZE.x).floor() as i32 + 1
+}
+
+/// Empty room with a floor that the player is simulated in, see `MovementReach::measure()`
+struct EmptyRoom {
+    player: Player,
+    input: Input,
+    tilemap: Tilemap,
+    objects: ObjectSet,
+    level_state: LevelState,
+}
+
+impl EmptyRoom {
+    const WIDTH: i32 = 64;
+    const HEIGHT: i32 = 128;
+
+    /// The player starts on the floor, or at the top of the room with `in_air`
+    fn new(tuning: &PlayerTuning, ability: Ability, in_air: bool) -> Self {
+        let mut tilemap = Tilemap::new(EmptyRoom::WIDTH, EmptyRoom::HEIGHT);
+        for x in 0..EmptyRoom::WIDTH {
+            tilemap.set_tile(x, EmptyRoom::HEIGHT - 1, Tile::Solid);
+        }
+        let objects = ObjectSet::from_json(&[], Difficulty::Normal).expect("empty object maps are valid");
+        let level_state = LevelState::new(&objects);
+
+        let mut player = Player::new();
+        player.set_tuning(tuning.clone());
+        player.set_abilities(AbilityPair::new(ability, ability));
+        let y = if in_air {
+            1.0
+        } else {
+            (EmptyRoom::HEIGHT - 1) as f32 - Player::SIZE.y
+        };
+        player.reset(FVec2::new(2.0, y));
+
+        EmptyRoom {
+            player,
+            input: Input::new(),
+            tilemap,
+            objects,
+            level_state,
+        }
+    }
+
+    /// Hold `buttons` for a tick and release all others
+    fn tick(&mut self, buttons: &[ButtonType]) {
+        let mask = buttons.iter().fold(0, |mask, button| mask | (1 << *button as u32));
+        self.input.set_pressed_buttons(mask);
+        self.input.tick();
+        self.player.tick(&mut PlayerTickState {
+            input: &self.input,
+            tilemap: &mut self.tilemap,
+            objects: &mut self.objects,
+            level_state: &mut self.level_state,
+            world_type: WorldType::Light,
+        });
+    }
+}
+
+/// Goals of a level that the player can and can't reach, see `Reachability::check()`
+#[derive(Debug, Clone, Default)]
+pub struct Reachability {
+    /// Positions of all goal tiles
+    pub goals: Vec<(i32, i32)>,
+    /// Positions of the goal tiles that the player can't touch
+    pub unreachable_goals: Vec<(i32, i32)>,
+}
+
+impl Reachability {
+    /// Check a freshly loaded level, starting at its spawn point with `abilities`
+    pub fn of_level(level: &Level, abilities: AbilityPair, reach: &MovementReach) -> Self {
+        Reachability::check(
+            &level.tilemap,
+            &level.objects,
+            level.spawn_point(FVec2::zero()),
+            abilities,
+            reach,
+        )
+    }
+
+    /// Search every tile the player can get to from `start` in the light world, and find the
+    /// goal tiles next to them. Without a start, no goal is reachable.
+    pub fn check(
+        tilemap: &Tilemap,
+        objects: &ObjectSet,
+        start: Option<FVec2>,
+        abilities: AbilityPair,
+        reach: &MovementReach,
+    ) -> Self {
+        let grid = Grid::new(tilemap, objects, abilities);
+        let reached = match start {
+            Some(start) => grid.search(start, reach),
+            None => vec![false; (tilemap.width() * tilemap.height()) as usize],
+        };
+        let reached = |x: i32, y: i32| tilemap.contains_tile(x, y) && reached[(y * tilemap.width() + x) as usize];
+
+        let mut goals = Vec::new();
+        let mut unreachable_goals = Vec::new();
+        for y in 0..tilemap.height() {
+            for x in 0..tilemap.width() {
+                if !matches!(
+                    tilemap.get_tile(x, y),
+                    Tile::GoalLeft | Tile::GoalRight | Tile::GoalUp | Tile::GoalDown
+                ) {
+                    continue;
+                }
+                goals.push((x, y));
+                if !Grid::NEIGHBORS.iter().any(|(dx, dy)| reached(x + dx, y + dy)) {
+                    unreachable_goals.push((x, y));
+                }
+            }
+        }
+        Reachability { goals, unreachable_goals }
+    }
+
+    /// Whether any goal can be reached. Levels without goals, like the level select, always
+    /// count as completable.
+    pub fn completable(&self) -> bool {
+        self.goals.is_empty() || self.unreachable_goals.len() < self.goals.len()
+    }
+}
+
+/// Position and movement state of the player during the search
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SearchState {
+    x: i32,
+    y: i32,
+    world_type: WorldType,
+    /// Index in `Grid::abilities`
+    abilities: usize,
+    /// Tiles the player can still rise and move sideways in the current jump
+    rise: i32,
+    side: i32,
+    /// Tiles above the height where the player last jumped, see `MovementReach::jump_distance`
+    height: i32,
+    /// Whether the double jump and the dash weren't used since the player left the ground
+    air_jump: bool,
+    dash: bool,
+}
+
+/// What the search needs to know about a tile in one world
+#[derive(Debug, Clone, Copy, Default)]
+struct GridTile {
+    /// Whether the player can occupy the tile
+    free: bool,
+    /// Whether the player stands when they're in the tile, on top of the one below or on a slope
+    grounded: bool,
+    /// Whether there's a tile to the left or right that the player can wall jump off
+    next_to_wall: bool,
+    /// Direction in which wind carries the player, -1, 0 or 1 on each axis
+    wind: (i32, i32),
+}
+
+/// A level's tiles and the objects that matter for the search, see `Reachability::check()`
+struct Grid {
+    width: i32,
+    height: i32,
+    /// Tiles of the light and the dark world in reading order
+    tiles: [Vec<GridTile>; 2],
+    /// Every distinct pair of abilities the player can have, starting with the initial ones
+    abilities: Vec<AbilityPair>,
+    /// Indices in `abilities` of the ability blocks next to each tile
+    touched_abilities: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    const NEIGHBORS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+    fn new(tilemap: &Tilemap, objects: &ObjectSet, abilities: AbilityPair) -> Self {
+        let mut abilities = vec![abilities];
+        // Ability blocks are solid in both worlds
+        let mut ability_blocks = Vec::new();
+        for block in &objects.objects.ability_blocks {
+            let index = match abilities.iter().position(|pair| *pair == block.abilities()) {
+                Some(index) => index,
+                None => {
+                    abilities.push(block.abilities());
+                    abilities.len() - 1
+                }
+            };
+            ability_blocks.push((block.bounds(), index));
+        }
+        let platforms: Vec<_> = objects
+            .objects
+            .platforms
+            .iter()
+            .map(|platform| (platform.path_bounds(), platform.world_type()))
+            .collect();
+
+        let tile = |x, y| tilemap.contains_tile(x, y).then(|| tilemap.get_tile(x, y));
+        let on_ability_block = |x, y| {
+            let bounds = tile_bounds(x, y);
+            ability_blocks.iter().any(|(block, _)| block.overlaps(&bounds))
+        };
+        let free = |x, y, world_type| {
+            tile(x, y).map_or(false, |tile| !tile.is_solid_in(world_type)) && !on_ability_block(x, y)
+        };
+        // Whether the player can stand on top of the tile without dying
+        let standable = |x, y, world_type| match tile(x, y) {
+            None => false,
+            Some(tile) if tile.is_solid_in(world_type) => !matches!(tile, Tile::SpikesUp | Tile::SpikeAllSides),
+            Some(Tile::OneWayUp) => true,
+            Some(_) => {
+                let bounds = tile_bounds(x, y);
+                on_ability_block(x, y)
+                    || platforms.iter().any(|(platform, platform_world)| {
+                        platform_world.map_or(true, |w| w == world_type) && platform.overlaps(&bounds)
+                    })
+            }
+        };
+        let wall = |x, y, world_type| tile(x, y).map_or(false, |tile| tile.is_wall() && tile.is_solid_in(world_type));
+        let wind = |x, y| {
+            let center = tile_bounds(x, y).center();
+            let force: FVec2 = objects.objects.winds.iter().filter_map(|wind| wind.force_at(center)).sum();
+            let direction = |force: f32| if force.abs() < f32::EPSILON { 0 } else { force.signum() as i32 };
+            (direction(force.x), direction(force.y))
+        };
+
+        let mut tiles = [Vec::new(), Vec::new()];
+        let mut touched_abilities = Vec::new();
+        for y in 0..tilemap.height() {
+            for x in 0..tilemap.width() {
+                for world_type in [WorldType::Light, WorldType::Dark] {
+                    tiles[world_index(world_type)].push(GridTile {
+                        free: free(x, y, world_type),
+                        grounded: standable(x, y + 1, world_type)
+                            || tile(x, y).map_or(false, |tile| tile.slope_direction().is_some()),
+                        next_to_wall: wall(x - 1, y, world_type) || wall(x + 1, y, world_type),
+                        wind: wind(x, y),
+                    });
+                }
+
+                let mut touched = Vec::new();
+                for (dx, dy) in Grid::NEIGHBORS {
+                    let bounds = tile_bounds(x + dx, y + dy);
+                    for (block, index) in &ability_blocks {
+                        if block.overlaps(&bounds) && !touched.contains(index) {
+                            touched.push(*index);
+                        }
+                    }
+                }
+                touched_abilities.push(touched);
+            }
+        }
+
+        Grid {
+            width: tilemap.width(),
+            height: tilemap.height(),
+            tiles,
+            abilities,
+            touched_abilities,
+        }
+    }
+
+    /// The tile in `world_type`, outside of the tile map counts as solid
+    fn tile(&self, x: i32, y: i32, world_type: WorldType) -> GridTile {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return GridTile::default();
+        }
+        self.tiles[world_index(world_type)][(y * self.width + x) as usize]
+    }
+
+    fn free(&self, x: i32, y: i32, world_type: WorldType) -> bool {
+        self.tile(x, y, world_type).free
+    }
+
+    /// Whether the player can occupy each tile after starting at `start`, in reading order
+    fn search(&self, start: FVec2, reach: &MovementReach) -> Vec<bool> {
+        let start = SearchState {
+            x: start.x.floor() as i32,
+            y: start.y.floor() as i32,
+            world_type: WorldType::Light,
+            abilities: 0,
+            rise: 0,
+            side: 0,
+            height: 0,
+            air_jump: true,
+            dash: true,
+        };
+        let mut reached = vec![false; (self.width * self.height) as usize];
+        if !self.free(start.x, start.y, start.world_type) {
+            return reached;
+        }
+
+        // Visited states are tracked in a bit set, since there are millions of them in
+        // larger levels
+        let rise_count = reach.jump_height as usize + 1;
+        let side_count = reach.jump_distance.max(reach.fall_drift).max(reach.glide_drift) as usize + 1;
+        let index = |state: &SearchState| {
+            let mut index = (state.y * self.width + state.x) as usize;
+            index = index * 2 + world_index(state.world_type);
+            index = index * self.abilities.len() + state.abilities;
+            index = index * rise_count + state.rise as usize;
+            index = index * side_count + state.side as usize;
+            index = index * rise_count + state.height as usize;
+            index = index * 2 + state.air_jump as usize;
+            index * 2 + state.dash as usize
+        };
+        let state_count = reached.len() * 2 * self.abilities.len() * rise_count * side_count * rise_count * 4;
+        let mut visited = vec![0_u64; state_count.div_ceil(64)];
+        let mut visit = |state: &SearchState| {
+            let index = index(state);
+            let (word, bit) = (index / 64, 1 << (index % 64));
+            let new = visited[word] & bit == 0;
+            visited[word] |= bit;
+            new
+        };
+
+        visit(&start);
+        let mut queue = VecDeque::from([start]);
+        let mut moves = Vec::new();
+        while let Some(state) = queue.pop_front() {
+            let tile_index = (state.y * self.width + state.x) as usize;
+            reached[tile_index] = true;
+            moves.clear();
+            self.moves(state, reach, &mut moves);
+            for next in &moves {
+                // Touching an ability block is optional, since the player could avoid it
+                let touched = &self.touched_abilities[(next.y * self.width + next.x) as usize];
+                let with_abilities = touched.iter().map(|abilities| SearchState { abilities: *abilities, ..*next });
+                for next in std::iter::once(*next).chain(with_abilities) {
+                    if visit(&next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        reached
+    }
+
+    /// Add the states the player can get to from `state` in a single step to `moves`
+    fn moves(&self, state: SearchState, reach: &MovementReach, moves: &mut Vec<SearchState>) {
+        let SearchState { x, y, world_type, .. } = state;
+        let ability = self.abilities[state.abilities].current(world_type);
+        let tile = self.tile(x, y, world_type);
+        let mut state = state;
+        if tile.grounded {
+            state.height = 0;
+            state.air_jump = true;
+            state.dash = true;
+        }
+        let jump = SearchState {
+            rise: reach.jump_height,
+            side: reach.jump_distance,
+            height: 0,
+            ..state
+        };
+
+        if tile.grounded {
+            moves.push(jump);
+            for dx in [-1, 1] {
+                if self.free(x + dx, y, world_type) {
+                    moves.push(SearchState { x: x + dx, rise: 0, side: 0, ..state });
+                }
+            }
+        } else {
+            if ability == Ability::DoubleJump && state.air_jump {
+                moves.push(SearchState { air_jump: false, ..jump });
+            }
+            if ability == Ability::WallJump && tile.next_to_wall {
+                moves.push(jump);
+            }
+            if state.side > 0 {
+                for dx in [-1, 1] {
+                    if self.free(x + dx, y, world_type) {
+                        moves.push(SearchState { x: x + dx, side: state.side - 1, ..state });
+                    }
+                }
+            }
+        }
+        // Moving platforms and one-way platforms count as ground, but can be fallen through
+        if self.free(x, y + 1, world_type) {
+            let drift = if ability == Ability::Glider {
+                reach.glide_drift
+            } else {
+                reach.fall_drift
+            };
+            // Jumps already cover the way back down to where they started, unless the player glides
+            let side = if state.height > 0 && ability != Ability::Glider {
+                state.side
+            } else {
+                state.side.max(drift)
+            };
+            moves.push(SearchState { y: y + 1, rise: 0, side, height: (state.height - 1).max(0), ..state });
+        }
+        if state.rise > 0 && self.free(x, y - 1, world_type) {
+            moves.push(SearchState { y: y - 1, rise: state.rise - 1, height: state.height + 1, ..state });
+        }
+        if ability == Ability::Dash && state.dash {
+            for dx in [-1, 1] {
+                for distance in 1..=reach.dash_distance {
+                    if !self.free(x + dx * distance, y, world_type) {
+                        break;
+                    }
+                    moves.push(SearchState { x: x + dx * distance, rise: 0, side: 0, dash: false, ..state });
+                }
+            }
+        }
+        // Wind pushes the player along without using up the jump, as if they were gliding on it
+        let (wind_x, wind_y) = tile.wind;
+        for (dx, dy) in [(wind_x, 0), (0, wind_y), (wind_x, wind_y)] {
+            if (dx, dy) != (0, 0) && self.free(x + dx, y + dy, world_type) {
+                moves.push(SearchState { x: x + dx, y: y + dy, height: 0, ..state });
+            }
+        }
+        let other_world = world_type.inverse();
+        if self.free(x, y, other_world) {
+            moves.push(SearchState { world_type: other_world, ..state });
+        }
+    }
+}
+
+fn world_index(world_type: WorldType) -> usize {
+    match world_type {
+        WorldType::Light => 0,
+        WorldType::Dark => 1,
+    }
+}
+
+fn tile_bounds(x: i32, y: i32) -> Bounds {
+    let min = FVec2::new(x as f32, y as f32);
+    Bounds::new(min, min + FVec2::new(1.0, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use serde_json::{json, Value};
+
+    const FLOOR_Y: i32 = 10;
+
+    fn reach() -> MovementReach {
+        MovementReach::measure(&PlayerTuning::default())
+    }
+
+    /// A room with a floor, and a gap in it that's one tile wider than a jump can cross. The
+    /// goal is on the far side of the gap.
+    fn gap_room(reach: &MovementReach) -> Tilemap {
+        let gap = reach.jump_distance;
+        let mut tilemap = Tilemap::new(gap + 8, FLOOR_Y + 1);
+        for x in (0..4).chain(gap + 4..tilemap.width()) {
+            tilemap.set_tile(x, FLOOR_Y, Tile::Solid);
+        }
+        tilemap.set_tile(tilemap.width() - 1, FLOOR_Y - 1, Tile::GoalLeft);
+        tilemap
+    }
+
+    fn ability_block(x: i32, abilities: [&str; 2]) -> Value {
+        json!({
+            "type": "AbilityBlock",
+            "position": { "x": x, "y": FLOOR_Y - 1 },
+            "data": { "size": { "x": 1.0, "y": 1.0 }, "abilities": abilities },
+        })
+    }
+
+    fn check(tilemap: &Tilemap, objects: &[Value], abilities: AbilityPair, reach: &MovementReach) -> Reachability {
+        let objects = ObjectSet::from_json(objects, Difficulty::Normal).unwrap();
+        let start = FVec2::new(1.0, (FLOOR_Y - 1) as f32);
+        Reachability::check(tilemap, &objects, Some(start), abilities, reach)
+    }
+
+    #[test]
+    fn measured_reach_matches_default_tuning() {
+        let reach = reach();
+        assert!(reach.jump_height >= 2, "{reach:?}");
+        assert!(reach.jump_distance >= 3, "{reach:?}");
+        assert!(reach.dash_distance >= 3, "{reach:?}");
+        assert!(reach.glide_drift > reach.fall_drift, "{reach:?}");
+    }
+
+    #[test]
+    fn gap_wider_than_a_jump_is_unreachable() {
+        let reach = reach();
+        let reachability = check(&gap_room(&reach), &[], AbilityPair::default(), &reach);
+        assert!(!reachability.completable());
+        assert_eq!(reachability.unreachable_goals, reachability.goals);
+    }
+
+    #[test]
+    fn ability_blocks_open_up_goals() {
+        let reach = reach();
+        let tilemap = gap_room(&reach);
+        let dash = check(&tilemap, &[ability_block(0, ["Dash", "Dash"])], AbilityPair::default(), &reach);
+        assert!(dash.completable());
+
+        // Both worlds have to be considered, since the player can switch before jumping
+        let dark_dash = check(&tilemap, &[ability_block(0, ["None", "Dash"])], AbilityPair::default(), &reach);
+        assert!(dark_dash.completable());
+
+        // A block behind the gap doesn't help
+        let behind = check(
+            &tilemap,
+            &[ability_block(tilemap.width() - 2, ["Dash", "Dash"])],
+            AbilityPair::default(),
+            &reach,
+        );
+        assert!(!behind.completable());
+    }
+
+    #[test]
+    fn walls_need_wall_jumps_or_world_switches() {
+        let reach = reach();
+        let height = reach.jump_height + 2;
+        let mut tilemap = Tilemap::new(12, FLOOR_Y + 1);
+        for x in 0..tilemap.width() {
+            tilemap.set_tile(x, FLOOR_Y, Tile::Solid);
+        }
+        // A wall with the goal on top, too high to jump onto
+        for y in FLOOR_Y - height..FLOOR_Y {
+            tilemap.set_tile(8, y, Tile::Solid);
+        }
+        tilemap.set_tile(8, FLOOR_Y - height - 1, Tile::GoalDown);
+        let no_ability = check(&tilemap, &[], AbilityPair::default(), &reach);
+        assert!(!no_ability.completable());
+        let wall_jump = check(&tilemap, &[], AbilityPair::new(Ability::WallJump, Ability::None), &reach);
+        assert!(wall_jump.completable());
+
+        // Steps that only exist in the dark world lead up the wall
+        for step in 1..height {
+            tilemap.set_tile(8 - step, FLOOR_Y - height + step, Tile::DarkOnly);
+        }
+        let steps = check(&tilemap, &[], AbilityPair::default(), &reach);
+        assert!(steps.completable());
+    }
+
+    #[test]
+    fn shipped_object_maps_match_the_schema() {
+        test_support::use_repository_root();
+        let mut checked = 0;
+        for entry in fs::read_dir("assets/maps").unwrap() {
+            let path = entry.unwrap().path();
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            if !file_name.ends_with(".json") || file_name.ends_with(".meta.json") {
+                continue;
+            }
+            let objects: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            assert_eq!(object_map_errors(&objects), Vec::<String>::new(), "{file_name}");
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn object_maps_with_typos_are_reported() {
+        let objects = json!([{
+            "type": "Key",
+            "position": { "x": 1.0, "y": 2.0 },
+            "data": { "gruop": 1 },
+        }]);
+        assert!(!object_map_errors(&objects).is_empty());
+    }
+
+    #[test]
+    fn every_level_is_completable() {
+        test_support::use_repository_root();
+        let reach = MovementReach::measure(&PlayerTuning::load_or_default());
+        for name in crate::level::get_all_levels().unwrap() {
+            let level = Level::load(&name).unwrap();
+            let abilities = level.metadata.abilities.unwrap_or_default();
+            let reachability = Reachability::of_level(&level, abilities, &reach);
+            assert!(
+                reachability.completable(),
+                "{name}: unreachable goals {:?}",
+                reachability.unreachable_goals
+            );
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Game logic of Complementary. The binary in `main.rs` only sets up logging and calls `run()`,
+//! other tools can use `Game`, `Level`, `Tilemap` and `ObjectSet` to load and simulate levels
+//! without a window, see `Level::load()` and `Game::tick()`.
+
+#[cfg(feature = "automation")]
+mod automation;
+mod camera;
+pub mod collision;
+mod controls;
+mod debug_view;
+mod display;
+mod editor;
+pub mod game;
+mod gpu_memory;
+mod imgui_helpers;
+mod imgui_sdl2_support;
+pub mod input;
+mod latency;
+pub mod level;
+mod level_code;
+mod level_validator;
+mod localization;
+pub mod math;
+mod menu;
+mod mods;
+pub mod objects;
+mod onboarding;
+mod pacing;
+pub mod physics;
+pub mod player;
+mod player_tuning;
+mod post_processing;
+mod profiler;
+mod randomizer;
+#[cfg(all(test, feature = "renderer_check"))]
+mod renderer_check;
+mod rendering;
+mod replay;
+mod save;
+mod schema;
+pub mod settings;
+mod shader;
+mod tas;
+#[cfg(test)]
+mod test_support;
+mod telegraph;
+mod thumbnail;
+pub mod tilemap;
+mod timer;
+mod toast;
+mod touch;
+mod ui_layout;
+mod user_levels;
+mod window;
+mod audio;
+
+pub use game::Game;
+pub use level::Level;
+pub use mods::{ModLoadError, ModTickState, TickHook};
+pub use objects::ObjectSet;
+pub use tilemap::Tilemap;
+
+use std::{env, error::Error, path::Path};
+
+use audio::SoundCategory;
+use settings::Settings;
+use window::Window;
+
+/// Open the window and run the game until it's closed, using the command line arguments
+pub fn run() -> Result<(), Box<dyn Error>> {
+    run_with(Vec::new())
+}
+
+/// Like `run()`, but registers `tick_hooks` for the mods with the given ids first, see
+/// `Game::register_tick_hook()`. Hooks for mods that aren't installed are skipped with a warning.
+pub fn run_with(tick_hooks: Vec<(&str, Box<dyn TickHook>)>) -> Result<(), Box<dyn Error>> {
+    // Dev command for editors, see `schema`
+    let mut args = env::args().skip(1);
+    if args.any(|arg| arg == "--object-schema") {
+        let path = args.next().unwrap_or_else(|| String::from(schema::OBJECT_SCHEMA_PATH));
+        schema::write_object_schema(Path::new(&path))?;
+        log::info!("Wrote the object map schema to {path}");
+        return Ok(());
+    }
+
+    let mut settings = Settings::detect();
+    settings.load_saved();
+    settings.apply_args(env::args().skip(1));
+    if let Err(err) = localization::set_language(&settings.language) {
+        log::warn!("Failed to load language \"{}\": {}", settings.language, err);
+    }
+    if settings.audio {
+        if let Err(err) = audio::init() {
+            log::warn!("Failed to initialize audio, continuing without sound: {err}");
+        }
+    }
+    audio::set_volume(SoundCategory::Music, settings.music_volume);
+    audio::set_volume(SoundCategory::Effects, settings.effects_volume);
+
+    let mut window = Window::new(settings, tick_hooks)?;
+    window.run_main_loop()?;
+    Ok(())
+}
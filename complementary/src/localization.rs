@@ -0,0 +1,150 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::RwLock};
+
+use log::warn;
+use num_traits::FromPrimitive;
+
+use crate::input::ButtonType;
+
+const FALLBACK_LANGUAGE: &str = "en";
+
+lazy_static::lazy_static! {
+    static ref LOCALIZATION: RwLock<Localization> = RwLock::new(Localization::load_or_empty(FALLBACK_LANGUAGE));
+    /// Names of the buttons for `tr_with_prompts()`, indexed by `ButtonType`
+    static ref BUTTON_PROMPTS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+/// Strings for a single language, loaded from `assets/localization/<language>.json`.
+/// The files are generated by the data converter. Empty strings haven't been translated yet
+/// and are treated like missing ones.
+pub struct Localization {
+    language: String,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Localization {
+    pub fn load(language: &str) -> Result<Self, LocalizationLoadError> {
+        let strings = Localization::load_strings(language)?;
+        let fallback = if language == FALLBACK_LANGUAGE {
+            HashMap::new()
+        } else {
+            Localization::load_strings(FALLBACK_LANGUAGE)?
+        };
+
+        Ok(Localization {
+            language: language.to_owned(),
+            strings,
+            fallback,
+        })
+    }
+
+    fn load_or_empty(language: &str) -> Self {
+        Localization::load(language).unwrap_or_else(|err| {
+            warn!("Failed to load language \"{language}\": {err}");
+            Localization {
+                language: language.to_owned(),
+                strings: HashMap::new(),
+                fallback: HashMap::new(),
+            }
+        })
+    }
+
+    fn load_strings(language: &str) -> Result<HashMap<String, String>, LocalizationLoadError> {
+        let path: PathBuf = ["assets", "localization", &format!("{language}.json")]
+            .iter()
+            .collect();
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        [&self.strings, &self.fallback]
+            .into_iter()
+            .filter_map(|strings| strings.get(key))
+            .find(|text| !text.is_empty())
+            .map(String::as_str)
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+}
+
+/// Get the names of all languages in `assets/localization`
+pub fn available_languages() -> Result<Vec<String>, io::Error> {
+    let mut languages = Vec::new();
+    for entry in fs::read_dir("assets/localization")? {
+        let path = entry?.path();
+        if matches!(path.extension().and_then(|ext| ext.to_str()), Some("json")) {
+            if let Some(name) = path.file_stem() {
+                languages.push(name.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    languages.sort();
+    Ok(languages)
+}
+
+pub fn current_language() -> String {
+    LOCALIZATION
+        .read()
+        .expect("Poisoned `Localization` lock")
+        .language()
+        .to_owned()
+}
+
+/// Switch the language used by `tr()`
+pub fn set_language(language: &str) -> Result<(), LocalizationLoadError> {
+    let localization = Localization::load(language)?;
+    *LOCALIZATION.write().expect("Poisoned `Localization` lock") = localization;
+    Ok(())
+}
+
+/// Get the translated string for a key. Falls back to the key itself if it's missing.
+pub fn tr(key: &str) -> String {
+    let localization = LOCALIZATION.read().expect("Poisoned `Localization` lock");
+    match localization.get(key) {
+        Some(text) => text.to_owned(),
+        None => key.to_owned(),
+    }
+}
+
+/// Replace button names in braces (e.g. "{Jump}") with the name returned by `prompt`
+pub fn replace_button_prompts<F: Fn(ButtonType) -> String>(text: &str, prompt: F) -> String {
+    let mut text = text.to_owned();
+    for index in 0..ButtonType::COUNT {
+        let button = ButtonType::from_usize(index).unwrap();
+        let placeholder = format!("{{{:?}}}", button);
+        if text.contains(&placeholder) {
+            text = text.replace(&placeholder, &prompt(button));
+        }
+    }
+    text
+}
+
+/// Set the names shown for buttons by `tr_with_prompts()`, see `Settings::button_prompt()`
+pub fn set_button_prompts<F: Fn(ButtonType) -> String>(prompt: F) {
+    let prompts = (0..ButtonType::COUNT)
+        .map(|index| prompt(ButtonType::from_usize(index).unwrap()))
+        .collect();
+    *BUTTON_PROMPTS.write().expect("Poisoned button prompts lock") = prompts;
+}
+
+/// Like `tr()`, but button names in braces are replaced by the prompts set with
+/// `set_button_prompts()`. Buttons keep their names until prompts are set.
+pub fn tr_with_prompts(key: &str) -> String {
+    let prompts = BUTTON_PROMPTS.read().expect("Poisoned button prompts lock");
+    replace_button_prompts(&tr(key), |button| match prompts.get(button as usize) {
+        Some(prompt) => prompt.clone(),
+        None => format!("{button:?}"),
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LocalizationLoadError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid data: {0}")]
+    InvalidData(#[from] serde_json::Error),
+}
@@ -1,20 +1,5 @@
-mod game;
-mod imgui_helpers;
-mod imgui_sdl2_support;
-mod input;
-mod level;
-mod math;
-mod objects;
-mod player;
-mod rendering;
-mod tilemap;
-mod window;
-mod audio;
-
 use std::error::Error;
 
-use window::Window;
-
 fn main() -> Result<(), Box<dyn Error>> {
     #[cfg(debug_assertions)]
     env_logger::builder()
@@ -24,7 +9,5 @@ fn main() -> Result<(), Box<dyn Error>> {
     #[cfg(not(debug_assertions))]
     env_logger::init();
 
-    let mut window = Window::new()?;
-    window.run_main_loop()?;
-    Ok(())
+    complementary::run()
 }
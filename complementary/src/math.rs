@@ -1,15 +1,17 @@
 use std::ops::{Mul, MulAssign};
 
 pub use cgmath::*;
-use serde::Deserialize;
+use complementary_macros::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 pub type FVec2 = Vector2<f32>;
 pub type FVec3 = Vector3<f32>;
+pub type FVec4 = Vector4<f32>;
 pub type IVec2 = Vector2<i32>;
 pub type IVec3 = Vector3<i32>;
 pub type FMat4 = Matrix4<f32>;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Bounds {
     pub min: FVec2,
     pub max: FVec2,
@@ -20,13 +22,57 @@ impl Bounds {
         Self { min, max }
     }
 
+    pub fn center(&self) -> FVec2 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn contains_point(&self, point: FVec2) -> bool {
+        point.x >= self.min.x && point.x < self.max.x && point.y >= self.min.y && point.y < self.max.y
+    }
+
+    /// The closest point to `point` inside of the bounds. Unlike `contains_point()`, the edges
+    /// count as inside.
+    pub fn clamp_point(&self, point: FVec2) -> FVec2 {
+        FVec2::new(
+            point.x.clamp(self.min.x, self.max.x),
+            point.y.clamp(self.min.y, self.max.y),
+        )
+    }
+
+    /// Distance from `point` to the bounds, zero if it's inside or on an edge
+    pub fn distance_to(&self, point: FVec2) -> f32 {
+        (point - self.clamp_point(point)).magnitude()
+    }
+
     pub fn overlaps(&self, other: &Bounds) -> bool {
         return self.min.x < other.max.x && self.max.x > other.min.x &&
            self.min.y < other.max.y && self.max.y > other.min.y
     }
+
+    /// How far the bounds can move in `direction` before they touch `other`, or `None` if they
+    /// would pass it. Bounds that already overlap `other` can't move at all.
+    pub fn sweep_distance(&self, direction: Direction, other: &Bounds) -> Option<f32> {
+        let (in_path, distance) = match direction {
+            Direction::Left => (self.min.y < other.max.y && self.max.y > other.min.y, self.min.x - other.max.x),
+            Direction::Right => (self.min.y < other.max.y && self.max.y > other.min.y, other.min.x - self.max.x),
+            Direction::Up => (self.min.x < other.max.x && self.max.x > other.min.x, self.min.y - other.max.y),
+            Direction::Down => (self.min.x < other.max.x && self.max.x > other.min.x, other.min.y - self.max.y),
+        };
+        if !in_path {
+            None
+        } else if self.overlaps(other) {
+            Some(0.0)
+        } else {
+            (distance >= 0.0).then_some(distance)
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Deserialize)]
+/// RGBA color with components from 0 to 1. Colors are in sRGB, the way they're authored, and
+/// the scene is drawn and blended in sRGB too. They're only converted to linear when writing to
+/// an sRGB surface, see `PostProcessing`. Use `from_linear()` for colors computed in linear
+/// space, e.g. from lighting. Alpha is always linear.
+#[derive(Debug, Clone, Copy, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Deserialize, JsonSchema)]
 #[repr(C)]
 pub struct Color {
     pub r: f32,
@@ -45,7 +91,7 @@ impl Color {
     pub const PINK: Color = Color::new_solid(1.0, 0.69, 0.69);
     pub const ORANGE: Color = Color::new_solid(1.0, 0.79, 0.0);
     pub const YELLOW: Color = Color::new_solid(1.0, 1.0, 0.0);
-    pub const GREEN: Color = Color::new_solid(0.0, 1.0, 1.0);
+    pub const GREEN: Color = Color::new_solid(0.0, 1.0, 0.0);
     pub const MAGENTA: Color = Color::new_solid(1.0, 0.0, 1.0);
     pub const CYAN: Color = Color::new_solid(0.0, 1.0, 1.0);
     pub const BLUE: Color = Color::new_solid(0.0, 0.0, 1.0);
@@ -59,11 +105,49 @@ impl Color {
         Self { r, g, b, a: 1.0 }
     }
 
+    /// Same as `new()`, for code that mixes sRGB and linear colors
+    pub const fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::new(r, g, b, a)
+    }
+
+    pub fn from_linear(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::new(r, g, b, a).to_srgb()
+    }
+
+    /// Decode the sRGB components to linear ones, using the exact sRGB transfer function
+    pub fn to_linear(self) -> Self {
+        let decode = |c: f32| {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Self { r: decode(self.r), g: decode(self.g), b: decode(self.b), a: self.a }
+    }
+
+    /// Encode linear components to sRGB, the inverse of `to_linear()`
+    pub fn to_srgb(self) -> Self {
+        let encode = |c: f32| {
+            if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            }
+        };
+        Self { r: encode(self.r), g: encode(self.g), b: encode(self.b), a: self.a }
+    }
+
     pub fn with_alpha(self, a: f32) -> Self {
         Self { r: self.r, g: self.g, b: self.b, a }
     }
+
+    pub fn inverted(self) -> Self {
+        Self { r: 1.0 - self.r, g: 1.0 - self.g, b: 1.0 - self.b, a: self.a }
+    }
 }
 
+/// Color packed as `0xAABBGGRR`, like in the original game's data
 impl From<u32> for Color {
     fn from(val: u32) -> Self {
         let r = val & 0xFF;
@@ -94,7 +178,7 @@ impl MulAssign for Color {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
 pub enum Direction {
     Left,
     Right,
@@ -127,4 +211,63 @@ impl Direction {
             Direction::Down => Direction::Up,
         }
     }
+
+    /// Flip horizontally, keeping vertical directions
+    pub fn mirrored(self) -> Direction {
+        match self {
+            Direction::Left | Direction::Right => self.inverse(),
+            Direction::Up | Direction::Down => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Color, b: Color) {
+        let components = [(a.r, b.r), (a.g, b.g), (a.b, b.b), (a.a, b.a)];
+        assert!(
+            components.iter().all(|(a, b)| (a - b).abs() < 1e-5),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        for i in 0..=255 {
+            let value = i as f32 / 255.0;
+            let color = Color::from_srgb(value, 1.0 - value, 0.5, value);
+            assert_close(color.to_linear().to_srgb(), color);
+        }
+    }
+
+    #[test]
+    fn linear_round_trip() {
+        for i in 0..=255 {
+            let value = i as f32 / 255.0;
+            let (r, g, b, a) = (value, 1.0 - value, 0.5, value);
+            assert_close(Color::from_linear(r, g, b, a).to_linear(), Color::new(r, g, b, a));
+        }
+    }
+
+    #[test]
+    fn srgb_reference_values() {
+        // Black, white and alpha stay the same, mid gray is darker in linear space
+        assert_close(Color::BLACK.to_linear(), Color::BLACK);
+        assert_close(Color::WHITE.to_linear(), Color::WHITE);
+        assert_close(Color::new(0.5, 0.5, 0.5, 0.5).to_linear(), Color::new(0.21404, 0.21404, 0.21404, 0.5));
+        assert_close(Color::from_linear(0.5, 0.5, 0.5, 1.0), Color::new_solid(0.735357, 0.735357, 0.735357));
+    }
+
+    #[test]
+    fn reads_colors_written_by_the_data_converter() {
+        let json = include_str!("../../complementary_data_converter/tests/color.json");
+        assert_eq!(serde_json::from_str::<Color>(json).unwrap(), Color::new(1.0, 0.5, 0.25, 0.75));
+    }
+
+    #[test]
+    fn packed_colors_are_normalized() {
+        assert_close(Color::from(0xFF00_80FF), Color::new(1.0, 128.0 / 255.0, 0.0, 1.0));
+    }
 }
@@ -0,0 +1,108 @@
+use crate::{
+    input::{ButtonType, Input},
+    localization,
+    ui_layout::{anchored_position, Anchor},
+};
+
+/// Entries of the pause menu, in the order they are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMenuItem {
+    Resume,
+    RestartLevel,
+    Quit,
+}
+
+impl PauseMenuItem {
+    const ALL: [Self; 3] = [
+        PauseMenuItem::Resume,
+        PauseMenuItem::RestartLevel,
+        PauseMenuItem::Quit,
+    ];
+
+    fn label(self) -> String {
+        localization::tr(match self {
+            PauseMenuItem::Resume => "pause.resume",
+            PauseMenuItem::RestartLevel => "pause.restart",
+            PauseMenuItem::Quit => "pause.quit",
+        })
+    }
+}
+
+/// Menu shown while the game is paused. It's navigated with the game's buttons, so it works
+/// with every input device that is mapped to them.
+#[derive(Debug)]
+pub struct PauseMenu {
+    items: Vec<PauseMenuItem>,
+    selected: usize,
+}
+
+impl PauseMenu {
+    /// Quitting isn't offered if `allow_quit` is false, e.g. in kiosk mode
+    pub fn new(allow_quit: bool) -> Self {
+        let items = PauseMenuItem::ALL
+            .into_iter()
+            .filter(|item| allow_quit || *item != PauseMenuItem::Quit)
+            .collect();
+        Self { items, selected: 0 }
+    }
+
+    /// Select the first entry, called when the menu is opened
+    pub fn reset(&mut self) {
+        self.selected = 0;
+    }
+
+    /// Move the selection and return the entry that was confirmed, if any
+    pub fn tick(&mut self, input: &Input) -> Option<PauseMenuItem> {
+        if input.get_button(ButtonType::Up).pressed_first_frame() {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+        if input.get_button(ButtonType::Down).pressed_first_frame() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+        input
+            .get_button(ButtonType::Confirm)
+            .pressed_first_frame()
+            .then(|| self.items[self.selected])
+    }
+
+    pub fn draw_gui(&self, gui: &imgui::Ui) {
+        let display_size = gui.io().display_size;
+        let draw_list = gui.get_background_draw_list();
+
+        let title = localization::tr("pause.title");
+        let lines: Vec<_> = self
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                if index == self.selected {
+                    format!("> {} <", item.label())
+                } else {
+                    item.label()
+                }
+            })
+            .collect();
+
+        let line_height = gui.calc_text_size(&title)[1] * 2.0;
+        let block_size = [0.0, line_height * (lines.len() + 2) as f32];
+        let mut y = anchored_position(Anchor::CENTER, block_size, [0.0, 0.0], display_size)[1];
+        for (index, line) in std::iter::once(&title).chain(lines.iter()).enumerate() {
+            let [x, _] = anchored_position(Anchor::CENTER, gui.calc_text_size(line), [0.0, 0.0], display_size);
+            // The title and the selected entry are highlighted
+            let highlighted = index == 0 || index - 1 == self.selected;
+            let color = if highlighted {
+                [1.0, 1.0, 1.0, 1.0]
+            } else {
+                [0.7, 0.7, 0.7, 1.0]
+            };
+            draw_list.add_text([x, y], color, line);
+            // Leave some space between the title and the entries
+            y += if index == 0 { line_height * 2.0 } else { line_height };
+        }
+
+        // Explain the controls below the entries, with the player's button prompts
+        let hint = localization::tr_with_prompts("pause.hint");
+        let [x, _] = anchored_position(Anchor::CENTER, gui.calc_text_size(&hint), [0.0, 0.0], display_size);
+        draw_list.add_text([x, y + line_height], [0.7, 0.7, 0.7, 1.0], hint);
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::WorldType,
+    input::Input,
+    level::Level,
+    math::Color,
+    player::{Ability, Player},
+};
+
+/// Every directory in here with a `mod.json` manifest is a mod
+pub const MODS_PATH: &str = "mods";
+const MANIFEST_FILE_NAME: &str = "mod.json";
+const LOAD_ORDER_FILE_NAME: &str = "load_order.json";
+const PALETTES_DIRECTORY: &str = "palettes";
+
+lazy_static::lazy_static! {
+    /// Palette chosen in the mod list, see `ability_color()`
+    static ref PALETTE: RwLock<Palette> = RwLock::new(Palette::default());
+}
+
+/// Contents of `mods/<id>/mod.json`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModManifest {
+    pub name: String,
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Levels in `mods/<id>/maps`, played after the main levels in this order
+    #[serde(default)]
+    pub levels: Vec<String>,
+    /// Palettes in `mods/<id>/palettes/<name>.json` that can be chosen in the mod list
+    #[serde(default)]
+    pub palettes: Vec<String>,
+}
+
+/// Colors that replace the default ones while the palette is active. Colors that aren't
+/// listed keep their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    pub abilities: HashMap<Ability, Color>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mod {
+    /// Name of the mod's directory
+    pub id: String,
+    pub manifest: ModManifest,
+    pub enabled: bool,
+}
+
+/// Contents of `mods/load_order.json`. Mods that aren't listed are loaded last, sorted by id.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct LoadOrder {
+    order: Vec<String>,
+    disabled: Vec<String>,
+    /// Chosen palette, see `palette_name()`
+    palette: Option<String>,
+}
+
+/// Game state that `TickHook`s can read and modify
+pub struct ModTickState<'a> {
+    pub input: &'a Input,
+    pub player: &'a mut Player,
+    pub level: &'a mut Level,
+    pub world_type: WorldType,
+    pub tick_count: u64,
+}
+
+/// Code that runs once per simulated tick, registered for a mod with `complementary::run_with()`
+/// or `Game::register_tick_hook()`. Hooks run in the load order of their mods after the player
+/// and objects were updated, and are skipped while their mod is disabled.
+pub trait TickHook {
+    fn tick(&mut self, state: &mut ModTickState);
+}
+
+/// Installed mods in load order
+#[derive(Default)]
+pub struct ModList {
+    mods: Vec<Mod>,
+    /// Hooks with the id of the mod they were registered for
+    tick_hooks: Vec<(String, Box<dyn TickHook>)>,
+    palette: Option<String>,
+    /// Set when the load order was changed since the game started
+    changed: bool,
+}
+
+impl ModList {
+    /// Find all mods in `MODS_PATH`. Mods with invalid manifests are skipped.
+    pub fn discover() -> Self {
+        let mut mods = match ModList::load_manifests(Path::new(MODS_PATH)) {
+            Ok(mods) => mods,
+            Err(ModLoadError::Io(err)) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                warn!("Failed to load mods: {err}");
+                Vec::new()
+            }
+        };
+
+        let load_order = match LoadOrder::load(&load_order_path()) {
+            Ok(load_order) => load_order,
+            Err(ModLoadError::Io(err)) if err.kind() == io::ErrorKind::NotFound => LoadOrder::default(),
+            Err(err) => {
+                warn!("Failed to load mod load order, using the default: {err}");
+                LoadOrder::default()
+            }
+        };
+
+        mods.sort_by(|a, b| a.id.cmp(&b.id));
+        mods.sort_by_key(|m| load_order.order.iter().position(|id| *id == m.id).unwrap_or(usize::MAX));
+        for m in &mut mods {
+            m.enabled = !load_order.disabled.contains(&m.id);
+        }
+
+        if !mods.is_empty() {
+            info!("Found {} mods", mods.len());
+        }
+        let mut mod_list = ModList {
+            mods,
+            ..Default::default()
+        };
+        if let Some(palette) = load_order.palette {
+            mod_list.set_palette(Some(palette));
+        }
+        mod_list
+    }
+
+    fn load_manifests(path: &Path) -> Result<Vec<Mod>, ModLoadError> {
+        let mut mods = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let id = match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            match ModManifest::load(&path.join(MANIFEST_FILE_NAME)) {
+                Ok(manifest) => mods.push(Mod { id, manifest, enabled: true }),
+                Err(err) => warn!("Skipping mod \"{id}\": {err}"),
+            }
+        }
+        Ok(mods)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_mods(mods: Vec<Mod>) -> Self {
+        ModList {
+            mods,
+            ..Default::default()
+        }
+    }
+
+    pub fn mods(&self) -> &[Mod] {
+        &self.mods
+    }
+
+    /// Names of the levels added by enabled mods, in load order, see `level_name()`
+    pub fn levels(&self) -> Vec<String> {
+        self.mods
+            .iter()
+            .filter(|m| m.enabled)
+            .flat_map(|m| m.manifest.levels.iter().map(|level| level_name(&m.id, level)))
+            .collect()
+    }
+
+    /// Names of the palettes added by enabled mods, in load order, see `palette_name()`
+    pub fn palettes(&self) -> Vec<String> {
+        self.mods
+            .iter()
+            .filter(|m| m.enabled)
+            .flat_map(|m| m.manifest.palettes.iter().map(|palette| palette_name(&m.id, palette)))
+            .collect()
+    }
+
+    /// Switch to a palette from `palettes()`, or back to the default colors with `None`.
+    /// Palettes that fail to load are skipped with a warning.
+    pub fn set_palette(&mut self, name: Option<String>) {
+        let palette = match name.as_deref().map(Palette::load_by_name) {
+            Some(Ok(palette)) => palette,
+            Some(Err(err)) => {
+                warn!("Failed to load palette \"{}\": {err}", name.unwrap_or_default());
+                return;
+            }
+            None => Palette::default(),
+        };
+        *PALETTE.write().unwrap() = palette;
+        self.palette = name;
+    }
+
+    /// Let `hook` run once per tick while the mod `mod_id` is enabled
+    pub fn register_tick_hook(&mut self, mod_id: &str, hook: Box<dyn TickHook>) -> Result<(), ModLoadError> {
+        if !self.mods.iter().any(|m| m.id == mod_id) {
+            return Err(ModLoadError::UnknownMod(mod_id.to_owned()));
+        }
+        self.tick_hooks.push((mod_id.to_owned(), hook));
+        Ok(())
+    }
+
+    pub fn run_tick_hooks(&mut self, state: &mut ModTickState) {
+        for m in self.mods.iter().filter(|m| m.enabled) {
+            for (_, hook) in self.tick_hooks.iter_mut().filter(|(id, _)| *id == m.id) {
+                hook.tick(state);
+            }
+        }
+    }
+
+    pub fn save_load_order(&self) -> Result<(), ModLoadError> {
+        let load_order = LoadOrder {
+            order: self.mods.iter().map(|m| m.id.clone()).collect(),
+            disabled: self.mods.iter().filter(|m| !m.enabled).map(|m| m.id.clone()).collect(),
+            palette: self.palette.clone(),
+        };
+        fs::write(load_order_path(), serde_json::to_string_pretty(&load_order)?)?;
+        Ok(())
+    }
+
+    /// Mod list screen with controls for enabling and reordering mods. Changes to the load order
+    /// only take effect after a restart, since levels from mods might already be part of the
+    /// playlist. Palettes are switched and saved immediately.
+    pub fn draw_gui(&mut self, gui: &imgui::Ui) {
+        if self.mods.is_empty() {
+            gui.text(format!("No mods installed, see {MODS_PATH}/"));
+            return;
+        }
+
+        let palettes = self.palettes();
+        if !palettes.is_empty() {
+            let current = self.palette.as_deref().unwrap_or("Default");
+            if let Some(_combo) = gui.begin_combo("Palette", current) {
+                let mut selected = None;
+                if imgui::Selectable::new("Default").build(gui) {
+                    selected = Some(None);
+                }
+                for palette in palettes {
+                    if imgui::Selectable::new(&palette).build(gui) {
+                        selected = Some(Some(palette));
+                    }
+                }
+                if let Some(palette) = selected {
+                    self.set_palette(palette);
+                    if let Err(err) = self.save_load_order() {
+                        warn!("Failed to save mod load order: {err}");
+                    }
+                }
+            }
+        }
+
+        let mut swap = None;
+        let count = self.mods.len();
+        for (index, m) in self.mods.iter_mut().enumerate() {
+            let _id = gui.push_id(m.id.as_str());
+            if gui.checkbox(&m.manifest.name, &mut m.enabled) {
+                self.changed = true;
+            }
+            gui.same_line();
+            if gui.small_button("Up") && index > 0 {
+                swap = Some(index - 1);
+            }
+            gui.same_line();
+            if gui.small_button("Down") && index + 1 < count {
+                swap = Some(index);
+            }
+
+            gui.indent();
+            gui.text_disabled(format!(
+                "{} by {}, {} levels, {} palettes",
+                m.manifest.version.as_deref().unwrap_or("unversioned"),
+                m.manifest.author.as_deref().unwrap_or("unknown author"),
+                m.manifest.levels.len(),
+                m.manifest.palettes.len()
+            ));
+            if let Some(description) = &m.manifest.description {
+                gui.text_wrapped(description);
+            }
+            gui.unindent();
+        }
+        if let Some(index) = swap {
+            self.mods.swap(index, index + 1);
+            self.changed = true;
+        }
+
+        if self.changed {
+            gui.text("Restart the game to apply the changes");
+            if gui.button("Save load order") {
+                match self.save_load_order() {
+                    Ok(()) => self.changed = false,
+                    Err(err) => warn!("Failed to save mod load order: {err}"),
+                }
+            }
+        }
+    }
+}
+
+impl ModManifest {
+    pub fn load(path: &Path) -> Result<Self, ModLoadError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+impl Palette {
+    /// Load a palette from `mods/<id>/palettes/<name>.json`, see `palette_name()`
+    pub fn load_by_name(name: &str) -> Result<Self, ModLoadError> {
+        let (mod_id, palette) = name
+            .split_once(':')
+            .ok_or_else(|| ModLoadError::UnknownPalette(name.to_owned()))?;
+        let path = Path::new(MODS_PATH)
+            .join(mod_id)
+            .join(PALETTES_DIRECTORY)
+            .join(format!("{palette}.json"));
+        Palette::load(&path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ModLoadError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+impl LoadOrder {
+    fn load(path: &Path) -> Result<Self, ModLoadError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+fn load_order_path() -> PathBuf {
+    Path::new(MODS_PATH).join(LOAD_ORDER_FILE_NAME)
+}
+
+/// Levels from mods are named `<mod id>:<level>`, so that they can't collide with the main
+/// levels or levels from other mods
+pub fn level_name(mod_id: &str, level: &str) -> String {
+    format!("{mod_id}:{level}")
+}
+
+/// Palettes from mods are named like their levels, `<mod id>:<palette>`
+pub fn palette_name(mod_id: &str, palette: &str) -> String {
+    format!("{mod_id}:{palette}")
+}
+
+/// Color of `ability` in the chosen palette, if the palette replaces it
+pub fn ability_color(ability: Ability) -> Option<Color> {
+    PALETTE.read().unwrap().abilities.get(&ability).copied()
+}
+
+/// Split a level name from `level_name()` into the mod id and the level's name in the mod
+pub fn split_level_name(name: &str) -> Option<(&str, &str)> {
+    name.split_once(':')
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ModLoadError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid data: {0}")]
+    InvalidData(#[from] serde_json::Error),
+    #[error("unknown mod \"{0}\"")]
+    UnknownMod(String),
+    #[error("unknown palette \"{0}\"")]
+    UnknownPalette(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_mod;
+
+    struct NoopHook;
+
+    impl TickHook for NoopHook {
+        fn tick(&mut self, _state: &mut ModTickState) {}
+    }
+
+    #[test]
+    fn tick_hooks_can_only_be_registered_for_installed_mods() {
+        let mut mods = ModList::with_mods(vec![test_mod("enabled", true), test_mod("disabled", false)]);
+        assert!(mods.register_tick_hook("enabled", Box::new(NoopHook)).is_ok());
+        assert!(mods.register_tick_hook("disabled", Box::new(NoopHook)).is_ok());
+        assert!(matches!(
+            mods.register_tick_hook("missing", Box::new(NoopHook)),
+            Err(ModLoadError::UnknownMod(_))
+        ));
+        assert_eq!(mods.tick_hooks.len(), 2);
+    }
+}
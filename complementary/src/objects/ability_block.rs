@@ -1,18 +1,20 @@
 use bytemuck::Zeroable;
+use complementary_macros::{JsonSchema, WgslStruct};
 use serde::Deserialize;
-use wgpu::{include_wgsl, vertex_attr_array};
+use wgpu::vertex_attr_array;
 
 use crate::{
     game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
     math::{FVec2, FMat4, Color, Direction},
-    player::{AbilityPair, Player},
-    rendering::{DrawState, UniformBuffer, SQUARE_VERTICES, create_vertex_buffer, create_pipeline_descriptor, Vertex, create_instance_buffer},
-    window::DrawContext, level::LevelState,
+    player::{self, AbilityPair, Player},
+    rendering::{DrawState, UniformBuffer, SQUARE_VERTICES, create_vertex_buffer, color_targets, create_pipeline_descriptor, Vertex, create_instance_buffer, SpriteRenderer},
+    window::DrawContext, level::LevelState, shader,
 };
 
-use super::{Object, Tickable, PositionalWithSize, Collidable};
+use super::{Object, Tickable, PositionalWithSize, Collidable, Mirror};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct AbilityBlockData {
     size: FVec2,
     abilities: AbilityPair,
@@ -24,6 +26,14 @@ impl AbilityBlockObject {
     pub fn new(position: FVec2, data: AbilityBlockData) -> Self {
         Self { position, data, state: () }
     }
+
+    pub fn abilities(&self) -> AbilityPair {
+        self.data.abilities
+    }
+
+    pub fn set_abilities(&mut self, abilities: AbilityPair) {
+        self.data.abilities = abilities;
+    }
 }
 
 impl Tickable for AbilityBlockObject {
@@ -37,6 +47,12 @@ impl PositionalWithSize for AbilityBlockObject {
     }
 }
 
+impl Mirror for AbilityBlockObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+    }
+}
+
 impl Collidable for AbilityBlockObject {
     fn on_directional_collision(&mut self, player: &mut Player, _level_state: &mut LevelState, _direction: Direction) {
         player.set_abilities(self.data.abilities)
@@ -45,14 +61,16 @@ impl Collidable for AbilityBlockObject {
 
 pub struct AbilityBlockRenderer {
     uniform_buffer: UniformBuffer<DrawState>,
-    vertex_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    vertex_buffer: TrackedBuffer,
+    instance_buffer: TrackedBuffer,
     render_pipeline: wgpu::RenderPipeline,
+    icon_renderer: Option<SpriteRenderer>,
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct AbilityBlockInstance {
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+pub(crate) struct AbilityBlockInstance {
     color: Color,
     position: FVec2,
     size: FVec2,
@@ -73,7 +91,7 @@ impl AbilityBlockInstance {
 }
 
 impl AbilityBlockRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         let uniform_buffer = UniformBuffer::new(device, "ability_block_uniforms");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -89,12 +107,15 @@ impl AbilityBlockRenderer {
 
         let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
             Some("ability_block_pipeline"),
-            &device.create_shader_module(&include_wgsl!("../shaders/ability_block.wgsl")),
+            &shader::create_shader_module(device, "ability_block.wgsl", &[]),
             Some(&pipeline_layout),
             &[Vertex::layout(), AbilityBlockInstance::layout()],
+            &color_targets(format),
         ));
 
-        Self { uniform_buffer, vertex_buffer, instance_buffer, render_pipeline }
+        let icon_renderer = player::ability_icon_renderer(device, format);
+
+        Self { uniform_buffer, vertex_buffer, instance_buffer, render_pipeline, icon_renderer }
     }
 
     pub fn draw(
@@ -105,7 +126,7 @@ impl AbilityBlockRenderer {
         world_type: WorldType,
     ) {
         let instances: Vec<_> = objects.iter().map(|obj| AbilityBlockInstance {
-            color: obj.data.abilities.current(world_type).color(),
+            color: world_type.uninverted_color(obj.data.abilities.current(world_type).color()),
             position: obj.position,
             size: obj.data.size,
         }).collect();
@@ -114,24 +135,35 @@ impl AbilityBlockRenderer {
             .write_with_queue(context.queue, state.clone());
         context.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
 
-        let mut rpass = context
-            .encoder
-            .begin_render_pass(&wgpu::RenderPassDescriptor {
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &context.output,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-                label: Some("ability_block_rpass"),
-            });
-        rpass.set_pipeline(&self.render_pipeline);
-        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
-        rpass.draw(0..6, 0..instances.len() as u32);
+        {
+            let mut rpass = context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &context.output,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                    label: Some("ability_block_rpass"),
+                });
+            rpass.set_pipeline(&self.render_pipeline);
+            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+            rpass.draw(0..6, 0..instances.len() as u32);
+        }
+
+        if let Some(icon_renderer) = &mut self.icon_renderer {
+            for (obj, instance) in objects.iter().zip(&instances) {
+                if let Some(icon) = obj.data.abilities.current(world_type).icon() {
+                    icon_renderer.queue_sprite(icon, player::icon_bounds(obj.bounds()), instance.color);
+                }
+            }
+            icon_renderer.flush(context, state);
+        }
     }
 }
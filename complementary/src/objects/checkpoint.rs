@@ -0,0 +1,79 @@
+use log::debug;
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    game::{ObjectTickState, WorldType},
+    localization,
+    math::FVec2,
+    rendering::DrawState,
+    toast,
+    window::DrawContext,
+};
+
+use super::{Mirror, Object, PositionalWithSize, Tickable};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CheckpointData {
+    /// Checkpoints are activated in increasing order of their ids. Multiple checkpoints can
+    /// share an id, the one nearest to the player is used when respawning.
+    id: i32,
+    size: FVec2,
+}
+
+pub type CheckpointObject = Object<CheckpointData, ()>;
+
+impl CheckpointObject {
+    pub fn new(position: FVec2, data: CheckpointData) -> Self {
+        Self { position, data, state: () }
+    }
+
+    pub fn id(&self) -> i32 {
+        self.data.id
+    }
+
+    /// The player respawns at the bottom center of the checkpoint
+    pub fn spawn_position(&self) -> FVec2 {
+        self.position + FVec2::new((self.data.size.x - 1.0) / 2.0, self.data.size.y - 1.0)
+    }
+}
+
+impl PositionalWithSize for CheckpointObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for CheckpointObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+    }
+}
+
+impl Tickable for CheckpointObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        if self.bounds().overlaps(&state.player.bounds()) && state.level_state.activate_checkpoint(self.data.id) {
+            debug!("Reached checkpoint {}", self.data.id);
+            toast::show(localization::tr("toast.checkpoint"));
+        }
+    }
+}
+
+/// Checkpoints are invisible
+#[derive(Debug)]
+pub struct CheckpointRenderer {}
+
+impl CheckpointRenderer {
+    pub fn new(_device: &wgpu::Device, _format: wgpu::TextureFormat) -> Self {
+        Self {}
+    }
+
+    pub fn draw(
+        &mut self,
+        _objects: &Vec<CheckpointObject>,
+        _context: &mut DrawContext,
+        _state: &DrawState,
+        _world_type: WorldType,
+    ) {
+    }
+}
@@ -1,36 +1,75 @@
+use complementary_macros::{JsonSchema, WgslStruct};
 use serde::Deserialize;
-use wgpu::{vertex_attr_array, include_wgsl};
+use wgpu::vertex_attr_array;
 
 use crate::{
+    audio::{self, SoundId},
     game::{ObjectTickState, WorldType},
-    rendering::{DrawState, UniformBuffer, SQUARE_VERTICES, create_vertex_buffer, create_instance_buffer, create_pipeline_descriptor, Vertex},
-    window::DrawContext, math::{Color, FVec2, Bounds}, player::CollisionType,
+    gpu_memory::TrackedBuffer,
+    rendering::{DrawState, TransparentInstance, sort_for_blending, UniformBuffer, SQUARE_VERTICES, create_vertex_buffer, create_instance_buffer, color_targets, create_pipeline_descriptor, Vertex},
+    window::DrawContext, math::{Color, FVec2, Bounds}, player::CollisionType, shader,
 };
 
-use super::{Object, Tickable, PositionalWithSize, Collidable};
+use super::{Object, Tickable, PositionalWithSize, Collidable, Mirror, particle_system::EmitterGeometry};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct DoorData {
     size: FVec2,
     group: i32,
+    /// The door only opens while this group of `SwitchObject`s is on, in addition to the keys
+    #[serde(default)]
+    switch_group: Option<i32>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DoorState {
-    key_collected_percentage: f32
+    key_collected_percentage: f32,
+    /// Always true for doors without a `switch_group`
+    switch_on: bool,
 }
 
 pub type DoorObject = Object<DoorData, DoorState>;
 
 impl DoorObject {
     pub fn new(position: FVec2, data: DoorData) -> Self {
-        Self { position, data, state: DoorState { key_collected_percentage: 0.0 } }
+        Self { position, data, state: DoorState { key_collected_percentage: 0.0, switch_on: false } }
+    }
+
+    pub fn group(&self) -> i32 {
+        self.data.group
+    }
+
+    /// Fraction of the group's keys that were collected, updated every tick
+    pub fn key_collected_percentage(&self) -> f32 {
+        self.state.key_collected_percentage
+    }
+
+    pub fn open(&self) -> bool {
+        self.state.key_collected_percentage >= 1.0 && self.state.switch_on
+    }
+
+    /// How far the door faded out, the key percentage unless its switch is off
+    fn openness(&self) -> f32 {
+        if self.state.switch_on {
+            self.state.key_collected_percentage
+        } else {
+            0.0
+        }
+    }
+
+    pub fn emitter_geometry(&self) -> EmitterGeometry {
+        EmitterGeometry { size: self.data.size, ..Default::default() }
     }
 }
 
 impl Tickable for DoorObject {
     fn tick(&mut self, state: &mut ObjectTickState) {
+        let was_open = self.open();
         self.state.key_collected_percentage = state.level_state.key_collected_percentage(self.data.group);
+        self.state.switch_on = self.data.switch_group.map_or(true, |group| state.level_state.switch_on(group));
+        if !was_open && self.open() {
+            audio::play_sound(SoundId::DoorOpen);
+        }
     }
 }
 
@@ -40,9 +79,15 @@ impl PositionalWithSize for DoorObject {
     }
 }
 
+impl Mirror for DoorObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+    }
+}
+
 impl Collidable for DoorObject {
     fn collides_with(&self, other: &Bounds, _world_type: WorldType) -> Option<CollisionType> {
-        if self.state.key_collected_percentage < 1.0 {
+        if !self.open() {
 		    self.bounds().overlaps(other).then_some(CollisionType::Solid)
         } else {
             None
@@ -52,14 +97,15 @@ impl Collidable for DoorObject {
 
 pub struct DoorRenderer {
     uniform_buffer: UniformBuffer<DrawState>,
-    vertex_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    vertex_buffer: TrackedBuffer,
+    instance_buffer: TrackedBuffer,
     render_pipeline: wgpu::RenderPipeline,
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct DoorInstance {
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+pub(crate) struct DoorInstance {
     color: Color,
     position: FVec2,
     size: FVec2,
@@ -79,8 +125,18 @@ impl DoorInstance {
     }
 }
 
+impl TransparentInstance for DoorInstance {
+    fn color(&self) -> Color {
+        self.color
+    }
+
+    fn position(&self) -> FVec2 {
+        self.position
+    }
+}
+
 impl DoorRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         let uniform_buffer = UniformBuffer::new(device, "door_uniforms");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -96,9 +152,10 @@ impl DoorRenderer {
 
         let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
             Some("door_pipeline"),
-            &device.create_shader_module(&include_wgsl!("../shaders/door.wgsl")),
+            &shader::create_shader_module(device, "door.wgsl", &[]),
             Some(&pipeline_layout),
             &[Vertex::layout(), DoorInstance::layout()],
+            &color_targets(format),
         ));
 
         Self { uniform_buffer, vertex_buffer, instance_buffer, render_pipeline }
@@ -109,16 +166,14 @@ impl DoorRenderer {
         objects: &Vec<DoorObject>,
         context: &mut DrawContext,
         state: &DrawState,
-        world_type: WorldType,
+        _world_type: WorldType,
     ) {
-        let instances: Vec<_> = objects.iter().map(|obj| DoorInstance {
-            color: match world_type {
-                WorldType::Light => Color::DARK_GRAY,
-                WorldType::Dark => Color::LIGHT_GRAY,
-            }.with_alpha(1.0 - obj.state.key_collected_percentage),
+        let mut instances: Vec<_> = objects.iter().map(|obj| DoorInstance {
+            color: Color::DARK_GRAY.with_alpha(1.0 - obj.openness()),
             position: obj.position,
             size: obj.data.size,
         }).collect();
+        sort_for_blending(&mut instances);
 
         self.uniform_buffer
             .write_with_queue(context.queue, state.clone());
@@ -0,0 +1,263 @@
+use cgmath::InnerSpace;
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
+    level::LevelState,
+    math::{Bounds, Color, Direction, FVec2},
+    player::{CollisionType, Player},
+    rendering::{color_targets, create_instance_buffer, create_pipeline_descriptor, ColoredVertex, DrawState, UniformBuffer},
+    shader,
+    tilemap::{Tilemap, TilemapRenderer},
+    window::DrawContext,
+};
+
+use super::{Collidable, Mirror, Object, PositionalWithSize, Tickable};
+
+/// How an enemy moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+pub enum Patrol {
+    /// Back and forth between the start position and `EnemyData::goal`
+    Points,
+    /// Walks along the ground it stands on and turns around at walls and edges
+    Ground,
+}
+
+impl Default for Patrol {
+    fn default() -> Self {
+        Patrol::Points
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct EnemyData {
+    size: FVec2,
+    /// Relative to the start position, only used by `Patrol::Points`
+    #[serde(default = "EnemyData::default_goal")]
+    goal: FVec2,
+    speed: f32,
+    #[serde(default)]
+    patrol: Patrol,
+    /// The enemy only exists in this world, or in both if `None`
+    #[serde(default)]
+    world_type: Option<WorldType>,
+}
+
+impl EnemyData {
+    fn default_goal() -> FVec2 {
+        FVec2::new(0.0, 0.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct EnemyState {
+    /// Point the enemy moves towards with `Patrol::Points`
+    current_goal: FVec2,
+    next_goal: FVec2,
+    /// `Direction::Left` or `Direction::Right`, the way the enemy walks or looks
+    facing: Direction,
+}
+
+pub type EnemyObject = Object<EnemyData, EnemyState>;
+
+impl EnemyState {
+    /// The enemy starts moving towards its goal, or to the right if it walks on the ground
+    fn new(position: FVec2, data: &EnemyData) -> Self {
+        let facing = if data.patrol == Patrol::Points && data.goal.x < 0.0 {
+            Direction::Left
+        } else {
+            Direction::Right
+        };
+        EnemyState { current_goal: position + data.goal, next_goal: position, facing }
+    }
+}
+
+impl EnemyObject {
+    pub fn new(position: FVec2, data: EnemyData) -> Self {
+        let state = EnemyState::new(position, &data);
+        Self { position, data, state }
+    }
+
+    pub fn exists_in(&self, world_type: WorldType) -> bool {
+        self.data.world_type.map_or(true, |ty| ty == world_type)
+    }
+
+    fn patrol_points(&mut self) {
+        let delta = self.state.current_goal - self.position;
+        if delta.magnitude() <= self.data.speed {
+            self.position = self.state.current_goal;
+            std::mem::swap(&mut self.state.current_goal, &mut self.state.next_goal);
+        } else {
+            self.position += delta.normalize() * self.data.speed;
+        }
+        if delta.x != 0.0 {
+            self.state.facing = if delta.x < 0.0 { Direction::Left } else { Direction::Right };
+        }
+    }
+
+    /// Walk in the facing direction unless a wall or an edge is in the way
+    fn patrol_ground(&mut self, tilemap: &Tilemap, world_type: WorldType) {
+        let step = self.state.facing.as_vec() * self.data.speed;
+        let next = Bounds::new(self.position + step, self.position + step + self.data.size);
+        // A thin strip below the leading edge, which has to rest on something
+        let leading_x = if self.state.facing == Direction::Left { next.min.x } else { next.max.x - 0.01 };
+        let below = Bounds::new(
+            FVec2::new(leading_x, next.max.y + 0.01),
+            FVec2::new(leading_x + 0.01, next.max.y + 0.02),
+        );
+        // Touching a tile's top edge already counts as overlapping it, so shrink the body
+        let body = Bounds::new(next.min, next.max - FVec2::new(0.0, 0.01));
+        if tilemap.overlaps_solid(body, world_type) || !tilemap.overlaps_solid(below, world_type) {
+            self.state.facing = self.state.facing.inverse();
+        } else {
+            self.position += step;
+        }
+    }
+}
+
+impl Tickable for EnemyObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        match self.data.patrol {
+            Patrol::Points => self.patrol_points(),
+            Patrol::Ground => {
+                let world_type = self.data.world_type.unwrap_or(state.world_type);
+                self.patrol_ground(state.tilemap, world_type);
+            }
+        }
+
+        // Moving into the player is handled here, the player moving into the enemy in
+        // `on_directional_collision()`
+        if self.exists_in(state.world_type)
+            && state.player.allowed_to_move()
+            && self.bounds().overlaps(&state.player.bounds())
+        {
+            state.player.kill();
+        }
+    }
+}
+
+impl PositionalWithSize for EnemyObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for EnemyObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+        // The goal is relative to the enemy's position
+        self.data.goal.x = -self.data.goal.x;
+        self.state = EnemyState::new(self.position, &self.data);
+    }
+}
+
+impl Collidable for EnemyObject {
+    fn collides_with(&self, other: &Bounds, world_type: WorldType) -> Option<CollisionType> {
+        if self.exists_in(world_type) {
+            self.bounds().overlaps(other).then_some(CollisionType::NonSolid)
+        } else {
+            None
+        }
+    }
+
+    fn on_directional_collision(&mut self, player: &mut Player, _level_state: &mut LevelState, _direction: Direction) {
+        player.kill();
+    }
+}
+
+pub struct EnemyRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl EnemyRenderer {
+    /// The body and two eyes with six vertices each per enemy
+    const MAX_VERTEX_COUNT: usize = 18 * 64;
+    const BODY_COLOR: Color = Color::new_solid(0.8, 0.15, 0.15);
+    const EYE_SIZE: f32 = 0.15;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "enemy_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("enemy_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffer = create_instance_buffer::<ColoredVertex>(device, Some("enemy_vertex_buffer"),
+            EnemyRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("enemy_pipeline"),
+            &shader::create_shader_module(device, "tilemap.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[ColoredVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, render_pipeline }
+    }
+
+    /// The body with two eyes on the side the enemy is facing
+    fn append_vertices(object: &EnemyObject, vertices: &mut Vec<ColoredVertex>, world_type: WorldType) {
+        let bounds = object.bounds();
+        TilemapRenderer::append_rectangle(vertices, bounds, world_type.uninverted_color(EnemyRenderer::BODY_COLOR));
+
+        let eye_size = FVec2::new(EnemyRenderer::EYE_SIZE, EnemyRenderer::EYE_SIZE);
+        let eye_y = bounds.min.y + object.data.size.y * 0.25;
+        let eye_xs = if object.state.facing == Direction::Left {
+            [bounds.min.x + 0.1, bounds.min.x + 0.35]
+        } else {
+            [bounds.max.x - 0.35 - eye_size.x, bounds.max.x - 0.1 - eye_size.x]
+        };
+        for eye_x in eye_xs {
+            let min = FVec2::new(eye_x, eye_y);
+            TilemapRenderer::append_rectangle(vertices, Bounds::new(min, min + eye_size), world_type.uninverted_color(Color::WHITE));
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<EnemyObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        let mut vertices = Vec::new();
+        // Enemies of the other world are hidden, like platforms
+        for object in objects.iter().filter(|object| object.exists_in(world_type)) {
+            EnemyRenderer::append_vertices(object, &mut vertices, world_type);
+        }
+        vertices.truncate(EnemyRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("enemy_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
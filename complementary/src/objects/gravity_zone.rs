@@ -0,0 +1,154 @@
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
+    math::{Color, Direction, FVec2},
+    rendering::{color_targets, create_instance_buffer, create_pipeline_descriptor, ColoredVertex, DrawState, UniformBuffer},
+    shader,
+    tilemap::TilemapRenderer,
+    window::DrawContext,
+};
+
+use super::{Mirror, Object, PositionalWithSize, Tickable};
+
+/// Area in which gravity pulls the player in `direction` instead of down
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct GravityZoneData {
+    size: FVec2,
+    direction: Direction,
+    /// Scales the strength of gravity, e.g. 0.5 for low gravity
+    #[serde(default = "GravityZoneData::default_multiplier")]
+    multiplier: f32,
+}
+
+impl GravityZoneData {
+    fn default_multiplier() -> f32 {
+        1.0
+    }
+}
+
+pub type GravityZoneObject = Object<GravityZoneData, ()>;
+
+impl GravityZoneObject {
+    pub fn new(position: FVec2, data: GravityZoneData) -> Self {
+        Self { position, data, state: () }
+    }
+
+    /// Direction and strength of gravity inside of the zone, relative to the normal gravity
+    pub fn gravity(&self) -> FVec2 {
+        self.data.direction.as_vec() * self.data.multiplier
+    }
+}
+
+impl PositionalWithSize for GravityZoneObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for GravityZoneObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+        self.data.direction = self.data.direction.mirrored();
+    }
+}
+
+impl Tickable for GravityZoneObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        let player = &mut state.player;
+        if player.allowed_to_move() && self.bounds().overlaps(&player.bounds()) {
+            player.set_gravity_zone(self.gravity());
+        }
+    }
+}
+
+pub struct GravityZoneRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl GravityZoneRenderer {
+    /// Six vertices per zone
+    const MAX_VERTEX_COUNT: usize = 6 * 256;
+    const COLOR: Color = Color::new(0.6, 0.4, 0.9, 0.35);
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "gravity_zone_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("gravity_zone_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffer = create_instance_buffer::<ColoredVertex>(device, Some("gravity_zone_vertex_buffer"),
+            GravityZoneRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("gravity_zone_pipeline"),
+            &shader::create_shader_module(device, "tilemap.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[ColoredVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, render_pipeline }
+    }
+
+    /// A gradient that gets stronger towards the side that gravity pulls to
+    fn append_vertices(object: &GravityZoneObject, vertices: &mut Vec<ColoredVertex>, world_type: WorldType) {
+        let strong = world_type.uninverted_color(GravityZoneRenderer::COLOR);
+        let weak = strong.with_alpha(strong.a * 0.25);
+        // Top left, top right, bottom left, bottom right
+        let colors = match object.data.direction {
+            Direction::Left => (strong, weak, strong, weak),
+            Direction::Right => (weak, strong, weak, strong),
+            Direction::Up => (strong, strong, weak, weak),
+            Direction::Down => (weak, weak, strong, strong),
+        };
+        TilemapRenderer::append_rectangle_individually_colored(vertices, object.bounds(), colors);
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<GravityZoneObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        let mut vertices = Vec::new();
+        for object in objects {
+            GravityZoneRenderer::append_vertices(object, &mut vertices, world_type);
+        }
+        vertices.truncate(GravityZoneRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("gravity_zone_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
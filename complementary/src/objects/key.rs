@@ -1,15 +1,19 @@
+use complementary_macros::{JsonSchema, WgslStruct};
 use serde::Deserialize;
-use wgpu::{vertex_attr_array, include_wgsl};
+use wgpu::vertex_attr_array;
 
 use crate::{
+    audio::{self, SoundId},
     game::{ObjectTickState, WorldType},
-    rendering::{DrawState, UniformBuffer, create_vertex_buffer, DIAMOND_VERTICES, create_instance_buffer, Vertex, create_pipeline_descriptor},
-    window::DrawContext, math::{Color, FVec2, Bounds, Direction}, player::{CollisionType, Player}, level::LevelState,
+    gpu_memory::TrackedBuffer,
+    rendering::{DrawState, TransparentInstance, sort_for_blending, UniformBuffer, create_vertex_buffer, DIAMOND_VERTICES, create_instance_buffer, Vertex, color_targets, create_pipeline_descriptor},
+    window::DrawContext, math::{Color, FVec2, Bounds, Direction}, player::{CollisionType, Player}, level::LevelState, shader,
+    timer::TickTimer,
 };
 
-use super::{Object, Tickable, PositionalWithSize, Collidable};
+use super::{Object, Tickable, PositionalWithSize, Collidable, Mirror, particle_system::EmitterGeometry};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct KeyData {
     group: i32
 }
@@ -17,12 +21,15 @@ pub struct KeyData {
 #[derive(Debug, Deserialize)]
 pub enum KeyState {
     Collectible,
-    Collected { ticks: i32 }
+    /// The key fades out while `fade` runs
+    Collected { fade: TickTimer }
 }
 
 pub type KeyObject = Object<KeyData, KeyState>;
 
 impl KeyObject {
+    const FADE_TICKS: i32 = 30;
+
     pub fn new(position: FVec2, data: KeyData) -> Self {
         Self { position, data, state: KeyState::Collectible }
     }
@@ -31,12 +38,35 @@ impl KeyObject {
         self.data.group
     }
 
-    fn alpha(&self) -> f32 {
-        const ALPHA_ANIM_TICKS: i32 = 30;
+    pub fn collected(&self) -> bool {
+        matches!(self.state, KeyState::Collected { .. })
+    }
+
+    /// Add the key to its group, unless it was already collected
+    pub fn collect(&mut self, level_state: &mut LevelState) {
+        if matches!(self.state, KeyState::Collectible) {
+            level_state.add_collected_key(self.group());
+            let mut fade = TickTimer::default();
+            fade.start(Self::FADE_TICKS);
+            self.state = KeyState::Collected { fade };
+            audio::play_sound(SoundId::KeyPickup);
+        }
+    }
+
+    /// Make the key collectible again. The group's count has to be cleared separately, see
+    /// `LevelState::clear_collected_keys()`.
+    pub fn reset(&mut self) {
+        self.state = KeyState::Collectible;
+    }
+
+    pub fn emitter_geometry(&self) -> EmitterGeometry {
+        EmitterGeometry { size: self.size(), ..Default::default() }
+    }
 
+    fn alpha(&self) -> f32 {
         match self.state {
             KeyState::Collectible => 1.0,
-            KeyState::Collected { ticks } => 1.0 - (ticks as f32 / ALPHA_ANIM_TICKS as f32),
+            KeyState::Collected { fade } => 1.0 - fade.progress(),
         }
     }
 }
@@ -44,9 +74,7 @@ impl KeyObject {
 impl Tickable for KeyObject {
     fn tick(&mut self, _state: &mut ObjectTickState) {
         match self.state {
-            KeyState::Collected { ref mut ticks } => {
-                *ticks += 1;
-            },
+            KeyState::Collected { ref mut fade } => fade.tick(),
             _ => ()
         }
     }
@@ -58,29 +86,33 @@ impl PositionalWithSize for KeyObject {
     }
 }
 
+impl Mirror for KeyObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.size().x, width);
+    }
+}
+
 impl Collidable for KeyObject {
     fn collides_with(&self, other: &Bounds, _world_type: WorldType) -> Option<CollisionType> {
         self.bounds().overlaps(other).then_some(CollisionType::NonSolid)
     }
 
     fn on_directional_collision(&mut self, _player: &mut Player, level_state: &mut LevelState, _direction: Direction) {
-        if matches!(self.state, KeyState::Collectible) {
-            level_state.add_collected_key(self.group());
-            self.state = KeyState::Collected { ticks: 0 }
-        }
+        self.collect(level_state);
     }
 }
 
 pub struct KeyRenderer {
     uniform_buffer: UniformBuffer<DrawState>,
-    vertex_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    vertex_buffer: TrackedBuffer,
+    instance_buffer: TrackedBuffer,
     render_pipeline: wgpu::RenderPipeline,
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct KeyInstance {
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+pub(crate) struct KeyInstance {
     color: Color,
     position: FVec2,
 }
@@ -99,8 +131,18 @@ impl KeyInstance {
     }
 }
 
+impl TransparentInstance for KeyInstance {
+    fn color(&self) -> Color {
+        self.color
+    }
+
+    fn position(&self) -> FVec2 {
+        self.position
+    }
+}
+
 impl KeyRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         let uniform_buffer = UniformBuffer::new(device, "key_uniforms");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -116,9 +158,10 @@ impl KeyRenderer {
 
         let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
             Some("key_pipeline"),
-            &device.create_shader_module(&include_wgsl!("../shaders/key.wgsl")),
+            &shader::create_shader_module(device, "key.wgsl", &[]),
             Some(&pipeline_layout),
             &[Vertex::layout(), KeyInstance::layout()],
+            &color_targets(format),
         ));
 
         Self { uniform_buffer, vertex_buffer, instance_buffer, render_pipeline }
@@ -129,15 +172,13 @@ impl KeyRenderer {
         objects: &Vec<KeyObject>,
         context: &mut DrawContext,
         state: &DrawState,
-        world_type: WorldType,
+        _world_type: WorldType,
     ) {
-        let instances: Vec<_> = objects.iter().map(|obj| KeyInstance {
-            color: match world_type {
-                WorldType::Light => Color::DARK_GRAY,
-                WorldType::Dark => Color::LIGHT_GRAY,
-            }.with_alpha(obj.alpha()),
+        let mut instances: Vec<_> = objects.iter().map(|obj| KeyInstance {
+            color: Color::DARK_GRAY.with_alpha(obj.alpha()),
             position: obj.position,
         }).collect();
+        sort_for_blending(&mut instances);
 
         self.uniform_buffer
             .write_with_queue(context.queue, state.clone());
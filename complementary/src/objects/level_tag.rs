@@ -1,3 +1,4 @@
+use complementary_macros::JsonSchema;
 use serde::Deserialize;
 
 use crate::{
@@ -8,10 +9,15 @@ use crate::{
     window::DrawContext,
 };
 
-use super::{Object, Tickable};
+use super::{Mirror, Object, PositionalWithSize, Tickable};
 
-#[derive(Debug, Deserialize)]
-pub struct LevelTagData {}
+/// Marks the entrance to another level, e.g. in the level select
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LevelTagData {
+    /// Index of the main level that is entered when the player touches a goal inside the tag
+    level_id: i32,
+    size: FVec2,
+}
 
 pub type LevelTagObject = Object<LevelTagData, ()>;
 
@@ -19,10 +25,34 @@ impl LevelTagObject {
     pub fn new(position: FVec2, data: LevelTagData) -> Self {
         Self { position, data, state: () }
     }
+
+    pub fn level_id(&self) -> i32 {
+        self.data.level_id
+    }
+
+    /// The player respawns at the bottom center of the last entered tag
+    pub fn spawn_position(&self) -> FVec2 {
+        self.position + FVec2::new((self.data.size.x - 1.0) / 2.0, self.data.size.y - 1.0)
+    }
+}
+
+impl PositionalWithSize for LevelTagObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for LevelTagObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+    }
 }
 
 impl Tickable for LevelTagObject {
-    fn tick(&mut self, _state: &mut ObjectTickState) {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        if self.bounds().overlaps(&state.player.bounds()) {
+            state.level_state.enter_level_tag(self.data.level_id, self.spawn_position());
+        }
     }
 }
 
@@ -30,7 +60,7 @@ impl Tickable for LevelTagObject {
 pub struct LevelTagRenderer {}
 
 impl LevelTagRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         Self {}
     }
 
@@ -1,48 +1,108 @@
 pub mod ability_block;
+pub mod checkpoint;
 pub mod door;
+pub mod enemy;
+pub mod gravity_zone;
 pub mod key;
 pub mod level_tag;
 pub mod particle_system;
+pub mod pendulum;
 pub mod platform;
+pub mod shard;
+pub mod switch;
+pub mod text_label;
 pub mod tutorial;
+pub mod water;
 pub mod wind;
 
 use std::{
+	collections::HashMap,
+	fmt,
 	fs::File,
 	io::{self, BufReader},
 	path::Path,
 };
 
-use serde::Deserialize;
+use log::warn;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
 use crate::{
 	game::{ObjectTickState, WorldType},
 	math::{FVec2, Bounds, Direction},
-	rendering::DrawState,
-	window::DrawContext, player::{Player, CollisionType}, level::LevelState,
+	rendering::{DrawState, RenderLayer},
+	gpu_memory,
+	window::DrawContext, player::{AbilityPair, Player, CollisionType}, level::{Difficulty, LevelState, LEVEL_SCOPE},
+	schema::JsonSchema,
 };
 
 use self::{
 	ability_block::{AbilityBlockData, AbilityBlockRenderer},
+	checkpoint::{CheckpointData, CheckpointRenderer},
 	door::{DoorData, DoorRenderer, DoorState},
+	enemy::{EnemyData, EnemyRenderer, EnemyState},
+	gravity_zone::{GravityZoneData, GravityZoneRenderer},
 	key::{KeyData, KeyRenderer, KeyState},
 	level_tag::{LevelTagData, LevelTagRenderer},
-	particle_system::{ParticleSystemData, ParticleSystemRenderer, ParticleSystemObject, ParticleSystemState},
+	particle_system::{EmitterGeometry, ParticleLayer, ParticleSystemData, ParticleSystemRenderer, ParticleSystemObject, ParticleSystemState},
+	pendulum::{PendulumData, PendulumRenderer, PendulumState},
 	platform::{PlatformData, PlatformRenderer, PlatformState},
-	tutorial::{TutorialData, TutorialRenderer},
+	shard::{ShardData, ShardRenderer, ShardState},
+	switch::{SwitchData, SwitchRenderer, SwitchState},
+	text_label::{TextLabelData, TextLabelRenderer},
+	tutorial::{TutorialData, TutorialRenderer, TutorialState},
+	water::{WaterData, WaterRenderer, WaterState},
 	wind::{WindData, WindRenderer},
 };
 
+/// Name of an object that stays the same across sessions and level reloads, so that other
+/// objects and save data can refer to it. The converter and the level editor assign them,
+/// objects without one are named after their index, see `ObjectId::from_index()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ObjectId(String);
+
+impl ObjectId {
+	/// Fallback for objects without an `id`. It changes when objects before it are removed,
+	/// so nothing should rely on it.
+	fn from_index(index: usize) -> Self {
+		ObjectId(format!("#{index}"))
+	}
+}
+
+impl fmt::Display for ObjectId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+/// Reference to another object in the same object map
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum SerializedRef {
+	Index(usize),
+	Id(ObjectId),
+}
+
 // Used during deserialization
 #[derive(Debug, Deserialize)]
 struct SerializedObject {
+	#[serde(default)]
+	id: Option<ObjectId>,
 	position: FVec2,
+	/// Object this object is attached to in the same object list, by index or id
+	#[serde(default)]
+	parent: Option<SerializedRef>,
+	/// Difficulties in which the object exists, all of them if empty
+	#[serde(default)]
+	modes: Vec<Difficulty>,
 	#[serde(flatten)]
 	data: ObjectData,
 }
 
 macro_rules! object_multi_list {
-	($(($vec_name:ident, $name:ident, $data:ty, $state:ty)),*) => {
+	($(($vec_name:ident, $name:ident, $data:ty, $state:ty, $layer:ident)),*) => {
 		// Used during deserialization
 		#[derive(Debug, Deserialize)]
 		#[serde(tag = "type", content = "data")]
@@ -65,41 +125,143 @@ macro_rules! object_multi_list {
             }
         )*
 
+		/// Name and data schema of every object type, see `object_map_schema()`
+		fn object_data_schemas() -> Vec<(&'static str, Value)> {
+			vec![
+				$(
+					(stringify!($name), <$data as JsonSchema>::json_schema()),
+				)*
+			]
+		}
+
+		/// Which object types are drawn, for debugging
+		#[derive(Debug, Clone)]
+		pub struct ObjectVisibility {
+			$(
+				pub $vec_name: bool,
+			)*
+		}
+
+		impl Default for ObjectVisibility {
+			fn default() -> Self {
+				Self {
+					$(
+						$vec_name: true,
+					)*
+				}
+			}
+		}
+
+		impl ObjectVisibility {
+			pub fn draw_gui(&mut self, gui: &imgui::Ui) {
+				$(
+					gui.checkbox(stringify!($vec_name), &mut self.$vec_name);
+				)*
+			}
+		}
+
+		/// Refers to an object in the `ObjectMultiList` by its type and index
+		#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+		pub enum ObjectRef {
+			$(
+				$name(usize),
+			)*
+		}
+
 		// The paste! macro is used to create an identifier in the form "renderer_[name]"
 		paste::paste! {
 			pub struct ObjectMultiList {
 				$(
 					pub $vec_name: Vec<Object::<$data, $state>>,
-					[<renderer_ $vec_name>]: [<$name Renderer>],
+					/// Created when the objects are first drawn, so that levels can be simulated without a GPU
+					[<renderer_ $vec_name>]: Option<[<$name Renderer>]>,
 				)*
+				ids: HashMap<ObjectId, ObjectRef>,
 			}
 
 			impl ObjectMultiList {
-				fn new(serialized_objects: Vec<SerializedObject>, device: &wgpu::Device) -> Self {
+				/// Create the objects that exist in `difficulty`
+				fn new(serialized_objects: Vec<SerializedObject>, difficulty: Difficulty) -> Self {
 					$(
 						let mut $vec_name = Vec::new();
-						let [<renderer_ $vec_name>] = [<$name Renderer>]::new(&device);
 					)*
 
-					for obj in serialized_objects {
-						match obj.data {
+					// Parents are resolved after all objects were created since they can
+					// appear after their children in the list
+					let mut refs = Vec::with_capacity(serialized_objects.len());
+					let mut parents = Vec::new();
+					let mut ids = HashMap::new();
+					for (index, obj) in serialized_objects.into_iter().enumerate() {
+						if !obj.modes.is_empty() && !obj.modes.contains(&difficulty) {
+							// Keep the indices of the remaining objects intact
+							refs.push(None);
+							continue;
+						}
+						let obj_ref = match obj.data {
 							$(
-								ObjectData::$name(inner) => $vec_name.push(Object::<$data, $state>::new(obj.position, inner)),
+								ObjectData::$name(inner) => {
+									$vec_name.push(Object::<$data, $state>::new(obj.position, inner));
+									ObjectRef::$name($vec_name.len() - 1)
+								}
 							)*
 						};
+						if let Some(parent) = obj.parent {
+							parents.push((obj_ref, parent));
+						}
+						let id = obj.id.unwrap_or_else(|| ObjectId::from_index(index));
+						if ids.contains_key(&id) {
+							warn!("Duplicate object id {id}, only the first object can be referenced");
+						} else {
+							ids.insert(id, obj_ref);
+						}
+						refs.push(Some(obj_ref));
 					}
 
-					Self {
+					let mut list = Self {
 						$(
 							$vec_name,
-							[<renderer_ $vec_name>],
+							[<renderer_ $vec_name>]: None,
 						)*
+						ids,
+					};
+
+					for (child, parent) in parents {
+						match parent {
+							SerializedRef::Index(index) => match refs.get(index) {
+								Some(Some(parent_ref)) => list.attach(child, *parent_ref),
+								Some(None) => warn!("Parent {index} of {child:?} doesn't exist in {difficulty:?}"),
+								None => warn!("Invalid parent index {index} for {child:?}"),
+							},
+							SerializedRef::Id(id) => match list.find(&id) {
+								Some(parent_ref) => list.attach(child, parent_ref),
+								None => warn!("Parent {id} of {child:?} doesn't exist in {difficulty:?}"),
+							},
+						}
 					}
+
+					list
+				}
+
+				/// Flip all objects horizontally, see `Mirror`
+				fn mirror(&mut self, width: f32) {
+					$(
+						for obj in &mut self.$vec_name {
+							obj.mirror(width);
+						}
+					)*
+					self.update_attached_objects();
 				}
 
-				fn draw(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType) {
+				/// Draw all objects in the given layer, in the order they're listed in `object_multi_list!`
+				fn draw(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType, layer: RenderLayer, visibility: &ObjectVisibility) {
+					let device = context.device;
+					let format = context.format;
 					$(
-						self.[<renderer_ $vec_name>].draw(&self.$vec_name, context, state, world_type);
+						if layer == RenderLayer::$layer && visibility.$vec_name {
+							self.[<renderer_ $vec_name>]
+								.get_or_insert_with(|| [<$name Renderer>]::new(device, format))
+								.draw(&self.$vec_name, context, state, world_type);
+						}
 					)*
 				}
 			}
@@ -111,6 +273,8 @@ macro_rules! object_multi_list {
 							obj.tick(state);
 						}
 					)*
+					self.update_attached_objects();
+					self.apply_wind_to_particles();
 				}
 			}
 		}
@@ -129,6 +293,19 @@ macro_rules! object_multi_list_collision {
 				None
 			}
 
+			/// Bounds of the objects that `check_collision()` treats as solid in `world_type`
+			pub fn solid_bounds(&self, world_type: WorldType) -> Vec<Bounds> {
+				let mut solid_bounds = Vec::new();
+				$(
+					solid_bounds.extend(self.$vec_name.iter().filter_map(|obj| {
+						let bounds = obj.bounds();
+						matches!(obj.collides_with(&bounds, world_type), Some(CollisionType::Solid | CollisionType::Wall))
+							.then_some(bounds)
+					}));
+				)*
+				solid_bounds
+			}
+
 			pub fn handle_directional_collision(&mut self, bounds: &Bounds, player: &mut Player, level_state: &mut LevelState, world_type: WorldType, direction: Direction) -> Option<CollisionType> {
 				$(
 					for obj in &mut self.$vec_name {
@@ -145,17 +322,130 @@ macro_rules! object_multi_list_collision {
 }
 
 object_multi_list! {
-	(ability_blocks, AbilityBlock, AbilityBlockData, ()),
-	(winds, Wind, WindData, ()),
-	(platforms, Platform, PlatformData, PlatformState),
-	(particle_systems, ParticleSystem, ParticleSystemData, ParticleSystemState),
-	(keys, Key, KeyData, KeyState),
-	(doors, Door, DoorData, DoorState),
-	(level_tags, LevelTag, LevelTagData, ()),
-	(tutorials, Tutorial, TutorialData, ())
+	(ability_blocks, AbilityBlock, AbilityBlockData, (), Opaque),
+	(level_tags, LevelTag, LevelTagData, (), Opaque),
+	(checkpoints, Checkpoint, CheckpointData, (), Opaque),
+	(platforms, Platform, PlatformData, PlatformState, Transparent),
+	(pendulums, Pendulum, PendulumData, PendulumState, Opaque),
+	(enemies, Enemy, EnemyData, EnemyState, Opaque),
+	(winds, Wind, WindData, (), Transparent),
+	(waters, Water, WaterData, WaterState, Transparent),
+	(gravity_zones, GravityZone, GravityZoneData, (), Transparent),
+	(doors, Door, DoorData, DoorState, Transparent),
+	(switches, Switch, SwitchData, SwitchState, Transparent),
+	(keys, Key, KeyData, KeyState, Transparent),
+	(shards, Shard, ShardData, ShardState, Transparent),
+	(particle_systems, ParticleSystem, ParticleSystemData, ParticleSystemState, Transparent),
+	(text_labels, TextLabel, TextLabelData, (), Transparent),
+	(tutorials, Tutorial, TutorialData, TutorialState, Transparent)
+}
+
+macro_rules! object_multi_list_bounds {
+	($($vec_name:ident),*) => {
+		impl ObjectMultiList {
+			/// Get the bounds of all visible objects, for debugging
+			pub fn debug_bounds(&self, visibility: &ObjectVisibility) -> Vec<Bounds> {
+				let mut bounds = Vec::new();
+				$(
+					if visibility.$vec_name {
+						bounds.extend(self.$vec_name.iter().map(|obj| obj.bounds()));
+					}
+				)*
+				bounds
+			}
+		}
+	};
+}
+
+object_multi_list_collision!(ability_blocks, platforms, keys, doors, enemies);
+object_multi_list_bounds!(ability_blocks, level_tags, checkpoints, platforms, pendulums, enemies, winds, waters, gravity_zones, doors, switches, keys, shards, text_labels, tutorials);
+
+impl ObjectMultiList {
+	fn attach(&mut self, child: ObjectRef, parent: ObjectRef) {
+		match child {
+			ObjectRef::ParticleSystem(index) => {
+				let parent_state = self.parent_state(parent);
+				let particle_system = &mut self.particle_systems[index];
+				match parent_state {
+					Some(parent_state) => particle_system.attach(parent, &parent_state),
+					None => warn!("Particle systems can't be attached to {parent:?}"),
+				}
+			}
+			_ => warn!("{child:?} can't be attached to other objects"),
+		}
+	}
+
+	/// Get the current state of an object that other objects can be attached to
+	fn parent_state(&self, parent: ObjectRef) -> Option<ParentState> {
+		match parent {
+			ObjectRef::Platform(index) => {
+				let platform = &self.platforms[index];
+				Some(ParentState { position: platform.position, geometry: platform.emitter_geometry(), triggered: false })
+			}
+			ObjectRef::Wind(index) => {
+				let wind = &self.winds[index];
+				Some(ParentState { position: wind.position, geometry: wind.emitter_geometry(), triggered: false })
+			}
+			ObjectRef::Door(index) => {
+				let door = &self.doors[index];
+				Some(ParentState { position: door.position, geometry: door.emitter_geometry(), triggered: door.open() })
+			}
+			ObjectRef::Key(index) => {
+				let key = &self.keys[index];
+				Some(ParentState { position: key.position, geometry: key.emitter_geometry(), triggered: key.collected() })
+			}
+			ObjectRef::Shard(index) => {
+				let shard = &self.shards[index];
+				let geometry = EmitterGeometry { size: shard.size(), ..Default::default() };
+				Some(ParentState { position: shard.position, geometry, triggered: shard.collected() })
+			}
+			ObjectRef::Switch(index) => {
+				let switch = &self.switches[index];
+				Some(ParentState { position: switch.position, geometry: EmitterGeometry::default(), triggered: switch.on() })
+			}
+			_ => None,
+		}
+	}
+
+	fn apply_wind_to_particles(&mut self) {
+		let winds = &self.winds;
+		for particle_system in &mut self.particle_systems {
+			particle_system.apply_force_field(|position| {
+				winds.iter().filter_map(|wind| wind.force_at(position)).reduce(|a, b| a + b)
+			});
+		}
+	}
+
+	/// Find an object by its id, see `ObjectId`
+	pub fn find(&self, id: &ObjectId) -> Option<ObjectRef> {
+		self.ids.get(id).copied()
+	}
+
+	/// Get the id of an object. Objects with a duplicate id don't have one.
+	pub fn id_of(&self, obj_ref: ObjectRef) -> Option<&ObjectId> {
+		self.ids.iter().find(|(_, other)| **other == obj_ref).map(|(id, _)| id)
+	}
+
+	/// Move attached objects along with their parents
+	fn update_attached_objects(&mut self) {
+		for index in 0..self.particle_systems.len() {
+			if let Some(parent) = self.particle_systems[index].parent() {
+				if let Some(parent_state) = self.parent_state(parent) {
+					self.particle_systems[index].update_parent(&parent_state);
+				}
+			}
+		}
+	}
 }
 
-object_multi_list_collision!(ability_blocks, platforms, keys, doors);
+/// State of a parent object that attached objects follow
+#[derive(Debug, Clone, Copy)]
+pub struct ParentState {
+	pub position: FVec2,
+	pub geometry: EmitterGeometry,
+	/// Set if the parent was activated, e.g. a door was opened or a key was collected
+	pub triggered: bool,
+}
 
 // Used at run-time
 #[derive(Debug)]
@@ -169,6 +459,20 @@ pub trait Tickable {
 	fn tick(&mut self, state: &mut ObjectTickState);
 }
 
+/// Objects are flipped horizontally in mirror mode, see `Level::mirror()`
+pub trait Mirror {
+	/// Flip the object inside of a level that is `width` tiles wide. Only called before the
+	/// first tick, so state depending on the position can simply be recreated.
+	fn mirror(&mut self, width: f32);
+}
+
+impl<TData, TState> Object<TData, TState> {
+	/// Mirror the position of an object that is `size` tiles wide
+	fn mirror_position(&mut self, size: f32, width: f32) {
+		self.position.x = width - self.position.x - size;
+	}
+}
+
 pub trait Positional {
 	fn position(&self) -> FVec2;
 }
@@ -210,36 +514,187 @@ pub fn load_particle_system<P: AsRef<Path>>(path: &P) -> Result<ParticleSystemOb
     prefab.try_into()
 }
 
+/// Object map entries with a `"prefab": "<name>"` field are based on
+/// `assets/prefabs/<name>.json`. All other fields of the entry, usually the position and
+/// some of the data, override the prefab's fields.
+fn resolve_prefab(value: &Value) -> Result<Value, ObjectSetLoadError> {
+	let name = match value.get("prefab") {
+		Some(Value::String(name)) => name,
+		Some(_) => return Err(ObjectSetLoadError::InvalidPrefab(value.to_string())),
+		None => return Ok(value.clone()),
+	};
+
+	let file = File::open(format!("assets/prefabs/{name}.json"))?;
+	let mut prefab: Value = serde_json::from_reader(BufReader::new(file))?;
+	if prefab.get("prefab").is_some() {
+		// Could reference itself, so prefabs can't be based on other prefabs
+		return Err(ObjectSetLoadError::InvalidPrefab(name.clone()));
+	}
+
+	let mut overrides = value.clone();
+	if let Some(fields) = overrides.as_object_mut() {
+		fields.remove("prefab");
+	}
+	merge_json(&mut prefab, overrides);
+	Ok(prefab)
+}
+
+/// Recursively replace fields of `target` with the ones in `overrides`
+fn merge_json(target: &mut Value, overrides: Value) {
+	match (target, overrides) {
+		(Value::Object(target), Value::Object(overrides)) => {
+			for (key, value) in overrides {
+				merge_json(target.entry(key).or_insert(Value::Null), value);
+			}
+		}
+		(target, overrides) => *target = overrides,
+	}
+}
+
+/// JSON Schema of object map files, matching `SerializedObject` and the prefab references
+/// resolved by `resolve_prefab()`
+pub fn object_map_schema() -> Value {
+	// Prefab references may override any field, so only the name is checked
+	let prefab_entry = json!({
+		"type": "object",
+		"properties": { "prefab": { "type": "string" } },
+		"required": ["prefab"],
+	});
+	let parent = json!({ "anyOf": [{ "type": "integer", "minimum": 0 }, { "type": "string" }] });
+	let modes = <Vec<Difficulty>>::json_schema();
+
+	let mut entries = vec![prefab_entry];
+	for (name, data) in object_data_schemas() {
+		entries.push(json!({
+			"type": "object",
+			"properties": {
+				"type": { "const": name },
+				"data": data,
+				"id": { "type": "string" },
+				"position": FVec2::json_schema(),
+				"parent": parent,
+				"modes": modes,
+			},
+			"required": ["type", "position", "data"],
+			"additionalProperties": false,
+		}));
+	}
+
+	json!({
+		"$schema": "http://json-schema.org/draft-07/schema#",
+		"title": "Object map",
+		"type": "array",
+		"items": { "anyOf": entries },
+	})
+}
+
 pub struct ObjectSet {
 	pub objects: ObjectMultiList,
 }
 
 impl ObjectSet {
-	pub fn load_from_file<T: AsRef<Path>>(
-		path: T,
-		device: &wgpu::Device,
-	) -> Result<ObjectSet, ObjectSetLoadError> {
+	pub fn load_from_file<T: AsRef<Path>>(path: T, difficulty: Difficulty) -> Result<ObjectSet, ObjectSetLoadError> {
 		let file = File::open(path)?;
 		let reader = BufReader::new(file);
 
-		let object_data: Vec<SerializedObject> = serde_json::from_reader(reader)?;
+		let values: Vec<Value> = serde_json::from_reader(reader)?;
+
+		ObjectSet::from_json(&values, difficulty)
+	}
+
+	/// Create objects from the JSON values of an object map, e.g. after they were changed
+	/// in the level editor. References to prefabs are resolved, see `resolve_prefab()`.
+	/// Objects that don't exist in `difficulty` are skipped.
+	pub fn from_json(values: &[Value], difficulty: Difficulty) -> Result<ObjectSet, ObjectSetLoadError> {
+		let object_data = values.iter()
+			.map(|value| Ok(SerializedObject::deserialize(resolve_prefab(value)?)?))
+			.collect::<Result<Vec<_>, ObjectSetLoadError>>()?;
 
-		let objects = ObjectMultiList::new(object_data, &device);
+		let objects = ObjectMultiList::new(object_data, difficulty);
 
 		Ok(ObjectSet { objects })
 	}
 
-	pub fn draw(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType) {
-		self.objects.draw(context, state, world_type);
+	pub fn draw(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType, layer: RenderLayer, visibility: &ObjectVisibility) {
+		let _scope = gpu_memory::enter_scope(LEVEL_SCOPE);
+		// Particle systems are drawn in two layers, so they're handled separately here
+		let device = context.device;
+		let format = context.format;
+		if layer == RenderLayer::BehindTilemap && visibility.particle_systems {
+			self.objects.renderer_particle_systems
+				.get_or_insert_with(|| ParticleSystemRenderer::new(device, format))
+				.draw_layer(&self.objects.particle_systems, context, state, world_type, ParticleLayer::BehindTilemap);
+		}
+		self.objects.draw(context, state, world_type, layer, visibility);
+	}
+
+	/// Draw glowing objects into the bloom target
+	pub fn draw_emissive(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType, visibility: &ObjectVisibility) {
+		let _scope = gpu_memory::enter_scope(LEVEL_SCOPE);
+		let device = context.device;
+		let format = context.format;
+		if visibility.keys {
+			self.objects.renderer_keys
+				.get_or_insert_with(|| KeyRenderer::new(device, format))
+				.draw(&self.objects.keys, context, state, world_type);
+		}
 	}
 
 	pub fn check_collision(&self, bounds: &Bounds, world_type: WorldType) -> Option<CollisionType> {
 		self.objects.check_collision(bounds, world_type)
 	}
 
+	/// Bounds of the objects that block the player, for swept collision
+	pub fn solid_bounds(&self, world_type: WorldType) -> Vec<Bounds> {
+		self.objects.solid_bounds(world_type)
+	}
+
+	/// Bounds of moving objects that can hurt the player
+	pub fn hazard_bounds(&self, world_type: WorldType) -> Vec<Bounds> {
+		let platforms = self.objects.platforms.iter()
+			.filter(|platform| platform.is_hazard(world_type))
+			.map(|platform| platform.bounds());
+		let pendulums = self.objects.pendulums.iter()
+			.filter(|pendulum| pendulum.is_hazard(world_type))
+			.map(|pendulum| pendulum.bounds());
+		let enemies = self.objects.enemies.iter()
+			.filter(|enemy| enemy.exists_in(world_type))
+			.map(|enemy| enemy.bounds());
+		platforms.chain(pendulums).chain(enemies).collect()
+	}
+
+	/// Whether something whose bottom edge moved down from `old_bottom` to `bounds.max.y` landed
+	/// on a one-way platform
+	pub fn lands_on_one_way_platform(&self, old_bottom: f32, bounds: &Bounds, world_type: WorldType) -> bool {
+		self.objects.platforms.iter().any(|platform| platform.lands_on(old_bottom, bounds, world_type))
+	}
+
 	pub fn handle_directional_collision(&mut self, bounds: &Bounds, player: &mut Player, level_state: &mut LevelState, world_type: WorldType, direction: Direction) -> Option<CollisionType> {
 		self.objects.handle_directional_collision(bounds, player, level_state, world_type, direction)
 	}
+
+	/// Flip all objects horizontally inside of a level that is `width` tiles wide
+	pub fn mirror(&mut self, width: f32) {
+		self.objects.mirror(width);
+	}
+
+	/// Swap the abilities granted by the ability blocks, see `Randomizer`
+	pub fn shuffle_abilities<R: Rng>(&mut self, rng: &mut R) {
+		let mut abilities = self.ability_block_abilities();
+		abilities.shuffle(rng);
+		self.set_ability_block_abilities(&abilities);
+	}
+
+	/// Abilities granted by each ability block, in order
+	pub fn ability_block_abilities(&self) -> Vec<AbilityPair> {
+		self.objects.ability_blocks.iter().map(|block| block.abilities()).collect()
+	}
+
+	pub fn set_ability_block_abilities(&mut self, abilities: &[AbilityPair]) {
+		for (block, abilities) in self.objects.ability_blocks.iter_mut().zip(abilities) {
+			block.set_abilities(*abilities);
+		}
+	}
 }
 
 impl Tickable for ObjectSet {
@@ -256,4 +711,6 @@ pub enum ObjectSetLoadError {
 	InvalidData(#[from] serde_json::Error),
     #[error("invalid source type")]
 	InvalidSourceType,
+	#[error("invalid prefab: {0}")]
+	InvalidPrefab(String),
 }
@@ -1,14 +1,23 @@
+use std::{fs::File, io::BufReader};
+
+use cgmath::{InnerSpace, Zero};
+use complementary_macros::{JsonSchema, WgslStruct};
+use rand::Rng;
 use serde::Deserialize;
+use wgpu::vertex_attr_array;
 
 use crate::{
     game::{ObjectTickState, WorldType},
-    rendering::DrawState,
-    window::DrawContext, math::{FVec2, Color},
+    gpu_memory::TrackedBuffer,
+    rendering::{DrawState, UniformBuffer, Vertex, TransparentInstance, sort_for_blending, create_vertex_buffer, create_instance_buffer, color_targets, create_pipeline_descriptor, SQUARE_VERTICES, DIAMOND_VERTICES},
+    window::DrawContext, math::{FVec2, Color, Bounds}, tilemap::Tilemap, shader,
 };
 
-use super::{Object, Tickable};
+use super::{Mirror, Object, ObjectRef, ObjectSetLoadError, ParentState, SerializedObject, Tickable};
 
-#[derive(Debug, Deserialize)]
+/// Durations are given in ticks and sizes in pixels
+/// of the original game (see `PIXEL_SIZE`)
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct ParticleSystemData {
     duration: i32,
     #[serde(rename = "type")]
@@ -41,20 +50,20 @@ pub struct ParticleSystemData {
     symmetrical: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
 enum ParticleType {
     Triangle,
     Square,
     Diamond,
 }
 
-#[derive(Debug, Deserialize)]
-enum ParticleLayer {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+pub enum ParticleLayer {
     BehindTilemap,
     OverTilemap,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 enum ParticleEmissionType {
     Center,
     BoxEdge(FVec2),
@@ -63,6 +72,86 @@ enum ParticleEmissionType {
     BoxEdgeSpiky(FVec2),
 }
 
+/// Geometry of the object a particle system is attached to. Required by emission types
+/// which depend on the shape of their parent.
+#[derive(Debug, Clone, Copy)]
+pub struct EmitterGeometry {
+    pub size: FVec2,
+    /// Spiky faces in the order left, right, up, down
+    pub spiky: (bool, bool, bool, bool),
+    /// Force of the parent wind region
+    pub force: FVec2,
+}
+
+impl Default for EmitterGeometry {
+    fn default() -> Self {
+        Self { size: FVec2::new(1.0, 1.0), spiky: (false, false, false, false), force: FVec2::new(0.0, 0.0) }
+    }
+}
+
+impl ParticleEmissionType {
+    /// Get a random spawn position relative to the particle system's position
+    fn spawn_offset<R: Rng>(&self, rng: &mut R, geometry: &EmitterGeometry) -> FVec2 {
+        match *self {
+            ParticleEmissionType::Center => FVec2::new(0.0, 0.0),
+            ParticleEmissionType::Box(size) => FVec2::new(rng.gen::<f32>() * size.x, rng.gen::<f32>() * size.y),
+            ParticleEmissionType::BoxEdge(size) => {
+                ParticleEmissionType::random_edge_point(rng, size, (false, false, false, false))
+                    .unwrap_or(FVec2::new(0.0, 0.0))
+            }
+            ParticleEmissionType::BoxEdgeSpiky(size) => {
+                // Fall back to the center if all faces are spiky
+                ParticleEmissionType::random_edge_point(rng, size, geometry.spiky)
+                    .unwrap_or(size * 0.5)
+            }
+            ParticleEmissionType::Wind => ParticleEmissionType::random_wind_point(rng, geometry),
+        }
+    }
+
+    /// Pick a random point on the edges of a box with the given size, skipping excluded faces
+    /// (in the order left, right, up, down). Edges are weighted by their length.
+    fn random_edge_point<R: Rng>(rng: &mut R, size: FVec2, excluded: (bool, bool, bool, bool)) -> Option<FVec2> {
+        let (left, right, up, down) = excluded;
+        let edges = [
+            (!left, FVec2::new(0.0, 0.0), FVec2::new(0.0, size.y)),
+            (!right, FVec2::new(size.x, 0.0), FVec2::new(0.0, size.y)),
+            (!up, FVec2::new(0.0, 0.0), FVec2::new(size.x, 0.0)),
+            (!down, FVec2::new(0.0, size.y), FVec2::new(size.x, 0.0)),
+        ];
+
+        let total_length: f32 = edges.iter().filter(|(enabled, ..)| *enabled).map(|(_, _, dir)| dir.x + dir.y).sum();
+        if total_length <= 0.0 {
+            return None;
+        }
+
+        let mut remaining = rng.gen::<f32>() * total_length;
+        for &(_, start, dir) in edges.iter().filter(|(enabled, ..)| *enabled) {
+            let length = dir.x + dir.y;
+            if remaining <= length {
+                return Some(start + dir * (remaining / length));
+            }
+            remaining -= length;
+        }
+        None
+    }
+
+    /// Pick a random point on the edge of a wind region where the wind enters, so that
+    /// particles travel through the whole region along the force
+    fn random_wind_point<R: Rng>(rng: &mut R, geometry: &EmitterGeometry) -> FVec2 {
+        let size = geometry.size;
+        let force = geometry.force;
+        let t = rng.gen::<f32>();
+
+        if force.x.abs() >= force.y.abs() {
+            let x = if force.x >= 0.0 { 0.0 } else { size.x };
+            FVec2::new(x, t * size.y)
+        } else {
+            let y = if force.y >= 0.0 { 0.0 } else { size.y };
+            FVec2::new(t * size.x, y)
+        }
+    }
+}
+
 struct Particle {
     position: FVec2,
     velocity: FVec2,
@@ -70,35 +159,377 @@ struct Particle {
 }
 
 pub struct ParticleSystemState {
-    particles: Vec<Particle>
+    particles: Vec<Particle>,
+    parent: Option<ObjectRef>,
+    /// Position relative to the parent object
+    parent_offset: FVec2,
+    geometry: EmitterGeometry,
+    /// Whether the parent was triggered (e.g. a door was opened) during the last tick
+    parent_triggered: bool,
+    /// Used to detect when the parent gets triggered
+    parent_triggered_last_tick: bool,
+    /// Whether new particles are emitted
+    playing: bool,
+    /// Ticks since the system started playing
+    elapsed_ticks: i32,
+    /// Ticks until the next particles are emitted
+    emission_timer: i32,
+    /// Set after the system finished playing if `destroy_on_end` is set. Destroyed systems
+    /// can't be restarted.
+    destroyed: bool,
 }
 
 pub type ParticleSystemObject = Object<ParticleSystemData, ParticleSystemState>;
 
 impl ParticleSystemObject {
+    /// Size of a pixel of the original game in tiles
+    const PIXEL_SIZE: f32 = 1.0 / 16.0;
+    /// Particle cap per system while the simulation is reduced (see `TickBudget`)
+    const REDUCED_MAX_PARTICLES: usize = 64;
+
     pub fn new(position: FVec2, data: ParticleSystemData) -> Self {
-        Self { position, data, state: ParticleSystemState { particles: Vec::with_capacity(128) } }
+        let state = ParticleSystemState {
+            particles: Vec::with_capacity(128),
+            parent: None,
+            parent_offset: FVec2::new(0.0, 0.0),
+            geometry: EmitterGeometry::default(),
+            parent_triggered: false,
+            parent_triggered_last_tick: false,
+            playing: data.play_on_spawn,
+            elapsed_ticks: 0,
+            emission_timer: 0,
+            destroyed: false,
+        };
+        Self { position, data, state }
+    }
+
+    /// Load a particle system from `assets/particlesystems/<name>.json` and place it at `position`
+    pub fn load_prefab(name: &str, position: FVec2) -> Result<Self, ObjectSetLoadError> {
+        let file = File::open(format!("assets/particlesystems/{name}.json"))?;
+        let object: SerializedObject = serde_json::from_reader(BufReader::new(file))?;
+        let mut system = ParticleSystemObject::try_from(object)?;
+        system.position = position;
+        Ok(system)
+    }
+
+    /// Attach the particle system to another object, keeping the current offset between both
+    pub fn attach(&mut self, parent: ObjectRef, parent_state: &ParentState) {
+        self.state.parent = Some(parent);
+        self.state.parent_offset = self.position - parent_state.position;
+        self.update_parent(parent_state);
+        // Don't play immediately if the parent starts out triggered
+        self.state.parent_triggered_last_tick = parent_state.triggered;
+    }
+
+    pub fn parent(&self) -> Option<ObjectRef> {
+        self.state.parent
+    }
+
+    pub fn update_parent(&mut self, parent_state: &ParentState) {
+        self.position = parent_state.position + self.state.parent_offset;
+        self.state.geometry = parent_state.geometry;
+        self.state.parent_triggered = parent_state.triggered;
+    }
+
+    /// Get a random position in world space where a new particle is spawned
+    pub fn spawn_position<R: Rng>(&self, rng: &mut R) -> FVec2 {
+        self.position + self.data.emission_type.spawn_offset(rng, &self.state.geometry)
+    }
+
+    /// Start emitting particles from the beginning
+    pub fn play(&mut self) {
+        if !self.state.destroyed {
+            self.state.playing = true;
+            self.state.elapsed_ticks = 0;
+            self.state.emission_timer = 0;
+        }
+    }
+
+    pub fn playing(&self) -> bool {
+        self.state.playing
+    }
+
+    /// Whether the system stopped playing and all particles are gone
+    pub fn finished(&self) -> bool {
+        !self.state.playing && self.state.particles.is_empty()
+    }
+
+    pub fn layer(&self) -> ParticleLayer {
+        self.data.layer
+    }
+
+    /// Accelerate all particles by the force at their position, e.g. from wind
+    pub fn apply_force_field<F: Fn(FVec2) -> Option<FVec2>>(&mut self, force_at: F) {
+        for particle in &mut self.state.particles {
+            if let Some(force) = force_at(particle.position) {
+                particle.velocity += force;
+            }
+        }
+    }
+
+    /// Area that particles should stay in, if any
+    fn bounds(&self) -> Option<Bounds> {
+        let size = if self.data.clamp_box_size != FVec2::zero() {
+            self.data.clamp_box_size
+        } else {
+            match self.data.emission_type {
+                ParticleEmissionType::Center => return None,
+                ParticleEmissionType::Box(size)
+                | ParticleEmissionType::BoxEdge(size)
+                | ParticleEmissionType::BoxEdgeSpiky(size) => size,
+                ParticleEmissionType::Wind => self.state.geometry.size,
+            }
+        };
+        Some(Bounds::new(self.position, self.position + size))
+    }
+
+    fn emit<R: Rng>(&mut self, rng: &mut R, max_particles: usize) {
+        let count = rng.gen_range(self.data.min_emission_rate..=self.data.max_emission_rate.max(self.data.min_emission_rate));
+        for _ in 0..count {
+            if self.state.particles.len() >= max_particles {
+                break;
+            }
+            let position = self.spawn_position(rng);
+            let min = self.data.min_start_velocity;
+            let max = self.data.max_start_velocity;
+            let velocity = FVec2::new(
+                min.x + rng.gen::<f32>() * (max.x - min.x),
+                min.y + rng.gen::<f32>() * (max.y - min.y),
+            );
+
+            self.state.particles.push(Particle { position, velocity, lifetime: 0 });
+            if self.data.symmetrical {
+                // Mirror horizontally
+                let mirrored_velocity = FVec2::new(-velocity.x, velocity.y);
+                self.state.particles.push(Particle { position, velocity: mirrored_velocity, lifetime: 0 });
+            }
+        }
+    }
+
+    fn tick_emission<R: Rng>(&mut self, rng: &mut R, max_particles: usize) {
+        if !self.state.playing {
+            return;
+        }
+
+        if self.state.emission_timer <= 0 {
+            self.emit(rng, max_particles);
+            self.state.emission_timer = rng.gen_range(self.data.min_emission_interval..=self.data.max_emission_interval.max(self.data.min_emission_interval));
+        }
+        self.state.emission_timer -= 1;
+        self.state.elapsed_ticks += 1;
+
+        // A duration of zero means that the system loops forever
+        if self.data.duration > 0 && self.state.elapsed_ticks >= self.data.duration {
+            self.state.playing = false;
+        }
+    }
+
+    fn tick_particles(&mut self, tilemap: &Tilemap) {
+        let bounds = self.bounds();
+        let center = self.position;
+        let data = &self.data;
+
+        for particle in &mut self.state.particles {
+            particle.velocity.y += data.gravity;
+            if data.attract_speed != 0.0 {
+                let delta = center - particle.position;
+                if delta.magnitude2() > 0.0 {
+                    particle.velocity += delta.normalize() * data.attract_speed;
+                }
+            }
+            particle.position += particle.velocity;
+            particle.lifetime += 1;
+
+            if let Some(bounds) = bounds {
+                // Particles that were clamped onto the edges are still inside, so they don't keep
+                // losing lifetime
+                if bounds.distance_to(particle.position) > 0.0 {
+                    particle.lifetime += data.out_of_box_lifetime_loss;
+                    if data.clamp_position_in_bounds {
+                        particle.position = bounds.clamp_point(particle.position);
+                    }
+                }
+            }
+
+            if data.enable_collision && ParticleSystemObject::is_solid(tilemap, particle.position) {
+                particle.lifetime = data.max_life_time;
+            }
+        }
+
+        let max_life_time = data.max_life_time;
+        self.state.particles.retain(|particle| particle.lifetime < max_life_time);
+    }
+
+    fn is_solid(tilemap: &Tilemap, position: FVec2) -> bool {
+        let x = position.x.floor() as i32;
+        let y = position.y.floor() as i32;
+        x >= 0 && y >= 0 && x < tilemap.width() && y < tilemap.height() && tilemap.get_tile(x, y).is_solid()
+    }
+
+    fn instances(&self, world_type: WorldType) -> impl Iterator<Item = ParticleInstance> + '_ {
+        self.state.particles.iter().map(move |particle| {
+            let t = particle.lifetime as f32 / self.data.max_life_time.max(1) as f32;
+            let start = self.data.start_color;
+            let end = self.data.end_color;
+            let mut color = Color::new(
+                start.r + (end.r - start.r) * t,
+                start.g + (end.g - start.g) * t,
+                start.b + (end.b - start.b) * t,
+                start.a + (end.a - start.a) * t,
+            );
+            // Everything is inverted in the Dark world, so colors that shouldn't change need to be inverted beforehand
+            if !self.data.auto_invert_color {
+                color = world_type.uninverted_color(color);
+            }
+
+            let size = (self.data.start_size + (self.data.end_size - self.data.start_size) * t) * ParticleSystemObject::PIXEL_SIZE;
+            ParticleInstance { color, position: particle.position, size }
+        })
+    }
+}
+
+impl Mirror for ParticleSystemObject {
+    /// Attached systems are mirrored inside of their parent, which moves them to the right place
+    /// once the parent was mirrored as well
+    fn mirror(&mut self, width: f32) {
+        let extent = match self.data.emission_type {
+            ParticleEmissionType::Center => 0.0,
+            ParticleEmissionType::Box(size)
+            | ParticleEmissionType::BoxEdge(size)
+            | ParticleEmissionType::BoxEdgeSpiky(size) => size.x,
+            ParticleEmissionType::Wind => self.state.geometry.size.x,
+        };
+        match self.state.parent {
+            Some(_) => {
+                let offset = &mut self.state.parent_offset;
+                offset.x = self.state.geometry.size.x - offset.x - extent;
+            }
+            None => self.mirror_position(extent, width),
+        }
+
+        let (min, max) = (self.data.min_start_velocity, self.data.max_start_velocity);
+        self.data.min_start_velocity.x = -max.x;
+        self.data.max_start_velocity.x = -min.x;
     }
 }
 
 impl Tickable for ParticleSystemObject {
     fn tick(&mut self, state: &mut ObjectTickState) {
+        if self.data.follow_player {
+            self.position = state.player.bounds().center();
+        }
+
+        if self.state.parent_triggered && !self.state.parent_triggered_last_tick {
+            self.play();
+        }
+        self.state.parent_triggered_last_tick = self.state.parent_triggered;
+
+        let max_particles = if state.reduced_simulation {
+            ParticleSystemObject::REDUCED_MAX_PARTICLES
+        } else {
+            usize::MAX
+        };
+        self.tick_emission(state.rng, max_particles);
+        self.tick_particles(state.tilemap);
+
+        if self.data.destroy_on_end && self.finished() {
+            self.state.destroyed = true;
+        }
     }
 }
 
-struct ParticleInstance {
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+pub(crate) struct ParticleInstance {
     color: Color,
     position: FVec2,
+    size: f32,
+}
+
+impl ParticleInstance {
+    /// Maximum number of particles per layer
+    const MAX_INSTANCE_COUNT: usize = 4096;
+
+    const ATTR: &'static [wgpu::VertexAttribute] = &vertex_attr_array![1 => Float32x4, 2 => Float32x2, 3 => Float32];
+
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: Self::ATTR,
+        }
+    }
+}
+
+impl TransparentInstance for ParticleInstance {
+    fn color(&self) -> Color {
+        self.color
+    }
+
+    fn position(&self) -> FVec2 {
+        self.position
+    }
+}
+
+const TRIANGLE_VERTICES: [Vertex; 3] = [
+    Vertex::new(0.5, 0.0),
+    Vertex::new(0.0, 1.0),
+    Vertex::new(1.0, 1.0),
+];
+
+impl ParticleType {
+    const ALL: [ParticleType; 3] = [ParticleType::Triangle, ParticleType::Square, ParticleType::Diamond];
+
+    /// Range of the shape in the particle vertex buffer
+    fn vertex_range(self) -> std::ops::Range<u32> {
+        match self {
+            ParticleType::Triangle => 0..3,
+            ParticleType::Square => 3..9,
+            ParticleType::Diamond => 9..15,
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct ParticleSystemRenderer {}
+pub struct ParticleSystemRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    /// Contains instances for both layers, see `instance_buffer_offset()`
+    instance_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
 
 impl ParticleSystemRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
-        Self {}
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "particle_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("particle_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertices: Vec<Vertex> = TRIANGLE_VERTICES.iter()
+            .chain(SQUARE_VERTICES.iter())
+            .chain(DIAMOND_VERTICES.iter())
+            .copied()
+            .collect();
+        let vertex_buffer = create_vertex_buffer(device, Some("particle_vertex_buffer"), &vertices);
+        let instance_buffer = create_instance_buffer::<ParticleInstance>(device, Some("particle_instance_buffer"),
+        ParticleInstance::MAX_INSTANCE_COUNT * 2);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("particle_pipeline"),
+            &shader::create_shader_module(device, "particle.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[Vertex::layout(), ParticleInstance::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, instance_buffer, render_pipeline }
     }
 
+    /// Draw particle systems in the `OverTilemap` layer
     pub fn draw(
         &mut self,
         objects: &Vec<ParticleSystemObject>,
@@ -106,5 +537,71 @@ impl ParticleSystemRenderer {
         state: &DrawState,
         world_type: WorldType,
     ) {
+        self.draw_layer(objects, context, state, world_type, ParticleLayer::OverTilemap);
+    }
+
+    pub fn draw_layer(
+        &mut self,
+        objects: &Vec<ParticleSystemObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+        layer: ParticleLayer,
+    ) {
+        // Group instances by shape so that each shape only needs one draw call
+        let mut instances = Vec::new();
+        let mut ranges = Vec::with_capacity(ParticleType::ALL.len());
+        for particle_type in ParticleType::ALL {
+            let start = instances.len();
+            let mut shape_instances: Vec<_> = objects.iter()
+                .filter(|obj| obj.data.layer == layer && obj.data.particle_type == particle_type)
+                .flat_map(|obj| obj.instances(world_type))
+                .collect();
+            sort_for_blending(&mut shape_instances);
+            instances.extend(shape_instances);
+            instances.truncate(ParticleInstance::MAX_INSTANCE_COUNT);
+            ranges.push((particle_type, start as u32..instances.len() as u32));
+        }
+        if instances.is_empty() {
+            return;
+        }
+
+        // Both layers are drawn in the same frame, so they need separate parts of the buffer
+        let offset = ParticleSystemRenderer::instance_buffer_offset(layer);
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.instance_buffer, offset, bytemuck::cast_slice(&instances));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("particle_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buffer.slice(offset..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        for (particle_type, range) in ranges {
+            if !range.is_empty() {
+                rpass.draw(particle_type.vertex_range(), range);
+            }
+        }
+    }
+
+    fn instance_buffer_offset(layer: ParticleLayer) -> wgpu::BufferAddress {
+        let layer_size = (ParticleInstance::MAX_INSTANCE_COUNT * std::mem::size_of::<ParticleInstance>()) as wgpu::BufferAddress;
+        match layer {
+            ParticleLayer::BehindTilemap => 0,
+            ParticleLayer::OverTilemap => layer_size,
+        }
     }
 }
@@ -0,0 +1,248 @@
+use std::f32::consts::TAU;
+
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
+    math::{Bounds, Color, FVec2, InnerSpace},
+    physics::{self, Chain},
+    rendering::{color_targets, create_instance_buffer, create_pipeline_descriptor, ColoredVertex, DrawState, UniformBuffer},
+    shader,
+    window::DrawContext,
+};
+
+use super::{Mirror, Object, PositionalWithSize, Tickable};
+
+/// Spike ball swinging on a chain. The object's position is where the chain is attached.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PendulumData {
+    /// Length of the chain in tiles
+    length: f32,
+    /// Angle of the chain from hanging straight down when the level starts, in degrees
+    angle: f32,
+    /// Radius of the spike ball
+    radius: f32,
+    #[serde(default)]
+    segments: Option<usize>,
+    world_type: Option<WorldType>,
+}
+
+#[derive(Debug)]
+pub struct PendulumState {
+    chain: Chain,
+    /// Energy of the tip when released, kept up so that the pendulum swings forever
+    energy: f32,
+}
+
+pub type PendulumObject = Object<PendulumData, PendulumState>;
+
+impl PendulumObject {
+    const DEFAULT_SEGMENT_COUNT: usize = 8;
+    const GRAVITY: FVec2 = FVec2::new(0.0, 0.004);
+
+    pub fn new(position: FVec2, data: PendulumData) -> Self {
+        let state = PendulumObject::initial_state(position, &data);
+        Self { position, data, state }
+    }
+
+    /// Chain released at the starting angle
+    fn initial_state(position: FVec2, data: &PendulumData) -> PendulumState {
+        let angle = data.angle.to_radians();
+        let tip = position + FVec2::new(angle.sin(), angle.cos()) * data.length;
+        let chain = Chain::new(position, tip, data.segments.unwrap_or(PendulumObject::DEFAULT_SEGMENT_COUNT));
+        let energy = PendulumObject::potential_energy(&chain);
+        PendulumState { chain, energy }
+    }
+
+    /// Potential energy of the tip relative to the anchor
+    fn potential_energy(chain: &Chain) -> f32 {
+        -PendulumObject::GRAVITY.dot(chain.tip() - chain.anchor())
+    }
+
+    /// Constraints take away a bit of energy every tick, which is added back here
+    fn restore_energy(&mut self) {
+        let chain = &mut self.state.chain;
+        let kinetic = 0.5 * chain.tip_velocity().magnitude2();
+        let target = self.state.energy - PendulumObject::potential_energy(chain);
+        if kinetic > f32::EPSILON && target > kinetic {
+            chain.scale_velocity((target / kinetic).sqrt().min(1.05));
+        }
+    }
+
+    fn solid_in(&self, world_type: WorldType) -> bool {
+        self.data.world_type == Some(world_type) || self.data.world_type == None
+    }
+
+    pub fn is_hazard(&self, world_type: WorldType) -> bool {
+        self.solid_in(world_type)
+    }
+}
+
+impl Tickable for PendulumObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        self.state.chain.tick(PendulumObject::GRAVITY);
+        self.restore_energy();
+
+        if self.solid_in(state.world_type)
+            && state.player.allowed_to_move()
+            && physics::circle_overlaps_bounds(self.state.chain.tip(), self.data.radius, &state.player.bounds())
+        {
+            state.player.kill();
+        }
+    }
+}
+
+impl Mirror for PendulumObject {
+    /// The position is where the chain is attached, so there's no width to account for
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(0.0, width);
+        self.data.angle = -self.data.angle;
+        self.state = PendulumObject::initial_state(self.position, &self.data);
+    }
+}
+
+impl PositionalWithSize for PendulumObject {
+    fn size(&self) -> FVec2 {
+        FVec2::new(self.data.radius, self.data.radius) * 2.0
+    }
+
+    /// Only the spike ball at the tip of the chain is dangerous
+    fn bounds(&self) -> Bounds {
+        let radius = FVec2::new(self.data.radius, self.data.radius);
+        let tip = self.state.chain.tip();
+        Bounds::new(tip - radius, tip + radius)
+    }
+}
+
+pub struct PendulumRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl PendulumRenderer {
+    /// Multiple of three, so that only whole triangles are drawn
+    const MAX_VERTEX_COUNT: usize = 3 * 2048;
+    const CHAIN_THICKNESS: f32 = 0.08;
+    /// Number of triangles the ball is made of
+    const BALL_SEGMENTS: usize = 16;
+    const SPIKE_COUNT: usize = 8;
+    /// Length of the spikes relative to the ball's radius
+    const SPIKE_LENGTH: f32 = 0.5;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "pendulum_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("pendulum_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        // The vertices change every frame, so they're written like instances
+        let vertex_buffer = create_instance_buffer::<ColoredVertex>(device, Some("pendulum_vertex_buffer"),
+            PendulumRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("pendulum_pipeline"),
+            &shader::create_shader_module(device, "tilemap.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[ColoredVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, render_pipeline }
+    }
+
+    fn append_triangle(vertices: &mut Vec<ColoredVertex>, corners: [FVec2; 3], color: Color) {
+        vertices.extend(corners.map(|corner| ColoredVertex::new(corner, color)));
+    }
+
+    fn append_vertices(object: &PendulumObject, vertices: &mut Vec<ColoredVertex>, color: Color) {
+        // Every segment of the chain is a thin quad
+        for segment in object.state.chain.points().windows(2) {
+            let direction = segment[1] - segment[0];
+            if direction.magnitude2() == 0.0 {
+                continue;
+            }
+            let side = FVec2::new(-direction.y, direction.x).normalize() * (PendulumRenderer::CHAIN_THICKNESS / 2.0);
+            PendulumRenderer::append_triangle(vertices, [segment[0] - side, segment[0] + side, segment[1] + side], color);
+            PendulumRenderer::append_triangle(vertices, [segment[0] - side, segment[1] + side, segment[1] - side], color);
+        }
+
+        let center = object.state.chain.tip();
+        let radius = object.data.radius;
+        let point_on_circle = |angle: f32, distance: f32| center + FVec2::new(angle.cos(), angle.sin()) * distance;
+
+        for i in 0..PendulumRenderer::BALL_SEGMENTS {
+            let start = i as f32 / PendulumRenderer::BALL_SEGMENTS as f32 * TAU;
+            let end = (i + 1) as f32 / PendulumRenderer::BALL_SEGMENTS as f32 * TAU;
+            PendulumRenderer::append_triangle(
+                vertices,
+                [center, point_on_circle(start, radius), point_on_circle(end, radius)],
+                color,
+            );
+        }
+
+        // Spikes rotate with the chain
+        let rotation = {
+            let direction = center - object.state.chain.points()[object.state.chain.points().len() - 2];
+            direction.y.atan2(direction.x)
+        };
+        let spike_width = TAU / PendulumRenderer::SPIKE_COUNT as f32 / 4.0;
+        for i in 0..PendulumRenderer::SPIKE_COUNT {
+            let angle = rotation + i as f32 / PendulumRenderer::SPIKE_COUNT as f32 * TAU;
+            PendulumRenderer::append_triangle(
+                vertices,
+                [
+                    point_on_circle(angle - spike_width, radius * 0.9),
+                    point_on_circle(angle, radius * (1.0 + PendulumRenderer::SPIKE_LENGTH)),
+                    point_on_circle(angle + spike_width, radius * 0.9),
+                ],
+                color,
+            );
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<PendulumObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        let mut vertices = Vec::new();
+        for object in objects.iter().filter(|object| object.solid_in(world_type)) {
+            PendulumRenderer::append_vertices(object, &mut vertices, Color::BLACK);
+        }
+        vertices.truncate(PendulumRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("pendulum_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
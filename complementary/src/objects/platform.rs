@@ -1,26 +1,36 @@
 use std::mem;
 
 use cgmath::InnerSpace;
+use complementary_macros::{JsonSchema, WgslStruct};
 use serde::Deserialize;
-use wgpu::{vertex_attr_array, include_wgsl};
+use wgpu::vertex_attr_array;
 
 use crate::{
     game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
     math::{FVec2, FMat4, Color, Direction, Bounds},
     player::{AbilityPair, Player, CollisionType},
-    rendering::{DrawState, UniformBuffer, Vertex, create_vertex_buffer, SQUARE_VERTICES, create_instance_buffer, create_pipeline_descriptor},
+    rendering::{DrawState, TransparentInstance, sort_for_blending, UniformBuffer, Vertex, create_vertex_buffer, SQUARE_VERTICES, create_instance_buffer, color_targets, create_pipeline_descriptor},
+    shader,
+    tilemap::{Tile, Tilemap},
     window::DrawContext,
 };
 
-use super::{Object, Tickable, PositionalWithSize, Collidable};
+use super::{Object, Tickable, PositionalWithSize, Collidable, Mirror, particle_system::EmitterGeometry};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct PlatformData {
     size: FVec2,
     goal: FVec2,
     speed: f32,
     spiky: (bool, bool, bool, bool),
     world_type: Option<WorldType>,
+    /// Only the top edge is solid, like `Tile::OneWayUp`
+    #[serde(default)]
+    one_way: bool,
+    /// The platform only moves while this group of `SwitchObject`s is on
+    #[serde(default)]
+    switch_group: Option<i32>,
 }
 
 #[derive(Debug)]
@@ -31,28 +41,145 @@ pub struct PlatformState {
 
 pub type PlatformObject = Object<PlatformData, PlatformState>;
 
+impl PlatformState {
+    /// The platform starts moving towards its goal
+    fn new(position: FVec2, data: &PlatformData) -> Self {
+        PlatformState { current_goal: position + data.goal, next_goal: position }
+    }
+}
+
 impl PlatformObject {
     pub fn new(position: FVec2, data: PlatformData) -> Self {
-        let state = PlatformState { current_goal: position + data.goal, next_goal: position };
+        let state = PlatformState::new(position, &data);
         Self { position, data, state }
     }
+
+    pub fn emitter_geometry(&self) -> EmitterGeometry {
+        EmitterGeometry { size: self.data.size, spiky: self.data.spiky, ..Default::default() }
+    }
+}
+
+impl PlatformObject {
+    /// Maximum gap between the player and the platform's top for the player to ride it
+    const RIDING_DISTANCE: f32 = 0.01;
+
+    /// Whether touching the platform can hurt the player, i.e. any side is spiky
+    pub fn is_hazard(&self, world_type: WorldType) -> bool {
+        let (left, right, up, down) = self.data.spiky;
+        self.solid_in(world_type) && (left || right || up || down)
+    }
+
+    /// The only world in which the platform exists, or `None` if it's in both
+    pub fn world_type(&self) -> Option<WorldType> {
+        self.data.world_type
+    }
+
+    /// Area that the platform passes through while moving between its position and its goal
+    pub fn path_bounds(&self) -> Bounds {
+        let start = self.state.next_goal;
+        let end = self.state.current_goal;
+        let min = FVec2::new(start.x.min(end.x), start.y.min(end.y));
+        let max = FVec2::new(start.x.max(end.x), start.y.max(end.y));
+        Bounds::new(min, max + self.data.size)
+    }
+
+    fn solid_in(&self, world_type: WorldType) -> bool {
+        self.data.world_type == Some(world_type) || self.data.world_type == None
+    }
+
+    /// Whether the player is standing on top of the platform
+    fn carries(&self, player: &Player) -> bool {
+        let player_bounds = player.bounds();
+        let bounds = self.bounds();
+        player_bounds.max.x > bounds.min.x
+            && player_bounds.min.x < bounds.max.x
+            && (player_bounds.max.y - bounds.min.y).abs() < Self::RIDING_DISTANCE
+    }
+
+    /// Whether something whose bottom edge moved down from `old_bottom` to `bounds.max.y` landed
+    /// on this platform if it's one-way, see `Tilemap::lands_on_one_way_platform()`
+    pub fn lands_on(&self, old_bottom: f32, bounds: &Bounds, world_type: WorldType) -> bool {
+        let top = self.position.y;
+        // The platform moves before the player, so a rider can end up slightly below its top
+        self.data.one_way
+            && self.solid_in(world_type)
+            && bounds.max.x > self.position.x
+            && bounds.min.x < self.position.x + self.data.size.x
+            && old_bottom <= top + Self::RIDING_DISTANCE
+            && bounds.max.y > top
+    }
+
+    /// Push the player out of the platform after it moved by `delta`, killing them if they're
+    /// squashed against a wall
+    fn push_player(&self, player: &mut Player, tilemap: &Tilemap, world_type: WorldType, delta: FVec2) {
+        let bounds = self.bounds();
+        let player_bounds = player.bounds();
+        if !bounds.overlaps(&player_bounds) {
+            return;
+        }
+
+        // Push along the axis that requires the smallest correction
+        let push_x = if delta.x > 0.0 {
+            bounds.max.x - player_bounds.min.x
+        } else if delta.x < 0.0 {
+            bounds.min.x - player_bounds.max.x
+        } else {
+            f32::INFINITY
+        };
+        let push_y = if delta.y > 0.0 {
+            bounds.max.y - player_bounds.min.y
+        } else if delta.y < 0.0 {
+            bounds.min.y - player_bounds.max.y
+        } else {
+            f32::INFINITY
+        };
+        let push = if push_x.abs() < push_y.abs() {
+            FVec2::new(push_x, 0.0)
+        } else {
+            FVec2::new(0.0, push_y)
+        };
+        if !push.x.is_finite() || !push.y.is_finite() {
+            return;
+        }
+
+        player.set_position(player.position() + push);
+        if tilemap.overlaps_solid(player.bounds(), world_type) {
+            player.kill();
+        }
+    }
 }
 
 impl Tickable for PlatformObject {
     fn tick(&mut self, state: &mut ObjectTickState) {
-        let delta = self.state.current_goal - self.position;
-        let distance = delta.magnitude2();
-        if distance < 0.0005 {
-            mem::swap(&mut self.state.current_goal, &mut self.state.next_goal);
+        let old_position = self.position;
+        let carrying = self.solid_in(state.world_type) && self.carries(state.player);
+
+        let moving = self.data.switch_group.map_or(true, |group| state.level_state.switch_on(group));
+        if moving {
+            let delta = self.state.current_goal - self.position;
+            let distance = delta.magnitude2();
+            if distance < 0.0005 {
+                mem::swap(&mut self.state.current_goal, &mut self.state.next_goal);
+            }
+            if distance < self.data.speed {
+                self.position = self.state.current_goal;
+                mem::swap(&mut self.state.current_goal, &mut self.state.next_goal);
+            } else {
+                self.position += delta.normalize() * self.data.speed;
+            }
         }
-        if distance < self.data.speed {
-            self.position = self.state.current_goal;
-            mem::swap(&mut self.state.current_goal, &mut self.state.next_goal);
-        } else {
-            self.position += delta.normalize() * self.data.speed;
+
+        if !self.solid_in(state.world_type) {
+            return;
         }
-        
-        // TODO: force move player
+        let movement = self.position - old_position;
+        if carrying {
+            state.player.add_base_velocity(movement);
+        } else if self.data.one_way {
+            // Players below or inside a one-way platform are passed through
+            return;
+        }
+        self.push_player(state.player, state.tilemap, state.world_type, movement);
     }
 }
 
@@ -62,9 +189,21 @@ impl PositionalWithSize for PlatformObject {
     }
 }
 
+impl Mirror for PlatformObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+        // The goal is relative to the platform's position
+        self.data.goal.x = -self.data.goal.x;
+        let (left, right, up, down) = self.data.spiky;
+        self.data.spiky = (right, left, up, down);
+        self.state = PlatformState::new(self.position, &self.data);
+    }
+}
+
 impl Collidable for PlatformObject {
     fn collides_with(&self, other: &Bounds, world_type: WorldType) -> Option<CollisionType> {
-        if self.data.world_type == Some(world_type) || self.data.world_type == None {
+        // One-way platforms depend on the direction of movement, see `lands_on()`
+        if self.solid_in(world_type) && !self.data.one_way {
             self.bounds().overlaps(other).then_some(CollisionType::Wall)
         } else {
             None
@@ -74,14 +213,15 @@ impl Collidable for PlatformObject {
 
 pub struct PlatformRenderer {
     uniform_buffer: UniformBuffer<DrawState>,
-    vertex_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    vertex_buffer: TrackedBuffer,
+    instance_buffer: TrackedBuffer,
     render_pipeline: wgpu::RenderPipeline,
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct PlatformInstance {
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+pub(crate) struct PlatformInstance {
     color: Color,
     position: FVec2,
     size: FVec2,
@@ -101,8 +241,18 @@ impl PlatformInstance {
     }
 }
 
+impl TransparentInstance for PlatformInstance {
+    fn color(&self) -> Color {
+        self.color
+    }
+
+    fn position(&self) -> FVec2 {
+        self.position
+    }
+}
+
 impl PlatformRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         let uniform_buffer = UniformBuffer::new(device, "platform_uniforms");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -118,9 +268,10 @@ impl PlatformRenderer {
 
         let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
             Some("ability_block_pipeline"),
-            &device.create_shader_module(&include_wgsl!("../shaders/platform.wgsl")),
+            &shader::create_shader_module(device, "platform.wgsl", &[]),
             Some(&pipeline_layout),
             &[Vertex::layout(), PlatformInstance::layout()],
+            &color_targets(format),
         ));
 
         Self { uniform_buffer, vertex_buffer, instance_buffer, render_pipeline }
@@ -133,20 +284,25 @@ impl PlatformRenderer {
         state: &DrawState,
         world_type: WorldType,
     ) {
-        let instances: Vec<_> = objects.iter().map(|obj| PlatformInstance {
+        let mut instances: Vec<_> = objects.iter().map(|obj| PlatformInstance {
             color: match obj.data.world_type {
                 Some(ty) => {
                     if ty == world_type {
-                        ty.foreground_color()
+                        Color::BLACK
                     } else {
                         Color::TRANSPARENT
                     }
                 },
-                None => world_type.foreground_color(),
+                None => Color::BLACK,
             },
             position: obj.position,
-            size: obj.data.size,
+            size: if obj.data.one_way {
+                FVec2::new(obj.data.size.x, obj.data.size.y.min(Tile::ONE_WAY_THICKNESS))
+            } else {
+                obj.data.size
+            },
         }).collect();
+        sort_for_blending(&mut instances);
 
         self.uniform_buffer
             .write_with_queue(context.queue, state.clone());
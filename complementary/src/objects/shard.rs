@@ -0,0 +1,168 @@
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    audio::{self, SoundId},
+    game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
+    math::{Color, FVec2},
+    rendering::{color_targets, create_instance_buffer, create_pipeline_descriptor, ColoredVertex, DrawState, UniformBuffer},
+    shader,
+    timer::TickTimer,
+    window::DrawContext,
+};
+
+use super::{Mirror, Object, PositionalWithSize, Tickable};
+
+/// Optional collectible. Levels don't need them to be completed, the most shards collected in
+/// each level are kept in the save file.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ShardData {}
+
+#[derive(Debug)]
+pub enum ShardState {
+    Collectible,
+    /// The shard fades out while `fade` runs
+    Collected { fade: TickTimer },
+}
+
+pub type ShardObject = Object<ShardData, ShardState>;
+
+impl ShardObject {
+    const FADE_TICKS: i32 = 30;
+
+    pub fn new(position: FVec2, data: ShardData) -> Self {
+        Self { position, data, state: ShardState::Collectible }
+    }
+
+    pub fn collected(&self) -> bool {
+        matches!(self.state, ShardState::Collected { .. })
+    }
+
+    fn alpha(&self) -> f32 {
+        match self.state {
+            ShardState::Collectible => 1.0,
+            ShardState::Collected { fade } => 1.0 - fade.progress(),
+        }
+    }
+}
+
+impl Tickable for ShardObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        match self.state {
+            ShardState::Collectible => {
+                if state.player.allowed_to_move() && self.bounds().overlaps(&state.player.bounds()) {
+                    state.level_state.add_collected_shard();
+                    let mut fade = TickTimer::default();
+                    fade.start(Self::FADE_TICKS);
+                    self.state = ShardState::Collected { fade };
+                    audio::play_sound(SoundId::KeyPickup);
+                }
+            }
+            ShardState::Collected { ref mut fade } => fade.tick(),
+        }
+    }
+}
+
+impl PositionalWithSize for ShardObject {
+    fn size(&self) -> FVec2 {
+        FVec2::new(1.0, 1.0)
+    }
+}
+
+impl Mirror for ShardObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.size().x, width);
+    }
+}
+
+pub struct ShardRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl ShardRenderer {
+    /// Six vertices per shard
+    const MAX_VERTEX_COUNT: usize = 6 * 128;
+    const COLOR: Color = Color::new_solid(0.4, 0.9, 1.0);
+    /// Distance from the center of the tile to the corners of the diamond
+    const RADIUS: f32 = 0.3;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "shard_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("shard_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffer = create_instance_buffer::<ColoredVertex>(device, Some("shard_vertex_buffer"),
+            ShardRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("shard_pipeline"),
+            &shader::create_shader_module(device, "tilemap.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[ColoredVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, render_pipeline }
+    }
+
+    /// A diamond in the center of the tile, made of two triangles
+    fn append_vertices(object: &ShardObject, vertices: &mut Vec<ColoredVertex>, world_type: WorldType) {
+        let color = world_type.uninverted_color(ShardRenderer::COLOR);
+        let color = color.with_alpha(color.a * object.alpha());
+        let center = object.position + FVec2::new(0.5, 0.5);
+        let top = center - FVec2::new(0.0, ShardRenderer::RADIUS);
+        let bottom = center + FVec2::new(0.0, ShardRenderer::RADIUS);
+        let left = center - FVec2::new(ShardRenderer::RADIUS, 0.0);
+        let right = center + FVec2::new(ShardRenderer::RADIUS, 0.0);
+        for position in [left, top, right, left, right, bottom] {
+            vertices.push(ColoredVertex::new(position, color));
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<ShardObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        let mut vertices = Vec::new();
+        for object in objects.iter().filter(|object| object.alpha() > 0.0) {
+            ShardRenderer::append_vertices(object, &mut vertices, world_type);
+        }
+        vertices.truncate(ShardRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("shard_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
@@ -0,0 +1,186 @@
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    audio::{self, SoundId},
+    game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
+    input::ButtonType,
+    math::{Bounds, Color, FVec2},
+    rendering::{color_targets, create_instance_buffer, create_pipeline_descriptor, ColoredVertex, DrawState, UniformBuffer},
+    shader,
+    tilemap::TilemapRenderer,
+    window::DrawContext,
+};
+
+use super::{Mirror, Object, PositionalWithSize, Tickable};
+
+/// How the player flips a switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+pub enum SwitchActivation {
+    /// Flipped whenever the player starts touching the switch
+    Touch,
+    /// Flipped by pressing Down while touching the switch
+    Press,
+}
+
+impl Default for SwitchActivation {
+    fn default() -> Self {
+        SwitchActivation::Touch
+    }
+}
+
+/// Lever that toggles its group in `LevelState`. Doors and platforms with the same
+/// `switch_group` are only open or moving while the group is on.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SwitchData {
+    group: i32,
+    #[serde(default)]
+    activation: SwitchActivation,
+}
+
+#[derive(Debug)]
+pub struct SwitchState {
+    /// Whether the player touched the switch in the last tick, so that touching it only
+    /// flips it once
+    touching: bool,
+    /// The group's flag, copied from the `LevelState` every tick
+    on: bool,
+}
+
+pub type SwitchObject = Object<SwitchData, SwitchState>;
+
+impl SwitchObject {
+    pub fn new(position: FVec2, data: SwitchData) -> Self {
+        Self { position, data, state: SwitchState { touching: false, on: false } }
+    }
+
+    pub fn group(&self) -> i32 {
+        self.data.group
+    }
+
+    pub fn on(&self) -> bool {
+        self.state.on
+    }
+}
+
+impl Tickable for SwitchObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        let touching = state.player.allowed_to_move() && self.bounds().overlaps(&state.player.bounds());
+        let flipped = touching
+            && match self.data.activation {
+                SwitchActivation::Touch => !self.state.touching,
+                SwitchActivation::Press => state.input.get_button(ButtonType::Down).pressed_first_frame(),
+            };
+        self.state.touching = touching;
+        if flipped {
+            state.level_state.toggle_switch(self.data.group);
+            audio::play_sound(SoundId::Switch);
+        }
+        self.state.on = state.level_state.switch_on(self.data.group);
+    }
+}
+
+impl PositionalWithSize for SwitchObject {
+    fn size(&self) -> FVec2 {
+        FVec2::new(1.0, 1.0)
+    }
+}
+
+impl Mirror for SwitchObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.size().x, width);
+    }
+}
+
+pub struct SwitchRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl SwitchRenderer {
+    /// Two rectangles with six vertices each per switch
+    const MAX_VERTEX_COUNT: usize = 12 * 64;
+    const BASE_COLOR: Color = Color::DARK_GRAY;
+    const OFF_COLOR: Color = Color::new(0.6, 0.2, 0.2, 1.0);
+    const ON_COLOR: Color = Color::new(0.3, 0.8, 0.3, 1.0);
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "switch_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("switch_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffer = create_instance_buffer::<ColoredVertex>(device, Some("switch_vertex_buffer"),
+            SwitchRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("switch_pipeline"),
+            &shader::create_shader_module(device, "tilemap.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[ColoredVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, render_pipeline }
+    }
+
+    /// A base in the bottom half of the tile and a lever that leans right while the switch is on
+    fn append_vertices(object: &SwitchObject, vertices: &mut Vec<ColoredVertex>, world_type: WorldType) {
+        let position = object.position;
+        let base = Bounds::new(position + FVec2::new(0.1, 0.6), position + FVec2::new(0.9, 1.0));
+        TilemapRenderer::append_rectangle(vertices, base, world_type.uninverted_color(SwitchRenderer::BASE_COLOR));
+
+        let (lever_x, color) = if object.on() {
+            (0.55, SwitchRenderer::ON_COLOR)
+        } else {
+            (0.2, SwitchRenderer::OFF_COLOR)
+        };
+        let lever = Bounds::new(position + FVec2::new(lever_x, 0.2), position + FVec2::new(lever_x + 0.25, 0.6));
+        TilemapRenderer::append_rectangle(vertices, lever, world_type.uninverted_color(color));
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<SwitchObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        let mut vertices = Vec::new();
+        for object in objects {
+            SwitchRenderer::append_vertices(object, &mut vertices, world_type);
+        }
+        vertices.truncate(SwitchRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("switch_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
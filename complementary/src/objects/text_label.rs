@@ -0,0 +1,93 @@
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    game::{ObjectTickState, WorldType},
+    localization,
+    math::{Color, FVec2},
+    rendering::{text::{self, TextRenderer}, DrawState},
+    window::DrawContext,
+};
+
+use super::{Mirror, Object, PositionalWithSize, Tickable};
+
+/// Sign or hint drawn into the level. Unlike tutorials, the text isn't tied to the player's
+/// abilities and is always shown.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TextLabelData {
+    /// Localization key or literal text, lines are separated by '\n'
+    text: String,
+    /// Height of a line in tiles
+    #[serde(default = "TextLabelData::default_size")]
+    size: f32,
+    /// The label is only shown in this world, or in both if `None`
+    #[serde(default)]
+    world_type: Option<WorldType>,
+}
+
+impl TextLabelData {
+    fn default_size() -> f32 {
+        1.0
+    }
+}
+
+pub type TextLabelObject = Object<TextLabelData, ()>;
+
+impl TextLabelObject {
+    pub fn new(position: FVec2, data: TextLabelData) -> Self {
+        Self { position, data, state: () }
+    }
+
+    /// Localized text, see `localization::tr()`
+    pub fn text(&self) -> String {
+        localization::tr(&self.data.text)
+    }
+
+    pub fn visible_in(&self, world_type: WorldType) -> bool {
+        self.data.world_type.map_or(true, |ty| ty == world_type)
+    }
+}
+
+impl Tickable for TextLabelObject {
+    fn tick(&mut self, _state: &mut ObjectTickState) {
+    }
+}
+
+impl PositionalWithSize for TextLabelObject {
+    fn size(&self) -> FVec2 {
+        text::measure(&self.text(), self.data.size)
+    }
+}
+
+impl Mirror for TextLabelObject {
+    fn mirror(&mut self, width: f32) {
+        // The text itself stays readable
+        self.mirror_position(self.size().x, width);
+    }
+}
+
+pub struct TextLabelRenderer {
+    text_renderer: TextRenderer,
+}
+
+impl TextLabelRenderer {
+    /// Inverted with the scene, so labels are dark in the Light world and light in the Dark one
+    const COLOR: Color = Color::DARK_GRAY;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self { text_renderer: TextRenderer::new(device, format) }
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<TextLabelObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        for object in objects.iter().filter(|object| object.visible_in(world_type)) {
+            self.text_renderer.queue_text(&object.text(), object.position, object.data.size, TextLabelRenderer::COLOR);
+        }
+        self.text_renderer.flush(context, state);
+    }
+}
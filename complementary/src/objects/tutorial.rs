@@ -1,35 +1,93 @@
+use complementary_macros::JsonSchema;
 use serde::Deserialize;
 
 use crate::{
     game::{ObjectTickState, WorldType},
-    rendering::DrawState,
-    window::DrawContext, math::FVec2,
+    localization,
+    math::{Color, FVec2},
+    rendering::{text::{self, TextRenderer}, DrawState},
+    window::DrawContext,
 };
 
-use super::{Object, Tickable};
+use super::{Mirror, Object, PositionalWithSize, Tickable};
 
-#[derive(Debug, Deserialize)]
-pub struct TutorialData {}
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TutorialData {
+    tutorial_type: TutorialType,
+    /// The text is shown while the player is inside this area
+    size: FVec2,
+    /// Show the text right away instead of fading it in
+    instant: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, JsonSchema)]
+pub enum TutorialType {
+    WorldSwitch,
+    Jump,
+    DashSwitchCombo,
+    DoubleJump,
+    Glider,
+    Dash,
+    WallJump,
+}
+
+#[derive(Debug, Default)]
+pub struct TutorialState {
+    /// Opacity of the text, fades towards 1 while the player is inside the tutorial area
+    alpha: f32,
+}
 
-pub type TutorialObject = Object<TutorialData, ()>;
+pub type TutorialObject = Object<TutorialData, TutorialState>;
 
 impl TutorialObject {
+    const FADE_TICKS: f32 = 20.0;
+
     pub fn new(position: FVec2, data: TutorialData) -> Self {
-        Self { position, data, state: () }
+        Self { position, data, state: TutorialState::default() }
+    }
+
+    /// Localized tutorial text with the player's button prompts, see
+    /// `localization::tr_with_prompts()`
+    pub fn text(&self) -> String {
+        localization::tr_with_prompts(&format!("tutorial.{:?}", self.data.tutorial_type))
     }
 }
 
 impl Tickable for TutorialObject {
     fn tick(&mut self, state: &mut ObjectTickState) {
+        let inside = self.bounds().overlaps(&state.player.bounds());
+        self.state.alpha = match (inside, self.data.instant) {
+            (true, true) => 1.0,
+            (true, false) => (self.state.alpha + 1.0 / TutorialObject::FADE_TICKS).min(1.0),
+            (false, _) => (self.state.alpha - 1.0 / TutorialObject::FADE_TICKS).max(0.0),
+        };
     }
 }
 
-#[derive(Debug)]
-pub struct TutorialRenderer {}
+impl PositionalWithSize for TutorialObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for TutorialObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+    }
+}
+
+pub struct TutorialRenderer {
+    text_renderer: TextRenderer,
+}
 
 impl TutorialRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
-        Self {}
+    /// Same color as text labels, so it stays readable in both worlds
+    const COLOR: Color = Color::DARK_GRAY;
+    /// Height of a line in tiles
+    const LINE_HEIGHT: f32 = 0.75;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self { text_renderer: TextRenderer::new(device, format) }
     }
 
     pub fn draw(
@@ -37,7 +95,16 @@ impl TutorialRenderer {
         objects: &Vec<TutorialObject>,
         context: &mut DrawContext,
         state: &DrawState,
-        world_type: WorldType,
+        _world_type: WorldType,
     ) {
+        for object in objects.iter().filter(|object| object.state.alpha > 0.0) {
+            let text = object.text();
+            // Centered at the top of the tutorial area
+            let size = text::measure(&text, TutorialRenderer::LINE_HEIGHT);
+            let position = object.position + FVec2::new((object.data.size.x - size.x) / 2.0, 0.0);
+            let color = TutorialRenderer::COLOR.with_alpha(object.state.alpha);
+            self.text_renderer.queue_text(&text, position, TutorialRenderer::LINE_HEIGHT, color);
+        }
+        self.text_renderer.flush(context, state);
     }
 }
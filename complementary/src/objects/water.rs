@@ -0,0 +1,186 @@
+use log::warn;
+use complementary_macros::JsonSchema;
+use serde::Deserialize;
+
+use crate::{
+    game::{ObjectTickState, WorldType},
+    gpu_memory::TrackedBuffer,
+    math::{Bounds, Color, FVec2},
+    rendering::{color_targets, create_instance_buffer, create_pipeline_descriptor, ColoredVertex, DrawState, UniformBuffer},
+    shader,
+    tilemap::TilemapRenderer,
+    window::DrawContext,
+};
+
+use super::{particle_system::ParticleSystemObject, Mirror, Object, PositionalWithSize, Tickable};
+
+/// Liquid volume which slows the player down and lets them swim. The top edge is the surface.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WaterData {
+    size: FVec2,
+}
+
+#[derive(Debug, Default)]
+pub struct WaterState {
+    /// Whether the player overlapped the water in the last tick, used to detect splashes
+    player_inside: bool,
+    /// Drives the waves on the surface
+    elapsed_ticks: i32,
+}
+
+pub type WaterObject = Object<WaterData, WaterState>;
+
+impl WaterObject {
+    pub fn new(position: FVec2, data: WaterData) -> Self {
+        Self { position, data, state: WaterState::default() }
+    }
+
+    /// Vertical offset of the surface at `x` in world space
+    fn wave_offset(&self, x: f32) -> f32 {
+        const AMPLITUDE: f32 = 0.04;
+        AMPLITUDE * (x * 2.0 + self.state.elapsed_ticks as f32 * 0.05).sin()
+    }
+
+    /// Splash where the player crosses the surface
+    fn splash(&self, state: &mut ObjectTickState) {
+        let position = FVec2::new(state.player.bounds().center().x, self.position.y);
+        match ParticleSystemObject::load_prefab("splash", position) {
+            Ok(splash) => state.spawned_effects.push(splash),
+            Err(err) => warn!("Failed to load splash effect: {err}"),
+        }
+    }
+}
+
+impl PositionalWithSize for WaterObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for WaterObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+    }
+}
+
+impl Tickable for WaterObject {
+    fn tick(&mut self, state: &mut ObjectTickState) {
+        self.state.elapsed_ticks = self.state.elapsed_ticks.wrapping_add(1);
+
+        let inside = self.bounds().overlaps(&state.player.bounds());
+        if inside && state.player.allowed_to_move() {
+            state.player.set_submerged();
+        }
+        if inside != self.state.player_inside {
+            self.splash(state);
+        }
+        self.state.player_inside = inside;
+    }
+}
+
+pub struct WaterRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl WaterRenderer {
+    /// Multiple of six, so that only whole rectangles are drawn
+    const MAX_VERTEX_COUNT: usize = 6 * 1024;
+    /// Width of the segments the surface is made of, in tiles
+    const SURFACE_SEGMENT_WIDTH: f32 = 0.25;
+    const SURFACE_THICKNESS: f32 = 0.06;
+    const BODY_COLOR: Color = Color::new(0.2, 0.45, 0.9, 0.35);
+    const SURFACE_COLOR: Color = Color::new(0.6, 0.8, 1.0, 0.9);
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "water_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("water_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        // The vertices change every frame, so they're written like instances
+        let vertex_buffer = create_instance_buffer::<ColoredVertex>(device, Some("water_vertex_buffer"),
+            WaterRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("water_pipeline"),
+            &shader::create_shader_module(device, "tilemap.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[ColoredVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, vertex_buffer, render_pipeline }
+    }
+
+    fn append_vertices(object: &WaterObject, vertices: &mut Vec<ColoredVertex>, world_type: WorldType) {
+        let bounds = object.bounds();
+        let body_color = world_type.uninverted_color(WaterRenderer::BODY_COLOR);
+        let surface_color = world_type.uninverted_color(WaterRenderer::SURFACE_COLOR);
+
+        // The body starts below the lowest point of the waves, each surface segment fills the gap
+        let body_top = bounds.min.y + WaterRenderer::SURFACE_THICKNESS;
+        TilemapRenderer::append_rectangle(vertices, Bounds::new(FVec2::new(bounds.min.x, body_top), bounds.max), body_color);
+
+        let mut x = bounds.min.x;
+        while x < bounds.max.x {
+            let right = (x + WaterRenderer::SURFACE_SEGMENT_WIDTH).min(bounds.max.x);
+            let top = bounds.min.y + object.wave_offset((x + right) / 2.0);
+            TilemapRenderer::append_rectangle(
+                vertices,
+                Bounds::new(FVec2::new(x, top), FVec2::new(right, body_top)),
+                body_color,
+            );
+            TilemapRenderer::append_rectangle(
+                vertices,
+                Bounds::new(FVec2::new(x, top), FVec2::new(right, top + WaterRenderer::SURFACE_THICKNESS)),
+                surface_color,
+            );
+            x = right;
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        objects: &Vec<WaterObject>,
+        context: &mut DrawContext,
+        state: &DrawState,
+        world_type: WorldType,
+    ) {
+        let mut vertices = Vec::new();
+        for object in objects {
+            WaterRenderer::append_vertices(object, &mut vertices, world_type);
+        }
+        vertices.truncate(WaterRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("water_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
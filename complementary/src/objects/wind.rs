@@ -1,3 +1,4 @@
+use complementary_macros::JsonSchema;
 use serde::Deserialize;
 
 use crate::{
@@ -6,21 +7,59 @@ use crate::{
     window::DrawContext, math::FVec2,
 };
 
-use super::{Object, Tickable};
+use super::{Object, Tickable, PositionalWithSize, Mirror, particle_system::EmitterGeometry};
 
-#[derive(Debug, Deserialize)]
-pub struct WindData {}
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct WindData {
+    size: FVec2,
+    force: FVec2,
+}
 
 pub type WindObject = Object<WindData, ()>;
 
 impl WindObject {
+    /// Gliders catch more of the wind, which allows them to ride updrafts
+    const GLIDER_FORCE_FACTOR: f32 = 3.0;
+
     pub fn new(position: FVec2, data: WindData) -> Self {
         Self { position, data, state: () }
     }
+
+    /// Force applied to anything at `point`, or `None` if the point is outside of the wind
+    pub fn force_at(&self, point: FVec2) -> Option<FVec2> {
+        self.bounds().contains_point(point).then_some(self.data.force)
+    }
+
+    pub fn emitter_geometry(&self) -> EmitterGeometry {
+        EmitterGeometry { size: self.data.size, force: self.data.force, ..Default::default() }
+    }
+}
+
+impl PositionalWithSize for WindObject {
+    fn size(&self) -> FVec2 {
+        self.data.size
+    }
+}
+
+impl Mirror for WindObject {
+    fn mirror(&mut self, width: f32) {
+        self.mirror_position(self.data.size.x, width);
+        self.data.force.x = -self.data.force.x;
+    }
 }
 
 impl Tickable for WindObject {
     fn tick(&mut self, state: &mut ObjectTickState) {
+        let player = &mut state.player;
+        if !player.allowed_to_move() || !self.bounds().overlaps(&player.bounds()) {
+            return;
+        }
+
+        if player.gliding(state.input, state.world_type) {
+            player.add_force(self.data.force * WindObject::GLIDER_FORCE_FACTOR);
+        } else {
+            player.add_force(self.data.force);
+        }
     }
 }
 
@@ -28,7 +67,7 @@ impl Tickable for WindObject {
 pub struct WindRenderer {}
 
 impl WindRenderer {
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         Self {}
     }
 
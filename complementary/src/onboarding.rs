@@ -0,0 +1,238 @@
+use crate::{
+    input::{ButtonType, Input},
+    localization,
+    post_processing,
+    settings::{ButtonPrompts, Settings},
+    ui_layout::{anchored_position, Anchor},
+};
+
+/// Screens of the onboarding, in the order they're shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnboardingStep {
+    InputDevice,
+    Brightness,
+    Accessibility,
+}
+
+/// Entries of the accessibility screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AccessibilityItem {
+    ReduceMotion,
+    ReduceFlashing,
+    Hitstop,
+    Continue,
+}
+
+impl AccessibilityItem {
+    const ALL: [Self; 4] = [
+        AccessibilityItem::ReduceMotion,
+        AccessibilityItem::ReduceFlashing,
+        AccessibilityItem::Hitstop,
+        AccessibilityItem::Continue,
+    ];
+
+    /// The setting toggled by this entry, `None` for "Continue"
+    fn setting(self, settings: &mut Settings) -> Option<&mut bool> {
+        match self {
+            AccessibilityItem::ReduceMotion => Some(&mut settings.reduce_motion),
+            AccessibilityItem::ReduceFlashing => Some(&mut settings.reduce_flashing),
+            AccessibilityItem::Hitstop => Some(&mut settings.hitstop),
+            AccessibilityItem::Continue => None,
+        }
+    }
+
+    fn label(self) -> String {
+        localization::tr(match self {
+            AccessibilityItem::ReduceMotion => "onboarding.reduce_motion",
+            AccessibilityItem::ReduceFlashing => "onboarding.reduce_flashing",
+            AccessibilityItem::Hitstop => "onboarding.hitstop",
+            AccessibilityItem::Continue => "onboarding.continue",
+        })
+    }
+}
+
+/// Shown on the first launch, before there's a settings file: the player picks their input
+/// device, calibrates the brightness and can turn on accessibility options. The settings file
+/// is written afterwards, so it's only shown once.
+///
+/// Like the `PauseMenu`, it's navigated with the game's buttons.
+#[derive(Debug)]
+pub struct Onboarding {
+    step: OnboardingStep,
+    selected: usize,
+    /// Prompts used when the controller is picked, keeps the handheld preset's glyphs
+    controller_prompts: ButtonPrompts,
+    finished: bool,
+}
+
+impl Onboarding {
+    const GAMMA_STEP: f32 = 0.05;
+    /// Gray levels of the calibration squares before gamma correction
+    const CALIBRATION_LEVELS: [f32; 3] = [0.02, 0.06, 0.15];
+    const CALIBRATION_SQUARE_SIZE: f32 = 80.0;
+
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            step: OnboardingStep::InputDevice,
+            // Start with the device that was detected, e.g. a controller on handhelds
+            selected: match settings.button_prompts {
+                ButtonPrompts::Keyboard => 0,
+                ButtonPrompts::Controller | ButtonPrompts::Handheld => 1,
+            },
+            controller_prompts: match settings.button_prompts {
+                ButtonPrompts::Handheld => ButtonPrompts::Handheld,
+                _ => ButtonPrompts::Controller,
+            },
+            finished: false,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Returns true if a setting was changed, so that it can be applied right away
+    pub fn tick(&mut self, input: &Input, settings: &mut Settings) -> bool {
+        let pressed = |button| input.get_button(button).pressed_first_frame();
+        let confirmed = pressed(ButtonType::Confirm);
+
+        match self.step {
+            OnboardingStep::InputDevice => {
+                if pressed(ButtonType::Up) || pressed(ButtonType::Down) {
+                    self.selected = 1 - self.selected;
+                }
+                let button_prompts = if self.selected == 0 {
+                    ButtonPrompts::Keyboard
+                } else {
+                    self.controller_prompts
+                };
+                let changed = settings.button_prompts != button_prompts;
+                settings.button_prompts = button_prompts;
+                if confirmed {
+                    self.step = OnboardingStep::Brightness;
+                }
+                changed
+            }
+            OnboardingStep::Brightness => {
+                let mut gamma = settings.gamma;
+                if pressed(ButtonType::Left) {
+                    gamma -= Onboarding::GAMMA_STEP;
+                }
+                if pressed(ButtonType::Right) {
+                    gamma += Onboarding::GAMMA_STEP;
+                }
+                gamma = gamma.clamp(Settings::MIN_GAMMA, Settings::MAX_GAMMA);
+                let changed = gamma != settings.gamma;
+                settings.gamma = gamma;
+                if confirmed {
+                    self.step = OnboardingStep::Accessibility;
+                    self.selected = 0;
+                }
+                changed
+            }
+            OnboardingStep::Accessibility => {
+                let count = AccessibilityItem::ALL.len();
+                if pressed(ButtonType::Up) {
+                    self.selected = (self.selected + count - 1) % count;
+                }
+                if pressed(ButtonType::Down) {
+                    self.selected = (self.selected + 1) % count;
+                }
+                if !confirmed {
+                    return false;
+                }
+                match AccessibilityItem::ALL[self.selected].setting(settings) {
+                    Some(value) => {
+                        *value = !*value;
+                        true
+                    }
+                    None => {
+                        self.finished = true;
+                        false
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn draw_gui(&self, gui: &imgui::Ui, settings: &Settings) {
+        let display_size = gui.io().display_size;
+        // Black rather than translucent so that the calibration isn't affected by the level
+        gui.get_background_draw_list()
+            .add_rect([0.0, 0.0], display_size, [0.0, 0.0, 0.0, 1.0])
+            .filled(true)
+            .build();
+
+        let (title, lines) = match self.step {
+            OnboardingStep::InputDevice => (
+                localization::tr("onboarding.input_device"),
+                vec![
+                    self.entry(0, localization::tr("onboarding.keyboard")),
+                    self.entry(1, localization::tr("onboarding.controller")),
+                ],
+            ),
+            OnboardingStep::Brightness => {
+                self.draw_calibration(gui, settings.gamma);
+                (
+                    localization::tr("onboarding.brightness"),
+                    vec![
+                        localization::tr("onboarding.brightness_hint"),
+                        format!("< {:.2} >", settings.gamma),
+                    ],
+                )
+            }
+            OnboardingStep::Accessibility => {
+                let mut settings = settings.clone();
+                let lines = AccessibilityItem::ALL
+                    .iter()
+                    .enumerate()
+                    .map(|(index, item)| {
+                        let label = match item.setting(&mut settings) {
+                            Some(value) => format!("{}: {}", item.label(), if *value { "On" } else { "Off" }),
+                            None => item.label(),
+                        };
+                        self.entry(index, label)
+                    })
+                    .collect();
+                (localization::tr("onboarding.accessibility"), lines)
+            }
+        };
+
+        let draw_list = gui.get_background_draw_list();
+        let line_height = gui.calc_text_size(&title)[1] * 2.0;
+        let mut y = display_size[1] * 0.2;
+        for (index, line) in std::iter::once(&title).chain(lines.iter()).enumerate() {
+            let [x, _] = anchored_position(Anchor::CENTER, gui.calc_text_size(line), [0.0, 0.0], display_size);
+            draw_list.add_text([x, y], [1.0, 1.0, 1.0, 1.0], line);
+            y += if index == 0 { line_height * 2.0 } else { line_height };
+        }
+    }
+
+    /// Highlight the selected entry the same way as the pause menu
+    fn entry(&self, index: usize, label: String) -> String {
+        if index == self.selected {
+            format!("> {label} <")
+        } else {
+            label
+        }
+    }
+
+    /// Squares that are just above black. ImGui is drawn after post-processing, so the gamma
+    /// curve is applied to their colors here instead.
+    fn draw_calibration(&self, gui: &imgui::Ui, gamma: f32) {
+        let size = Onboarding::CALIBRATION_SQUARE_SIZE;
+        let count = Onboarding::CALIBRATION_LEVELS.len() as f32;
+        let row_size = [size * (count * 2.0 - 1.0), size];
+        let [mut x, y] = anchored_position(Anchor::CENTER, row_size, [0.0, 0.0], gui.io().display_size);
+
+        let draw_list = gui.get_background_draw_list();
+        for level in Onboarding::CALIBRATION_LEVELS {
+            let value = post_processing::gamma_correct(level, gamma);
+            draw_list
+                .add_rect([x, y], [x + size, y + size], [value, value, value, 1.0])
+                .filled(true)
+                .build();
+            x += size * 2.0;
+        }
+    }
+}
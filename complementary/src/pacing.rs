@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::game::Game;
+
+/// Keeps frames in step with the display's refresh rate.
+///
+/// The game ticks at a fixed rate (see `Game::TICK_DURATION`) which usually doesn't match the
+/// refresh rate, e.g. at 144 Hz some frames run one tick and others none. Measured frame times
+/// jitter around the refresh interval, which makes that pattern irregular and the motion
+/// stutter. Frame times close to a multiple of the refresh interval are snapped to it, so
+/// the ticks are spread evenly across frames.
+pub struct FramePacer {
+    /// Refresh rate of the display the window is on in Hz, zero if unknown
+    refresh_rate: i32,
+    last_frame: Instant,
+    /// Real time between the last two frames
+    last_frame_time: Duration,
+    /// Difference between the real and snapped time, so the game doesn't drift from real time
+    snap_error: f64,
+    /// Recent frame times and the number of ticks run in each frame, newest last
+    history: VecDeque<(Duration, i32)>,
+}
+
+impl FramePacer {
+    /// Frame times are snapped if they are within this fraction of the refresh interval
+    const SNAP_TOLERANCE: f64 = 0.15;
+    const HISTORY_LENGTH: usize = 240;
+    /// Assumed if the refresh rate is unknown
+    const FALLBACK_REFRESH_RATE: f64 = 60.0;
+
+    pub fn new(refresh_rate: i32) -> Self {
+        Self {
+            refresh_rate,
+            last_frame: Instant::now(),
+            last_frame_time: Duration::ZERO,
+            snap_error: 0.0,
+            history: VecDeque::with_capacity(FramePacer::HISTORY_LENGTH),
+        }
+    }
+
+    pub fn refresh_rate(&self) -> i32 {
+        self.refresh_rate
+    }
+
+    pub fn set_refresh_rate(&mut self, refresh_rate: i32) {
+        self.refresh_rate = refresh_rate;
+        self.snap_error = 0.0;
+    }
+
+    fn refresh_interval(&self) -> Option<f64> {
+        (self.refresh_rate > 0).then(|| 1.0 / self.refresh_rate as f64)
+    }
+
+    /// Time between two refreshes, the time a frame should take
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.refresh_interval().unwrap_or(1.0 / FramePacer::FALLBACK_REFRESH_RATE))
+    }
+
+    /// Time since the previous frame that should be simulated, snapped to the refresh interval
+    pub fn frame_elapsed(&mut self) -> Duration {
+        let now = Instant::now();
+        self.last_frame_time = now - self.last_frame;
+        self.last_frame = now;
+        let elapsed = self.last_frame_time.as_secs_f64();
+
+        let interval = match self.refresh_interval() {
+            Some(interval) => interval,
+            None => return Duration::from_secs_f64(elapsed),
+        };
+        let frames = (elapsed / interval).round();
+        let snapped = frames * interval;
+        let error = self.snap_error + elapsed - snapped;
+        if frames >= 1.0 && (elapsed - snapped).abs() < interval * FramePacer::SNAP_TOLERANCE && error.abs() < interval {
+            self.snap_error = error;
+            Duration::from_secs_f64(snapped)
+        } else {
+            // Missed the refresh, catch up with real time
+            self.snap_error = 0.0;
+            Duration::from_secs_f64(elapsed)
+        }
+    }
+
+    /// Remember how many ticks the current frame ran, for the measurements in the DevGUI
+    pub fn record_frame(&mut self, ticks: i32) {
+        if self.history.len() == FramePacer::HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.last_frame_time, ticks));
+    }
+
+    /// Wait until the next refresh, so that frames aren't submitted faster than the display
+    /// can show them without vsync
+    pub fn wait_for_next_frame(&self) {
+        if let Some(interval) = self.refresh_interval() {
+            let next_frame = self.last_frame + Duration::from_secs_f64(interval);
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            }
+        }
+    }
+
+    pub fn draw_gui(&self, gui: &imgui::Ui) {
+        // Adds a section to the game's DevGUI window
+        let _token = match imgui::Window::new("DevGUI").begin(gui) {
+            Some(token) => token,
+            None => return,
+        };
+        if !gui.collapsing_header("Frame pacing", imgui::TreeNodeFlags::empty()) {
+            return;
+        }
+
+        gui.indent();
+        match self.refresh_rate {
+            0 => gui.text("Refresh rate: unknown"),
+            refresh_rate => gui.text(format!("Refresh rate: {refresh_rate} Hz")),
+        }
+        gui.text(format!(
+            "Tick rate: {:.0} Hz",
+            1.0 / Game::TICK_DURATION.as_secs_f64()
+        ));
+        if !self.history.is_empty() {
+            let frame_times: Vec<f32> = self
+                .history
+                .iter()
+                .map(|(frame_time, _)| frame_time.as_secs_f32() * 1000.0)
+                .collect();
+            let average = frame_times.iter().sum::<f32>() / frame_times.len() as f32;
+            let max = frame_times.iter().cloned().fold(0.0, f32::max);
+            gui.text(format!("Frame time: {average:.2} ms avg, {max:.2} ms max"));
+
+            let mut ticks_per_frame = [0; 3];
+            for (_, ticks) in &self.history {
+                ticks_per_frame[(*ticks as usize).min(2)] += 1;
+            }
+            gui.text(format!(
+                "Ticks per frame: 0: {}, 1: {}, 2+: {}",
+                ticks_per_frame[0], ticks_per_frame[1], ticks_per_frame[2]
+            ));
+            gui.plot_lines("Frame times", &frame_times)
+                .scale_min(0.0)
+                .graph_size([0.0, 60.0])
+                .build();
+        }
+        gui.unindent();
+    }
+}
@@ -0,0 +1,83 @@
+use crate::math::{Bounds, FVec2, InnerSpace};
+
+/// Rope of point masses with a fixed distance between neighbours, simulated with Verlet
+/// integration. The first point is the anchor and never moves.
+#[derive(Debug, Clone)]
+pub struct Chain {
+    points: Vec<FVec2>,
+    /// Positions in the last tick, the difference to `points` is the velocity
+    previous_points: Vec<FVec2>,
+    segment_length: f32,
+}
+
+impl Chain {
+    /// More iterations make the chain stiffer
+    const CONSTRAINT_ITERATIONS: usize = 8;
+
+    /// Create a straight chain at rest between `anchor` and `tip`
+    pub fn new(anchor: FVec2, tip: FVec2, segment_count: usize) -> Self {
+        let segment_count = segment_count.max(1);
+        let points: Vec<FVec2> = (0..=segment_count)
+            .map(|i| anchor + (tip - anchor) * (i as f32 / segment_count as f32))
+            .collect();
+        Chain {
+            previous_points: points.clone(),
+            points,
+            segment_length: (tip - anchor).magnitude() / segment_count as f32,
+        }
+    }
+
+    pub fn tick(&mut self, gravity: FVec2) {
+        for (point, previous) in self.points.iter_mut().zip(&mut self.previous_points).skip(1) {
+            let velocity = *point - *previous;
+            *previous = *point;
+            *point += velocity + gravity;
+        }
+
+        for _ in 0..Chain::CONSTRAINT_ITERATIONS {
+            for i in 0..self.points.len() - 1 {
+                let delta = self.points[i + 1] - self.points[i];
+                let distance = delta.magnitude();
+                if distance == 0.0 {
+                    continue;
+                }
+                let correction = delta * ((distance - self.segment_length) / distance);
+                if i == 0 {
+                    self.points[i + 1] -= correction;
+                } else {
+                    self.points[i] += correction * 0.5;
+                    self.points[i + 1] -= correction * 0.5;
+                }
+            }
+        }
+    }
+
+    /// Scale the velocity of all points, e.g. to counter energy lost to the constraints
+    pub fn scale_velocity(&mut self, factor: f32) {
+        for (point, previous) in self.points.iter().zip(&mut self.previous_points) {
+            *previous = *point - (*point - *previous) * factor;
+        }
+    }
+
+    pub fn points(&self) -> &[FVec2] {
+        &self.points
+    }
+
+    pub fn anchor(&self) -> FVec2 {
+        self.points[0]
+    }
+
+    pub fn tip(&self) -> FVec2 {
+        self.points[self.points.len() - 1]
+    }
+
+    /// Distance the tip moved in the last tick
+    pub fn tip_velocity(&self) -> FVec2 {
+        self.tip() - self.previous_points[self.points.len() - 1]
+    }
+}
+
+/// Whether a circle overlaps the bounds
+pub fn circle_overlaps_bounds(center: FVec2, radius: f32, bounds: &Bounds) -> bool {
+    bounds.distance_to(center) < radius
+}
@@ -1,25 +1,57 @@
-use std::fmt;
+use std::{collections::VecDeque, fmt};
 
 use cgmath::{ElementWise, InnerSpace, Zero};
-use complementary_macros::ImGui;
-use log::debug;
-use serde::Deserialize;
-use wgpu::include_wgsl;
+use complementary_macros::{ImGui, JsonSchema, WgslStruct};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use wgpu::vertex_attr_array;
 
 use crate::{
+    audio::{self, SoundId},
+    collision::CollisionWorld,
     game::{PlayerTickState, WorldType},
+    gpu_memory::TrackedBuffer,
     imgui_helpers::ImGui,
-    input::ButtonType,
+    input::{ButtonType, Input},
     math::{Bounds, Color, Direction, FMat4, FVec2, FVec3},
+    mods,
     rendering::{
-        create_pipeline_descriptor, create_vertex_buffer, DrawState, UniformBuffer, Vertex,
+        color_targets, create_instance_buffer, create_pipeline_descriptor, create_vertex_buffer,
+        DrawState, Sprite, SpriteRenderer, Texture, UniformBuffer, Vertex, SQUARE_VERTICES,
     },
+    shader,
     tilemap::{Tile, Tilemap},
-    window::DrawContext, objects::ObjectSet,
+    timer::TickTimer,
+    window::DrawContext,
+    player_tuning::{DashTuning, PlayerTuning, WallJumpTuning},
 };
 
+/// What the player is currently doing, derived from the movement counters after every tick.
+/// Animation, audio and particles can react to changes, see `Player::take_state_changes()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ImGui)]
+pub enum PlayerState {
+    Grounded,
+    Airborne,
+    Dashing,
+    /// Holding towards a wall while falling with the Wall Jump ability, which slows the fall
+    WallSliding,
+    WallJumping,
+    Dead,
+}
+
+/// Transition between two `PlayerState`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerStateChange {
+    pub from: PlayerState,
+    pub to: PlayerState,
+}
+
 #[derive(ImGui)]
 pub struct Player {
+    state: PlayerState,
+    /// Changes since they were last taken, see `take_state_changes()`
+    #[gui_ignore]
+    state_changes: Vec<PlayerStateChange>,
     dead: bool,
     touched_goal: bool,
     
@@ -32,54 +64,97 @@ pub struct Player {
 
     /// Used to apply velocity from platforms etc.
     base_velocity: FVec2,
+    /// Set by water the player is in, reset after every tick
+    submerged: bool,
+    /// Direction and strength of gravity relative to the normal gravity, set by gravity zones
+    /// the player is in and reset after every tick
+    #[gui_ignore]
+    gravity_zone: Option<FVec2>,
 
     /// Jump buffering (see https://twitter.com/maddythorson/status/1238338575545978880)
     jump_buffer_ticks: i32,
+    /// Set when switching worlds with `SwitchAndAbility`, so that the new world's ability is
+    /// used as soon as it's ready
+    ability_buffer_ticks: i32,
     /// Coyote time (see https://twitter.com/MaddyThorson/status/1238338574220546049)
-    /// The value is `MAX_COYOTE_TIME` if we're grounded or value decreasing from `MAX_COYOTE_TIME`
+    /// The value is `max_coyote_time` if we're grounded or value decreasing from `max_coyote_time`
     /// to zero if we're in the air. Called `fakeGrounded` in C++ version
     ground_coyote_time: i32,
-    /// Decreasing timer which applies a force each frame after a jump for `MAX_JUMP_TICKS` frames
+    /// Decreasing timer which applies a force each frame after a jump for `max_jump_ticks` frames
     /// as long as the player keeps holding the Jump button. This allows precise control over the jump height.
     jump_ticks: i32,
 
+    /// One-way platforms are ignored while this is above zero, after pressing Down on one
+    drop_through_ticks: i32,
+
     /// Whether the player is allowed to jump in the air while they have the Double Jump
     can_jump_in_air: bool,
     dash_state: DashState,
+    /// Set for a single tick when a dash was stopped by a wall or an object
+    dash_impact: bool,
     wall_jump_state: WallJumpState,
 
+    /// Physics constants, edited in the "Player tuning" section of the DevGUI
+    #[gui_ignore]
+    tuning: PlayerTuning,
+    /// See `Settings::forgiving_spikes`
+    forgiving_spikes: bool,
+
+    /// Created when the player is first drawn, so that the player can be simulated without a GPU
     #[gui_ignore]
-    render_state: PlayerRenderState,
+    render_state: Option<PlayerRenderState>,
 }
 
 #[derive(ImGui)]
 pub struct DashState {
-    /// Decreasing timer which applies a force each frame after a jump for `MAX_DASH_TICKS` frames
-    dash_ticks: i32,
-    cooldown: i32,
+    /// Applies a force each frame after a dash for `max_dash_ticks` frames
+    dash: TickTimer,
+    cooldown: TickTimer,
     /// Set to `true` when either the ground was touched or a wall was collided while the wall jump is active
     useable: bool,
+    /// Whether the current dash already ran into something, so that the impact is only reported once
+    impacted: bool,
 
     #[gui_ignore]
     direction: Direction,
 }
 
 impl DashState {
-    const MAX_DASH_TICKS: i32 = 24;
-    const MAX_COOLDOWN: i32 = 24;
-    const DASH_FORCE: f32 = 0.35;
-
     fn dash_ready(&self) -> bool {
-        self.dash_ticks <= 0 && self.cooldown <= 0 && self.useable
+        !self.dash.active() && !self.cooldown.active() && self.useable
     }
 
     fn is_dashing(&self) -> bool {
-        self.dash_ticks > 0
+        self.dash.active()
+    }
+
+    /// Progress of the current dash from 0 to 1, or `None` if the player isn't dashing
+    fn progress(&self) -> Option<f32> {
+        self.is_dashing().then(|| self.dash.progress())
+    }
+
+    /// Whether jumping now would be a dash jump, see `DashTuning::dash_jump_window`
+    fn dash_jump_window_open(&self, tuning: &DashTuning) -> bool {
+        self.is_dashing() && tuning.max_dash_ticks - self.dash.remaining() <= tuning.dash_jump_window
+    }
+
+    /// Velocity of the dash at its current progress. The cosine decreases it over time.
+    fn velocity(&self, tuning: &DashTuning) -> FVec2 {
+        self.direction.as_vec()
+            * tuning.dash_force
+            * f32::cos(std::f32::consts::PI * 0.5 * self.dash.progress())
+    }
+
+    fn start(&mut self, tuning: &DashTuning) {
+        self.dash.start(tuning.max_dash_ticks);
+        self.cooldown.start(tuning.max_dash_ticks + tuning.max_cooldown);
+        self.impacted = false;
+        self.useable = false;
     }
 
     fn decrease_counters(&mut self) {
-        self.dash_ticks = 0.max(self.dash_ticks - 1);
-        self.cooldown = 0.max(self.cooldown - 1);
+        self.dash.tick();
+        self.cooldown.tick();
     }
 }
 
@@ -88,107 +163,159 @@ impl Default for DashState {
         // Dash to the right by default
         Self {
             direction: Direction::Right,
-            dash_ticks: 0,
-            cooldown: 0,
+            dash: TickTimer::default(),
+            cooldown: TickTimer::default(),
             useable: true,
+            impacted: false,
         }
     }
 }
 
 #[derive(ImGui, Default)]
 pub struct WallJumpState {
-    wall_jump_ticks: i32,
-    cooldown: i32,
+    wall_jump: TickTimer,
+    cooldown: TickTimer,
     #[gui_ignore]
     direction: Option<Direction>,
 
-    move_left_cooldown: i32,
-    move_right_cooldown: i32,
+    move_left_cooldown: TickTimer,
+    move_right_cooldown: TickTimer,
 
-    /// Counts down from the frame when a wall was touched
-    left_wall_collision_buffer: i32,
-    right_wall_collision_buffer: i32,
+    /// Runs from the frame when a wall was touched
+    left_wall_collision_buffer: TickTimer,
+    right_wall_collision_buffer: TickTimer,
 
-    /// Set if moving left/right AND we're still in the range of one of the above buffers
-    left_wall_input_buffer: i32,
-    right_wall_input_buffer: i32,
+    /// Started if moving left/right AND we're still in the range of one of the above buffers
+    left_wall_input_buffer: TickTimer,
+    right_wall_input_buffer: TickTimer,
+
+    /// Number of wall jumps in a row in `direction`, i.e. off the same wall, see
+    /// `WallJumpTuning::max_chained_wall_jumps`
+    chained_wall_jumps: i32,
+    /// Stamina used since the ground was last touched, see `WallJumpTuning::max_stamina_ticks`
+    used_stamina: i32,
 }
 
 impl WallJumpState {
-    const INITIAL_FORCE: FVec2 = FVec2::new(0.5, -0.4);
-    /// Applied in the same direction as `INITIAL_FORCE`
-    const CONTINUOUS_FORCE_MAGNITUDE: f32 = 0.12;
-    const MAX_WALL_JUMP_TICKS: i32 = 40;
-    const WALL_STICK_Y_DRAG: f32 = 0.3;
-    const MAX_COOLDOWN: i32 = 10;
-    const MAX_COLLISION_BUFFER_TICKS: i32 = 5;
-    const MAX_INPUT_BUFFER_TICKS: i32 = 7;
-    /// The player can't move in the direction of the wall jump for this amount of ticks after a wall jump
-    const MOVE_COOLDOWN: i32 = 15;
-
     fn wall_jump_ready(&self) -> bool {
-        self.wall_jump_ticks <= 0
-            && self.cooldown <= 0
-            && (self.left_wall_input_buffer > 0 || self.right_wall_input_buffer > 0)
+        !self.wall_jump.active()
+            && !self.cooldown.active()
+            && (self.left_wall_input_buffer.active() || self.right_wall_input_buffer.active())
     }
 
     fn wall_jump_active(&self) -> bool {
-        self.wall_jump_ticks > 0
+        self.wall_jump.active()
+    }
+
+    /// Away from the wall that was touched last
+    fn next_direction(&self) -> Direction {
+        if self.left_wall_input_buffer.active() {
+            Direction::Right
+        } else {
+            Direction::Left
+        }
+    }
+
+    /// Whether the limits of late-game levels allow another wall jump in `direction`
+    fn within_limits(&self, direction: Direction, tuning: &WallJumpTuning) -> bool {
+        let chain_full = tuning.max_chained_wall_jumps > 0
+            && self.direction == Some(direction)
+            && self.chained_wall_jumps >= tuning.max_chained_wall_jumps;
+        !chain_full && self.has_stamina(tuning)
+    }
+
+    fn has_stamina(&self, tuning: &WallJumpTuning) -> bool {
+        tuning.max_stamina_ticks <= 0 || self.used_stamina < tuning.max_stamina_ticks
+    }
+
+    fn use_stamina(&mut self, ticks: i32, tuning: &WallJumpTuning) {
+        if tuning.max_stamina_ticks > 0 {
+            self.used_stamina = (self.used_stamina + ticks).min(tuning.max_stamina_ticks);
+        }
+    }
+
+    /// Called while the player is on the ground
+    fn reset_limits(&mut self) {
+        self.chained_wall_jumps = 0;
+        self.used_stamina = 0;
     }
 
     fn decrease_counters(&mut self) {
-        self.wall_jump_ticks = 0.max(self.wall_jump_ticks - 1);
-        self.cooldown = 0.max(self.cooldown - 1);
-        self.move_left_cooldown = 0.max(self.move_left_cooldown - 1);
-        self.move_right_cooldown = 0.max(self.move_right_cooldown - 1);
-        self.left_wall_collision_buffer = 0.max(self.left_wall_collision_buffer - 1);
-        self.right_wall_collision_buffer = 0.max(self.right_wall_collision_buffer - 1);
-        self.left_wall_input_buffer = 0.max(self.left_wall_input_buffer - 1);
-        self.right_wall_input_buffer = 0.max(self.right_wall_input_buffer - 1);
+        self.wall_jump.tick();
+        self.cooldown.tick();
+        self.move_left_cooldown.tick();
+        self.move_right_cooldown.tick();
+        self.left_wall_collision_buffer.tick();
+        self.right_wall_collision_buffer.tick();
+        self.left_wall_input_buffer.tick();
+        self.right_wall_input_buffer.tick();
     }
 
-    fn reset_buffers(&mut self) {
-        self.left_wall_collision_buffer = WallJumpState::MAX_COLLISION_BUFFER_TICKS;
-        self.right_wall_collision_buffer = WallJumpState::MAX_COLLISION_BUFFER_TICKS;
-        self.left_wall_input_buffer = WallJumpState::MAX_INPUT_BUFFER_TICKS;
-        self.right_wall_input_buffer = WallJumpState::MAX_INPUT_BUFFER_TICKS;
+    fn reset_buffers(&mut self, tuning: &WallJumpTuning) {
+        self.left_wall_collision_buffer.start(tuning.max_collision_buffer_ticks);
+        self.right_wall_collision_buffer.start(tuning.max_collision_buffer_ticks);
+        self.left_wall_input_buffer.start(tuning.max_input_buffer_ticks);
+        self.right_wall_input_buffer.start(tuning.max_input_buffer_ticks);
     }
 
-    fn initial_force_with_direction(&self) -> FVec2 {
+    fn initial_force_with_direction(&self, tuning: &WallJumpTuning) -> FVec2 {
         let direction = self.direction.unwrap_or(Direction::Right);
         assert!(
             matches!(direction, Direction::Left | Direction::Right),
             "Wall jump direction must be left or right"
         );
-        let mut force = WallJumpState::INITIAL_FORCE;
+        let mut force = tuning.initial_force;
         force.x *= direction.as_vec().x;
         force
     }
 }
 
 pub struct PlayerRenderState {
-    buffer: wgpu::Buffer,
+    buffer: TrackedBuffer,
     uniform_buffer: UniformBuffer<PlayerUniforms>,
     render_pipeline: wgpu::RenderPipeline,
+
+    /// Recent positions of the player, newest first, drawn as fading copies behind the player
+    trail: VecDeque<FVec2>,
+    trail_uniform_buffer: UniformBuffer<DrawState>,
+    trail_vertex_buffer: TrackedBuffer,
+    trail_instance_buffer: TrackedBuffer,
+    trail_pipeline: wgpu::RenderPipeline,
+    icon_renderer: Option<SpriteRenderer>,
 }
 
-impl Player {
-    pub const SIZE: FVec2 = FVec2::new(0.8, 0.8);
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+struct TrailInstance {
+    color: Color,
+    position: FVec2,
+    size: FVec2,
+}
 
-    pub const MOVE_SPEED: f32 = 0.04;
-    pub const MOVE_SPEED_EXPONENT: f32 = 5.0;
-    pub const GRAVITY: FVec2 = FVec2::new(0.0, 0.0275);
-    pub const GRAVITY_GLIDER: FVec2 = FVec2::new(0.0, 0.005);
-    pub const DRAG: FVec2 = FVec2::new(0.7, 0.9);
-
-    const INITIAL_JUMP_FORCE: FVec2 = FVec2::new(0.0, -0.3);
-    const CONTINUOUS_JUMP_FORCE: FVec2 = FVec2::new(0.0, -0.1);
-    const MAX_JUMP_TICKS: i32 = 40;
-    const MAX_JUMP_BUFFER_TICKS: i32 = 6;
-    const MAX_COYOTE_TIME: i32 = 5;
-    const COLLISION_STEP: f32 = 0.0025;
+impl TrailInstance {
+    const ATTR: &'static [wgpu::VertexAttribute] = &vertex_attr_array![1 => Float32x4, 2 => Float32x2, 3 => Float32x2];
 
-    pub fn new(device: &wgpu::Device) -> Self {
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: Self::ATTR,
+        }
+    }
+}
+
+impl PlayerRenderState {
+    /// Number of ticks the trail lags behind the player
+    const TRAIL_LENGTH: usize = 10;
+    /// Opacity of the newest trail quad while walking or falling
+    const TRAIL_ALPHA: f32 = 0.2;
+    /// Opacity of the newest trail quad at the start of a dash
+    const DASH_TRAIL_ALPHA: f32 = 0.6;
+    /// Size of the oldest trail quad relative to the player
+    const MIN_TRAIL_SCALE: f32 = 0.4;
+
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
         let uniform_buffer = UniformBuffer::new(device, "player_uniforms");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -210,46 +337,162 @@ impl Player {
 
         let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
             Some("player_pipeline"),
-            &device.create_shader_module(&include_wgsl!("shaders/player.wgsl")),
+            &shader::create_shader_module(device, "player.wgsl", &[]),
             Some(&pipeline_layout),
             &[Vertex::layout()],
+            &color_targets(format),
+        ));
+
+        let trail_uniform_buffer = UniformBuffer::new(device, "player_trail_uniforms");
+
+        let trail_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[trail_uniform_buffer.bind_group_layout()],
+            label: Some("player_trail_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let trail_vertex_buffer =
+            create_vertex_buffer(device, Some("player_trail_vertex_buffer"), &SQUARE_VERTICES);
+        let trail_instance_buffer = create_instance_buffer::<TrailInstance>(
+            device,
+            Some("player_trail_instance_buffer"),
+            PlayerRenderState::TRAIL_LENGTH,
+        );
+
+        // Trail quads are colored rectangles just like ability blocks
+        let trail_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("player_trail_pipeline"),
+            &shader::create_shader_module(device, "ability_block.wgsl", &[]),
+            Some(&trail_pipeline_layout),
+            &[Vertex::layout(), TrailInstance::layout()],
+            &color_targets(format),
         ));
 
+        PlayerRenderState {
+            buffer,
+            uniform_buffer,
+            render_pipeline,
+            trail: VecDeque::with_capacity(PlayerRenderState::TRAIL_LENGTH),
+            trail_uniform_buffer,
+            trail_vertex_buffer,
+            trail_instance_buffer,
+            trail_pipeline,
+            icon_renderer: ability_icon_renderer(device, format),
+        }
+    }
+
+    fn record_trail(&mut self, position: FVec2) {
+        if self.trail.len() == PlayerRenderState::TRAIL_LENGTH {
+            self.trail.pop_back();
+        }
+        self.trail.push_front(position);
+    }
+
+    /// Quads for the trail, oldest first so that newer ones are drawn on top. They shrink and
+    /// fade out with age and are more opaque while dashing.
+    fn trail_instances(&self, color: Color, dash_progress: Option<f32>) -> Vec<TrailInstance> {
+        let alpha = match dash_progress {
+            Some(progress) => {
+                PlayerRenderState::TRAIL_ALPHA
+                    + (PlayerRenderState::DASH_TRAIL_ALPHA - PlayerRenderState::TRAIL_ALPHA) * (1.0 - progress)
+            }
+            None => PlayerRenderState::TRAIL_ALPHA,
+        };
+
+        self.trail
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(age, &position)| {
+                let remaining = 1.0 - age as f32 / PlayerRenderState::TRAIL_LENGTH as f32;
+                let scale = PlayerRenderState::MIN_TRAIL_SCALE
+                    + (1.0 - PlayerRenderState::MIN_TRAIL_SCALE) * remaining;
+                let size = Player::SIZE * scale;
+                TrailInstance {
+                    color: color.with_alpha(alpha * remaining),
+                    // Shrink towards the center of the player
+                    position: position + (Player::SIZE - size) / 2.0,
+                    size,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Player::new()
+    }
+}
+
+impl Player {
+    pub const SIZE: FVec2 = FVec2::new(0.8, 0.8);
+
+    const COLLISION_STEP: f32 = 0.0025;
+    /// Space left between the player and whatever they moved into. Touching a tile already
+    /// counts as colliding with it, and the gap has to be smaller than `COLLISION_STEP` so that
+    /// `handle_directional_collision()` still notices the contact.
+    const SWEEP_GAP: f32 = 0.001;
+    /// How far the player can be pushed out of geometry that moved into them, in tiles
+    const MAX_DEPENETRATION: f32 = 0.5;
+    const DEPENETRATION_STEP: f32 = 0.01;
+    /// Long enough to fall past the top edge of a one-way platform
+    const DROP_THROUGH_TICKS: i32 = 12;
+
+    pub fn new() -> Self {
         Player {
+            state: PlayerState::Airborne,
+            state_changes: Vec::new(),
             position: FVec2::new(30.0, 30.0),
             velocity: FVec2::zero(),
             acceleration: FVec2::zero(),
             abilities: AbilityPair::default(),
 
             base_velocity: FVec2::zero(),
+            submerged: false,
+            gravity_zone: None,
             dead: false,
             touched_goal: false,
             jump_ticks: 0,
             jump_buffer_ticks: 0,
+            ability_buffer_ticks: 0,
             ground_coyote_time: 0,
+            drop_through_ticks: 0,
 
             dash_state: DashState::default(),
+            dash_impact: false,
             wall_jump_state: WallJumpState::default(),
             can_jump_in_air: false,
 
-            render_state: PlayerRenderState {
-                buffer,
-                uniform_buffer,
-                render_pipeline,
-            },
+            tuning: PlayerTuning::default(),
+            forgiving_spikes: false,
+            render_state: None,
         }
     }
 
     pub fn tick(&mut self, state: &mut PlayerTickState) {
+        // The player never ends a tick inside geometry by moving, so this was caused by an
+        // object moving into them since the last tick
+        if self.allowed_to_move() {
+            self.depenetrate(&CollisionWorld::new(state.tilemap, state.objects, state.world_type));
+        }
+
+        let on_ice = self.ground_tiles(state.tilemap).any(|tile| matches!(tile, Tile::Ice));
         let horizontal = state.input.get_button(ButtonType::Right).pressed() as i32 as f32
             - state.input.get_button(ButtonType::Left).pressed() as i32 as f32; // TODO: add input.get_horizontal()
         if self.allowed_to_move() {
-            let mut right_force = horizontal.abs().powf(Player::MOVE_SPEED_EXPONENT)
-                * Player::MOVE_SPEED
+            let mut right_force = horizontal.abs().powf(self.tuning.move_speed_exponent)
+                * self.tuning.move_speed
                 * horizontal.signum();
+            if on_ice && !self.submerged {
+                // Keep the top speed, which is `force * drag / (1 - drag)`, the same as on the ground
+                let drag = self.tuning.drag.x;
+                let ice_drag = self.tuning.ice_drag_x;
+                right_force *= (1.0 - ice_drag) * drag / ((1.0 - drag) * ice_drag);
+            }
 
-            if (right_force > 0.0 && self.wall_jump_state.move_right_cooldown > 0)
-                || (right_force < 0.0 && self.wall_jump_state.move_left_cooldown > 0)
+            if (right_force > 0.0 && self.wall_jump_state.move_right_cooldown.active())
+                || (right_force < 0.0 && self.wall_jump_state.move_left_cooldown.active())
             {
                 // Prevent moving against the direction where the player wall jumped from
                 // for some ticks after a wall jump
@@ -259,12 +502,31 @@ impl Player {
         }
 
         self.apply_gravity(&state);
+        if let Some(direction) = self.slope_below(state.tilemap) {
+            // Slopes are slippery: the part of gravity along the slope pushes the player down.
+            // The vertical part is handled by `step_down_slope()`.
+            self.add_force(direction.as_vec() * self.tuning.gravity.y * self.tuning.slope_slide_factor);
+        }
+        if let Some(direction) = self.ground_tiles(state.tilemap).find_map(|tile| tile.conveyor_direction()) {
+            self.add_base_velocity(direction.as_vec() * self.tuning.conveyor_speed);
+        }
+
+        self.drop_through_ticks = 0.max(self.drop_through_ticks - 1);
+        if state.input.get_button(ButtonType::Down).pressed_first_frame()
+            && self.allowed_to_move()
+            && self.on_one_way_platform(&CollisionWorld::new(state.tilemap, state.objects, state.world_type))
+        {
+            self.drop_through_ticks = Player::DROP_THROUGH_TICKS;
+            // Don't allow jumping off the platform that the player is dropping through
+            self.ground_coyote_time = 0;
+        }
 
         let collision_faces = self.handle_directional_collision(state);
         if collision_faces[Direction::Down as usize].is_some() {
-            self.ground_coyote_time = Player::MAX_COYOTE_TIME;
+            self.ground_coyote_time = self.tuning.max_coyote_time;
             self.dash_state.useable = true;
             self.can_jump_in_air = true;
+            self.wall_jump_state.reset_limits();
         }
         self.ground_coyote_time = 0.max(self.ground_coyote_time - 1);
 
@@ -277,12 +539,14 @@ impl Player {
             Some(CollisionType::Wall)
         );
         if left_wall_collision {
-            self.wall_jump_state.left_wall_collision_buffer =
-                WallJumpState::MAX_COLLISION_BUFFER_TICKS;
+            self.wall_jump_state
+                .left_wall_collision_buffer
+                .start(self.tuning.wall_jump.max_collision_buffer_ticks);
         }
         if right_wall_collision {
-            self.wall_jump_state.right_wall_collision_buffer =
-                WallJumpState::MAX_COLLISION_BUFFER_TICKS;
+            self.wall_jump_state
+                .right_wall_collision_buffer
+                .start(self.tuning.wall_jump.max_collision_buffer_ticks);
         }
 
         if state
@@ -291,21 +555,42 @@ impl Player {
             .pressed_first_frame()
             && self.allowed_to_move()
         {
-            self.jump_buffer_ticks = Player::MAX_JUMP_BUFFER_TICKS;
+            self.jump_buffer_ticks = self.tuning.max_jump_buffer_ticks;
         }
         self.jump_buffer_ticks = 0.max(self.jump_buffer_ticks - 1);
 
+        if self.submerged {
+            if state.input.get_button(ButtonType::Jump).pressed_first_frame() && self.allowed_to_move() {
+                self.add_force(self.tuning.swim_force);
+            }
+            // Swimming replaces jumping
+            self.jump_buffer_ticks = 0;
+            self.jump_ticks = 0;
+        }
+
         if self.allowed_to_move() {
             // Buffer directional inputs required for wall jumps, so that a slight delay after
             // holding the button registers as a wall jump
-            if self.wall_jump_state.left_wall_collision_buffer > 0 && horizontal < 0.0 {
-                self.wall_jump_state.left_wall_input_buffer = WallJumpState::MAX_INPUT_BUFFER_TICKS;
-            } else if self.wall_jump_state.right_wall_collision_buffer > 0 && horizontal > 0.0 {
-                self.wall_jump_state.right_wall_input_buffer =
-                    WallJumpState::MAX_INPUT_BUFFER_TICKS;
+            if self.wall_jump_state.left_wall_collision_buffer.active() && horizontal < 0.0 {
+                self.wall_jump_state
+                    .left_wall_input_buffer
+                    .start(self.tuning.wall_jump.max_input_buffer_ticks);
+            } else if self.wall_jump_state.right_wall_collision_buffer.active() && horizontal > 0.0 {
+                self.wall_jump_state
+                    .right_wall_input_buffer
+                    .start(self.tuning.wall_jump.max_input_buffer_ticks);
             }
         }
 
+        // Double jumps and wall jumps from the switch combo are regular jumps
+        if self.ability_buffer_ticks > 0
+            && matches!(self.active_ability(state.world_type), Ability::DoubleJump | Ability::WallJump)
+            && self.allowed_to_move()
+        {
+            self.jump_buffer_ticks = self.jump_buffer_ticks.max(self.ability_buffer_ticks);
+            self.ability_buffer_ticks = 0;
+        }
+
         if self.jump_buffer_ticks > 0 {
             self.start_jumping(state);
         }
@@ -315,12 +600,12 @@ impl Player {
         if self.wall_jump_state.wall_jump_active() {
             let normalized_direction = self
                 .wall_jump_state
-                .initial_force_with_direction()
+                .initial_force_with_direction(&self.tuning.wall_jump)
                 .normalize();
-            let force = normalized_direction * WallJumpState::CONTINUOUS_FORCE_MAGNITUDE
+            let force = normalized_direction * self.tuning.wall_jump.continuous_force_magnitude
                 / 1.1_f32.powf(
-                    WallJumpState::MAX_WALL_JUMP_TICKS as f32 + 1.0
-                        - self.wall_jump_state.wall_jump_ticks as f32,
+                    self.tuning.wall_jump.max_wall_jump_ticks as f32 + 1.0
+                        - self.wall_jump_state.wall_jump.remaining() as f32,
                 );
             self.add_force(force);
 
@@ -328,7 +613,7 @@ impl Player {
             self.dash_state.direction = self.wall_jump_state.direction.unwrap_or(Direction::Right);
         }
 
-        if !state.input.get_button(ButtonType::Jump).pressed() && self.allowed_to_move() {
+        if !self.jump_held(state.input) && self.allowed_to_move() {
             // Cancel the jump
             self.jump_ticks = 0;
         }
@@ -337,8 +622,8 @@ impl Player {
             // Add an additional force for some time as long as the player keeps holding the Jump button,
             // scaled by jump duration
             self.add_force(
-                Player::CONTINUOUS_JUMP_FORCE
-                    * (1.0 / 1.1_f32.powf((Player::MAX_JUMP_TICKS + 1 - self.jump_ticks) as f32)),
+                self.tuning.continuous_jump_force
+                    * (1.0 / 1.1_f32.powf((self.tuning.max_jump_ticks + 1 - self.jump_ticks) as f32)),
             );
             self.jump_ticks -= 1;
         }
@@ -352,54 +637,81 @@ impl Player {
             };
         }
 
-        let mut drag = Player::DRAG;
+        let mut drag = if self.submerged {
+            self.tuning.drag_water
+        } else {
+            self.tuning.drag
+        };
+        if on_ice && !self.submerged {
+            drag.x = self.tuning.ice_drag_x;
+        }
 
+        let mut wall_sliding = false;
         match self.active_ability(state.world_type) {
             Ability::Dash => self.tick_dash_active(state),
-            Ability::WallJump => self.handle_wall_sticking(
-                &mut drag,
-                horizontal,
-                left_wall_collision,
-                right_wall_collision,
-            ),
+            Ability::WallJump => {
+                wall_sliding = self.handle_wall_sticking(
+                    &mut drag,
+                    horizontal,
+                    left_wall_collision,
+                    right_wall_collision,
+                )
+            }
             _ => (),
         }
+        self.ability_buffer_ticks = 0.max(self.ability_buffer_ticks - 1);
 
         self.dash_state.decrease_counters();
 
         if self.dash_state.is_dashing() {
-            // The cosine here leads to a decrease of the dash velocity over time
-            let dash_velocity = self.dash_state.direction.as_vec() * DashState::DASH_FORCE;
-            self.velocity = dash_velocity
-                * f32::cos(
-                    std::f32::consts::PI
-                        * 0.5
-                        * (1.0
-                            - self.dash_state.dash_ticks as f32 / DashState::MAX_DASH_TICKS as f32),
-                );
+            self.velocity = self.dash_state.velocity(&self.tuning.dash);
         }
 
         self.velocity += self.acceleration;
         self.velocity.mul_assign_element_wise(drag);
         self.velocity += (FVec2::new(1.0, 1.0) - drag).mul_element_wise(self.base_velocity);
 
-        self.move_until_collision(&state.tilemap, &state.objects, state.world_type);
+        let dash_direction = self.dash_state.is_dashing().then(|| self.dash_state.direction.as_vec());
+        let velocity_before_move = self.velocity;
+        self.move_until_collision(&CollisionWorld::new(state.tilemap, state.objects, state.world_type));
+        // Collisions zero the velocity on the axis the player ran into
+        self.dash_impact = match dash_direction {
+            Some(direction) if !self.dash_state.impacted => {
+                velocity_before_move.dot(direction) > 0.0 && self.velocity.dot(direction) <= 0.0
+            }
+            _ => false,
+        };
+        self.dash_state.impacted |= self.dash_impact;
 
         self.acceleration = FVec2::zero();
         self.base_velocity = FVec2::zero();
+        self.submerged = false;
+        self.gravity_zone = None;
+
+        self.set_state(self.next_state(wall_sliding));
+
+        // Recorded per tick rather than per frame so that the trail's length doesn't depend on the frame rate
+        if let Some(render_state) = &mut self.render_state {
+            render_state.record_trail(self.position);
+        }
     }
 
     fn start_jumping(&mut self, state: &PlayerTickState) {
+        let dash_jump = self.dash_state.dash_jump_window_open(&self.tuning.dash);
         if (self.grounded()
             || self.active_ability(state.world_type) == Ability::DoubleJump && self.can_jump_in_air)
-            && !self.dash_state.is_dashing()
+            && (!self.dash_state.is_dashing() || dash_jump)
         {
             // Regular jump or double jump
+            if dash_jump {
+                self.convert_dash_momentum();
+            }
             self.jump_buffer_ticks = 0;
-            self.add_force(Player::INITIAL_JUMP_FORCE);
-            self.jump_ticks = Player::MAX_JUMP_TICKS;
+            self.add_force(self.tuning.initial_jump_force);
+            self.jump_ticks = self.tuning.max_jump_ticks;
             self.velocity.y = 0.0;
-            self.wall_jump_state.cooldown = WallJumpState::MAX_COOLDOWN;
+            self.wall_jump_state.cooldown.start(self.tuning.wall_jump.max_cooldown);
+            audio::play_sound(SoundId::Jump);
 
             if !self.grounded() {
                 self.can_jump_in_air = false;
@@ -407,105 +719,186 @@ impl Player {
             self.ground_coyote_time = 0;
         } else if self.active_ability(state.world_type) == Ability::WallJump
             && self.wall_jump_state.wall_jump_ready()
+            && self
+                .wall_jump_state
+                .within_limits(self.wall_jump_state.next_direction(), &self.tuning.wall_jump)
         {
             // Wall jump
-            self.wall_jump_state.direction =
-                Some(if self.wall_jump_state.left_wall_input_buffer > 0 {
-                    Direction::Right
-                } else {
-                    Direction::Left
-                });
+            let direction = self.wall_jump_state.next_direction();
+            if self.wall_jump_state.direction == Some(direction) {
+                self.wall_jump_state.chained_wall_jumps += 1;
+            } else {
+                self.wall_jump_state.chained_wall_jumps = 1;
+            }
+            self.wall_jump_state.direction = Some(direction);
+            self.wall_jump_state
+                .use_stamina(self.tuning.wall_jump.wall_jump_stamina_cost, &self.tuning.wall_jump);
             debug!("Wall jump direction: {:?}", self.wall_jump_state.direction);
-            let force = self.wall_jump_state.initial_force_with_direction();
+            let force = self.wall_jump_state.initial_force_with_direction(&self.tuning.wall_jump);
             self.add_force(force);
             self.jump_buffer_ticks = 0;
+            audio::play_sound(SoundId::WallJump);
 
-            self.wall_jump_state.cooldown = WallJumpState::MAX_COOLDOWN;
-            self.wall_jump_state.wall_jump_ticks = WallJumpState::MAX_WALL_JUMP_TICKS;
-            self.wall_jump_state.reset_buffers();
+            self.wall_jump_state.cooldown.start(self.tuning.wall_jump.max_cooldown);
+            self.wall_jump_state.wall_jump.start(self.tuning.wall_jump.max_wall_jump_ticks);
+            self.wall_jump_state.reset_buffers(&self.tuning.wall_jump);
             if self.wall_jump_state.direction == Some(Direction::Right) {
-                self.wall_jump_state.move_right_cooldown = WallJumpState::MOVE_COOLDOWN;
+                self.wall_jump_state.move_right_cooldown.start(self.tuning.wall_jump.move_cooldown);
             } else {
-                self.wall_jump_state.move_left_cooldown = WallJumpState::MOVE_COOLDOWN;
+                self.wall_jump_state.move_left_cooldown.start(self.tuning.wall_jump.move_cooldown);
             }
             self.reset_dash();
         }
     }
 
+    /// End the dash and keep what's left of its velocity as horizontal velocity, which drag
+    /// slows down like any other
+    fn convert_dash_momentum(&mut self) {
+        let dash_velocity = self.dash_state.velocity(&self.tuning.dash);
+        self.velocity.x = dash_velocity.x * self.tuning.dash.dash_jump_momentum_scale;
+        self.dash_state.dash.stop();
+        debug!("Dash jump");
+    }
+
     fn tick_dash_active(&mut self, state: &PlayerTickState) {
-        if (state.input.ability_button_pressed_first_frame())
-            && self.allowed_to_move()
-            && self.dash_state.dash_ready()
-        {
-            self.dash_state.dash_ticks = DashState::MAX_DASH_TICKS;
-            self.dash_state.useable = false;
-            self.dash_state.cooldown = DashState::MAX_DASH_TICKS + DashState::MAX_COOLDOWN;
+        let dash_requested = state.input.get_button(ButtonType::Ability).pressed_first_frame()
+            || self.ability_buffer_ticks > 0;
+        if dash_requested && self.allowed_to_move() && self.dash_state.dash_ready() {
+            self.ability_buffer_ticks = 0;
+            self.dash_state.start(&self.tuning.dash);
             debug!("Dashing");
+            audio::play_sound(SoundId::Dash);
         }
     }
 
-    fn handle_wall_sticking(&mut self, drag: &mut FVec2, horizontal: f32, left: bool, right: bool) {
-        if self.velocity.y > 0.0 && ((left && horizontal < 0.0) || (right && horizontal > 0.0)) {
-            drag.y *= WallJumpState::WALL_STICK_Y_DRAG;
+    /// Slow down the fall while holding towards a wall, returns whether the player is sliding down one.
+    /// Sliding uses stamina, the player falls normally without any left.
+    fn handle_wall_sticking(&mut self, drag: &mut FVec2, horizontal: f32, left: bool, right: bool) -> bool {
+        let sliding = self.velocity.y > 0.0
+            && ((left && horizontal < 0.0) || (right && horizontal > 0.0))
+            && self.wall_jump_state.has_stamina(&self.tuning.wall_jump);
+        if sliding {
+            drag.y *= self.tuning.wall_jump.wall_stick_y_drag;
+            self.wall_jump_state.use_stamina(1, &self.tuning.wall_jump);
         }
+        sliding
+    }
+
+    /// State after a tick, the earlier checks take precedence
+    fn next_state(&self, wall_sliding: bool) -> PlayerState {
+        if self.dead {
+            PlayerState::Dead
+        } else if self.dash_state.is_dashing() {
+            PlayerState::Dashing
+        } else if self.wall_jump_state.wall_jump_active() {
+            PlayerState::WallJumping
+        } else if wall_sliding {
+            PlayerState::WallSliding
+        } else if self.grounded() && self.jump_ticks == 0 {
+            PlayerState::Grounded
+        } else {
+            PlayerState::Airborne
+        }
+    }
+
+    fn set_state(&mut self, state: PlayerState) {
+        if state == self.state {
+            return;
+        }
+        let change = PlayerStateChange { from: self.state, to: state };
+        debug!("Player state: {:?} -> {:?}", change.from, change.to);
+        self.state_changes.push(change);
+        self.state = state;
     }
 
     pub fn draw(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType) {
         let model_matrix =
             FMat4::from_translation(FVec3::new(self.position.x, self.position.y, 0.0));
 
+        let color = world_type.uninverted_color(self.active_ability(world_type).color());
+        let icon = self.active_ability(world_type).icon();
+        let bounds = self.bounds();
         let uniforms = PlayerUniforms {
             view_matrix: state.view_matrix,
             model_matrix,
-            color: self.active_ability(world_type).color(),
+            color,
         };
-        self.render_state
+        let device = context.device;
+        let format = context.format;
+        let render_state = self
+            .render_state
+            .get_or_insert_with(|| PlayerRenderState::new(device, format));
+        render_state
             .uniform_buffer
             .write_with_queue(context.queue, uniforms);
 
-        let mut rpass = context
-            .encoder
-            .begin_render_pass(&wgpu::RenderPassDescriptor {
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &context.output,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-                label: Some("player_rpass"),
-            });
-        rpass.set_pipeline(&self.render_state.render_pipeline);
-        rpass.set_vertex_buffer(0, self.render_state.buffer.slice(..));
-        rpass.set_bind_group(0, &self.render_state.uniform_buffer.bind_group(), &[]);
-        rpass.draw(0..6, 0..1);
+        let trail_instances = render_state.trail_instances(color, self.dash_state.progress());
+        render_state
+            .trail_uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(
+            &render_state.trail_instance_buffer,
+            0,
+            bytemuck::cast_slice(&trail_instances),
+        );
+
+        {
+            let mut rpass = context
+                .encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &context.output,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                    label: Some("player_rpass"),
+                });
+            if !trail_instances.is_empty() {
+                rpass.set_pipeline(&render_state.trail_pipeline);
+                rpass.set_vertex_buffer(0, render_state.trail_vertex_buffer.slice(..));
+                rpass.set_vertex_buffer(1, render_state.trail_instance_buffer.slice(..));
+                rpass.set_bind_group(0, &render_state.trail_uniform_buffer.bind_group(), &[]);
+                rpass.draw(0..6, 0..trail_instances.len() as u32);
+            }
+
+            rpass.set_pipeline(&render_state.render_pipeline);
+            rpass.set_vertex_buffer(0, render_state.buffer.slice(..));
+            rpass.set_bind_group(0, &render_state.uniform_buffer.bind_group(), &[]);
+            rpass.draw(0..6, 0..1);
+        }
+
+        if let (Some(icon_renderer), Some(icon)) = (&mut render_state.icon_renderer, icon) {
+            icon_renderer.queue_sprite(icon, icon_bounds(bounds), color);
+            icon_renderer.flush(context, state);
+        }
     }
 
     pub fn add_force(&mut self, force: FVec2) {
         self.acceleration += force;
     }
 
-    /// Whether the player is colliding with the tile map or an object
-    pub fn is_colliding_solid(&self, tilemap: &Tilemap, objects: &ObjectSet, world_type: WorldType) -> bool {
-        let bounds = self.bounds();
-        if !tilemap.contains_bounds(bounds) {
-            return true;
-        }
-        for y in bounds.min.y as i32..=bounds.max.y as i32 {
-            for x in bounds.min.x as i32..=bounds.max.x as i32 {
-                if tilemap.get_tile(x, y).is_solid() {
-                    return true;
-                }
-            }
-        }
+    /// Mark the player as being in water for the next tick, see `WaterObject`
+    pub fn set_submerged(&mut self) {
+        self.submerged = true;
+    }
+
+    /// Redirect gravity for the next tick, see `GravityZoneObject`
+    pub fn set_gravity_zone(&mut self, gravity: FVec2) {
+        self.gravity_zone = Some(gravity);
+    }
 
-        self.is_colliding_with_solid_objects(objects, world_type)
+    /// Add velocity that's not affected by drag, e.g. from a moving platform
+    pub fn add_base_velocity(&mut self, velocity: FVec2) {
+        self.base_velocity += velocity;
     }
 
-    pub fn is_colliding_with_solid_objects(&self, objects: &ObjectSet, world_type: WorldType) -> bool {
-        matches!(objects.check_collision(&self.bounds(), world_type), Some(CollisionType::Solid | CollisionType::Wall))
+    /// Whether the player is colliding with the tile map or a solid object
+    pub fn is_colliding_solid(&self, world: &CollisionWorld) -> bool {
+        world.overlaps_solid(self.bounds())
     }
 
     /// Get the bounding box of the player in world space
@@ -513,9 +906,63 @@ impl Player {
         Bounds::new(self.position, self.position + Player::SIZE)
     }
 
-    /// Move the player in small steps, interrupting movement on collision
-    fn move_until_collision(&mut self, tilemap: &Tilemap, objects: &ObjectSet, world_type: WorldType) {
-        let mut energy = self.velocity;
+    /// Move the player by their velocity, first horizontally and then vertically, stopping right
+    /// before the first solid tile or object in the way. Slopes and one-way platforms aren't
+    /// boxes, so the player is moved in small steps near them instead, see `move_in_steps()`.
+    fn move_until_collision(&mut self, world: &CollisionWorld) {
+        let start = self.bounds();
+        let velocity = self.velocity;
+        // One more row below for `step_down_slope()`
+        let swept = Bounds::new(
+            FVec2::new(start.min.x.min(start.min.x + velocity.x), start.min.y.min(start.min.y + velocity.y)),
+            FVec2::new(start.max.x.max(start.max.x + velocity.x), start.max.y.max(start.max.y + velocity.y) + 1.0),
+        );
+        if world.tilemap.contains_slope(swept) {
+            self.move_in_steps(velocity, world);
+            return;
+        }
+
+        if velocity.x != 0.0 {
+            let direction = if velocity.x > 0.0 { Direction::Right } else { Direction::Left };
+            let distance = self.sweep(direction, velocity.x.abs(), world);
+            self.position.x += distance.copysign(velocity.x);
+            if distance < velocity.x.abs() {
+                self.velocity.x = 0.0;
+            }
+        }
+        if velocity.y != 0.0 {
+            let old_y = self.position.y;
+            let direction = if velocity.y > 0.0 { Direction::Down } else { Direction::Up };
+            let distance = self.sweep(direction, velocity.y.abs(), world);
+            self.position.y += distance.copysign(velocity.y);
+            if distance < velocity.y.abs() {
+                self.velocity.y = 0.0;
+            }
+            if self.lands_on_one_way_platform(old_y + Player::SIZE.y, self.bounds(), world) {
+                self.position.y = old_y;
+                self.move_in_steps(FVec2::new(0.0, distance.copysign(velocity.y)), world);
+            }
+        }
+
+        // Shouldn't happen, but stepping is always safe
+        if self.is_colliding_solid(world) {
+            self.position = start.min;
+            self.velocity = velocity;
+            self.move_in_steps(velocity, world);
+        }
+    }
+
+    /// How far the player can move in `direction`, up to `distance`, without touching a solid
+    /// tile or object
+    fn sweep(&self, direction: Direction, distance: f32, world: &CollisionWorld) -> f32 {
+        match world.sweep(self.bounds(), direction, distance) {
+            Some(contact) => (contact - Player::SWEEP_GAP).max(0.0),
+            None => distance,
+        }
+    }
+
+    /// Move the player by `energy` in small steps, interrupting movement on collision
+    fn move_in_steps(&mut self, mut energy: FVec2, world: &CollisionWorld) {
         while energy.x != 0.0 || energy.y != 0.0 {
             // Move X component
             let old_x = self.position.x;
@@ -529,10 +976,14 @@ impl Player {
                 self.position.x += energy.x;
                 energy.x = 0.0;
             }
-            if self.is_colliding_solid(tilemap, objects, world_type) {
-                energy.x = 0.0;
-                self.position.x = old_x;
-                self.velocity.x = 0.0;
+            if self.is_colliding_solid(world) {
+                if !self.step_up_slope(old_x, world) {
+                    energy.x = 0.0;
+                    self.position.x = old_x;
+                    self.velocity.x = 0.0;
+                }
+            } else if energy.y >= 0.0 {
+                self.step_down_slope(old_x, world);
             }
 
             // Move Y component
@@ -547,7 +998,10 @@ impl Player {
                 self.position.y += energy.y;
                 energy.y = 0.0;
             }
-            if self.is_colliding_solid(tilemap, objects, world_type) {
+            let bounds = self.bounds();
+            if self.is_colliding_solid(world)
+                || self.lands_on_one_way_platform(old_y + Player::SIZE.y, bounds, world)
+            {
                 energy.y = 0.0;
                 self.position.y = old_y;
                 self.velocity.y = 0.0;
@@ -555,6 +1009,107 @@ impl Player {
         }
     }
 
+    /// Whether moving the player's bottom edge down from `old_bottom` to `bounds.max.y` puts them
+    /// onto a one-way platform. They're passed through in all other directions and while
+    /// dropping through.
+    fn lands_on_one_way_platform(&self, old_bottom: f32, bounds: Bounds, world: &CollisionWorld) -> bool {
+        self.drop_through_ticks == 0 && bounds.max.y > old_bottom && world.lands_on_one_way_platform(old_bottom, bounds)
+    }
+
+    /// Whether the player is standing on a one-way platform, so that they can drop through
+    fn on_one_way_platform(&self, world: &CollisionWorld) -> bool {
+        let bounds = self.bounds();
+        let below = Bounds::new(
+            bounds.min + FVec2::new(0.0, Player::COLLISION_STEP),
+            bounds.max + FVec2::new(0.0, Player::COLLISION_STEP),
+        );
+        self.lands_on_one_way_platform(bounds.max.y, below, world)
+    }
+
+    /// Push the player out of solid tiles or objects along the axis with the shortest way out,
+    /// e.g. after a door closed on them. The player is crushed if there's no space nearby.
+    /// Called before the player moves and again after objects moved, see `Game::tick_simulation()`.
+    pub fn depenetrate(&mut self, world: &CollisionWorld) {
+        if !self.is_colliding_solid(world) {
+            return;
+        }
+        let start = self.position;
+        let mut distance = Player::DEPENETRATION_STEP;
+        while distance <= Player::MAX_DEPENETRATION {
+            for direction in Direction::ALL {
+                let offset = direction.as_vec() * distance;
+                self.position = start + offset;
+                if !self.is_colliding_solid(world) {
+                    debug!("Pushed the player out of geometry by {offset:?}");
+                    // Don't keep moving into whatever pushed the player
+                    if offset.x != 0.0 {
+                        self.velocity.x = 0.0;
+                    } else {
+                        self.velocity.y = 0.0;
+                    }
+                    return;
+                }
+            }
+            distance += Player::DEPENETRATION_STEP;
+        }
+        self.position = start;
+        debug!("Player was crushed");
+        self.kill();
+    }
+
+    /// Walk up a slope after moving horizontally into it. Because slopes are at 45°, the
+    /// player is moved up by the same distance they moved horizontally.
+    /// Returns `false` if the player didn't hit a slope or can't move up.
+    fn step_up_slope(&mut self, old_x: f32, world: &CollisionWorld) -> bool {
+        if !world.tilemap.overlaps_slope(self.bounds()) {
+            return false;
+        }
+        let old_y = self.position.y;
+        self.position.y -= (self.position.x - old_x).abs();
+        if self.is_colliding_solid(world) {
+            self.position.y = old_y;
+            return false;
+        }
+        true
+    }
+
+    /// Keep the player on a slope while walking down, instead of falling off in small hops
+    fn step_down_slope(&mut self, old_x: f32, world: &CollisionWorld) {
+        let step = (self.position.x - old_x).abs();
+        let bounds = self.bounds();
+        let below = Bounds::new(bounds.min + FVec2::new(0.0, step * 2.0), bounds.max + FVec2::new(0.0, step * 2.0));
+        if step == 0.0 || !world.tilemap.overlaps_slope(below) {
+            return;
+        }
+        let old_y = self.position.y;
+        self.position.y += step;
+        if self.is_colliding_solid(world) {
+            self.position.y = old_y;
+        }
+    }
+
+    /// Direction in which the player slides if they're standing on a slope
+    fn slope_below(&self, tilemap: &Tilemap) -> Option<Direction> {
+        let bounds = self.bounds();
+        let below = Bounds::new(
+            bounds.min + FVec2::new(0.0, Player::COLLISION_STEP),
+            bounds.max + FVec2::new(0.0, Player::COLLISION_STEP),
+        );
+        tilemap.slope_at(below).and_then(|tile| tile.slope_direction())
+    }
+
+    /// Tiles that the player is standing on, i.e. the ones right below them that they overlap
+    /// horizontally
+    fn ground_tiles<'a>(&self, tilemap: &'a Tilemap) -> impl Iterator<Item = Tile> + 'a {
+        let bounds = self.bounds();
+        let y = (bounds.max.y + Player::COLLISION_STEP) as i32;
+        let min_x = bounds.min.x as i32;
+        let max_x = bounds.max.x.ceil() as i32 - 1;
+        (min_x..=max_x)
+            .filter(move |&x| tilemap.contains_tile(x, y))
+            .map(move |x| tilemap.get_tile(x, y))
+    }
+
     /// Check on which direction the player has collided with something and handle the collision
     /// Returns the type of collision that took place for each direction
     fn handle_directional_collision(&mut self, state: &mut PlayerTickState) -> [Option<CollisionType>; 4] {
@@ -573,7 +1128,7 @@ impl Player {
             'outer: for y in bounds.min.y as i32..=bounds.max.y as i32 {
                 for x in bounds.min.x as i32..=bounds.max.x as i32 {
                     let tile = state.tilemap.get_tile(x, y);
-                    if tile.is_solid() {
+                    if tile.is_solid_in(state.world_type) {
                         collisions_by_direction[i] = Some(if tile.is_wall() {
                             CollisionType::Wall
                         } else {
@@ -589,39 +1144,49 @@ impl Player {
                                 | Tile::SpikesUp
                                 | Tile::SpikesDown
                         ) {
-                            match tile.direction() {
-                                Some(tile_dir) => {
-                                    if *direction == tile_dir.inverse() {
-                                        // Only kill if the direction of the spike is the inverse to the one we're testing
-                                        self.kill();
-                                        break 'outer;
-                                    }
-                                }
-                                // The tile spike goes in all directions; always kill
-                                None => {
-                                    self.kill();
-                                    break 'outer;
-                                }
+                            // Only kill if the direction of the spike is the inverse to the one we're
+                            // testing, or if the spike goes in all directions
+                            let dangerous = tile.direction().map_or(true, |tile_dir| *direction == tile_dir.inverse());
+                            if dangerous && bounds.overlaps(&spike_hitbox(x, y, *direction, self.spike_inset())) {
+                                self.kill();
+                                break 'outer;
                             }
                         }
 
                         if matches!(tile, Tile::GoalDown | Tile::GoalLeft | Tile::GoalRight | Tile::GoalUp) {
                             self.touched_goal = true;
                         }
+                    } else if tile.overlaps_slope(x, y, bounds) {
+                        collisions_by_direction[i] = Some(CollisionType::Solid);
                     }
                 }
             }
             if let Some(ty) = state.objects.handle_directional_collision(&bounds, self, state.level_state, state.world_type, *direction) {
                 collisions_by_direction[i] = Some(ty);
             }
+            // One-way platforms only count as ground, and only when the player is above them
+            if *direction == Direction::Down
+                && collisions_by_direction[i].is_none()
+                && self.lands_on_one_way_platform(
+                    self.bounds().max.y,
+                    bounds,
+                    &CollisionWorld::new(state.tilemap, state.objects, state.world_type),
+                )
+            {
+                collisions_by_direction[i] = Some(CollisionType::Solid);
+            }
         }
 
         collisions_by_direction
     }
 
     pub fn kill(&mut self) {
-        debug!("Player died");
+        if !self.dead {
+            debug!("Player died");
+            audio::play_sound(SoundId::Death);
+        }
         self.dead = true;
+        self.set_state(PlayerState::Dead);
     }
 
     pub fn reset(&mut self, position: FVec2) {
@@ -632,7 +1197,57 @@ impl Player {
         self.velocity = FVec2::zero();
         self.acceleration = FVec2::zero();
         self.reset_dash();
+        self.dash_impact = false;
         self.wall_jump_state = WallJumpState::default();
+        self.ability_buffer_ticks = 0;
+        if let Some(render_state) = &mut self.render_state {
+            render_state.trail.clear();
+        }
+        self.set_state(PlayerState::Airborne);
+    }
+
+    pub fn state(&self) -> PlayerState {
+        self.state
+    }
+
+    /// State changes in order since this was last called. The game takes them once per tick,
+    /// see `Game::handle_player_state_changes()`.
+    pub fn take_state_changes(&mut self) -> Vec<PlayerStateChange> {
+        std::mem::take(&mut self.state_changes)
+    }
+
+    /// Whether a dash ran into a wall or an object during the last tick
+    pub fn dash_impacted(&self) -> bool {
+        self.dash_impact
+    }
+
+    pub fn tuning(&self) -> &PlayerTuning {
+        &self.tuning
+    }
+
+    pub fn tuning_mut(&mut self) -> &mut PlayerTuning {
+        &mut self.tuning
+    }
+
+    pub fn set_tuning(&mut self, tuning: PlayerTuning) {
+        self.tuning = tuning;
+    }
+
+    pub fn set_forgiving_spikes(&mut self, forgiving_spikes: bool) {
+        self.forgiving_spikes = forgiving_spikes;
+    }
+
+    pub fn forgiving_spikes(&self) -> bool {
+        self.forgiving_spikes
+    }
+
+    /// See `PlayerTuning::spike_inset` and `PlayerTuning::assist_spike_inset_scale`
+    fn spike_inset(&self) -> f32 {
+        if self.forgiving_spikes {
+            self.tuning.spike_inset * self.tuning.assist_spike_inset_scale
+        } else {
+            self.tuning.spike_inset
+        }
     }
 
     pub fn position(&self) -> FVec2 {
@@ -643,6 +1258,10 @@ impl Player {
         self.position = position;
     }
 
+    pub fn velocity(&self) -> FVec2 {
+        self.velocity
+    }
+
     pub fn dead(&self) -> bool {
         self.dead
     }
@@ -652,7 +1271,28 @@ impl Player {
     }
 
     pub fn allowed_to_move(&self) -> bool {
-        true
+        !self.dead
+    }
+
+    /// Direction and progress (from 0 to 1) of the current dash, if the player is dashing
+    pub fn dash_progress(&self) -> Option<(Direction, f32)> {
+        self.dash_state
+            .progress()
+            .map(|progress| (self.dash_state.direction, progress))
+    }
+
+    /// Wall jumps off the current wall that are left before touching the ground or another
+    /// wall, `None` if they aren't limited. See `WallJumpTuning::max_chained_wall_jumps`.
+    pub fn remaining_chained_wall_jumps(&self) -> Option<i32> {
+        let max = self.tuning.wall_jump.max_chained_wall_jumps;
+        (max > 0).then(|| max - self.wall_jump_state.chained_wall_jumps)
+    }
+
+    /// Remaining stamina from 0 to 1, `None` if it's unlimited. See
+    /// `WallJumpTuning::max_stamina_ticks`.
+    pub fn wall_stamina(&self) -> Option<f32> {
+        let max = self.tuning.wall_jump.max_stamina_ticks;
+        (max > 0).then(|| 1.0 - self.wall_jump_state.used_stamina as f32 / max as f32)
     }
 
     /// Whether the player is considered to be "on the ground" (coyote time included!)
@@ -672,41 +1312,88 @@ impl Player {
         }
     }
 
+    pub fn abilities(&self) -> AbilityPair {
+        self.abilities
+    }
+
     pub fn set_abilities(&mut self, abilities: AbilityPair) {
         self.abilities = abilities
     }
 
+    /// Set the direction used for dashing until the player moves horizontally
+    pub fn set_facing(&mut self, direction: Direction) {
+        self.dash_state.direction = direction;
+    }
+
+    /// Use the current world's ability within the next few ticks, see `Game::try_switch_world()`
+    pub fn buffer_ability(&mut self) {
+        self.ability_buffer_ticks = self.tuning.max_ability_buffer_ticks;
+    }
+
+    /// Jumps are higher while the button is held. Jumps from the switch combo are held with
+    /// the combo's button.
+    fn jump_held(&self, input: &Input) -> bool {
+        input.get_button(ButtonType::Jump).pressed()
+            || input.get_button(ButtonType::SwitchAndAbility).pressed()
+    }
+
     fn reset_dash(&mut self) {
         self.dash_state = DashState::default();
     }
 
+    /// Whether the player is falling slowly using the glider
+    pub fn gliding(&self, input: &Input, world_type: WorldType) -> bool {
+        self.active_ability(world_type) == Ability::Glider
+            && input.ability_button_pressed()
+            && self.velocity.y > 0.0
+            && self.allowed_to_move()
+    }
+
     fn apply_gravity(&mut self, state: &PlayerTickState) {
-        self.add_force(
-            if self.active_ability(state.world_type) == Ability::Glider
-                && state.input.ability_button_pressed()
-                && self.velocity.y > 0.0
-                && self.allowed_to_move()
-            {
-                Player::GRAVITY_GLIDER
-            } else {
-                Player::GRAVITY
-            },
-        );
+        let gravity = if self.submerged {
+            self.tuning.gravity_water
+        } else if self.gliding(state.input, state.world_type) {
+            self.tuning.gravity_glider
+        } else {
+            self.tuning.gravity
+        };
+        self.add_force(match self.gravity_zone {
+            Some(zone) => zone * gravity.magnitude(),
+            None => gravity,
+        });
     }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct PlayerUniforms {
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+pub(crate) struct PlayerUniforms {
     view_matrix: FMat4,
     model_matrix: FMat4,
     color: Color,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Default)]
+/// Part of the spike tile at `x`, `y` that kills the player when they move into it in
+/// `direction`. The tile is shrunk by `inset` on all edges except the one facing the player.
+fn spike_hitbox(x: i32, y: i32, direction: Direction, inset: f32) -> Bounds {
+    let tile = Bounds::new(FVec2::new(x as f32, y as f32), FVec2::new(x as f32 + 1.0, y as f32 + 1.0));
+    let mut hitbox = Bounds::new(tile.min + FVec2::new(inset, inset), tile.max - FVec2::new(inset, inset));
+    match direction {
+        Direction::Left => hitbox.max.x = tile.max.x,
+        Direction::Right => hitbox.min.x = tile.min.x,
+        Direction::Up => hitbox.max.y = tile.max.y,
+        Direction::Down => hitbox.min.y = tile.min.y,
+    }
+    hitbox
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default, JsonSchema)]
 pub struct AbilityPair(Ability, Ability);
 
 impl AbilityPair {
+    pub const fn new(light: Ability, dark: Ability) -> Self {
+        AbilityPair(light, dark)
+    }
+
     pub fn current(self, world_type: WorldType) -> Ability {
         match world_type {
             WorldType::Light => self.0,
@@ -715,7 +1402,7 @@ impl AbilityPair {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, bytemuck::Contiguous, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, bytemuck::Contiguous, Serialize, Deserialize, JsonSchema)]
 #[repr(i32)]
 pub enum Ability {
     None,
@@ -732,7 +1419,12 @@ impl Default for Ability {
 }
 
 impl Ability {
+    /// Color in the palette chosen in the mod list, or the default color
     pub fn color(self) -> Color {
+        mods::ability_color(self).unwrap_or_else(|| self.default_color())
+    }
+
+    pub fn default_color(self) -> Color {
         match self {
             Ability::None => Color::GRAY,
             Ability::DoubleJump => Color::new_solid(0.75, 0.0, 0.75),
@@ -752,6 +1444,15 @@ impl Ability {
         }
     }
 
+    /// Icon in the ability sheet, see `ability_icon_renderer()`
+    pub fn icon(self) -> Option<Sprite> {
+        match self {
+            Ability::None => None,
+            // The sheet has the other abilities in their order, two by two
+            ability => Some(Sprite::from_grid(2, 2, ability as u32 - 1)),
+        }
+    }
+
     pub fn tutorial_text(self) -> Option<String> {
         unimplemented!();
     }
@@ -767,6 +1468,29 @@ impl Ability {
     }
 }
 
+/// Sprite sheet with black icons on white, tinted with each ability's color
+const ABILITY_ICONS_PATH: &str = "assets/abilities.png";
+
+/// Renderer for `Ability::icon()`s, or `None` if the sheet couldn't be loaded. Icons are
+/// optional, the player and ability blocks are still drawn in the ability's color without them.
+pub(crate) fn ability_icon_renderer(device: &wgpu::Device, format: wgpu::TextureFormat) -> Option<SpriteRenderer> {
+    match Texture::load(device, "ability_icons", ABILITY_ICONS_PATH) {
+        Ok(texture) => Some(SpriteRenderer::new(device, format, texture)),
+        Err(err) => {
+            warn!("Failed to load ability icons: {err}");
+            None
+        }
+    }
+}
+
+/// The largest square centered in `bounds`, so that icons aren't stretched
+pub(crate) fn icon_bounds(bounds: Bounds) -> Bounds {
+    let size = bounds.max - bounds.min;
+    let half_side = size.x.min(size.y) * 0.5;
+    let center = bounds.center();
+    Bounds::new(center - FVec2::new(half_side, half_side), center + FVec2::new(half_side, half_side))
+}
+
 impl fmt::Display for Ability {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.name())
@@ -779,3 +1503,73 @@ pub enum CollisionType {
     Solid,
     Wall,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How far the probes in these tests reach into the spike tile on each axis, less than
+    /// `PlayerTuning::spike_inset`
+    const OVERLAP: f32 = 0.1;
+
+    /// Bounds of the player's size, moving into the spike tile at the origin in `direction` and
+    /// overlapping its dangerous edge by `overlap`. With `sideways`, they're also moved to the
+    /// side so that only the corner is grazed diagonally.
+    fn probe_into_spike(direction: Direction, sideways: bool, overlap: f32) -> Bounds {
+        let forward = direction.as_vec();
+        let side = if sideways {
+            FVec2::new(-forward.y, forward.x)
+        } else {
+            FVec2::zero()
+        };
+        let offset = (Player::SIZE.x + 1.0) * 0.5 - overlap;
+        let center = FVec2::new(0.5, 0.5) - forward * offset + side * offset;
+        Bounds::new(center - Player::SIZE * 0.5, center + Player::SIZE * 0.5)
+    }
+
+    #[test]
+    fn spike_hitbox_ignores_corner_grazes() {
+        let inset = Player::new().spike_inset();
+        assert!(inset > OVERLAP);
+        for direction in Direction::ALL {
+            let probe = probe_into_spike(direction, true, OVERLAP);
+            assert!(
+                !probe.overlaps(&spike_hitbox(0, 0, direction, inset)),
+                "grazing the corner moving {direction:?} shouldn't kill"
+            );
+        }
+    }
+
+    #[test]
+    fn spike_hitbox_kills_on_full_overlap() {
+        let mut player = Player::new();
+        for forgiving_spikes in [false, true] {
+            player.set_forgiving_spikes(forgiving_spikes);
+            for direction in Direction::ALL {
+                let probe = probe_into_spike(direction, false, OVERLAP);
+                assert!(
+                    probe.overlaps(&spike_hitbox(0, 0, direction, player.spike_inset())),
+                    "running into the spike moving {direction:?} should kill"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn forgiving_spikes_widen_the_inset() {
+        let mut player = Player::new();
+        assert!(!player.forgiving_spikes(), "forgiving spikes are an opt-in assist");
+        let inset = player.spike_inset();
+        player.set_forgiving_spikes(true);
+        let forgiving_inset = player.spike_inset();
+        assert_eq!(forgiving_inset, inset * player.tuning().assist_spike_inset_scale);
+
+        // Deeper than the normal inset, but still within the forgiving one
+        let overlap = (inset + forgiving_inset) * 0.5;
+        for direction in Direction::ALL {
+            let probe = probe_into_spike(direction, true, overlap);
+            assert!(probe.overlaps(&spike_hitbox(0, 0, direction, inset)));
+            assert!(!probe.overlaps(&spike_hitbox(0, 0, direction, forgiving_inset)));
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::{fs, io, path::Path};
+
+use complementary_macros::ImGui;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{imgui_helpers::ImGui, math::FVec2};
+
+/// Loaded when the game starts and written by the "Save" button in the DevGUI
+pub const PLAYER_TUNING_PATH: &str = "assets/player_tuning.json";
+
+/// Physics constants of the player, so that movement can be tweaked without recompiling.
+/// Forces are in tiles per tick, durations in ticks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ImGui)]
+#[serde(default)]
+pub struct PlayerTuning {
+    pub move_speed: f32,
+    pub move_speed_exponent: f32,
+    pub gravity: FVec2,
+    pub gravity_glider: FVec2,
+    pub drag: FVec2,
+    /// Gravity and drag in water, so that the player sinks slowly
+    pub gravity_water: FVec2,
+    pub drag_water: FVec2,
+    /// Impulse when pressing Jump in water
+    pub swim_force: FVec2,
+    pub initial_jump_force: FVec2,
+    pub continuous_jump_force: FVec2,
+    pub max_jump_ticks: i32,
+    pub max_jump_buffer_ticks: i32,
+    pub max_ability_buffer_ticks: i32,
+    pub max_coyote_time: i32,
+    /// Fraction of gravity that pushes the player along a 45° slope, `sin(45°) * cos(45°)`
+    pub slope_slide_factor: f32,
+    /// Horizontal drag while standing on ice. The movement force is scaled down so that the
+    /// top speed stays the same, only accelerating and stopping take longer.
+    pub ice_drag_x: f32,
+    /// Speed at which conveyors carry the player
+    pub conveyor_speed: f32,
+    /// Spike tiles only kill the player if they overlap the tile shrunk by this many tiles on
+    /// all edges but the dangerous one, so that grazing a corner is forgiven
+    pub spike_inset: f32,
+    /// `spike_inset` is multiplied by this with the "Forgiving spikes" assist option
+    pub assist_spike_inset_scale: f32,
+    pub dash: DashTuning,
+    pub wall_jump: WallJumpTuning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ImGui)]
+#[serde(default)]
+pub struct DashTuning {
+    pub max_dash_ticks: i32,
+    pub max_cooldown: i32,
+    pub dash_force: f32,
+    /// Jumping during the first ticks of a dash ends it and keeps its remaining horizontal
+    /// velocity, scaled by `dash_jump_momentum_scale`. Jumps wait for the dash to end if zero.
+    pub dash_jump_window: i32,
+    pub dash_jump_momentum_scale: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ImGui)]
+#[serde(default)]
+pub struct WallJumpTuning {
+    pub initial_force: FVec2,
+    /// Applied in the same direction as `initial_force`
+    pub continuous_force_magnitude: f32,
+    pub max_wall_jump_ticks: i32,
+    pub wall_stick_y_drag: f32,
+    pub max_cooldown: i32,
+    pub max_collision_buffer_ticks: i32,
+    pub max_input_buffer_ticks: i32,
+    /// The player can't move in the direction of the wall jump for this amount of ticks after a wall jump
+    pub move_cooldown: i32,
+    /// How many times in a row the player can wall jump off the same wall before touching the
+    /// ground or jumping off another wall, unlimited if zero
+    pub max_chained_wall_jumps: i32,
+    /// Ticks the player can slide down walls before touching the ground again. Once the
+    /// stamina is used up, walls don't slow the fall and can't be jumped off. Unlimited if zero.
+    pub max_stamina_ticks: i32,
+    /// Stamina used by a wall jump, in ticks of sliding
+    pub wall_jump_stamina_cost: i32,
+}
+
+impl PlayerTuning {
+    pub fn load(path: &Path) -> Result<Self, PlayerTuningError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), PlayerTuningError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Load the tuning from `PLAYER_TUNING_PATH`, or use the built-in values if it's missing
+    pub fn load_or_default() -> Self {
+        match PlayerTuning::load(Path::new(PLAYER_TUNING_PATH)) {
+            Ok(tuning) => tuning,
+            Err(PlayerTuningError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                PlayerTuning::default()
+            }
+            Err(err) => {
+                warn!("Failed to load player tuning, using defaults: {err}");
+                PlayerTuning::default()
+            }
+        }
+    }
+}
+
+impl Default for PlayerTuning {
+    fn default() -> Self {
+        Self {
+            move_speed: 0.04,
+            move_speed_exponent: 5.0,
+            gravity: FVec2::new(0.0, 0.0275),
+            gravity_glider: FVec2::new(0.0, 0.005),
+            drag: FVec2::new(0.7, 0.9),
+            gravity_water: FVec2::new(0.0, 0.006),
+            drag_water: FVec2::new(0.6, 0.8),
+            swim_force: FVec2::new(0.0, -0.2),
+            initial_jump_force: FVec2::new(0.0, -0.3),
+            continuous_jump_force: FVec2::new(0.0, -0.1),
+            max_jump_ticks: 40,
+            max_jump_buffer_ticks: 6,
+            max_ability_buffer_ticks: 6,
+            max_coyote_time: 5,
+            slope_slide_factor: 0.5,
+            ice_drag_x: 0.96,
+            conveyor_speed: 0.05,
+            spike_inset: 0.15,
+            assist_spike_inset_scale: 2.0,
+            dash: DashTuning::default(),
+            wall_jump: WallJumpTuning::default(),
+        }
+    }
+}
+
+impl Default for DashTuning {
+    fn default() -> Self {
+        Self {
+            max_dash_ticks: 24,
+            max_cooldown: 24,
+            dash_force: 0.35,
+            dash_jump_window: 0,
+            dash_jump_momentum_scale: 1.0,
+        }
+    }
+}
+
+impl Default for WallJumpTuning {
+    fn default() -> Self {
+        Self {
+            initial_force: FVec2::new(0.5, -0.4),
+            continuous_force_magnitude: 0.12,
+            max_wall_jump_ticks: 40,
+            wall_stick_y_drag: 0.3,
+            max_cooldown: 10,
+            max_collision_buffer_ticks: 5,
+            max_input_buffer_ticks: 7,
+            move_cooldown: 15,
+            max_chained_wall_jumps: 0,
+            max_stamina_ticks: 0,
+            wall_jump_stamina_cost: 0,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlayerTuningError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid player tuning: {0}")]
+    InvalidData(#[from] serde_json::Error),
+}
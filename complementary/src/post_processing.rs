@@ -0,0 +1,558 @@
+use complementary_macros::WgslStruct;
+
+use crate::{
+    gpu_memory::{self, TrackedTexture},
+    math::{Color, FVec2},
+    rendering::UniformBuffer,
+    shader::{self, ShaderConstant},
+};
+
+/// Color of the speed lines in the Light world, they're inverted in the Dark world
+const SPEED_LINE_COLOR: Color = Color::new_solid(0.1, 0.1, 0.1);
+const SPEED_LINE_OPACITY: f32 = 0.6;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+pub(crate) struct PostProcessingUniforms {
+    /// Offset between two blur samples in UV space, or the movement direction for speed lines
+    direction: [f32; 2],
+    /// Bloom or speed line intensity, or the gamma when copying the scene to the output
+    intensity: f32,
+    /// Amount of color inversion, 1.0 in the Dark world
+    inversion: f32,
+}
+
+/// Brightness correction of an sRGB color channel from 0 to 1, the same curve as in
+/// `post_processing.wgsl`. Values above 1 brighten dark colors.
+pub fn gamma_correct(value: f32, gamma: f32) -> f32 {
+    value.powf(1.0 / gamma)
+}
+
+/// Streaks drawn along the screen edges while dashing
+#[derive(Debug, Clone, Copy)]
+pub struct SpeedLines {
+    /// Direction of movement in screen space
+    pub direction: FVec2,
+    /// Opacity of the lines from 0 to 1. The lines also move as this changes.
+    pub intensity: f32,
+}
+
+/// A texture that can be rendered to and sampled from
+struct RenderTarget {
+    /// Only kept to track its memory, the view is used for drawing
+    _texture: TrackedTexture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl RenderTarget {
+    fn new(
+        device: &wgpu::Device,
+        label: &str,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> Self {
+        let texture = gpu_memory::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some(&format!("{label}_texture")),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some(&format!("{label}_bind_group")),
+        });
+
+        Self { _texture: texture, view, bind_group }
+    }
+}
+
+/// Effects applied to the whole frame after the scene was drawn.
+///
+/// The offscreen targets don't use sRGB encoding even if the surface does (see
+/// `scene_format()`), since colors are authored in sRGB. They're converted while writing to
+/// the surface instead, so that they aren't encoded twice.
+///
+/// The scene is drawn to an offscreen target (see `scene_target()`) using the colors of the
+/// Light world, and inverted while copying it to the output when the Dark world is visible.
+/// Objects which keep their colors in both worlds need to invert them beforehand.
+///
+/// Bloom: objects that should glow are drawn a second time into a half-resolution
+/// target (see `bloom_target()`), which is blurred and added on top of the scene.
+///
+/// Gamma correction is applied while copying the scene, before bloom is added.
+///
+/// Speed lines are drawn procedurally on top of everything else.
+pub struct PostProcessing {
+    /// Format of the offscreen targets
+    format: wgpu::TextureFormat,
+    sampler: wgpu::Sampler,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+
+    scene_target: RenderTarget,
+    bloom_targets: [RenderTarget; 2],
+    bloom_width: u32,
+    bloom_height: u32,
+    bloom_intensity: f32,
+
+    horizontal_blur_uniforms: UniformBuffer<PostProcessingUniforms>,
+    vertical_blur_uniforms: UniformBuffer<PostProcessingUniforms>,
+    composite_uniforms: UniformBuffer<PostProcessingUniforms>,
+    invert_uniforms: UniformBuffer<PostProcessingUniforms>,
+    speed_lines_uniforms: UniformBuffer<PostProcessingUniforms>,
+    invert_pipeline: wgpu::RenderPipeline,
+    blur_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    speed_lines_pipeline: wgpu::RenderPipeline,
+}
+
+impl PostProcessing {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        bloom_intensity: f32,
+    ) -> Self {
+        let output_format = format;
+        let format = PostProcessing::scene_format_for(output_format);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post_processing_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("post_processing_texture_bind_group_layout"),
+            });
+
+        let horizontal_blur_uniforms = UniformBuffer::new(device, "horizontal_blur_uniforms");
+        let vertical_blur_uniforms = UniformBuffer::new(device, "vertical_blur_uniforms");
+        let composite_uniforms = UniformBuffer::new(device, "composite_uniforms");
+        let invert_uniforms = UniformBuffer::new(device, "invert_uniforms");
+        let speed_lines_uniforms = UniformBuffer::new(device, "speed_lines_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                horizontal_blur_uniforms.bind_group_layout(),
+                &texture_bind_group_layout,
+            ],
+            label: Some("post_processing_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let shader = shader::create_shader_module(
+            device,
+            "post_processing.wgsl",
+            &[
+                ("SPEED_LINE_COLOR", ShaderConstant::Color(SPEED_LINE_COLOR)),
+                ("SPEED_LINE_OPACITY", ShaderConstant::F32(SPEED_LINE_OPACITY)),
+                ("OUTPUT_SRGB", ShaderConstant::Bool(output_format.describe().srgb)),
+            ],
+        );
+        let invert_pipeline = PostProcessing::create_pipeline(
+            device,
+            "invert_pipeline",
+            &shader,
+            "fs_invert",
+            &pipeline_layout,
+            output_format,
+            None,
+        );
+        let blur_pipeline = PostProcessing::create_pipeline(
+            device,
+            "bloom_blur_pipeline",
+            &shader,
+            "fs_blur",
+            &pipeline_layout,
+            format,
+            None,
+        );
+        let composite_pipeline = PostProcessing::create_pipeline(
+            device,
+            "bloom_composite_pipeline",
+            &shader,
+            "fs_composite",
+            &pipeline_layout,
+            output_format,
+            Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            }),
+        );
+        let speed_lines_pipeline = PostProcessing::create_pipeline(
+            device,
+            "speed_lines_pipeline",
+            &shader,
+            "fs_speed_lines",
+            &pipeline_layout,
+            output_format,
+            Some(wgpu::BlendState::ALPHA_BLENDING),
+        );
+
+        let scene_target = RenderTarget::new(
+            device,
+            "scene_target",
+            format,
+            width,
+            height,
+            &texture_bind_group_layout,
+            &sampler,
+        );
+        let (bloom_width, bloom_height) = PostProcessing::bloom_size(width, height);
+        let bloom_targets = PostProcessing::create_bloom_targets(
+            device,
+            format,
+            bloom_width,
+            bloom_height,
+            &texture_bind_group_layout,
+            &sampler,
+        );
+
+        Self {
+            format,
+            sampler,
+            texture_bind_group_layout,
+            scene_target,
+            bloom_targets,
+            bloom_width,
+            bloom_height,
+            bloom_intensity,
+            horizontal_blur_uniforms,
+            vertical_blur_uniforms,
+            composite_uniforms,
+            invert_uniforms,
+            speed_lines_uniforms,
+            invert_pipeline,
+            blur_pipeline,
+            composite_pipeline,
+            speed_lines_pipeline,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        shader: &wgpu::ShaderModule,
+        fragment_entry_point: &str,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                buffers: &[],
+                module: shader,
+                entry_point: "vs_main",
+            },
+            fragment: Some(wgpu::FragmentState {
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+                module: shader,
+                entry_point: fragment_entry_point,
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            label: Some(label),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// The surface's format without sRGB encoding
+    fn scene_format_for(output_format: wgpu::TextureFormat) -> wgpu::TextureFormat {
+        match output_format {
+            wgpu::TextureFormat::Bgra8UnormSrgb => wgpu::TextureFormat::Bgra8Unorm,
+            wgpu::TextureFormat::Rgba8UnormSrgb => wgpu::TextureFormat::Rgba8Unorm,
+            other => other,
+        }
+    }
+
+    /// Format of `scene_target()` and `bloom_target()`, which renderers must use for their pipelines
+    pub fn scene_format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    fn bloom_size(width: u32, height: u32) -> (u32, u32) {
+        ((width / 2).max(1), (height / 2).max(1))
+    }
+
+    fn create_bloom_targets(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> [RenderTarget; 2] {
+        [
+            RenderTarget::new(device, "bloom_target_0", format, width, height, layout, sampler),
+            RenderTarget::new(device, "bloom_target_1", format, width, height, layout, sampler),
+        ]
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.scene_target = RenderTarget::new(
+            device,
+            "scene_target",
+            self.format,
+            width,
+            height,
+            &self.texture_bind_group_layout,
+            &self.sampler,
+        );
+        let (bloom_width, bloom_height) = PostProcessing::bloom_size(width, height);
+        self.bloom_width = bloom_width;
+        self.bloom_height = bloom_height;
+        self.bloom_targets = PostProcessing::create_bloom_targets(
+            device,
+            self.format,
+            bloom_width,
+            bloom_height,
+            &self.texture_bind_group_layout,
+            &self.sampler,
+        );
+    }
+
+    pub fn bloom_intensity(&self) -> f32 {
+        self.bloom_intensity
+    }
+
+    pub fn set_bloom_intensity(&mut self, intensity: f32) {
+        self.bloom_intensity = intensity;
+    }
+
+    /// Target that the scene is drawn to before effects are applied
+    pub fn scene_target(&self) -> &wgpu::TextureView {
+        &self.scene_target.view
+    }
+
+    /// Target that glowing objects are drawn to. Must be cleared with `clear_bloom_target()` first.
+    pub fn bloom_target(&self) -> &wgpu::TextureView {
+        &self.bloom_targets[0].view
+    }
+
+    pub fn clear_bloom_target(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: self.bloom_target(),
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+            label: Some("bloom_clear_rpass"),
+        });
+    }
+
+    /// Apply all effects to the scene target and write the result to `output`.
+    /// `inversion` is the amount of color inversion, from 0 (Light world) to 1 (Dark world).
+    /// `gamma` is applied to the scene, see `gamma_correct()`.
+    pub fn apply(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        output: &wgpu::TextureView,
+        inversion: f32,
+        gamma: f32,
+        speed_lines: Option<SpeedLines>,
+    ) {
+        self.invert_uniforms.write_with_queue(
+            queue,
+            PostProcessingUniforms {
+                intensity: gamma,
+                inversion,
+                ..bytemuck::Zeroable::zeroed()
+            },
+        );
+        self.fullscreen_pass(
+            encoder,
+            "invert_rpass",
+            output,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.invert_pipeline,
+            &self.invert_uniforms,
+            &self.scene_target.bind_group,
+        );
+
+        if self.bloom_intensity > 0.0 {
+            self.apply_bloom(encoder, queue, output, inversion);
+        }
+
+        if let Some(speed_lines) = speed_lines {
+            self.speed_lines_uniforms.write_with_queue(
+                queue,
+                PostProcessingUniforms {
+                    direction: speed_lines.direction.into(),
+                    intensity: speed_lines.intensity,
+                    inversion,
+                },
+            );
+            // The scene texture isn't sampled, it's only bound to satisfy the pipeline layout
+            self.fullscreen_pass(
+                encoder,
+                "speed_lines_rpass",
+                output,
+                wgpu::LoadOp::Load,
+                &self.speed_lines_pipeline,
+                &self.speed_lines_uniforms,
+                &self.scene_target.bind_group,
+            );
+        }
+    }
+
+    /// Copy the scene to `output` like `apply()`, but without bloom and speed lines, e.g. for
+    /// thumbnails. `output` must have the surface's format, and `apply()` must have been called
+    /// in the same frame for the inversion and gamma.
+    pub fn copy_scene(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        self.fullscreen_pass(
+            encoder,
+            "thumbnail_rpass",
+            output,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &self.invert_pipeline,
+            &self.invert_uniforms,
+            &self.scene_target.bind_group,
+        );
+    }
+
+    fn apply_bloom(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        output: &wgpu::TextureView,
+        inversion: f32,
+    ) {
+        let texel_size = [1.0 / self.bloom_width as f32, 1.0 / self.bloom_height as f32];
+        self.horizontal_blur_uniforms.write_with_queue(
+            queue,
+            PostProcessingUniforms {
+                direction: [texel_size[0], 0.0],
+                ..bytemuck::Zeroable::zeroed()
+            },
+        );
+        self.vertical_blur_uniforms.write_with_queue(
+            queue,
+            PostProcessingUniforms {
+                direction: [0.0, texel_size[1]],
+                ..bytemuck::Zeroable::zeroed()
+            },
+        );
+        self.composite_uniforms.write_with_queue(
+            queue,
+            PostProcessingUniforms {
+                intensity: self.bloom_intensity,
+                inversion,
+                ..bytemuck::Zeroable::zeroed()
+            },
+        );
+
+        // Blur back and forth between both bloom targets
+        self.fullscreen_pass(
+            encoder,
+            "bloom_horizontal_blur_rpass",
+            &self.bloom_targets[1].view,
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            &self.blur_pipeline,
+            &self.horizontal_blur_uniforms,
+            &self.bloom_targets[0].bind_group,
+        );
+        self.fullscreen_pass(
+            encoder,
+            "bloom_vertical_blur_rpass",
+            &self.bloom_targets[0].view,
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            &self.blur_pipeline,
+            &self.vertical_blur_uniforms,
+            &self.bloom_targets[1].bind_group,
+        );
+        self.fullscreen_pass(
+            encoder,
+            "bloom_composite_rpass",
+            output,
+            wgpu::LoadOp::Load,
+            &self.composite_pipeline,
+            &self.composite_uniforms,
+            &self.bloom_targets[0].bind_group,
+        );
+    }
+
+    fn fullscreen_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        target: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+        pipeline: &wgpu::RenderPipeline,
+        uniforms: &UniformBuffer<PostProcessingUniforms>,
+        source: &wgpu::BindGroup,
+    ) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            }],
+            depth_stencil_attachment: None,
+            label: Some(label),
+        });
+        rpass.set_pipeline(pipeline);
+        rpass.set_bind_group(0, uniforms.bind_group(), &[]);
+        rpass.set_bind_group(1, source, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
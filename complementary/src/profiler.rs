@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use log::{info, warn};
+
+/// Collects how long each part of a tick took
+#[derive(Debug, Default)]
+pub struct Profiler {
+    /// Accumulated time per section since the last `reset()`
+    sections: Vec<(&'static str, Duration)>,
+}
+
+impl Profiler {
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        match self.sections.iter_mut().find(|(section, _)| *section == name) {
+            Some((_, total)) => *total += duration,
+            None => self.sections.push((name, duration)),
+        }
+    }
+
+    /// Sections sorted by their accumulated time, slowest first
+    pub fn slowest(&self, count: usize) -> Vec<(&'static str, Duration)> {
+        let mut sections = self.sections.clone();
+        sections.sort_by(|a, b| b.1.cmp(&a.1));
+        sections.truncate(count);
+        sections
+    }
+
+    pub fn reset(&mut self) {
+        self.sections.clear();
+    }
+}
+
+/// Watches tick durations and switches to a cheaper simulation if ticks keep taking longer
+/// than `Game::TICK_DURATION`
+#[derive(Debug)]
+pub struct TickBudget {
+    budget: Duration,
+    auto_slow_mode: bool,
+    /// Increased for every tick over budget, decreased for every tick within
+    over_budget_score: u32,
+    ticks_within_budget: u32,
+    ticks_since_warning: u32,
+    /// Number of ticks and their total duration since the last warning
+    sampled_ticks: u32,
+    sampled_duration: Duration,
+    reduced_simulation: bool,
+    last_tick_duration: Duration,
+    profiler: Profiler,
+}
+
+impl TickBudget {
+    /// Score at which the tick duration is considered to be consistently over budget
+    const OVER_BUDGET_THRESHOLD: u32 = 100;
+    /// Minimum number of ticks between two warnings
+    const WARNING_INTERVAL: u32 = 500;
+    /// Leave the reduced simulation after this many ticks in a row within budget
+    const RECOVERY_TICKS: u32 = 1000;
+    /// Number of sections included in warnings
+    const REPORTED_SECTIONS: usize = 3;
+
+    pub fn new(budget: Duration, auto_slow_mode: bool) -> Self {
+        Self {
+            budget,
+            auto_slow_mode,
+            over_budget_score: 0,
+            ticks_within_budget: 0,
+            ticks_since_warning: Self::WARNING_INTERVAL,
+            sampled_ticks: 0,
+            sampled_duration: Duration::ZERO,
+            reduced_simulation: false,
+            last_tick_duration: Duration::ZERO,
+            profiler: Profiler::default(),
+        }
+    }
+
+    pub fn profiler(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// Whether the simulation should skip expensive effects, e.g. limit the number of particles
+    pub fn reduced_simulation(&self) -> bool {
+        self.reduced_simulation
+    }
+
+    pub fn last_tick_duration(&self) -> Duration {
+        self.last_tick_duration
+    }
+
+    /// Called after every tick with the time the tick took
+    pub fn finish_tick(&mut self, duration: Duration) {
+        self.last_tick_duration = duration;
+        self.sampled_ticks += 1;
+        self.sampled_duration += duration;
+        self.ticks_since_warning = self.ticks_since_warning.saturating_add(1);
+
+        if duration > self.budget {
+            self.over_budget_score += 1;
+            self.ticks_within_budget = 0;
+        } else {
+            self.over_budget_score = self.over_budget_score.saturating_sub(1);
+            self.ticks_within_budget = self.ticks_within_budget.saturating_add(1);
+            if self.over_budget_score == 0 {
+                // Only report samples from the current slow phase
+                self.reset_samples();
+            }
+        }
+
+        if self.over_budget_score >= Self::OVER_BUDGET_THRESHOLD {
+            if self.ticks_since_warning >= Self::WARNING_INTERVAL {
+                self.warn_over_budget();
+            }
+            if self.auto_slow_mode && !self.reduced_simulation {
+                info!("Tick budget exceeded consistently, enabling reduced simulation");
+                self.reduced_simulation = true;
+            }
+        } else if self.reduced_simulation && self.ticks_within_budget >= Self::RECOVERY_TICKS {
+            info!("Ticks are within budget again, disabling reduced simulation");
+            self.reduced_simulation = false;
+        }
+    }
+
+    fn warn_over_budget(&mut self) {
+        let average = self.sampled_duration / self.sampled_ticks.max(1);
+        let slowest = self
+            .profiler
+            .slowest(Self::REPORTED_SECTIONS)
+            .iter()
+            .map(|(name, total)| {
+                let per_tick = *total / self.sampled_ticks.max(1);
+                format!("{name}={:.2}ms", per_tick.as_secs_f64() * 1000.0)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        warn!(
+            "Tick over budget: avg_ms={:.2} budget_ms={:.2} ticks={} reduced={} slowest=[{slowest}]",
+            average.as_secs_f64() * 1000.0,
+            self.budget.as_secs_f64() * 1000.0,
+            self.sampled_ticks,
+            self.reduced_simulation,
+        );
+
+        self.ticks_since_warning = 0;
+        self.reset_samples();
+    }
+
+    fn reset_samples(&mut self) {
+        self.sampled_ticks = 0;
+        self.sampled_duration = Duration::ZERO;
+        self.profiler.reset();
+    }
+}
@@ -0,0 +1,107 @@
+use log::{info, warn};
+use rand_xoshiro::{rand_core::SeedableRng, Xoshiro256PlusPlus};
+
+use crate::{
+    level::Level,
+    level_validator::{MovementReach, Reachability},
+    player::AbilityPair,
+};
+
+/// Game mode that shuffles which abilities the ability blocks of a level grant.
+///
+/// Abilities are only swapped between the blocks of the same level, so every level still
+/// offers the same abilities as before. Shuffles that make the goal unreachable according to
+/// the level validator are thrown away, see `Reachability`.
+pub struct Randomizer {
+    enabled: bool,
+    seed: u64,
+    /// Contents of the seed field in the DevGUI
+    seed_text: String,
+}
+
+impl Randomizer {
+    /// Shuffles tried per level before falling back to the original abilities
+    const MAX_ATTEMPTS: u32 = 100;
+
+    pub fn new() -> Self {
+        Randomizer {
+            enabled: false,
+            seed: 0,
+            seed_text: String::from("0"),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable the randomizer with `seed`, or disable it with `None`
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.enabled = seed.is_some();
+        if let Some(seed) = seed {
+            self.seed = seed;
+            self.seed_text = seed.to_string();
+        }
+    }
+
+    /// Seed shown in the pause menu, if the randomizer is enabled
+    pub fn seed(&self) -> Option<u64> {
+        self.enabled.then(|| self.seed)
+    }
+
+    /// Shuffle the abilities of a freshly loaded level, which the player enters with
+    /// `abilities`. Each level is shuffled differently, but always the same way for the same
+    /// seed. Levels whose goal is reachable stay that way; if no shuffle keeps it reachable,
+    /// the original abilities are kept.
+    pub fn apply(&self, level: &mut Level, abilities: AbilityPair, reach: &MovementReach) {
+        if !self.enabled {
+            return;
+        }
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(self.seed ^ hash_name(&level.name));
+        let must_stay_completable = Reachability::of_level(level, abilities, reach).completable();
+        let original = level.objects.ability_block_abilities();
+        for _ in 0..Randomizer::MAX_ATTEMPTS {
+            level.objects.shuffle_abilities(&mut rng);
+            if !must_stay_completable || Reachability::of_level(level, abilities, reach).completable() {
+                return;
+            }
+        }
+        warn!("No shuffle of level \"{}\" keeps its goal reachable, using the original abilities", level.name);
+        level.objects.set_ability_block_abilities(&original);
+    }
+
+    /// Returns true if the settings were changed, so that the current level should be reloaded
+    pub fn draw_gui(&mut self, gui: &imgui::Ui) -> bool {
+        let mut changed = gui.checkbox("Enabled", &mut self.enabled);
+
+        gui.input_text("Seed", &mut self.seed_text).build();
+        gui.same_line();
+        if gui.button("Apply") {
+            match self.seed_text.trim().parse() {
+                Ok(seed) => {
+                    self.seed = seed;
+                    changed = true;
+                }
+                Err(_) => self.seed_text = self.seed.to_string(),
+            }
+        }
+        if gui.button("Random seed") {
+            self.seed = rand::random();
+            self.seed_text = self.seed.to_string();
+            changed = true;
+        }
+
+        if changed && self.enabled {
+            info!("Randomizer seed: {}", self.seed);
+        }
+        changed
+    }
+}
+
+/// FNV-1a hash of a level's name. `DefaultHasher` isn't used since its output may change
+/// between Rust versions, which would change the shuffled levels for a seed.
+fn hash_name(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -0,0 +1,138 @@
+//! Headless tests that every renderer works with the current shaders and pipeline layouts.
+//!
+//! Mismatches between bind groups, layouts and shaders only show up as wgpu validation errors
+//! when something is drawn, so these draw every level once into an offscreen target and fail
+//! on any errors. They need a graphics adapter, so they only run with
+//! `cargo test --features renderer_check`. Machines without a GPU can use a software adapter
+//! like llvmpipe with `WGPU_BACKEND=gl`.
+
+use crate::{
+    game::Game,
+    input::Input,
+    level,
+    post_processing::PostProcessing,
+    settings::Settings,
+    test_support,
+    window::DrawContext,
+};
+
+/// The format that most surfaces prefer, see `Window::new()`
+const TARGET_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8UnormSrgb;
+const TARGET_WIDTH: u32 = 640;
+const TARGET_HEIGHT: u32 = 360;
+
+fn create_device() -> (wgpu::Device, wgpu::Queue) {
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all());
+    let instance = wgpu::Instance::new(backends);
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    }))
+    .expect("No graphics adapter available, try WGPU_BACKEND=gl with a software renderer");
+
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            limits: wgpu::Limits::default(),
+            label: Some("device"),
+            features: wgpu::Features::empty(),
+        },
+        None,
+    ))
+    .expect("Failed to create device")
+}
+
+#[test]
+fn every_level_draws_without_validation_errors() {
+    test_support::use_repository_root();
+    let (device, queue) = create_device();
+
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("renderer_check_target"),
+        size: wgpu::Extent3d {
+            width: TARGET_WIDTH,
+            height: TARGET_HEIGHT,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TARGET_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    let output = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let settings = Settings {
+        audio: false,
+        ..Settings::default()
+    };
+    let post_processing = PostProcessing::new(
+        &device,
+        TARGET_FORMAT,
+        TARGET_WIDTH,
+        TARGET_HEIGHT,
+        settings.bloom_intensity,
+    );
+    let mut game = Game::new_for_tests(&settings).unwrap();
+    let input = Input::new();
+
+    let mut failures = Vec::new();
+    for level in level::get_all_levels().unwrap() {
+        if let Err(err) = game.load_level(&level) {
+            failures.push(format!("{level}: failed to load: {err}"));
+            continue;
+        }
+        // Tick once so that objects and particles have something to draw
+        game.tick(&input);
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        draw_frame(&device, &queue, &mut game, &post_processing, &output);
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            failures.push(format!("{level}: {err}"));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// Draw a frame like `Window::run_main_loop()`, except for ImGui
+fn draw_frame(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    game: &mut Game,
+    post_processing: &PostProcessing,
+    output: &wgpu::TextureView,
+) {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("renderer_check_encoder"),
+    });
+
+    game.draw(&mut DrawContext {
+        device,
+        encoder: &mut encoder,
+        output: post_processing.scene_target(),
+        queue,
+        format: post_processing.scene_format(),
+        window_width: TARGET_WIDTH,
+        window_height: TARGET_HEIGHT,
+    });
+    post_processing.clear_bloom_target(&mut encoder);
+    game.draw_emissive(&mut DrawContext {
+        device,
+        encoder: &mut encoder,
+        output: post_processing.bloom_target(),
+        queue,
+        format: post_processing.scene_format(),
+        window_width: TARGET_WIDTH,
+        window_height: TARGET_HEIGHT,
+    });
+    post_processing.apply(
+        &mut encoder,
+        queue,
+        output,
+        game.inversion(),
+        1.0,
+        game.speed_lines(),
+    );
+
+    queue.submit([encoder.finish()]);
+}
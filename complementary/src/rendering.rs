@@ -1,13 +1,62 @@
-use std::marker::PhantomData;
+pub mod text;
+
+use std::{fs::File, io::{self, BufReader}, marker::PhantomData, num::NonZeroU32, path::Path};
 
 use bytemuck::{Pod, Zeroable};
-use cgmath::SquareMatrix;
-use wgpu::{util::DeviceExt, vertex_attr_array};
+use cgmath::{ElementWise, SquareMatrix};
+use complementary_macros::WgslStruct;
+use wgpu::vertex_attr_array;
+
+use crate::gpu_memory::{self, TrackedBuffer, TrackedTexture};
+use crate::math::{Bounds, Color, FMat4, FVec2, FVec3, FVec4};
+use crate::shader;
+use crate::ui_layout;
+use crate::window::DrawContext;
 
-use crate::math::{Color, FMat4, FVec2, FVec3};
+/// Layers in the order they are drawn. Every renderer belongs to exactly one layer.
+///
+/// - `Background` clears the target and must be drawn first.
+/// - `BehindTilemap` is for decoration like background particles, which is covered by the tiles.
+/// - `Tilemap` contains the tiles.
+/// - `Opaque` objects don't blend with each other, so their order doesn't matter.
+/// - `Player` is drawn over all opaque objects.
+/// - `Transparent` objects blend with whatever was drawn before. Object types are drawn in
+///   the order they're listed in `object_multi_list!`, and renderers in this layer must sort
+///   their instances with `sort_for_blending()` so that overlapping objects always blend in
+///   the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderLayer {
+    Background,
+    BehindTilemap,
+    Tilemap,
+    Opaque,
+    Player,
+    Transparent,
+}
+
+/// An instance that is drawn in the `Transparent` render layer
+pub trait TransparentInstance {
+    fn color(&self) -> Color;
+    fn position(&self) -> FVec2;
+}
+
+/// Put transparent instances into a fixed blending order. This isn't a depth sort, since
+/// objects have no depth: the most opaque instances are drawn first and fading ones are
+/// blended on top, ties are broken by position. Fully transparent instances are removed,
+/// they don't change the image with alpha blending.
+pub fn sort_for_blending<T: TransparentInstance>(instances: &mut Vec<T>) {
+    instances.retain(|instance| instance.color().a > 0.0);
+    instances.sort_by(|a, b| {
+        b.color()
+            .a
+            .total_cmp(&a.color().a)
+            .then(a.position().y.total_cmp(&b.position().y))
+            .then(a.position().x.total_cmp(&b.position().x))
+    });
+}
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
 pub struct DrawState {
     pub view_matrix: FMat4,
 }
@@ -46,13 +95,63 @@ impl DrawState {
                 1.0,
             );
     }
+
+    /// Map screen coordinates in pixels to the screen, with the origin in the top left corner
+    pub fn screen_space(window_width: f32, window_height: f32) -> DrawState {
+        Self {
+            view_matrix: FMat4::from_translation(FVec3::new(-1.0, 1.0, 0.0))
+                * FMat4::from_nonuniform_scale(2.0 / window_width, -2.0 / window_height, 1.0),
+        }
+    }
+
+    /// Show the area around `center` with the given scale, see `Camera`
+    pub fn update_view_matrix_centered(
+        &mut self,
+        window_width: f32,
+        window_height: f32,
+        center: FVec2,
+        pixels_per_tile: f32,
+    ) {
+        self.view_matrix = FMat4::from_nonuniform_scale(
+            (pixels_per_tile / window_width) * 2.0,
+            (pixels_per_tile / window_height) * -2.0,
+            1.0,
+        ) * FMat4::from_translation(FVec3::new(-center.x, -center.y, 0.0));
+    }
+
+    /// Area of the world that is visible on screen
+    pub fn visible_bounds(&self) -> Bounds {
+        let inverse = self.view_matrix.invert().unwrap_or(FMat4::identity());
+        let min = inverse * FVec4::new(-1.0, 1.0, 0.0, 1.0);
+        let max = inverse * FVec4::new(1.0, -1.0, 0.0, 1.0);
+        Bounds::new(FVec2::new(min.x, min.y), FVec2::new(max.x, max.y))
+    }
+
+    /// Transform a point in world space to screen coordinates in a screen of the given size
+    pub fn world_to_screen(&self, point: FVec2, screen_size: [f32; 2]) -> [f32; 2] {
+        let clip = self.view_matrix * FVec4::new(point.x, point.y, 0.0, 1.0);
+        [
+            (clip.x + 1.0) / 2.0 * screen_size[0],
+            (1.0 - clip.y) / 2.0 * screen_size[1],
+        ]
+    }
+
+    /// Transform screen coordinates in a screen of the given size to world space, the inverse
+    /// of `world_to_screen()`
+    pub fn screen_to_world(&self, point: [f32; 2], screen_size: [f32; 2]) -> FVec2 {
+        let inverse = self.view_matrix.invert().unwrap_or(FMat4::identity());
+        let ndc = ui_layout::screen_to_ndc(point, screen_size);
+        let clip = FVec4::new(ndc.x, ndc.y, 0.0, 1.0);
+        let world = inverse * clip;
+        FVec2::new(world.x, world.y)
+    }
 }
 
 pub struct UniformBuffer<T>
 where
     T: Clone + bytemuck::Pod + bytemuck::Zeroable,
 {
-    buffer: wgpu::Buffer,
+    buffer: TrackedBuffer,
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
     phantom: PhantomData<T>,
@@ -60,7 +159,7 @@ where
 
 impl<T: bytemuck::Pod> UniformBuffer<T> {
     pub fn new(device: &wgpu::Device, label: &str) -> Self {
-        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        let buffer = gpu_memory::create_buffer(device, &wgpu::BufferDescriptor {
             label: Some(&format!("{label}_uniform_buffer")),
             size: std::mem::size_of::<T>() as _,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
@@ -110,7 +209,8 @@ impl<T: bytemuck::Pod> UniformBuffer<T> {
     }
 }
 
-#[derive(Copy, Clone, Pod, Zeroable)]
+#[derive(Copy, Clone, Pod, Zeroable, WgslStruct)]
+#[wgsl(location = 0)]
 #[repr(C)]
 pub struct Vertex {
     position: FVec2,
@@ -152,7 +252,8 @@ pub const DIAMOND_VERTICES: [Vertex; 6] = [
     Vertex::new(0.9, 0.5),
 ];
 
-#[derive(Copy, Clone, Pod, Zeroable)]
+#[derive(Copy, Clone, Pod, Zeroable, WgslStruct)]
+#[wgsl(location = 0)]
 #[repr(C)]
 pub struct ColoredVertex {
     position: FVec2,
@@ -176,11 +277,379 @@ impl ColoredVertex {
     }
 }
 
+/// Decoded image with 8-bit RGBA pixels, see `load_image()`
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Decode a PNG file. Other color types are converted to RGBA.
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<Image, TextureError> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels)?;
+    let pixels = &pixels[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&value| [value, value, value, u8::MAX]).collect(),
+        // Palettes are expanded by `Transformations::EXPAND`
+        png::ColorType::Indexed => return Err(TextureError::UnsupportedFormat),
+    };
+    Ok(Image { width: info.width, height: info.height, rgba })
+}
+
+/// Sampled texture, bound as group 1 by the pipelines using it: the texture at binding 0 and
+/// the sampler at binding 1. Renderers are created without the queue, so the pixels are only
+/// written when `upload()` is first called while drawing.
+pub struct Texture {
+    texture: TrackedTexture,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    /// Pixels of the mip levels that weren't written yet
+    pending_mip_levels: Vec<Vec<u8>>,
+}
+
+impl Texture {
+    /// `mip_levels` contains the pixels of every level, starting with the full size. Each
+    /// level is half as large as the one before, rounded down.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        mip_levels: Vec<Vec<u8>>,
+    ) -> Self {
+        let texture = gpu_memory::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some(&format!("{label}_texture")),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mip_levels.len().max(1) as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(&format!("{label}_sampler")),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some(&format!("{label}_bind_group_layout")),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some(&format!("{label}_bind_group")),
+        });
+
+        Self { texture, bind_group_layout, bind_group, format, width, height, pending_mip_levels: mip_levels }
+    }
+
+    /// Colors are authored in sRGB and the scene isn't drawn with sRGB encoding (see
+    /// `PostProcessing`), so the pixels are used as they are
+    pub fn from_image(device: &wgpu::Device, label: &str, image: Image) -> Self {
+        Texture::new(device, label, wgpu::TextureFormat::Rgba8Unorm, image.width, image.height, vec![image.rgba])
+    }
+
+    pub fn load<P: AsRef<Path>>(device: &wgpu::Device, label: &str, path: P) -> Result<Self, TextureError> {
+        Ok(Texture::from_image(device, label, load_image(path)?))
+    }
+
+    /// Write the pixels passed to `new()`, does nothing after the first call
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        let block_size = self.format.describe().block_size as u32;
+        for (level, pixels) in self.pending_mip_levels.drain(..).enumerate() {
+            let width = (self.width >> level).max(1);
+            let height = (self.height >> level).max(1);
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &pixels,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(width * block_size),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+}
+
+/// Area of a texture that is drawn by `SpriteRenderer`, e.g. a frame of a sprite sheet
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    /// In UV coordinates, from (0, 0) in the top left to (1, 1) in the bottom right corner
+    uv: Bounds,
+}
+
+impl Sprite {
+    /// Cell `index` of a sheet with `columns` by `rows` cells of the same size, counted row
+    /// by row from the top left
+    pub fn from_grid(columns: u32, rows: u32, index: u32) -> Self {
+        let size = FVec2::new(1.0 / columns as f32, 1.0 / rows as f32);
+        let min = FVec2::new((index % columns) as f32, (index / columns) as f32).mul_element_wise(size);
+        Self { uv: Bounds::new(min, min + size) }
+    }
+}
+
+#[derive(Copy, Clone, Pod, Zeroable, WgslStruct)]
+#[wgsl(location = 0)]
+#[repr(C)]
+pub struct SpriteVertex {
+    position: FVec2,
+    uv: FVec2,
+    /// Multiplied with the texture's color
+    color: Color,
+}
+
+impl SpriteVertex {
+    const ATTR: &'static [wgpu::VertexAttribute] =
+        &vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: Self::ATTR,
+        }
+    }
+}
+
+/// Draws sprites from a single texture. Sprites are collected with `queue_sprite()` and drawn
+/// at once with `flush()`, which can only be called once per frame since the vertex buffer is
+/// written with the queue.
+pub struct SpriteRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    texture: Texture,
+    vertex_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+    vertices: Vec<SpriteVertex>,
+}
+
+impl SpriteRenderer {
+    /// Six vertices per sprite
+    const MAX_VERTEX_COUNT: usize = 6 * 1024;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, texture: Texture) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "sprite_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout(), texture.bind_group_layout()],
+            label: Some("sprite_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffer = create_instance_buffer::<SpriteVertex>(device, Some("sprite_vertex_buffer"),
+            SpriteRenderer::MAX_VERTEX_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("sprite_pipeline"),
+            &shader::create_shader_module(device, "sprite.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[SpriteVertex::layout()],
+            &color_targets(format),
+        ));
+
+        Self { uniform_buffer, texture, vertex_buffer, render_pipeline, vertices: Vec::new() }
+    }
+
+    /// Add `sprite` stretched over `bounds`, in the space of the `DrawState` passed to `flush()`
+    pub fn queue_sprite(&mut self, sprite: Sprite, bounds: Bounds, color: Color) {
+        let (min, max) = (sprite.uv.min, sprite.uv.max);
+        // Same winding as `TilemapRenderer::append_rectangle()`
+        let corners = [
+            (FVec2::new(bounds.min.x, bounds.max.y), FVec2::new(min.x, max.y)),
+            (bounds.min, min),
+            (bounds.max, max),
+            (bounds.max, max),
+            (bounds.min, min),
+            (FVec2::new(bounds.max.x, bounds.min.y), FVec2::new(max.x, min.y)),
+        ];
+        for (position, uv) in corners {
+            self.vertices.push(SpriteVertex { position, uv, color });
+        }
+    }
+
+    /// Draw the queued sprites with the view matrix of `state`
+    pub fn flush(&mut self, context: &mut DrawContext, state: &DrawState) {
+        self.texture.upload(context.queue);
+
+        let mut vertices = std::mem::take(&mut self.vertices);
+        vertices.truncate(SpriteRenderer::MAX_VERTEX_COUNT);
+        if vertices.is_empty() {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("sprite_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, self.uniform_buffer.bind_group(), &[]);
+        rpass.set_bind_group(1, self.texture.bind_group(), &[]);
+        rpass.draw(0..vertices.len() as u32, 0..1);
+    }
+}
+
+/// Covers the whole scene with a single color, used for fading in and out of transitions
+pub struct FadeRenderer {
+    uniform_buffer: UniformBuffer<Color>,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl FadeRenderer {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "fade_uniforms");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout()],
+            label: Some("fade_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let shader = shader::create_shader_module(device, "fade.wgsl", &[]);
+        let targets = color_targets(format);
+        let mut descriptor = create_pipeline_descriptor(
+            Some("fade_pipeline"),
+            &shader,
+            Some(&pipeline_layout),
+            &[],
+            &targets,
+        );
+        // The full-screen triangle is generated in the shader with counter-clockwise winding
+        descriptor.primitive.cull_mode = None;
+        let render_pipeline = device.create_render_pipeline(&descriptor);
+
+        Self {
+            uniform_buffer,
+            render_pipeline,
+        }
+    }
+
+    /// Blend `color` over everything that was drawn before. Does nothing if it's transparent.
+    pub fn draw(&mut self, context: &mut DrawContext, color: Color) {
+        if color.a <= 0.0 {
+            return;
+        }
+
+        self.uniform_buffer.write_with_queue(context.queue, color);
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("fade_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+/// Alpha blended color target for pipelines drawing into textures with the given format,
+/// usually `DrawContext::format`
+pub fn color_targets(format: wgpu::TextureFormat) -> [wgpu::ColorTargetState; 1] {
+    [wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+        write_mask: wgpu::ColorWrites::ALL,
+    }]
+}
+
+/// `targets` should be created with `color_targets()`, so that the pipeline matches the
+/// format of the surface instead of assuming one
 pub fn create_pipeline_descriptor<'a>(
     label: Option<&'a str>,
     shader: &'a wgpu::ShaderModule,
     layout: Option<&'a wgpu::PipelineLayout>,
     buffer_layouts: &'a [wgpu::VertexBufferLayout<'a>],
+    targets: &'a [wgpu::ColorTargetState],
 ) -> wgpu::RenderPipelineDescriptor<'a> {
     wgpu::RenderPipelineDescriptor {
         layout,
@@ -190,11 +659,7 @@ pub fn create_pipeline_descriptor<'a>(
             entry_point: "vs_main",
         },
         fragment: Some(wgpu::FragmentState {
-            targets: &[wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                write_mask: wgpu::ColorWrites::ALL,
-            }],
+            targets,
             module: &shader,
             entry_point: "fs_main",
         }),
@@ -222,8 +687,8 @@ pub fn create_vertex_buffer<T: bytemuck::Pod>(
     device: &wgpu::Device,
     label: Option<&str>,
     contents: &[T],
-) -> wgpu::Buffer {
-    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+) -> TrackedBuffer {
+    gpu_memory::create_buffer_init(device, &wgpu::util::BufferInitDescriptor {
         label,
         contents: bytemuck::cast_slice(&contents),
         usage: wgpu::BufferUsages::VERTEX,
@@ -234,11 +699,21 @@ pub fn create_instance_buffer<T: bytemuck::Pod>(
     device: &wgpu::Device,
     label: Option<&str>,
     max_instance_count: usize,
-) -> wgpu::Buffer {
-    device.create_buffer(&wgpu::BufferDescriptor {
+) -> TrackedBuffer {
+    gpu_memory::create_buffer(device, &wgpu::BufferDescriptor {
         label,
         size: (std::mem::size_of::<T>() * max_instance_count) as u64,
         usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         mapped_at_creation: false,
     })
 }
+
+#[derive(thiserror::Error, Debug)]
+pub enum TextureError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid image: {0}")]
+    InvalidData(#[from] png::DecodingError),
+    #[error("unsupported image format")]
+    UnsupportedFormat,
+}
@@ -0,0 +1,288 @@
+//! Text drawn with the bitmap font in `assets/font.png`, for text that is part of the scene
+//! instead of the ImGui overlay. The atlas was generated along with `assets/font.json`,
+//! which contains the position and metrics of every glyph.
+//!
+//! Glyphs are drawn as instanced quads. Text can be drawn in world space with the game's
+//! `DrawState` or in screen space with `DrawState::screen_space()`.
+
+use std::{collections::HashMap, fs::File, io::BufReader};
+
+use complementary_macros::WgslStruct;
+use log::warn;
+use serde::Deserialize;
+use wgpu::vertex_attr_array;
+
+use crate::{
+    gpu_memory::TrackedBuffer,
+    math::{Color, FVec2},
+    rendering::{
+        color_targets, create_instance_buffer, create_pipeline_descriptor, create_vertex_buffer, load_image,
+        DrawState, Texture, TextureError, UniformBuffer, Vertex, SQUARE_VERTICES,
+    },
+    shader,
+    window::DrawContext,
+};
+
+const FONT_METRICS_PATH: &str = "assets/font.json";
+const FONT_TEXTURE_PATH: &str = "assets/font.png";
+/// Drawn for characters that aren't in the font
+const REPLACEMENT_CHARACTER: char = '?';
+
+lazy_static::lazy_static! {
+    static ref FONT: Font = Font::load_or_empty();
+}
+
+/// Position of a glyph in the atlas and how it's placed relative to the pen, in pixels of
+/// the atlas
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Glyph {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    /// Offset from the top left corner of the glyph to the pen position on the baseline
+    origin_x: f32,
+    origin_y: f32,
+    advance: f32,
+}
+
+/// Contents of `assets/font.json`
+#[derive(Debug, Deserialize)]
+struct Font {
+    /// Distance between lines in pixels of the atlas
+    size: f32,
+    width: u32,
+    height: u32,
+    characters: HashMap<char, Glyph>,
+}
+
+impl Font {
+    fn load() -> Result<Self, TextError> {
+        let file = File::open(FONT_METRICS_PATH)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Text isn't shown if the font can't be loaded
+    fn load_or_empty() -> Self {
+        Font::load().unwrap_or_else(|err| {
+            warn!("Failed to load font: {err}");
+            Font { size: 1.0, width: 1, height: 1, characters: HashMap::new() }
+        })
+    }
+
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.characters.get(&c).or_else(|| self.characters.get(&REPLACEMENT_CHARACTER))
+    }
+
+    /// Distance from the top of a line to its baseline, in pixels of the atlas
+    fn ascent(&self) -> f32 {
+        self.characters.values().map(|glyph| glyph.origin_y).fold(0.0, f32::max)
+    }
+
+    /// Width of a line in pixels of the atlas
+    fn line_width(&self, line: &str) -> f32 {
+        line.chars().filter_map(|c| self.glyph(c)).map(|glyph| glyph.advance).sum()
+    }
+}
+
+/// Size of `text` if drawn with `queue_text()`, for layout without a GPU. Lines are
+/// separated by '\n'.
+pub fn measure(text: &str, line_height: f32) -> FVec2 {
+    let scale = line_height / FONT.size;
+    let width = text.lines().map(|line| FONT.line_width(line)).fold(0.0, f32::max);
+    FVec2::new(width * scale, text.lines().count() as f32 * line_height)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable, WgslStruct)]
+#[wgsl(location = 1)]
+pub(crate) struct GlyphInstance {
+    color: Color,
+    /// Top left corner
+    position: FVec2,
+    size: FVec2,
+    /// Area of the glyph in the atlas, in UV coordinates
+    uv_min: FVec2,
+    uv_max: FVec2,
+}
+
+impl GlyphInstance {
+    const ATTR: &'static [wgpu::VertexAttribute] =
+        &vertex_attr_array![1 => Float32x4, 2 => Float32x2, 3 => Float32x2, 4 => Float32x2, 5 => Float32x2];
+
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: Self::ATTR,
+        }
+    }
+}
+
+/// Collects text with `queue_text()` and draws it at once with `flush()`.
+///
+/// The instance buffer is written with the queue, so a renderer can only be flushed once per
+/// frame. Text in world and screen space needs a renderer each.
+pub struct TextRenderer {
+    uniform_buffer: UniformBuffer<DrawState>,
+    /// Coverage of the atlas in the red channel
+    texture: Texture,
+    vertex_buffer: TrackedBuffer,
+    instance_buffer: TrackedBuffer,
+    render_pipeline: wgpu::RenderPipeline,
+    glyphs: Vec<GlyphInstance>,
+}
+
+impl TextRenderer {
+    /// About a screen full of text
+    const MAX_INSTANCE_COUNT: usize = 4096;
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "text_uniforms");
+
+        let coverage = load_coverage().unwrap_or_else(|err| {
+            warn!("Failed to load font texture: {err}");
+            vec![0; (FONT.width * FONT.height) as usize]
+        });
+        let mip_levels = mip_levels(FONT.width, FONT.height, coverage);
+        let texture = Texture::new(device, "text_atlas", wgpu::TextureFormat::R8Unorm, FONT.width, FONT.height,
+            mip_levels);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[uniform_buffer.bind_group_layout(), texture.bind_group_layout()],
+            label: Some("text_pipeline_layout"),
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffer = create_vertex_buffer(device, Some("text_vertex_buffer"), &SQUARE_VERTICES);
+        let instance_buffer = create_instance_buffer::<GlyphInstance>(device, Some("text_instance_buffer"),
+            TextRenderer::MAX_INSTANCE_COUNT);
+
+        let render_pipeline = device.create_render_pipeline(&create_pipeline_descriptor(
+            Some("text_pipeline"),
+            &shader::create_shader_module(device, "text.wgsl", &[]),
+            Some(&pipeline_layout),
+            &[Vertex::layout(), GlyphInstance::layout()],
+            &color_targets(format),
+        ));
+
+        Self {
+            uniform_buffer,
+            texture,
+            vertex_buffer,
+            instance_buffer,
+            render_pipeline,
+            glyphs: Vec::new(),
+        }
+    }
+
+    /// Add `text` with its top left corner at `position` and lines that are `line_height`
+    /// units apart, in the space of the `DrawState` passed to `flush()`. See `measure()`.
+    pub fn queue_text(&mut self, text: &str, position: FVec2, line_height: f32, color: Color) {
+        let scale = line_height / FONT.size;
+        let ascent = FONT.ascent();
+        let uv_scale = FVec2::new(1.0 / FONT.width as f32, 1.0 / FONT.height as f32);
+        for (row, line) in text.lines().enumerate() {
+            let mut pen = FVec2::new(0.0, row as f32 * FONT.size + ascent);
+            for glyph in line.chars().filter_map(|c| FONT.glyph(c)) {
+                let min = pen - FVec2::new(glyph.origin_x, glyph.origin_y);
+                let size = FVec2::new(glyph.width, glyph.height);
+                self.glyphs.push(GlyphInstance {
+                    color,
+                    position: position + min * scale,
+                    size: size * scale,
+                    uv_min: FVec2::new(glyph.x * uv_scale.x, glyph.y * uv_scale.y),
+                    uv_max: FVec2::new((glyph.x + size.x) * uv_scale.x, (glyph.y + size.y) * uv_scale.y),
+                });
+                pen.x += glyph.advance;
+            }
+        }
+    }
+
+    /// Draw the queued text with the view matrix of `state`
+    pub fn flush(&mut self, context: &mut DrawContext, state: &DrawState) {
+        self.texture.upload(context.queue);
+
+        let glyphs = std::mem::take(&mut self.glyphs);
+        let count = glyphs.len().min(TextRenderer::MAX_INSTANCE_COUNT);
+        if count == 0 {
+            return;
+        }
+
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+        context.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&glyphs[..count]));
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("text_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        rpass.set_bind_group(0, self.uniform_buffer.bind_group(), &[]);
+        rpass.set_bind_group(1, self.texture.bind_group(), &[]);
+        rpass.draw(0..SQUARE_VERTICES.len() as u32, 0..count as u32);
+    }
+}
+
+/// Coverage of every pixel of the atlas. The glyphs are white on black, so the red channel
+/// is used.
+fn load_coverage() -> Result<Vec<u8>, TextError> {
+    let image = load_image(FONT_TEXTURE_PATH)?;
+    if image.width != FONT.width || image.height != FONT.height {
+        return Err(TextError::InvalidTexture);
+    }
+    Ok(image.rgba.chunks_exact(4).map(|pixel| pixel[0]).collect())
+}
+
+/// Halve the atlas until it's a single pixel wide or high, averaging 2x2 blocks. Glyphs are
+/// usually drawn a lot smaller than in the atlas and would flicker without mipmaps.
+fn mip_levels(width: u32, height: u32, pixels: Vec<u8>) -> Vec<Vec<u8>> {
+    let (mut width, mut height) = (width as usize, height as usize);
+    let mut levels = vec![pixels];
+    loop {
+        let pixels = levels.last().unwrap();
+        if width == 1 || height == 1 {
+            break;
+        }
+        let (next_width, next_height) = (width / 2, height / 2);
+        let mut next = Vec::with_capacity(next_width * next_height);
+        for y in 0..next_height {
+            for x in 0..next_width {
+                let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| pixels[(y * 2 + dy) * width + x * 2 + dx] as u32)
+                    .sum();
+                next.push((sum / 4) as u8);
+            }
+        }
+        levels.push(next);
+        width = next_width;
+        height = next_height;
+    }
+    levels
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TextError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid font: {0}")]
+    InvalidData(#[from] serde_json::Error),
+    #[error("invalid font texture: {0}")]
+    Texture(#[from] TextureError),
+    #[error("font texture doesn't match the font")]
+    InvalidTexture,
+}
@@ -0,0 +1,440 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+
+use crate::{
+    game::{Game, GameSpeed},
+    input::Input,
+    level::Difficulty,
+    player_tuning::PlayerTuning,
+    toast,
+};
+
+/// Directory recorded replays are saved to
+pub const REPLAYS_DIR: &str = "replays";
+
+/// Recorded inputs of a level, starting from the player's spawn.
+///
+/// The game runs with a fixed timestep, so playing back the same inputs with the same
+/// `ReplaySetup` always gives the same result.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub level: String,
+    /// Pressed buttons for every tick, see `Input::pressed_buttons()`
+    pub frames: Vec<u32>,
+    /// Replays recorded at other speeds don't count as regular runs
+    pub speed: GameSpeed,
+    pub setup: ReplaySetup,
+}
+
+/// Everything besides the inputs that changes how a level plays out
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplaySetup {
+    pub mirror_mode: bool,
+    pub difficulty: Difficulty,
+    /// Seed of the randomizer, if it was enabled
+    pub randomizer_seed: Option<u64>,
+    pub tuning: PlayerTuning,
+    /// See `Settings::forgiving_spikes`
+    pub forgiving_spikes: bool,
+}
+
+impl ReplaySetup {
+    const MIRROR_MODE: u32 = 1 << 0;
+    const HARD_MODE: u32 = 1 << 1;
+    const RANDOMIZER: u32 = 1 << 2;
+    const FORGIVING_SPIKES: u32 = 1 << 3;
+}
+
+impl Replay {
+    /// Longer replays are rejected, so that corrupt run lengths can't allocate gigabytes. Four
+    /// hours at 100 ticks per second.
+    const MAX_TICKS: usize = 4 * 60 * 60 * 100;
+
+    /// Load a replay in the format written by `save()`
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        Replay::from_bytes(&fs::read(path)?)
+    }
+
+    fn from_bytes(mut reader: &[u8]) -> Result<Self, ReplayError> {
+        let reader = &mut reader;
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != *b"CMR2" {
+            return Err(ReplayError::InvalidMagic);
+        }
+
+        let level = read_string(reader)?;
+        let speed = GameSpeed::new(read_u32(reader)?);
+        let flags = read_u32(reader)?;
+        let seed = read_u64(reader)?;
+        let tuning = serde_json::from_str(&read_string(reader)?)
+            .map_err(|err| ReplayError::InvalidData(format!("invalid player tuning: {err}")))?;
+        let setup = ReplaySetup {
+            mirror_mode: flags & ReplaySetup::MIRROR_MODE != 0,
+            difficulty: if flags & ReplaySetup::HARD_MODE != 0 {
+                Difficulty::Hard
+            } else {
+                Difficulty::Normal
+            },
+            randomizer_seed: (flags & ReplaySetup::RANDOMIZER != 0).then_some(seed),
+            tuning,
+            forgiving_spikes: flags & ReplaySetup::FORGIVING_SPIKES != 0,
+        };
+        let frames = read_frames(reader)?;
+        Ok(Replay {
+            level,
+            frames,
+            speed,
+            setup,
+        })
+    }
+
+    /// Save the replay in a compact binary format: the magic `CMR2`, the level name, the game
+    /// speed in percent, the setup and the inputs as runs of ticks with the same buttons
+    /// pressed. The setup is stored as flags for the modifiers and assists, the randomizer seed
+    /// and the player tuning as JSON. All integers are little endian.
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write(&self, writer: &mut impl Write) -> Result<(), ReplayError> {
+        let setup = &self.setup;
+        writer.write_all(b"CMR2")?;
+        write_string(writer, &self.level)?;
+        writer.write_all(&self.speed.percent().to_le_bytes())?;
+
+        let mut flags = 0;
+        if setup.mirror_mode {
+            flags |= ReplaySetup::MIRROR_MODE;
+        }
+        if setup.difficulty == Difficulty::Hard {
+            flags |= ReplaySetup::HARD_MODE;
+        }
+        if setup.randomizer_seed.is_some() {
+            flags |= ReplaySetup::RANDOMIZER;
+        }
+        if setup.forgiving_spikes {
+            flags |= ReplaySetup::FORGIVING_SPIKES;
+        }
+        writer.write_all(&flags.to_le_bytes())?;
+        writer.write_all(&setup.randomizer_seed.unwrap_or_default().to_le_bytes())?;
+        let tuning = serde_json::to_string(&setup.tuning)
+            .map_err(|err| ReplayError::InvalidData(format!("invalid player tuning: {err}")))?;
+        write_string(writer, &tuning)?;
+
+        let runs = self.runs();
+        writer.write_all(&(runs.len() as u32).to_le_bytes())?;
+        for (length, mask) in runs {
+            writer.write_all(&length.to_le_bytes())?;
+            writer.write_all(&mask.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Group consecutive frames with the same inputs
+    fn runs(&self) -> Vec<(u32, u32)> {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for &mask in &self.frames {
+            match runs.last_mut() {
+                Some((length, last_mask)) if *last_mask == mask => *length += 1,
+                _ => runs.push((1, mask)),
+            }
+        }
+        runs
+    }
+}
+
+/// Read runs of ticks with the same buttons pressed, see `Replay::save()`
+fn read_frames(reader: &mut &[u8]) -> Result<Vec<u32>, ReplayError> {
+    let run_count = read_u32(&mut *reader)? as usize;
+    // The counts come from the file, so check them before allocating
+    if run_count > reader.len() / 8 {
+        return Err(ReplayError::InvalidData(String::from("truncated replay")));
+    }
+    let mut frames = Vec::new();
+    for _ in 0..run_count {
+        let length = read_u32(&mut *reader)? as usize;
+        let mask = read_u32(&mut *reader)?;
+        if length > Replay::MAX_TICKS - frames.len() {
+            return Err(ReplayError::InvalidData(String::from("replay is too long")));
+        }
+        frames.extend(std::iter::repeat(mask).take(length));
+    }
+    Ok(frames)
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+    writer.write_all(&(string.len() as u32).to_le_bytes())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string(reader: &mut &[u8]) -> Result<String, ReplayError> {
+    let length = read_u32(&mut *reader)? as usize;
+    // The length comes from the file, so check it before allocating
+    if length > reader.len() {
+        return Err(ReplayError::InvalidData(String::from("truncated replay")));
+    }
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| ReplayError::InvalidData(String::from("string isn't valid UTF-8")))
+}
+
+/// Path for a new replay in `REPLAYS_DIR`, named after the current time
+pub fn new_replay_path(prefix: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    [REPLAYS_DIR, &format!("{prefix}-{timestamp}.cmrp")].iter().collect()
+}
+
+enum ReplayState {
+    Idle,
+    Recording(Replay),
+    Playing {
+        replay: Replay,
+        /// Index of the next frame to play back
+        frame: usize,
+        /// Replaces the keyboard input while playing
+        input: Input,
+    },
+}
+
+/// Records the inputs of every tick and plays them back, e.g. to reproduce physics bugs
+pub struct ReplayRecorder {
+    state: ReplayState,
+    load_path: String,
+}
+
+impl ReplayRecorder {
+    pub fn new() -> Self {
+        ReplayRecorder {
+            state: ReplayState::Idle,
+            load_path: String::new(),
+        }
+    }
+
+    /// Tick the game, either with the given input or the one from the replay that is played back
+    pub fn tick(&mut self, input: &Input, game: &mut Game) {
+        match &mut self.state {
+            ReplayState::Idle => game.tick(input),
+            ReplayState::Recording(replay) => {
+                replay.frames.push(input.pressed_buttons());
+                game.tick(input);
+            }
+            ReplayState::Playing {
+                replay,
+                frame,
+                input: replay_input,
+            } => match replay.frames.get(*frame) {
+                Some(&mask) => {
+                    replay_input.set_pressed_buttons(mask);
+                    replay_input.tick();
+                    game.tick(replay_input);
+                    *frame += 1;
+                }
+                None => {
+                    toast::show("Replay finished");
+                    self.state = ReplayState::Idle;
+                    game.tick(input);
+                }
+            },
+        }
+    }
+
+    fn start_recording(&mut self, game: &mut Game) {
+        if let Err(err) = game.restart_from_beginning() {
+            error!("Failed to restart level: {err}");
+            return;
+        }
+        self.state = ReplayState::Recording(Replay {
+            level: game.level_name().to_owned(),
+            frames: Vec::new(),
+            speed: game.speed(),
+            setup: game.replay_setup(),
+        });
+    }
+
+    fn stop_recording(&mut self) {
+        if let ReplayState::Recording(replay) = std::mem::replace(&mut self.state, ReplayState::Idle) {
+            let path = new_replay_path("replay");
+            match replay.save(&path) {
+                Ok(()) => toast::show(format!("Saved replay to {}", path.display())),
+                Err(err) => {
+                    error!("Failed to save replay: {err}");
+                    toast::show("Failed to save replay");
+                }
+            }
+        }
+    }
+
+    fn start_playback(&mut self, game: &mut Game) {
+        let replay = match Replay::load(Path::new(&self.load_path)) {
+            Ok(replay) => replay,
+            Err(err) => {
+                error!("Failed to load replay {}: {err}", self.load_path);
+                toast::show("Failed to load replay");
+                return;
+            }
+        };
+        game.apply_replay_setup(&replay.setup);
+        if let Err(err) = game.load_level_from_beginning(&replay.level) {
+            error!("Failed to load level {}: {err}", replay.level);
+            return;
+        }
+        self.state = ReplayState::Playing {
+            replay,
+            frame: 0,
+            input: Input::new(),
+        };
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, game: &mut Game) {
+        // Adds a section to the game's DevGUI window
+        let _token = match imgui::Window::new("DevGUI").begin(gui) {
+            Some(token) => token,
+            None => return,
+        };
+        if !gui.collapsing_header("Replay", imgui::TreeNodeFlags::empty()) {
+            return;
+        }
+
+        gui.indent();
+        match &self.state {
+            ReplayState::Idle => {
+                if gui.button("Record") {
+                    self.start_recording(game);
+                }
+                gui.input_text("Path", &mut self.load_path).build();
+                gui.same_line();
+                if gui.button("Play") {
+                    self.start_playback(game);
+                }
+            }
+            ReplayState::Recording(replay) => {
+                gui.text(format!("Recording {}: {} ticks", replay.level, replay.frames.len()));
+                if gui.button("Stop and save") {
+                    self.stop_recording();
+                }
+            }
+            ReplayState::Playing { replay, frame, .. } => {
+                gui.text(format!(
+                    "Playing {}: tick {} / {}",
+                    replay.level,
+                    frame,
+                    replay.frames.len()
+                ));
+                if !replay.speed.is_normal() {
+                    gui.text(format!("Recorded at {}% speed", replay.speed.percent()));
+                }
+                if gui.button("Stop") {
+                    self.state = ReplayState::Idle;
+                }
+            }
+        }
+        gui.unindent();
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid file magic")]
+    InvalidMagic,
+    #[error("invalid data: {0}")]
+    InvalidData(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        Replay {
+            level: String::from("level1"),
+            frames: vec![0, 0, 1, 1, 1, 5],
+            speed: GameSpeed::new(75),
+            setup: ReplaySetup {
+                mirror_mode: true,
+                difficulty: Difficulty::Hard,
+                randomizer_seed: Some(u64::MAX),
+                tuning: PlayerTuning {
+                    move_speed: 0.25,
+                    assist_spike_inset_scale: 3.0,
+                    ..PlayerTuning::default()
+                },
+                forgiving_spikes: true,
+            },
+        }
+    }
+
+    fn to_bytes(replay: &Replay) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        replay.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip_keeps_inputs_and_setup() {
+        let replay = replay();
+        let loaded = Replay::from_bytes(&to_bytes(&replay)).unwrap();
+        assert_eq!(loaded.level, replay.level);
+        assert_eq!(loaded.frames, replay.frames);
+        assert_eq!(loaded.speed, replay.speed);
+        assert_eq!(loaded.setup, replay.setup);
+    }
+
+    #[test]
+    fn untrusted_lengths_are_checked() {
+        let bytes = to_bytes(&replay());
+        // The level name claims to be longer than the file
+        let mut long_name = bytes.clone();
+        long_name[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(Replay::from_bytes(&long_name), Err(ReplayError::InvalidData(_))));
+
+        // A single run of billions of ticks
+        let runs_start = bytes.len() - 3 * 8;
+        let mut long_run = bytes.clone();
+        long_run[runs_start..runs_start + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(Replay::from_bytes(&long_run), Err(ReplayError::InvalidData(_))));
+
+        // More runs than the file has bytes for
+        let mut many_runs = bytes.clone();
+        many_runs[runs_start - 4..runs_start].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(Replay::from_bytes(&many_runs), Err(ReplayError::InvalidData(_))));
+
+        assert!(Replay::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn only_the_current_format_is_accepted() {
+        let mut bytes = to_bytes(&replay());
+        bytes[..4].copy_from_slice(b"CMRP");
+        assert!(matches!(Replay::from_bytes(&bytes), Err(ReplayError::InvalidMagic)));
+    }
+}
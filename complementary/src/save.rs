@@ -0,0 +1,151 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{game::GameSpeed, level::Difficulty, player::AbilityPair};
+
+const SAVE_FILE_NAME: &str = "save.json";
+const THUMBNAIL_FILE_NAME: &str = "thumbnail.ppm";
+
+/// Best results in the completed levels of one difficulty
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelRecords {
+    /// Most keys collected in each completed level
+    pub keys_by_level: BTreeMap<String, usize>,
+    /// Fastest completion of each level
+    pub best_times: BTreeMap<String, Duration>,
+    /// Most shards collected in each completed level
+    pub shards_by_level: BTreeMap<String, usize>,
+}
+
+/// Progress of the player, kept between sessions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaveData {
+    /// Index of the current level in the playlist
+    pub level_index: usize,
+    /// Records in `Difficulty::Normal`, flattened to stay compatible with older saves
+    #[serde(flatten)]
+    pub records: LevelRecords,
+    pub hard_mode_records: LevelRecords,
+    pub abilities: AbilityPair,
+    pub death_count: u32,
+    pub playtime: Duration,
+    /// Levels are flipped horizontally, see `Level::mirror()`
+    pub mirror_mode: bool,
+    /// Levels with a hard mode are played in `Difficulty::Hard`
+    pub hard_mode: bool,
+    pub game_speed: GameSpeed,
+    /// Levels whose intro flyover was already shown
+    pub seen_levels: BTreeSet<String>,
+}
+
+impl SaveData {
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Load the progress from `save_path()`, or start from scratch if nothing was saved yet
+    pub fn load_or_default() -> Self {
+        match SaveData::load(&save_path()) {
+            Ok(data) => data,
+            Err(SaveError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                SaveData::default()
+            }
+            Err(err) => {
+                warn!("Failed to load save data, starting a new game: {err}");
+                SaveData::default()
+            }
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        if self.hard_mode {
+            Difficulty::Hard
+        } else {
+            Difficulty::Normal
+        }
+    }
+
+    /// Remember the keys and shards collected in a completed level and the time it took, keeping
+    /// the best results. Returns true if the time is a new record. Times are `None` if they
+    /// don't count, see `GameSpeed::is_normal()`.
+    pub fn record_completion(
+        &mut self,
+        level: &str,
+        difficulty: Difficulty,
+        keys: usize,
+        shards: usize,
+        time: Option<Duration>,
+    ) -> bool {
+        let records = match difficulty {
+            Difficulty::Normal => &mut self.records,
+            Difficulty::Hard => &mut self.hard_mode_records,
+        };
+        let best_keys = records.keys_by_level.entry(level.to_owned()).or_default();
+        *best_keys = (*best_keys).max(keys);
+        let best_shards = records.shards_by_level.entry(level.to_owned()).or_default();
+        *best_shards = (*best_shards).max(shards);
+
+        let time = match time {
+            Some(time) => time,
+            None => return false,
+        };
+        match records.best_times.get(level) {
+            Some(best_time) if *best_time <= time => false,
+            _ => {
+                records.best_times.insert(level.to_owned(), time);
+                true
+            }
+        }
+    }
+}
+
+/// Directory for save data following the platform's conventions, e.g.
+/// `~/.local/share/complementary` on Linux
+pub fn save_directory() -> PathBuf {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    // Fall back to the working directory if no home directory is known
+    base.unwrap_or_default().join("complementary")
+}
+
+pub fn save_path() -> PathBuf {
+    save_directory().join(SAVE_FILE_NAME)
+}
+
+/// Screenshot taken when the progress was last saved, see `thumbnail`
+pub fn thumbnail_path() -> PathBuf {
+    save_directory().join(THUMBNAIL_FILE_NAME)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SaveError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid data: {0}")]
+    InvalidData(#[from] serde_json::Error),
+}
@@ -0,0 +1,294 @@
+//! JSON Schema of object map files, so that editors can complete and check hand-edited levels.
+//! The schemas of the object data types are generated by `#[derive(JsonSchema)]` from their
+//! serde representation, the format of the entries is described in `objects::object_map_schema()`.
+
+use std::{fs, io, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::{math::FVec2, objects};
+
+/// Written by `--object-schema` if no other path is given
+pub const OBJECT_SCHEMA_PATH: &str = "object_schema.json";
+
+/// Type whose serde representation can be described by a JSON Schema, usually derived with
+/// `complementary_macros::JsonSchema`
+pub trait JsonSchema {
+    /// Whether struct fields of this type have to be present. Missing `Option`s are `None`.
+    const REQUIRED: bool = true;
+
+    fn json_schema() -> Value;
+}
+
+/// Add a description to a schema, which editors show when hovering or completing a field
+pub fn describe(mut schema: Value, description: &str) -> Value {
+    if let Some(fields) = schema.as_object_mut() {
+        fields.insert(String::from("description"), Value::from(description));
+    }
+    schema
+}
+
+impl JsonSchema for bool {
+    fn json_schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchema for f32 {
+    fn json_schema() -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl JsonSchema for i32 {
+    fn json_schema() -> Value {
+        json!({ "type": "integer" })
+    }
+}
+
+impl JsonSchema for u32 {
+    fn json_schema() -> Value {
+        json!({ "type": "integer", "minimum": 0 })
+    }
+}
+
+impl JsonSchema for usize {
+    fn json_schema() -> Value {
+        json!({ "type": "integer", "minimum": 0 })
+    }
+}
+
+impl JsonSchema for String {
+    fn json_schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    const REQUIRED: bool = false;
+
+    fn json_schema() -> Value {
+        json!({ "anyOf": [T::json_schema(), { "type": "null" }] })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+/// Tuples are arrays with one item per element
+macro_rules! impl_json_schema_tuple {
+    ($len:expr, $($name:ident),*) => {
+        impl<$($name: JsonSchema),*> JsonSchema for ($($name,)*) {
+            fn json_schema() -> Value {
+                let items: Vec<Value> = vec![$($name::json_schema()),*];
+                json!({ "type": "array", "items": items, "minItems": $len, "maxItems": $len })
+            }
+        }
+    };
+}
+
+impl_json_schema_tuple!(2, A, B);
+impl_json_schema_tuple!(3, A, B, C);
+impl_json_schema_tuple!(4, A, B, C, D);
+
+impl JsonSchema for FVec2 {
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "x": { "type": "number" }, "y": { "type": "number" } },
+            "required": ["x", "y"],
+            "additionalProperties": false,
+        })
+    }
+}
+
+/// Check `value` against `schema`. Only the keywords used by the `JsonSchema` implementations
+/// are supported, others are ignored. Returns a message for every mismatch, starting with the
+/// JSON pointer of the value, e.g. `/3/data/size: expected number`.
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(value, schema, "", &mut errors);
+    errors
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema.as_object() {
+        Some(schema) => schema,
+        None => return,
+    };
+    let error = |errors: &mut Vec<String>, message: String| {
+        let path = if path.is_empty() { "/" } else { path };
+        errors.push(format!("{path}: {message}"));
+    };
+
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        let matches = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            "number" => value.is_number(),
+            // serde doesn't read integers from numbers like 1.0
+            "integer" => value.is_i64() || value.is_u64(),
+            _ => true,
+        };
+        if !matches {
+            error(errors, format!("expected {expected}, found {value}"));
+            return;
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            error(errors, format!("expected {expected}, found {value}"));
+        }
+    }
+    if let (Some(minimum), Some(number)) = (schema.get("minimum").and_then(Value::as_f64), value.as_f64()) {
+        if number < minimum {
+            error(errors, format!("{number} is less than {minimum}"));
+        }
+    }
+
+    if let Some(fields) = value.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for required in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(name) = required.as_str().filter(|name| !fields.contains_key(*name)) {
+                error(errors, format!("missing field \"{name}\""));
+            }
+        }
+        for (name, field) in fields {
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(field_schema) => validate_at(field, field_schema, &format!("{path}/{name}"), errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    error(errors, format!("unknown field \"{name}\""))
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        let length = items.len() as u64;
+        if let Some(min_items) = schema.get("minItems").and_then(Value::as_u64).filter(|min| length < *min) {
+            error(errors, format!("expected at least {min_items} items, found {length}"));
+        }
+        if let Some(max_items) = schema.get("maxItems").and_then(Value::as_u64).filter(|max| length > *max) {
+            error(errors, format!("expected at most {max_items} items, found {length}"));
+        }
+        match schema.get("items") {
+            // Tuples have a schema for each item
+            Some(Value::Array(item_schemas)) => {
+                for (index, (item, item_schema)) in items.iter().zip(item_schemas).enumerate() {
+                    validate_at(item, item_schema, &format!("{path}/{index}"), errors);
+                }
+            }
+            Some(item_schema) => {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item, item_schema, &format!("{path}/{index}"), errors);
+                }
+            }
+            None => {}
+        }
+    }
+
+    for (keyword, exactly_one) in [("anyOf", false), ("oneOf", true)] {
+        let branches = match schema.get(keyword).and_then(Value::as_array) {
+            Some(branches) => branches,
+            None => continue,
+        };
+        let results: Vec<Vec<String>> = branches
+            .iter()
+            .map(|branch| {
+                let mut branch_errors = Vec::new();
+                validate_at(value, branch, path, &mut branch_errors);
+                branch_errors
+            })
+            .collect();
+        let matching = results.iter().filter(|branch_errors| branch_errors.is_empty()).count();
+        if matching == 0 {
+            // Object entries and enum variants are told apart by a constant, e.g. the object
+            // type. If one branch has the value's constant, its errors are the useful ones.
+            let tagged: Vec<_> = branches
+                .iter()
+                .zip(&results)
+                .filter(|(branch, _)| constants_match(value, branch))
+                .collect();
+            match tagged.as_slice() {
+                [(_, branch_errors)] => errors.extend(branch_errors.iter().cloned()),
+                _ => error(errors, format!("{value} doesn't match any of the allowed schemas")),
+            }
+        } else if exactly_one && matching > 1 {
+            error(errors, format!("{value} matches {matching} schemas, expected exactly one"));
+        }
+    }
+}
+
+/// Whether `value` has all of the constant properties of `schema`. Schemas without any never
+/// match.
+fn constants_match(value: &Value, schema: &Value) -> bool {
+    let properties = match schema.get("properties").and_then(Value::as_object) {
+        Some(properties) => properties,
+        None => return false,
+    };
+    let mut constants = properties
+        .iter()
+        .filter_map(|(name, property)| Some((name, property.get("const")?)))
+        .peekable();
+    constants.peek().is_some() && constants.all(|(name, constant)| value.get(name) == Some(constant))
+}
+
+/// Write the schema of object maps to `path`
+pub fn write_object_schema(path: &Path) -> Result<(), SchemaError> {
+    let schema = objects::object_map_schema();
+    fs::write(path, serde_json::to_string_pretty(&schema)?)?;
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SchemaError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid schema: {0}")]
+    InvalidData(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_reports_mismatches_with_their_path() {
+        let point = json!({
+            "type": "object",
+            "properties": { "type": { "const": "Point" }, "position": FVec2::json_schema() },
+            "required": ["type", "position"],
+            "additionalProperties": false,
+        });
+        let pair = <Option<(u32, bool)>>::json_schema();
+        let schema = json!({
+            "type": "array",
+            "items": { "anyOf": [point, pair] },
+        });
+        let valid = json!([{ "type": "Point", "position": { "x": 1.0, "y": 2 } }, [3, true], null]);
+        assert_eq!(validate(&valid, &schema), Vec::<String>::new());
+
+        let invalid = json!([
+            { "type": "Point", "position": { "x": "1" }, "color": "red" },
+            [-3, true],
+            { "type": "Line" },
+        ]);
+        assert_eq!(
+            validate(&invalid, &schema),
+            vec![
+                "/0/position: missing field \"y\"",
+                "/0/position/x: expected number, found \"1\"",
+                "/0: unknown field \"color\"",
+                "/1: [-3,true] doesn't match any of the allowed schemas",
+                "/2: {\"type\":\"Line\"} doesn't match any of the allowed schemas",
+            ]
+        );
+    }
+}
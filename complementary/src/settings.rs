@@ -0,0 +1,527 @@
+use std::{env, fs, io, path::Path, time::Duration};
+
+use log::{info, warn};
+use sdl2::controller::Button as PadButton;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::Game,
+    input::{ButtonType, Input},
+    touch::TouchButton,
+};
+
+/// Levels played in kiosk mode
+const KIOSK_PLAYLIST_PATH: &str = "assets/kiosk_playlist.json";
+const KIOSK_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Settings that are kept between sessions, see `SettingsFile`
+pub const SETTINGS_PATH: &str = "settings.json";
+
+/// Which kind of button names are shown in prompts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ButtonPrompts {
+    Keyboard,
+    Controller,
+    /// Controller buttons named like on the Steam Deck, e.g. L1 instead of LB
+    Handheld,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FullscreenMode {
+    /// Window covering the display at its current resolution, switches quickly
+    Borderless,
+    /// Takes over the display and changes its resolution to the window's size
+    Exclusive,
+}
+
+impl FullscreenMode {
+    pub const ALL: [Self; 2] = [FullscreenMode::Borderless, FullscreenMode::Exclusive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FullscreenMode::Borderless => "Borderless",
+            FullscreenMode::Exclusive => "Exclusive",
+        }
+    }
+}
+
+/// How frames are shown on the screen, see `wgpu::PresentMode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentMode {
+    /// Vsync, the only mode that's supported everywhere
+    Fifo,
+    /// Lower latency than `Fifo` without tearing
+    Mailbox,
+    /// No vsync, frames can tear
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [Self; 3] = [PresentMode::Fifo, PresentMode::Mailbox, PresentMode::Immediate];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PresentMode::Fifo => "Vsync (Fifo)",
+            PresentMode::Mailbox => "Mailbox",
+            PresentMode::Immediate => "Immediate",
+        }
+    }
+
+    pub fn to_wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+/// What happens if the game can't keep up with real time, e.g. on slow hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CatchUpPolicy {
+    /// Run up to `Settings::max_ticks_per_frame` ticks in one frame and drop the rest, so the
+    /// game keeps real time but the player can jump ahead between frames
+    Skip,
+    /// Never simulate more than one refresh interval per frame, so the game runs slower
+    /// instead of jumping ahead
+    SlowDown,
+}
+
+impl CatchUpPolicy {
+    pub const ALL: [Self; 2] = [CatchUpPolicy::Skip, CatchUpPolicy::SlowDown];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CatchUpPolicy::Skip => "Skip ticks",
+            CatchUpPolicy::SlowDown => "Slow down",
+        }
+    }
+}
+
+/// Size and position of the window when the game was last closed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Name of the display the window was on
+    pub display: Option<String>,
+}
+
+/// Contents of `SETTINGS_PATH`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SettingsFile {
+    #[serde(default)]
+    fullscreen: Option<bool>,
+    #[serde(default)]
+    fullscreen_mode: Option<FullscreenMode>,
+    #[serde(default)]
+    window: Option<WindowGeometry>,
+    #[serde(default)]
+    display: Option<String>,
+    #[serde(default)]
+    present_mode: Option<PresentMode>,
+    #[serde(default)]
+    music_volume: Option<f32>,
+    #[serde(default)]
+    effects_volume: Option<f32>,
+    #[serde(default)]
+    show_timer: Option<bool>,
+    #[serde(default)]
+    reduce_motion: Option<bool>,
+    #[serde(default)]
+    reduce_flashing: Option<bool>,
+    #[serde(default)]
+    hitstop: Option<bool>,
+    #[serde(default)]
+    forgiving_spikes: Option<bool>,
+    #[serde(default)]
+    button_prompts: Option<ButtonPrompts>,
+    #[serde(default)]
+    gamma: Option<f32>,
+    #[serde(default)]
+    catch_up_policy: Option<CatchUpPolicy>,
+    #[serde(default)]
+    max_ticks_per_frame: Option<i32>,
+    #[serde(default)]
+    touch_layout: Option<Vec<TouchButton>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub fullscreen: bool,
+    pub fullscreen_mode: FullscreenMode,
+    /// Restored window size and position. The window is centered if this is `None`.
+    pub window: Option<WindowGeometry>,
+    /// Name of the display used in fullscreen. The display the window is on is used if this
+    /// is `None` or the display isn't connected.
+    pub display: Option<String>,
+    /// Global scale of the UI, applied to the ImGui font
+    pub ui_scale: f32,
+    pub button_prompts: ButtonPrompts,
+    /// On-screen buttons for touch screens, `TouchControls::default_layout()` is used if this
+    /// is `None`
+    pub touch_layout: Option<Vec<TouchButton>>,
+    /// Brightness correction applied in post-processing, see `post_processing::gamma_correct()`
+    pub gamma: f32,
+    /// Graphics backends that are tried first. All backends are tried if none of these work.
+    pub backends: wgpu::Backends,
+    /// Strength of the glow around goals, keys and the player. Zero disables bloom.
+    pub bloom_intensity: f32,
+    /// Name of the localization file in `assets/localization`, without extension
+    pub language: String,
+    /// Disables screen effects with a lot of motion, like the speed lines while dashing
+    pub reduce_motion: bool,
+    /// Tones down bright flashes, like the one after completing a level
+    pub reduce_flashing: bool,
+    /// Briefly freeze the game on impacts like deaths, see `Game::hitstop()`
+    pub hitstop: bool,
+    /// Assist option that widens the harmless margins of spike tiles, see
+    /// `PlayerTuning::assist_spike_inset_scale`
+    pub forgiving_spikes: bool,
+    /// Volumes from 0 to 1
+    pub music_volume: f32,
+    pub effects_volume: f32,
+    /// Show how long the current level has been played for
+    pub show_timer: bool,
+    /// Return to the title screen if the player didn't press anything for this long
+    pub idle_timeout: Option<Duration>,
+    /// Exhibition mode, see `enable_kiosk_mode()`
+    pub kiosk: bool,
+    /// Disabled with `--no-audio`, e.g. on machines without an audio device
+    pub audio: bool,
+    /// Levels that are played in order. All main levels are played if this is `None`.
+    pub playlist: Option<Vec<String>>,
+    /// Limit expensive effects like particles if ticks keep taking too long
+    pub auto_slow_mode: bool,
+    /// How lag is handled when ticks take longer than real time
+    pub catch_up_policy: CatchUpPolicy,
+    /// Ticks run at most in one frame before the remaining lag is dropped
+    pub max_ticks_per_frame: i32,
+    /// Stop the game while the window is minimized instead of running it in the background
+    pub pause_when_minimized: bool,
+    /// Modes other than `PresentMode::Fifo` aren't supported by every adapter. wgpu falls back
+    /// to `Fifo` if the chosen mode isn't available.
+    pub present_mode: PresentMode,
+    /// Set if there was no settings file yet, the `Onboarding` is shown then
+    pub first_run: bool,
+}
+
+impl Settings {
+    pub const MIN_GAMMA: f32 = 0.5;
+    pub const MAX_GAMMA: f32 = 2.0;
+    pub const MIN_TICKS_PER_FRAME: i32 = 1;
+    pub const MAX_TICKS_PER_FRAME: i32 = 20;
+
+    /// Preset for handheld devices like the Steam Deck
+    pub fn handheld() -> Self {
+        Settings {
+            fullscreen: true,
+            ui_scale: 1.5,
+            button_prompts: ButtonPrompts::Handheld,
+            backends: wgpu::Backends::VULKAN,
+            ..Settings::default()
+        }
+    }
+
+    /// Pick the default settings for the device the game is running on
+    pub fn detect() -> Self {
+        if is_handheld_device() {
+            info!("Handheld device detected, using handheld preset");
+            Settings::handheld()
+        } else {
+            Settings::default()
+        }
+    }
+
+    /// Exhibition mode for showing the game at events: the game always runs in fullscreen,
+    /// the DevGUI and quitting are disabled, only the levels in `KIOSK_PLAYLIST_PATH` can be
+    /// played and progress is reset after the player was idle.
+    pub fn enable_kiosk_mode(&mut self) {
+        self.kiosk = true;
+        self.fullscreen = true;
+        self.idle_timeout = self.idle_timeout.or(Some(KIOSK_IDLE_TIMEOUT));
+
+        match fs::read_to_string(KIOSK_PLAYLIST_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                serde_json::from_str::<Vec<String>>(&contents).map_err(|err| err.to_string())
+            }) {
+            Ok(playlist) if !playlist.is_empty() => self.playlist = Some(playlist),
+            Ok(_) => warn!("Kiosk playlist is empty, playing all levels"),
+            Err(err) => warn!("Failed to load kiosk playlist, playing all levels: {err}"),
+        }
+    }
+
+    /// Apply the settings saved in `SETTINGS_PATH` during the last session, if there are any
+    pub fn load_saved(&mut self) {
+        let file = match fs::read_to_string(SETTINGS_PATH) {
+            Ok(contents) => match serde_json::from_str::<SettingsFile>(&contents) {
+                Ok(file) => file,
+                Err(err) => {
+                    warn!("Failed to parse saved settings: {err}");
+                    return;
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.first_run = true;
+                return;
+            }
+            Err(err) => {
+                warn!("Failed to load saved settings: {err}");
+                return;
+            }
+        };
+
+        if let Some(fullscreen) = file.fullscreen {
+            self.fullscreen = fullscreen;
+        }
+        if let Some(fullscreen_mode) = file.fullscreen_mode {
+            self.fullscreen_mode = fullscreen_mode;
+        }
+        self.window = file.window;
+        self.display = file.display;
+        self.touch_layout = file.touch_layout;
+        if let Some(present_mode) = file.present_mode {
+            self.present_mode = present_mode;
+        }
+        if let Some(music_volume) = file.music_volume {
+            self.music_volume = music_volume.clamp(0.0, 1.0);
+        }
+        if let Some(effects_volume) = file.effects_volume {
+            self.effects_volume = effects_volume.clamp(0.0, 1.0);
+        }
+        if let Some(show_timer) = file.show_timer {
+            self.show_timer = show_timer;
+        }
+        if let Some(reduce_motion) = file.reduce_motion {
+            self.reduce_motion = reduce_motion;
+        }
+        if let Some(reduce_flashing) = file.reduce_flashing {
+            self.reduce_flashing = reduce_flashing;
+        }
+        if let Some(hitstop) = file.hitstop {
+            self.hitstop = hitstop;
+        }
+        if let Some(forgiving_spikes) = file.forgiving_spikes {
+            self.forgiving_spikes = forgiving_spikes;
+        }
+        if let Some(button_prompts) = file.button_prompts {
+            self.button_prompts = button_prompts;
+        }
+        if let Some(gamma) = file.gamma {
+            self.gamma = gamma.clamp(Settings::MIN_GAMMA, Settings::MAX_GAMMA);
+        }
+        if let Some(catch_up_policy) = file.catch_up_policy {
+            self.catch_up_policy = catch_up_policy;
+        }
+        if let Some(max_ticks_per_frame) = file.max_ticks_per_frame {
+            self.max_ticks_per_frame =
+                max_ticks_per_frame.clamp(Settings::MIN_TICKS_PER_FRAME, Settings::MAX_TICKS_PER_FRAME);
+        }
+    }
+
+    /// Save the settings that are kept between sessions to `SETTINGS_PATH`
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let file = SettingsFile {
+            fullscreen: Some(self.fullscreen),
+            fullscreen_mode: Some(self.fullscreen_mode),
+            window: self.window.clone(),
+            display: self.display.clone(),
+            present_mode: Some(self.present_mode),
+            music_volume: Some(self.music_volume),
+            effects_volume: Some(self.effects_volume),
+            show_timer: Some(self.show_timer),
+            reduce_motion: Some(self.reduce_motion),
+            reduce_flashing: Some(self.reduce_flashing),
+            hitstop: Some(self.hitstop),
+            forgiving_spikes: Some(self.forgiving_spikes),
+            button_prompts: Some(self.button_prompts),
+            gamma: Some(self.gamma),
+            catch_up_policy: Some(self.catch_up_policy),
+            max_ticks_per_frame: Some(self.max_ticks_per_frame),
+            touch_layout: self.touch_layout.clone(),
+        };
+        fs::write(Path::new(SETTINGS_PATH), serde_json::to_string_pretty(&file)?)?;
+        Ok(())
+    }
+
+    /// Apply command line arguments
+    pub fn apply_args<I: Iterator<Item = String>>(&mut self, args: I) {
+        for arg in args {
+            match arg.as_str() {
+                "--kiosk" => self.enable_kiosk_mode(),
+                "--no-audio" => self.audio = false,
+                other => warn!("Unknown argument: {other}"),
+            }
+        }
+    }
+
+    /// Name of a button shown to the player, depending on the prompt style
+    pub fn button_prompt(&self, button: ButtonType, input: &Input) -> String {
+        match self.button_prompts {
+            ButtonPrompts::Controller | ButtonPrompts::Handheld => input
+                .bindings()
+                .pad_buttons(button)
+                .first()
+                .map(|pad_button| pad_glyph(*pad_button, self.button_prompts).to_owned())
+                .unwrap_or_else(|| String::from("Unbound")),
+            ButtonPrompts::Keyboard => input
+                .bindings()
+                .keycodes(button)
+                .first()
+                .map(|keycode| keycode.name())
+                .unwrap_or_else(|| String::from("Unbound")),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            fullscreen: false,
+            fullscreen_mode: FullscreenMode::Borderless,
+            window: None,
+            display: None,
+            ui_scale: 1.0,
+            button_prompts: ButtonPrompts::Keyboard,
+            touch_layout: None,
+            gamma: 1.0,
+            backends: wgpu::Backends::PRIMARY,
+            bloom_intensity: 0.8,
+            language: env::var("COMPLEMENTARY_LANG").unwrap_or_else(|_| String::from("en")),
+            reduce_motion: false,
+            reduce_flashing: false,
+            hitstop: true,
+            forgiving_spikes: false,
+            music_volume: 1.0,
+            effects_volume: 1.0,
+            show_timer: false,
+            idle_timeout: None,
+            kiosk: false,
+            audio: true,
+            playlist: None,
+            auto_slow_mode: true,
+            catch_up_policy: CatchUpPolicy::Skip,
+            max_ticks_per_frame: Game::MAX_TICKS_PER_FRAME,
+            pause_when_minimized: true,
+            present_mode: PresentMode::Fifo,
+            first_run: false,
+        }
+    }
+}
+
+/// DevGUI section for the settings that players usually change. Display settings are in the
+/// `DisplayPanel` and key bindings in the `ControlsPanel`.
+#[derive(Debug, Default)]
+pub struct SettingsPanel;
+
+impl SettingsPanel {
+    /// Returns true if a setting was changed, so that it can be applied and saved
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, settings: &mut Settings) -> bool {
+        // Adds a section to the game's DevGUI window
+        let _token = match imgui::Window::new("DevGUI").begin(gui) {
+            Some(token) => token,
+            None => return false,
+        };
+        if !gui.collapsing_header("Settings", imgui::TreeNodeFlags::empty()) {
+            return false;
+        }
+
+        gui.indent();
+        let mut changed = false;
+        changed |= imgui::Slider::new("Music volume", 0.0, 1.0).build(gui, &mut settings.music_volume);
+        changed |= imgui::Slider::new("Effects volume", 0.0, 1.0).build(gui, &mut settings.effects_volume);
+        changed |= imgui::Slider::new("Gamma", Settings::MIN_GAMMA, Settings::MAX_GAMMA)
+            .build(gui, &mut settings.gamma);
+        changed |= gui.checkbox("Show timer", &mut settings.show_timer);
+        changed |= gui.checkbox("Reduce motion", &mut settings.reduce_motion);
+        changed |= gui.checkbox("Reduce flashing", &mut settings.reduce_flashing);
+        changed |= gui.checkbox("Hitstop", &mut settings.hitstop);
+        changed |= gui.checkbox("Forgiving spikes", &mut settings.forgiving_spikes);
+
+        let names: Vec<_> = CatchUpPolicy::ALL.iter().map(|policy| policy.name()).collect();
+        let mut selected = CatchUpPolicy::ALL
+            .iter()
+            .position(|policy| *policy == settings.catch_up_policy)
+            .unwrap_or(0);
+        if gui.combo_simple_string("When lagging", &mut selected, &names) {
+            settings.catch_up_policy = CatchUpPolicy::ALL[selected];
+            changed = true;
+        }
+        changed |= imgui::Slider::new(
+            "Max. ticks per frame",
+            Settings::MIN_TICKS_PER_FRAME,
+            Settings::MAX_TICKS_PER_FRAME,
+        )
+        .build(gui, &mut settings.max_ticks_per_frame);
+        gui.unindent();
+        changed
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SettingsError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid data: {0}")]
+    InvalidData(#[from] serde_json::Error),
+}
+
+/// Check whether we're running on a Steam Deck or if a handheld session was requested by
+/// setting the `COMPLEMENTARY_HANDHELD` environment variable
+pub fn is_handheld_device() -> bool {
+    if let Ok(value) = env::var("COMPLEMENTARY_HANDHELD") {
+        return value == "1";
+    }
+
+    // Set by Steam when running in game mode on the Deck
+    if matches!(env::var("SteamDeck").as_deref(), Ok("1")) {
+        return true;
+    }
+
+    // "Jupiter" is the LCD model, "Galileo" the OLED model
+    fs::read_to_string("/sys/devices/virtual/dmi/id/board_name")
+        .map(|name| matches!(name.trim(), "Jupiter" | "Galileo"))
+        .unwrap_or(false)
+}
+
+/// Name of a controller button shown in prompts, following the labels printed on an Xbox
+/// controller or, with `ButtonPrompts::Handheld`, on the Steam Deck
+fn pad_glyph(button: PadButton, prompts: ButtonPrompts) -> &'static str {
+    let handheld = prompts == ButtonPrompts::Handheld;
+    match button {
+        PadButton::A => "A",
+        PadButton::B => "B",
+        PadButton::X => "X",
+        PadButton::Y => "Y",
+        PadButton::Back => "View",
+        PadButton::Start => "Menu",
+        PadButton::Guide if handheld => "Steam",
+        PadButton::Guide => "Xbox",
+        PadButton::LeftStick if handheld => "L3",
+        PadButton::LeftStick => "LS",
+        PadButton::RightStick if handheld => "R3",
+        PadButton::RightStick => "RS",
+        PadButton::LeftShoulder if handheld => "L1",
+        PadButton::LeftShoulder => "LB",
+        PadButton::RightShoulder if handheld => "R1",
+        PadButton::RightShoulder => "RB",
+        PadButton::DPadUp => "Up",
+        PadButton::DPadDown => "Down",
+        PadButton::DPadLeft => "Left",
+        PadButton::DPadRight => "Right",
+        PadButton::Misc1 if handheld => "...",
+        PadButton::Misc1 => "Share",
+        // The Deck's back grips, in the order SDL reports them
+        PadButton::Paddle1 if handheld => "R4",
+        PadButton::Paddle2 if handheld => "L4",
+        PadButton::Paddle3 if handheld => "R5",
+        PadButton::Paddle4 if handheld => "L5",
+        PadButton::Paddle1 => "P1",
+        PadButton::Paddle2 => "P2",
+        PadButton::Paddle3 => "P3",
+        PadButton::Paddle4 => "P4",
+        PadButton::Touchpad => "Touchpad",
+    }
+}
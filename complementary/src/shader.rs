@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+
+use crate::{
+    math::{Color, FMat4, FVec2},
+    objects::{
+        ability_block::AbilityBlockInstance, door::DoorInstance, key::KeyInstance,
+        particle_system::ParticleInstance, platform::PlatformInstance,
+    },
+    player::PlayerUniforms,
+    post_processing::PostProcessingUniforms,
+    rendering::{text::GlyphInstance, ColoredVertex, DrawState, SpriteVertex, Vertex},
+};
+
+/// Files in `src/shaders`, embedded into the binary. Files in `include/` are only used
+/// as snippets for other shaders.
+const SOURCES: &[(&str, &str)] = &[
+    ("ability_block.wgsl", include_str!("shaders/ability_block.wgsl")),
+    ("door.wgsl", include_str!("shaders/door.wgsl")),
+    ("fade.wgsl", include_str!("shaders/fade.wgsl")),
+    ("key.wgsl", include_str!("shaders/key.wgsl")),
+    ("particle.wgsl", include_str!("shaders/particle.wgsl")),
+    ("platform.wgsl", include_str!("shaders/platform.wgsl")),
+    ("player.wgsl", include_str!("shaders/player.wgsl")),
+    ("post_processing.wgsl", include_str!("shaders/post_processing.wgsl")),
+    ("sprite.wgsl", include_str!("shaders/sprite.wgsl")),
+    ("text.wgsl", include_str!("shaders/text.wgsl")),
+    ("tilemap.wgsl", include_str!("shaders/tilemap.wgsl")),
+    ("include/colored.wgsl", include_str!("shaders/include/colored.wgsl")),
+    ("include/fullscreen.wgsl", include_str!("shaders/include/fullscreen.wgsl")),
+    ("include/invert.wgsl", include_str!("shaders/include/invert.wgsl")),
+    ("include/view.wgsl", include_str!("shaders/include/view.wgsl")),
+];
+
+/// Rust type of a field in a struct shared with shaders, see `WgslStruct`
+pub trait WgslType {
+    const NAME: &'static str;
+    /// Size and alignment in uniform buffers, see the "Alignment and Size" section of the WGSL spec
+    const SIZE: usize;
+    const ALIGN: usize;
+}
+
+impl WgslType for f32 {
+    const NAME: &'static str = "f32";
+    const SIZE: usize = 4;
+    const ALIGN: usize = 4;
+}
+
+impl WgslType for [f32; 2] {
+    const NAME: &'static str = "vec2<f32>";
+    const SIZE: usize = 8;
+    const ALIGN: usize = 8;
+}
+
+impl WgslType for FVec2 {
+    const NAME: &'static str = "vec2<f32>";
+    const SIZE: usize = 8;
+    const ALIGN: usize = 8;
+}
+
+impl WgslType for Color {
+    const NAME: &'static str = "vec4<f32>";
+    const SIZE: usize = 16;
+    const ALIGN: usize = 16;
+}
+
+impl WgslType for FMat4 {
+    const NAME: &'static str = "mat4x4<f32>";
+    const SIZE: usize = 64;
+    const ALIGN: usize = 16;
+}
+
+/// Struct that's passed to shaders, implemented with `#[derive(WgslStruct)]`. The derive
+/// also checks the struct's layout when compiling.
+pub trait WgslStruct {
+    fn wgsl_definition() -> String;
+}
+
+pub const fn wgsl_round_up(offset: usize, align: usize) -> usize {
+    (offset + align - 1) / align * align
+}
+
+/// Structs that shaders can include with `#include "struct/<name>"`
+fn generated_struct(name: &str) -> Option<String> {
+    Some(match name {
+        "AbilityBlockInstance" => AbilityBlockInstance::wgsl_definition(),
+        "ColoredVertex" => ColoredVertex::wgsl_definition(),
+        "DoorInstance" => DoorInstance::wgsl_definition(),
+        "DrawState" => DrawState::wgsl_definition(),
+        "GlyphInstance" => GlyphInstance::wgsl_definition(),
+        "KeyInstance" => KeyInstance::wgsl_definition(),
+        "ParticleInstance" => ParticleInstance::wgsl_definition(),
+        "PlatformInstance" => PlatformInstance::wgsl_definition(),
+        "PlayerUniforms" => PlayerUniforms::wgsl_definition(),
+        "PostProcessingUniforms" => PostProcessingUniforms::wgsl_definition(),
+        "SpriteVertex" => SpriteVertex::wgsl_definition(),
+        "Vertex" => Vertex::wgsl_definition(),
+        _ => return None,
+    })
+}
+
+/// Value passed from the game to a shader, declared as a constant at the top of it
+#[derive(Debug, Clone, Copy)]
+pub enum ShaderConstant {
+    Bool(bool),
+    F32(f32),
+    Color(Color),
+}
+
+impl ShaderConstant {
+    fn declaration(self, name: &str) -> String {
+        match self {
+            ShaderConstant::Bool(value) => format!("let {name}: bool = {value};"),
+            ShaderConstant::F32(value) => format!("let {name}: f32 = {};", float_literal(value)),
+            ShaderConstant::Color(Color { r, g, b, a }) => format!(
+                "let {name}: vec4<f32> = vec4<f32>({}, {}, {}, {});",
+                float_literal(r),
+                float_literal(g),
+                float_literal(b),
+                float_literal(a)
+            ),
+        }
+    }
+}
+
+/// Format `value` with a decimal point, so WGSL doesn't parse it as an integer. `Debug` only
+/// prints one if the value isn't written with an exponent (e.g. `1e20`).
+fn float_literal(value: f32) -> String {
+    let literal = format!("{value:?}");
+    if literal.contains('.') {
+        return literal;
+    }
+    match literal.split_once('e') {
+        Some((mantissa, exponent)) => format!("{mantissa}.0e{exponent}"),
+        None => format!("{literal}.0"),
+    }
+}
+
+/// Create a shader module from a file in `src/shaders`, see `preprocess()`
+pub fn create_shader_module(
+    device: &wgpu::Device,
+    name: &str,
+    constants: &[(&str, ShaderConstant)],
+) -> wgpu::ShaderModule {
+    device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(preprocess(name, constants))),
+    })
+}
+
+/// Replace `#include "<path>"` lines with the contents of that file, relative to `src/shaders`,
+/// or with a generated struct definition for `struct/<name>`. Every file is only included once,
+/// even if multiple files include it. `constants` are declared before the shader's code.
+fn preprocess(name: &str, constants: &[(&str, ShaderConstant)]) -> String {
+    let mut output = String::new();
+    for (constant_name, value) in constants {
+        output.push_str(&value.declaration(constant_name));
+        output.push('\n');
+    }
+    append_source(&mut output, SOURCES, name, &mut Vec::new());
+    output
+}
+
+fn append_source<'a>(
+    output: &mut String,
+    sources: &[(&'a str, &'a str)],
+    name: &'a str,
+    included: &mut Vec<&'a str>,
+) {
+    if let Some(struct_name) = name.strip_prefix("struct/") {
+        let definition =
+            generated_struct(struct_name).unwrap_or_else(|| panic!("Unknown shader struct \"{struct_name}\""));
+        output.push_str(&definition);
+        included.push(name);
+        return;
+    }
+
+    let source = sources
+        .iter()
+        .find(|(path, _)| *path == name)
+        .map(|(_, source)| *source)
+        .unwrap_or_else(|| panic!("Unknown shader \"{name}\""));
+    included.push(name);
+
+    for line in source.lines() {
+        match parse_include(line) {
+            Some(path) if included.contains(&path) => {}
+            Some(path) => append_source(output, sources, path, included),
+            None => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+}
+
+fn parse_include(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("#include")?
+        .trim()
+        .strip_prefix('"')?
+        .strip_suffix('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_SOURCES: &[(&str, &str)] = &[
+        ("main.wgsl", "#include \"a.wgsl\"\n#include \"b.wgsl\"\nmain\n"),
+        ("a.wgsl", "#include \"common.wgsl\"\na\n"),
+        ("b.wgsl", "  #include   \"common.wgsl\"\nb\n"),
+        ("common.wgsl", "#include \"nested/inner.wgsl\"\ncommon\n"),
+        ("nested/inner.wgsl", "inner\n"),
+        ("broken.wgsl", "#include \"missing.wgsl\"\n"),
+        ("uses_struct.wgsl", "#include \"struct/DrawState\"\nuses_struct\n"),
+    ];
+
+    fn expand(name: &str) -> String {
+        let mut output = String::new();
+        append_source(&mut output, TEST_SOURCES, name, &mut Vec::new());
+        output
+    }
+
+    #[test]
+    fn parse_include_accepts_whitespace() {
+        assert_eq!(parse_include("#include \"a.wgsl\""), Some("a.wgsl"));
+        assert_eq!(parse_include("  #include\t\"a.wgsl\"  "), Some("a.wgsl"));
+        assert_eq!(parse_include("#include a.wgsl"), None);
+        assert_eq!(parse_include("// #include \"a.wgsl\""), None);
+    }
+
+    #[test]
+    fn nested_includes_are_expanded_once() {
+        assert_eq!(expand("main.wgsl"), "inner\ncommon\na\nb\nmain\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown shader \"missing.wgsl\"")]
+    fn unknown_includes_panic() {
+        expand("broken.wgsl");
+    }
+
+    #[test]
+    fn struct_includes_are_generated() {
+        assert_eq!(
+            expand("uses_struct.wgsl"),
+            format!("{}uses_struct\n", DrawState::wgsl_definition())
+        );
+    }
+
+    #[test]
+    fn shipped_shaders_can_be_preprocessed() {
+        for (name, _) in SOURCES {
+            let output = preprocess(name, &[]);
+            assert!(!output.contains("#include"), "{name} still contains an include");
+        }
+    }
+
+    #[test]
+    fn float_constants_have_a_decimal_point() {
+        for value in [0.0, 1.0, -2.0, 0.5, 1e20, 1e-20, f32::MAX] {
+            let declaration = ShaderConstant::F32(value).declaration("value");
+            let literal = declaration
+                .strip_prefix("let value: f32 = ")
+                .and_then(|rest| rest.strip_suffix(';'))
+                .unwrap();
+            assert!(literal.contains('.'), "{literal} has no decimal point");
+            assert_eq!(literal.parse::<f32>().unwrap(), value);
+        }
+        assert_eq!(
+            ShaderConstant::Color(Color { r: 1.0, g: 0.0, b: 0.5, a: 1e20 }).declaration("color"),
+            "let color: vec4<f32> = vec4<f32>(1.0, 0.0, 0.5, 1.0e20);"
+        );
+    }
+}
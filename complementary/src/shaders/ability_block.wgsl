@@ -1,32 +1,12 @@
-struct AbilityBlockUniforms {
-    view_matrix: mat4x4<f32>;
-};
-[[group(0), binding(0)]] var<uniform> uniforms: AbilityBlockUniforms;
-
-struct VertexInput {
-    [[location(0)]] vert_position: vec2<f32>;
-};
-struct AbilityBlockInstance {
-    [[location(1)]] color: vec4<f32>;
-    [[location(2)]] position: vec2<f32>;
-    [[location(3)]] size: vec2<f32>;
-};
-
-struct VertexOutput {
-    [[builtin(position)]] position: vec4<f32>;
-    [[location(0)]] color: vec4<f32>;
-};
+#include "include/view.wgsl"
+#include "include/colored.wgsl"
+#include "struct/Vertex"
+#include "struct/AbilityBlockInstance"
 
 [[stage(vertex)]]
-fn vs_main(input: VertexInput, instance: AbilityBlockInstance) -> VertexOutput {
-    var pos = uniforms.view_matrix * vec4<f32>((input.vert_position * instance.size + instance.position), 0.0, 1.0);
+fn vs_main(input: Vertex, instance: AbilityBlockInstance) -> VertexOutput {
     var out: VertexOutput;
-    out.position = pos;
+    out.position = to_clip_space(input.position * instance.size + instance.position);
     out.color = instance.color;
     return out;
 }
-
-[[stage(fragment)]]
-fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return input.color;
-}
@@ -0,0 +1,16 @@
+#include "include/fullscreen.wgsl"
+
+struct FadeUniforms {
+    color: vec4<f32>;
+};
+[[group(0), binding(0)]] var<uniform> uniforms: FadeUniforms;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    return vec4<f32>(fullscreen_uv(index) * 2.0 - 1.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main() -> [[location(0)]] vec4<f32> {
+    return uniforms.color;
+}
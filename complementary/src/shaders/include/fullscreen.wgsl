@@ -0,0 +1,4 @@
+// UV coordinates of a triangle covering the whole screen, drawn without any vertex buffers
+fn fullscreen_uv(index: u32) -> vec2<f32> {
+    return vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+}
@@ -0,0 +1,5 @@
+// Invert a color by `amount`, which is 1.0 in the Dark world. Colors premultiplied by alpha
+// are inverted relative to their alpha.
+fn invert(rgb: vec3<f32>, alpha: f32, amount: f32) -> vec3<f32> {
+    return mix(rgb, vec3<f32>(alpha) - rgb, vec3<f32>(amount));
+}
@@ -0,0 +1,7 @@
+// Uniforms of shaders that only need the view matrix
+#include "struct/DrawState"
+[[group(0), binding(0)]] var<uniform> uniforms: DrawState;
+
+fn to_clip_space(position: vec2<f32>) -> vec4<f32> {
+    return uniforms.view_matrix * vec4<f32>(position, 0.0, 1.0);
+}
@@ -0,0 +1,90 @@
+#include "include/fullscreen.wgsl"
+#include "include/invert.wgsl"
+
+#include "struct/PostProcessingUniforms"
+[[group(0), binding(0)]] var<uniform> uniforms: PostProcessingUniforms;
+[[group(1), binding(0)]] var source: texture_2d<f32>;
+[[group(1), binding(1)]] var source_sampler: sampler;
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if (value <= 0.04045) {
+        return value / 12.92;
+    }
+    return pow((value + 0.055) / 1.055, 2.4);
+}
+
+// Colors are in sRGB, convert them if the surface encodes to sRGB itself
+fn to_output(rgb: vec3<f32>) -> vec3<f32> {
+    if (OUTPUT_SRGB) {
+        return vec3<f32>(srgb_to_linear(rgb.r), srgb_to_linear(rgb.g), srgb_to_linear(rgb.b));
+    }
+    return rgb;
+}
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    let uv = fullscreen_uv(index);
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// Separable gaussian blur, run once horizontally and once vertically
+[[stage(fragment)]]
+fn fs_blur(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    var result = textureSample(source, source_sampler, input.uv) * weights[0];
+    for (var i: i32 = 1; i < 5; i = i + 1) {
+        let offset = uniforms.direction * f32(i);
+        result = result + textureSample(source, source_sampler, input.uv + offset) * weights[i];
+        result = result + textureSample(source, source_sampler, input.uv - offset) * weights[i];
+    }
+    return result;
+}
+
+// Copy the scene to the output, inverting colors in the Dark world. `intensity` is the
+// gamma, see `gamma_correct()` in post_processing.rs.
+[[stage(fragment)]]
+fn fs_invert(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(source, source_sampler, input.uv);
+    let rgb = invert(color.rgb, 1.0, uniforms.inversion);
+    return vec4<f32>(to_output(pow(rgb, vec3<f32>(1.0 / uniforms.intensity))), 1.0);
+}
+
+// Output the blurred bloom texture, which is added to the scene by the blend state
+[[stage(fragment)]]
+fn fs_composite(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(source, source_sampler, input.uv);
+    // The colors are premultiplied by alpha
+    let rgb = invert(color.rgb, color.a, uniforms.inversion);
+    return vec4<f32>(to_output(rgb * uniforms.intensity), 0.0);
+}
+
+fn hash(x: f32) -> f32 {
+    return fract(sin(x * 127.1) * 43758.5453);
+}
+
+// Streaks along the movement direction near the screen edges, fading towards the center
+[[stage(fragment)]]
+fn fs_speed_lines(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let centered = input.uv - vec2<f32>(0.5);
+    let along = dot(centered, uniforms.direction);
+    let across = dot(centered, vec2<f32>(-uniforms.direction.y, uniforms.direction.x));
+
+    let row = floor(across * 80.0);
+    let row_random = hash(row);
+    let streak_length = 0.15 + 0.35 * hash(row + 17.0);
+    // Move the lines against the movement direction over the course of the dash
+    let offset = fract(along + row_random - (1.0 - uniforms.intensity) * 1.5);
+    let line = step(0.6, row_random) * step(offset, streak_length) * (offset / streak_length);
+
+    let edge = smoothstep(0.3, 0.5, abs(across));
+    let alpha = line * edge * uniforms.intensity * SPEED_LINE_OPACITY;
+    return vec4<f32>(to_output(invert(SPEED_LINE_COLOR.rgb, 1.0, uniforms.inversion)), alpha);
+}
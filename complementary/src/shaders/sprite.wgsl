@@ -0,0 +1,25 @@
+#include "include/view.wgsl"
+#include "struct/SpriteVertex"
+
+[[group(1), binding(0)]] var sprite_texture: texture_2d<f32>;
+[[group(1), binding(1)]] var sprite_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(input: SpriteVertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = to_clip_space(input.position);
+    out.color = input.color;
+    out.uv = input.uv;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(sprite_texture, sprite_sampler, input.uv) * input.color;
+}
@@ -0,0 +1,28 @@
+#include "include/view.wgsl"
+#include "struct/Vertex"
+#include "struct/GlyphInstance"
+
+[[group(1), binding(0)]] var atlas: texture_2d<f32>;
+[[group(1), binding(1)]] var atlas_sampler: sampler;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] uv: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(input: Vertex, instance: GlyphInstance) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = to_clip_space(instance.position + input.position * instance.size);
+    out.color = instance.color;
+    out.uv = mix(instance.uv_min, instance.uv_max, input.position);
+    return out;
+}
+
+// The atlas only contains the coverage of each pixel, which is used as alpha
+[[stage(fragment)]]
+fn fs_main(input: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let coverage = textureSample(atlas, atlas_sampler, input.uv).r;
+    return vec4<f32>(input.color.rgb, input.color.a * coverage);
+}
@@ -0,0 +1,201 @@
+use log::error;
+use num_traits::FromPrimitive;
+
+use crate::{
+    game::{Game, GameSpeed},
+    input::{ButtonType, Input},
+    replay::{self, Replay},
+    toast,
+};
+
+/// Frame-advance editor for tool-assisted runs. While active, the simulation only runs when
+/// requested by the editor and uses the edited inputs instead of the keyboard.
+///
+/// There are no savestates yet, so edits to past frames are applied by restarting the level
+/// and simulating all frames up to the current one again.
+pub struct TasEditor {
+    active: bool,
+    playing: bool,
+    /// Pressed buttons for every tick, see `Input::pressed_buttons()`
+    frames: Vec<u32>,
+    /// Number of frames that were simulated since the level started
+    current_frame: usize,
+    /// First frame shown in the timeline
+    view_start: usize,
+    input: Input,
+}
+
+impl TasEditor {
+    /// Number of frames shown in the timeline at once
+    const VISIBLE_FRAMES: usize = 30;
+
+    pub fn new() -> Self {
+        TasEditor {
+            active: false,
+            playing: false,
+            frames: Vec::new(),
+            current_frame: 0,
+            view_start: 0,
+            input: Input::new(),
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Run a frame if playing. Called instead of the regular tick loop while the editor is active.
+    pub fn tick(&mut self, game: &mut Game) {
+        if self.playing {
+            self.advance(game);
+        }
+    }
+
+    fn start(&mut self, game: &mut Game) {
+        self.active = true;
+        self.playing = false;
+        self.frames.clear();
+        self.view_start = 0;
+        self.restart(game);
+    }
+
+    fn restart(&mut self, game: &mut Game) {
+        if let Err(err) = game.restart_from_beginning() {
+            error!("Failed to restart level: {err}");
+        }
+        self.input = Input::new();
+        self.current_frame = 0;
+    }
+
+    /// Simulate the next frame. New frames keep the inputs of the previous one.
+    fn advance(&mut self, game: &mut Game) {
+        if self.current_frame == self.frames.len() {
+            let previous = self.frames.last().copied().unwrap_or(0);
+            self.frames.push(previous);
+        }
+
+        self.input.set_pressed_buttons(self.frames[self.current_frame]);
+        self.input.tick();
+        game.tick(&self.input);
+        self.current_frame += 1;
+    }
+
+    /// Simulate up to `frame`, restarting the level if it's in the past
+    fn seek(&mut self, frame: usize, game: &mut Game) {
+        if frame < self.current_frame {
+            self.restart(game);
+        }
+        while self.current_frame < frame {
+            self.advance(game);
+        }
+    }
+
+    fn export(&self, game: &Game) {
+        let replay = Replay {
+            level: game.level_name().to_owned(),
+            frames: self.frames.clone(),
+            // The editor steps through the ticks itself, independent of the game speed
+            speed: GameSpeed::NORMAL,
+            setup: game.replay_setup(),
+        };
+        let path = replay::new_replay_path("tas");
+
+        match replay.save(&path) {
+            Ok(()) => toast::show(format!("Exported TAS replay to {}", path.display())),
+            Err(err) => {
+                error!("Failed to export TAS replay: {err}");
+                toast::show("Failed to export TAS replay");
+            }
+        }
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui, game: &mut Game) {
+        let _token = match imgui::Window::new("TAS")
+            .size([700.0, 380.0], imgui::Condition::FirstUseEver)
+            .collapsed(true, imgui::Condition::FirstUseEver)
+            .begin(gui)
+        {
+            Some(token) => token,
+            None => return,
+        };
+
+        if !self.active {
+            if gui.button("Start TAS mode") {
+                self.start(game);
+            }
+            return;
+        }
+
+        if gui.button("Stop TAS mode") {
+            self.active = false;
+            return;
+        }
+        gui.same_line();
+        if gui.button(if self.playing { "Pause" } else { "Play" }) {
+            self.playing = !self.playing;
+        }
+        gui.same_line();
+        if gui.button("Frame advance") {
+            self.playing = false;
+            self.advance(game);
+        }
+        gui.same_line();
+        if gui.button("Back") && self.current_frame > 0 {
+            self.playing = false;
+            self.seek(self.current_frame - 1, game);
+        }
+        gui.same_line();
+        if gui.button("Export replay") {
+            self.export(game);
+        }
+        gui.text(format!(
+            "Frame {} / {}",
+            self.current_frame,
+            self.frames.len()
+        ));
+
+        // Keep the current frame in view
+        if self.current_frame < self.view_start
+            || self.current_frame >= self.view_start + TasEditor::VISIBLE_FRAMES
+        {
+            self.view_start = self.current_frame.saturating_sub(TasEditor::VISIBLE_FRAMES / 2);
+        }
+        let visible_frames = self.view_start..self.view_start + TasEditor::VISIBLE_FRAMES;
+
+        gui.separator();
+        let mut edited_frame = None;
+        for index in 0..ButtonType::COUNT {
+            let button = ButtonType::from_usize(index).unwrap();
+            let _row_id = gui.push_id(index as i32);
+
+            gui.text(format!("{:<16}", format!("{:?}", button)));
+            for frame in visible_frames.clone() {
+                let _id = gui.push_id(frame as i32);
+                gui.same_line();
+
+                let mask = self.frames.get(frame).copied().unwrap_or(0);
+                let mut pressed = mask & (1 << index) != 0;
+                // Highlight the frame that is simulated next
+                let _style = (frame == self.current_frame).then(|| {
+                    gui.push_style_color(imgui::StyleColor::FrameBg, [0.6, 0.4, 0.1, 1.0])
+                });
+                if gui.checkbox("##frame", &mut pressed) {
+                    if frame >= self.frames.len() {
+                        self.frames.resize(frame + 1, 0);
+                    }
+                    self.frames[frame] ^= 1 << index;
+                    edited_frame = Some(edited_frame.map_or(frame, |edited: usize| edited.min(frame)));
+                }
+            }
+        }
+
+        // Simulate the edited frames again
+        if let Some(frame) = edited_frame {
+            if frame < self.current_frame {
+                let current_frame = self.current_frame;
+                self.seek(0, game);
+                self.seek(current_frame, game);
+            }
+        }
+    }
+}
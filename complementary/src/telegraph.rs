@@ -0,0 +1,52 @@
+use crate::{
+    math::{Bounds, InnerSpace},
+    rendering::DrawState,
+};
+
+/// Hazards further away from the visible area than this (in tiles) aren't telegraphed
+const WARNING_DISTANCE: f32 = 6.0;
+/// Distance of the arrows from the edge of the screen in pixels
+const ARROW_MARGIN: f32 = 24.0;
+const ARROW_SIZE: f32 = 12.0;
+
+/// Draw arrows at the edges of the screen pointing towards hazards that are just outside
+/// of the visible area
+pub fn draw_hazard_warnings(gui: &imgui::Ui, state: &DrawState, hazards: &[Bounds]) {
+    let visible = state.visible_bounds();
+    let screen_size = gui.io().display_size;
+    let draw_list = gui.get_background_draw_list();
+
+    for hazard in hazards {
+        if hazard.overlaps(&visible) {
+            continue;
+        }
+        let center = hazard.center();
+        let closest = visible.clamp_point(center);
+        let offset = center - closest;
+        let distance = offset.magnitude();
+        if distance > WARNING_DISTANCE || distance == 0.0 {
+            continue;
+        }
+
+        // Closer hazards get more opaque arrows
+        let alpha = 1.0 - distance / WARNING_DISTANCE;
+        let direction = offset / distance;
+        let [x, y] = state.world_to_screen(closest, screen_size);
+        let tip = [
+            x.clamp(ARROW_MARGIN, screen_size[0] - ARROW_MARGIN),
+            y.clamp(ARROW_MARGIN, screen_size[1] - ARROW_MARGIN),
+        ];
+        // Screen space has the same orientation as world space
+        let back = [tip[0] - direction.x * ARROW_SIZE, tip[1] - direction.y * ARROW_SIZE];
+        let side = [-direction.y * ARROW_SIZE * 0.5, direction.x * ARROW_SIZE * 0.5];
+        draw_list
+            .add_triangle(
+                tip,
+                [back[0] + side[0], back[1] + side[1]],
+                [back[0] - side[0], back[1] - side[1]],
+                [1.0, 0.2, 0.2, alpha],
+            )
+            .filled(true)
+            .build();
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the unit tests
+
+use std::sync::Once;
+
+use crate::mods::{Mod, ModManifest};
+
+/// Load assets relative to the repository root like when running the game. The working
+/// directory is shared by all tests, which run in parallel, so it's only changed once.
+pub fn use_repository_root() {
+    static CHANGE_DIR: Once = Once::new();
+    CHANGE_DIR.call_once(|| {
+        std::env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+    });
+}
+
+/// An installed mod without levels or palettes
+pub fn test_mod(id: &str, enabled: bool) -> Mod {
+    Mod {
+        id: id.to_owned(),
+        manifest: ModManifest {
+            name: id.to_owned(),
+            version: None,
+            author: None,
+            description: None,
+            levels: Vec::new(),
+            palettes: Vec::new(),
+        },
+        enabled,
+    }
+}
@@ -0,0 +1,201 @@
+//! Small screenshots of the game that are stored next to the save file and shown on the title
+//! screen. The GPU readback, conversion and writing happen on a separate thread, so that
+//! saving doesn't hitch.
+//!
+//! Thumbnails are stored as binary PPM files, which don't need an image library.
+
+use std::{
+    fs,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use log::{error, warn};
+
+use crate::{
+    gpu_memory::{self, TrackedTexture},
+    post_processing::PostProcessing,
+};
+
+/// A multiple of 64 pixels, so that the rows of the copy don't need padding, see
+/// `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`
+pub const THUMBNAIL_WIDTH: u32 = 192;
+pub const THUMBNAIL_HEIGHT: u32 = 108;
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// Decoded thumbnail with 8-bit sRGB RGBA pixels
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+/// Draws the scene into a small texture and writes it to a file
+pub struct ThumbnailCapture {
+    texture: TrackedTexture,
+    view: wgpu::TextureView,
+    /// The surface's format, which the scene copy in `PostProcessing` is made for
+    format: wgpu::TextureFormat,
+    /// Set while a thumbnail is being read back and written, see `in_flight()`
+    in_flight: Arc<AtomicBool>,
+}
+
+impl ThumbnailCapture {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let texture = gpu_memory::create_texture(device, &wgpu::TextureDescriptor {
+            label: Some("thumbnail_texture"),
+            size: wgpu::Extent3d {
+                width: THUMBNAIL_WIDTH,
+                height: THUMBNAIL_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view, format, in_flight: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// Whether a thumbnail is still being written. The device must be polled meanwhile, so
+    /// that the readback finishes.
+    pub fn in_flight(&self) -> bool {
+        self.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Copy the current scene into a buffer, after `PostProcessing::apply()`. The returned buffer
+    /// must be passed to `save_in_background()` once the commands were submitted. Returns `None`
+    /// if the last thumbnail is still being written.
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        post_processing: &PostProcessing,
+    ) -> Option<wgpu::Buffer> {
+        if self.in_flight() {
+            warn!("Skipping thumbnail, the last one is still being written");
+            return None;
+        }
+        post_processing.copy_scene(encoder, &self.view);
+
+        let bytes_per_row = THUMBNAIL_WIDTH * BYTES_PER_PIXEL;
+        // Not tracked like other resources since it's dropped on another thread
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("thumbnail_readback_buffer"),
+            size: (bytes_per_row * THUMBNAIL_HEIGHT) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: THUMBNAIL_WIDTH,
+                height: THUMBNAIL_HEIGHT,
+                depth_or_array_layers: 1,
+            },
+        );
+        Some(buffer)
+    }
+
+    /// Wait for the copy from `capture()` and write it to `path` on another thread
+    pub fn save_in_background(&self, buffer: wgpu::Buffer, path: PathBuf) {
+        self.in_flight.store(true, Ordering::Release);
+        let in_flight = self.in_flight.clone();
+        let bgra = matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        thread::spawn(move || {
+            let slice = buffer.slice(..);
+            match pollster::block_on(slice.map_async(wgpu::MapMode::Read)) {
+                Ok(()) => {
+                    let pixels = slice.get_mapped_range().to_vec();
+                    buffer.unmap();
+                    if let Err(err) = write_ppm(&path, &pixels, bgra) {
+                        error!("Failed to write thumbnail: {err}");
+                    }
+                }
+                Err(err) => error!("Failed to read thumbnail: {err}"),
+            }
+            in_flight.store(false, Ordering::Release);
+        });
+    }
+}
+
+fn write_ppm(path: &Path, pixels: &[u8], bgra: bool) -> Result<(), ThumbnailError> {
+    let mut contents = format!("P6\n{THUMBNAIL_WIDTH} {THUMBNAIL_HEIGHT}\n255\n").into_bytes();
+    for pixel in pixels.chunks_exact(BYTES_PER_PIXEL as usize) {
+        if bgra {
+            contents.extend([pixel[2], pixel[1], pixel[0]]);
+        } else {
+            contents.extend(&pixel[..3]);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Load a thumbnail written by `ThumbnailCapture`
+pub fn load(path: &Path) -> Result<Thumbnail, ThumbnailError> {
+    let contents = fs::read(path)?;
+    // The header is exactly what `write_ppm()` writes: the magic number, the size and the
+    // maximum value, each on their own line
+    let mut parts = contents.splitn(4, |&byte| byte == b'\n');
+    let (magic, size, max_value, pixels) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(magic), Some(size), Some(max_value), Some(pixels)) => (magic, size, max_value, pixels),
+        _ => return Err(ThumbnailError::InvalidData),
+    };
+    if magic != b"P6" || max_value != b"255" {
+        return Err(ThumbnailError::InvalidData);
+    }
+    let size = String::from_utf8_lossy(size);
+    let (width, height) = size
+        .split_once(' ')
+        .and_then(|(width, height)| Some((width.parse::<u32>().ok()?, height.parse::<u32>().ok()?)))
+        .ok_or(ThumbnailError::InvalidData)?;
+    // Only thumbnails from `ThumbnailCapture` are loaded, which also keeps the size from
+    // overflowing below
+    if (width, height) != (THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT) {
+        return Err(ThumbnailError::InvalidData);
+    }
+    if pixels.len() != width as usize * height as usize * 3 {
+        return Err(ThumbnailError::InvalidData);
+    }
+
+    let rgba = pixels
+        .chunks_exact(3)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], u8::MAX])
+        .collect();
+    Ok(Thumbnail { width, height, rgba })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ThumbnailError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid thumbnail")]
+    InvalidData,
+}
@@ -2,22 +2,24 @@ use std::{
     error::Error,
     fmt::{Debug, Display},
     fs::File,
-    io::{self, BufReader, Read},
+    io::{self, BufReader, BufWriter, Read, Write},
     path::Path,
 };
 
-use bytemuck::Contiguous;
+use bytemuck::{Contiguous, Zeroable};
 use cgmath::Zero;
-use wgpu::include_wgsl;
+use serde::Deserialize;
 
 use crate::{
     game::WorldType,
-    math::{Bounds, Color, Direction, FMat4, FVec2},
+    gpu_memory::{self, TrackedBuffer},
+    math::{Bounds, Color, Direction, FVec2},
     rendering::{self, ColoredVertex, DrawState, UniformBuffer},
+    shader,
     window::DrawContext,
 };
 
-#[derive(Clone, Copy, Debug, Contiguous)]
+#[derive(Clone, Copy, Debug, Contiguous, Deserialize)]
 #[repr(u8)]
 pub enum Tile {
     Air,
@@ -36,9 +38,31 @@ pub enum Tile {
     GoalDown,
 
     SpikeAllSides,
+
+    /// 45° slope with the floor rising towards the right. Only the lower right half is solid.
+    SlopeUpRight,
+    /// 45° slope with the floor rising towards the left. Only the lower left half is solid.
+    SlopeUpLeft,
+
+    /// Platform that can be jumped through from below. Only its top edge is solid, and only
+    /// for things that fall onto it, see `Tilemap::lands_on_one_way_platform()`.
+    OneWayUp,
+
+    /// Solid block with less horizontal drag, so that the player slides on it
+    Ice,
+    /// Solid blocks that carry things standing on them to the left or right
+    ConveyorLeft,
+    ConveyorRight,
+
+    /// Solid blocks that only exist in one world, see `Tile::is_solid_in()`
+    LightOnly,
+    DarkOnly,
 }
 
 impl Tile {
+    /// Height of the strip that one-way platforms are drawn as
+    pub const ONE_WAY_THICKNESS: f32 = 0.2;
+
     fn spawn(&self) {}
 
     pub fn is_solid(&self) -> bool {
@@ -55,6 +79,32 @@ impl Tile {
             Tile::GoalUp => true,
             Tile::GoalDown => true,
             Tile::SpikeAllSides => true,
+            // Slopes are only partly solid, see `overlaps_slope()`
+            Tile::SlopeUpRight => false,
+            Tile::SlopeUpLeft => false,
+            // Depends on the direction of movement, see `lands_on_one_way_platform()`
+            Tile::OneWayUp => false,
+            Tile::Ice => true,
+            Tile::ConveyorLeft => true,
+            Tile::ConveyorRight => true,
+            // Depends on the world, see `is_solid_in()`
+            Tile::LightOnly => false,
+            Tile::DarkOnly => false,
+        }
+    }
+
+    /// Whether the tile is solid while `world_type` is active, including tiles that only
+    /// exist in that world
+    pub fn is_solid_in(&self, world_type: WorldType) -> bool {
+        self.is_solid() || self.world_type() == Some(world_type)
+    }
+
+    /// The only world in which the tile exists, or `None` if it's the same in both
+    pub fn world_type(&self) -> Option<WorldType> {
+        match self {
+            Tile::LightOnly => Some(WorldType::Light),
+            Tile::DarkOnly => Some(WorldType::Dark),
+            _ => None,
         }
     }
 
@@ -72,6 +122,14 @@ impl Tile {
             Tile::GoalUp => false,
             Tile::GoalDown => false,
             Tile::SpikeAllSides => false,
+            Tile::SlopeUpRight => false,
+            Tile::SlopeUpLeft => false,
+            Tile::OneWayUp => false,
+            Tile::Ice => true,
+            Tile::ConveyorLeft => true,
+            Tile::ConveyorRight => true,
+            Tile::LightOnly => true,
+            Tile::DarkOnly => true,
         }
     }
 
@@ -89,9 +147,77 @@ impl Tile {
             Tile::GoalUp => Some(Direction::Up),
             Tile::GoalDown => Some(Direction::Down),
             Tile::SpikeAllSides => None,
+            Tile::SlopeUpRight => None,
+            Tile::SlopeUpLeft => None,
+            Tile::OneWayUp => None,
+            Tile::Ice => None,
+            Tile::ConveyorLeft => Some(Direction::Left),
+            Tile::ConveyorRight => Some(Direction::Right),
+            Tile::LightOnly => None,
+            Tile::DarkOnly => None,
+        }
+    }
+
+    /// The same tile flipped horizontally
+    pub fn mirrored(&self) -> Tile {
+        match self {
+            Tile::SpikesLeft => Tile::SpikesRight,
+            Tile::SpikesRight => Tile::SpikesLeft,
+            Tile::GoalLeft => Tile::GoalRight,
+            Tile::GoalRight => Tile::GoalLeft,
+            Tile::SlopeUpRight => Tile::SlopeUpLeft,
+            Tile::SlopeUpLeft => Tile::SlopeUpRight,
+            Tile::ConveyorLeft => Tile::ConveyorRight,
+            Tile::ConveyorRight => Tile::ConveyorLeft,
+            tile => *tile,
+        }
+    }
+
+    /// Whether the tile glows, see `TilemapRenderer::draw_emissive()`
+    pub fn is_emissive(&self) -> bool {
+        matches!(self, Tile::GoalLeft | Tile::GoalRight | Tile::GoalUp | Tile::GoalDown)
+    }
+
+    /// All tiles in the order of their values in `.cmtm` files
+    pub fn all() -> impl Iterator<Item = Tile> {
+        (Tile::MIN_VALUE..=Tile::MAX_VALUE).filter_map(Tile::from_integer)
+    }
+
+    /// Direction in which objects slide down the slope, or `None` if the tile isn't a slope
+    pub fn slope_direction(&self) -> Option<Direction> {
+        match self {
+            Tile::SlopeUpRight => Some(Direction::Left),
+            Tile::SlopeUpLeft => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    /// Direction in which things standing on the tile are carried, or `None` if the tile isn't
+    /// a conveyor
+    pub fn conveyor_direction(&self) -> Option<Direction> {
+        match self {
+            Tile::ConveyorLeft => Some(Direction::Left),
+            Tile::ConveyorRight => Some(Direction::Right),
+            _ => None,
         }
     }
 
+    /// Whether `bounds` overlaps the solid half of a slope tile at the given tile coordinates.
+    /// Always `false` for other tiles.
+    pub fn overlaps_slope(&self, x: i32, y: i32, bounds: Bounds) -> bool {
+        let (x, y) = (x as f32, y as f32);
+        if bounds.max.x <= x || bounds.min.x >= x + 1.0 || bounds.min.y >= y + 1.0 {
+            return false;
+        }
+        // The highest point of the surface below the bounds, relative to the tile's bottom edge
+        let height = match self {
+            Tile::SlopeUpRight => (bounds.max.x - x).min(1.0),
+            Tile::SlopeUpLeft => 1.0 - (bounds.min.x - x).max(0.0),
+            _ => return false,
+        };
+        bounds.max.y > y + 1.0 - height
+    }
+
     fn color(&self) -> Color {
         match self {
             Tile::Air => Color::WHITE,
@@ -106,6 +232,14 @@ impl Tile {
             Tile::GoalUp => Color::BLACK,
             Tile::GoalDown => Color::BLACK,
             Tile::SpikeAllSides => Color::RED,
+            Tile::SlopeUpRight => Color::BLACK,
+            Tile::SlopeUpLeft => Color::BLACK,
+            Tile::OneWayUp => Color::BLACK,
+            Tile::Ice => Color::new_solid(0.6, 0.8, 0.9),
+            Tile::ConveyorLeft => Color::BLACK,
+            Tile::ConveyorRight => Color::BLACK,
+            Tile::LightOnly => Color::BLACK,
+            Tile::DarkOnly => Color::BLACK,
         }
     }
 }
@@ -157,6 +291,22 @@ impl Tilemap {
         })
     }
 
+    /// Save the tile map in the format read by `load_from_file()`
+    pub fn save_to_file<T: AsRef<Path>>(&self, path: T) -> Result<(), io::Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"CMTM")?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        let bytes: Vec<u8> = self.tiles.iter().map(|tile| tile.into_integer()).collect();
+        writer.write_all(&bytes)?;
+        writer.flush()
+    }
+
+    /// Whether the tile coordinates are inside of the tile map
+    pub fn contains_tile(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
     pub fn get_tile(&self, x: i32, y: i32) -> Tile {
         self.tiles[(self.width * y + x) as usize]
     }
@@ -166,18 +316,113 @@ impl Tilemap {
         tile.spawn();
     }
 
-    pub fn get_spawn_point(&self) -> Option<FVec2> {
+    /// Flip the tile map horizontally, see `Level::mirror()`
+    pub fn mirror(&mut self) {
+        for row in self.tiles.chunks_mut(self.width as usize) {
+            row.reverse();
+            for tile in row {
+                *tile = tile.mirrored();
+            }
+        }
+    }
+
+    /// Get the positions of all `SpawnPoint` tiles in reading order
+    pub fn spawn_points(&self) -> Vec<FVec2> {
+        let mut spawn_points = Vec::new();
         for y in 0..self.height {
             for x in 0..self.width {
                 if matches!(self.get_tile(x, y), Tile::SpawnPoint) {
-                    return Some(FVec2::new(x as f32, y as f32));
+                    spawn_points.push(FVec2::new(x as f32, y as f32));
                 }
             }
         }
+        spawn_points
+    }
+
+    /// Whether `bounds` overlaps the solid half of any slope tile
+    pub fn overlaps_slope(&self, bounds: Bounds) -> bool {
+        self.slope_at(bounds).is_some()
+    }
 
+    /// Get the first slope tile whose solid half is overlapped by `bounds`
+    pub fn slope_at(&self, bounds: Bounds) -> Option<Tile> {
+        let min_x = (bounds.min.x as i32).max(0);
+        let min_y = (bounds.min.y as i32).max(0);
+        let max_x = (bounds.max.x as i32).min(self.width - 1);
+        let max_y = (bounds.max.y as i32).min(self.height - 1);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let tile = self.get_tile(x, y);
+                if tile.overlaps_slope(x, y, bounds) {
+                    return Some(tile);
+                }
+            }
+        }
         None
     }
 
+    /// Whether `bounds` overlap a tile that's solid in `world_type`, the solid half of a slope or
+    /// are outside of the tile map. Touching a tile's left or top edge counts as overlapping it.
+    pub fn overlaps_solid(&self, bounds: Bounds, world_type: WorldType) -> bool {
+        for y in bounds.min.y.floor() as i32..=bounds.max.y.floor() as i32 {
+            for x in bounds.min.x.floor() as i32..=bounds.max.x.floor() as i32 {
+                // Tiles outside of the map are solid, like in `sweep()`
+                if !self.contains_tile(x, y) || self.get_tile(x, y).is_solid_in(world_type) {
+                    return true;
+                }
+            }
+        }
+        self.overlaps_slope(bounds)
+    }
+
+    /// Whether any tile that `bounds` touches is a slope, including the slope's empty half
+    pub fn contains_slope(&self, bounds: Bounds) -> bool {
+        let min_x = (bounds.min.x as i32).max(0);
+        let min_y = (bounds.min.y as i32).max(0);
+        let max_x = (bounds.max.x as i32).min(self.width - 1);
+        let max_y = (bounds.max.y as i32).min(self.height - 1);
+        (min_y..=max_y).any(|y| (min_x..=max_x).any(|x| self.get_tile(x, y).slope_direction().is_some()))
+    }
+
+    /// How far `bounds` can move in `direction`, up to `distance`, before touching a tile that's
+    /// solid in `world_type` or the edge of the map. `None` if nothing is in the way.
+    /// Only whole tiles are considered, see `contains_slope()`.
+    pub fn sweep(&self, bounds: Bounds, direction: Direction, distance: f32, world_type: WorldType) -> Option<f32> {
+        let blocked = |x: i32, y: i32| !self.contains_tile(x, y) || self.get_tile(x, y).is_solid_in(world_type);
+        let (min_x, max_x) = (bounds.min.x.floor() as i32, bounds.max.x.floor() as i32);
+        let (min_y, max_y) = (bounds.min.y.floor() as i32, bounds.max.y.floor() as i32);
+        match direction {
+            Direction::Left => ((bounds.min.x - distance).floor() as i32..min_x)
+                .rev()
+                .find(|&x| (min_y..=max_y).any(|y| blocked(x, y)))
+                .map(|x| bounds.min.x - (x + 1) as f32),
+            Direction::Right => (max_x + 1..=(bounds.max.x + distance).floor() as i32)
+                .find(|&x| (min_y..=max_y).any(|y| blocked(x, y)))
+                .map(|x| x as f32 - bounds.max.x),
+            Direction::Up => ((bounds.min.y - distance).floor() as i32..min_y)
+                .rev()
+                .find(|&y| (min_x..=max_x).any(|x| blocked(x, y)))
+                .map(|y| bounds.min.y - (y + 1) as f32),
+            Direction::Down => (max_y + 1..=(bounds.max.y + distance).floor() as i32)
+                .find(|&y| (min_x..=max_x).any(|x| blocked(x, y)))
+                .map(|y| y as f32 - bounds.max.y),
+        }
+    }
+
+    /// Whether something whose bottom edge moved down from `old_bottom` to `bounds.max.y`
+    /// crossed the top edge of a one-way platform, i.e. landed on it from above
+    pub fn lands_on_one_way_platform(&self, old_bottom: f32, bounds: Bounds) -> bool {
+        let y = bounds.max.y as i32;
+        let top = y as f32;
+        if old_bottom > top || bounds.max.y <= top || y < 0 || y >= self.height {
+            return false;
+        }
+        // Only tiles that are overlapped horizontally, not the ones that are just touched
+        let min_x = (bounds.min.x as i32).max(0);
+        let max_x = (bounds.max.x.ceil() as i32 - 1).min(self.width - 1);
+        (min_x..=max_x).any(|x| matches!(self.get_tile(x, y), Tile::OneWayUp))
+    }
+
     pub fn width(&self) -> i32 {
         self.width
     }
@@ -202,13 +447,24 @@ impl Default for Tilemap {
 
 pub struct TilemapRenderer {
     vertex_count: usize,
-    vertex_buffer: wgpu::Buffer,
-    uniform_buffer: UniformBuffer<TilemapUniforms>,
+    vertex_buffer: TrackedBuffer,
+    /// Contains only tiles which glow, see `draw_emissive()`
+    emissive_vertex_count: usize,
+    emissive_vertex_buffer: TrackedBuffer,
+    has_emissive_tiles: bool,
+    /// World whose tiles are drawn opaque, see `set_world_type()`
+    world_type: WorldType,
+    uniform_buffer: UniformBuffer<DrawState>,
     render_pipeline: wgpu::RenderPipeline,
 }
 
 impl TilemapRenderer {
-    pub fn new(device: &wgpu::Device, tilemap: &Tilemap) -> TilemapRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        tilemap: &Tilemap,
+        world_type: WorldType,
+    ) -> TilemapRenderer {
         let uniform_buffer = UniformBuffer::new(device, "tilemap_uniforms");
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -217,115 +473,234 @@ impl TilemapRenderer {
             push_constant_ranges: &[],
         });
 
-        let vertices = TilemapRenderer::get_tilemap_vertices(tilemap);
+        let vertices = TilemapRenderer::get_tilemap_vertices(tilemap, world_type);
 
-        let size = vertices.len() * std::mem::size_of::<ColoredVertex>();
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("tilemap_vertex_buffer"),
-            size: size as _,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: true,
-        });
+        let vertex_buffer = TilemapRenderer::create_writable_vertex_buffer(device, "tilemap_vertex_buffer", &vertices);
 
-        vertex_buffer.slice(..).get_mapped_range_mut()[..size as usize]
-            .copy_from_slice(bytemuck::cast_slice(&vertices));
-        vertex_buffer.unmap();
+        let emissive_vertices = TilemapRenderer::get_emissive_vertices(tilemap);
+        let emissive_vertex_buffer = TilemapRenderer::create_writable_vertex_buffer(
+            device,
+            "tilemap_emissive_vertex_buffer",
+            &emissive_vertices,
+        );
 
         let render_pipeline =
             device.create_render_pipeline(&rendering::create_pipeline_descriptor(
                 Some("tilemap_pipeline"),
-                &device.create_shader_module(&include_wgsl!("shaders/tilemap.wgsl")),
+                &shader::create_shader_module(device, "tilemap.wgsl", &[]),
                 Some(&pipeline_layout),
                 &[ColoredVertex::layout()],
+                &rendering::color_targets(format),
             ));
 
         TilemapRenderer {
             vertex_count: vertices.len(),
             vertex_buffer,
+            emissive_vertex_count: emissive_vertices.len(),
+            emissive_vertex_buffer,
+            has_emissive_tiles: TilemapRenderer::contains_emissive_tiles(tilemap),
+            world_type,
             uniform_buffer,
             render_pipeline,
         }
     }
 
-    fn get_tilemap_vertices(tilemap: &Tilemap) -> Vec<ColoredVertex> {
-        let mut vertices = Vec::with_capacity(5000);
+    /// Create a vertex buffer which can be updated later, see `update_tile()`
+    fn create_writable_vertex_buffer(device: &wgpu::Device, label: &str, vertices: &[ColoredVertex]) -> TrackedBuffer {
+        let size = vertices.len() * std::mem::size_of::<ColoredVertex>();
+        let vertex_buffer = gpu_memory::create_buffer(device, &wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+
+        vertex_buffer.slice(..).get_mapped_range_mut()[..size as usize]
+            .copy_from_slice(bytemuck::cast_slice(vertices));
+        vertex_buffer.unmap();
+        vertex_buffer
+    }
+
+    /// The background is a single rectangle at the start of the vertex buffer
+    const BACKGROUND_VERTEX_COUNT: u32 = 6;
+    /// Every tile has a fixed number of vertices in the buffer, so that single tiles can be
+    /// updated in place. Spikes on all sides need the most: a background and four corners.
+    const TILE_VERTEX_COUNT: usize = 6 + 4 * 9;
+    /// Alpha of tiles that only exist in the other world
+    const INACTIVE_WORLD_ALPHA: f32 = 0.2;
+    const EMISSIVE_TILE_VERTEX_COUNT: usize = 6;
+
+    fn get_tilemap_vertices(tilemap: &Tilemap, world_type: WorldType) -> Vec<ColoredVertex> {
+        let tile_count = (tilemap.width * tilemap.height) as usize;
+        let mut vertices = Vec::with_capacity(TilemapRenderer::BACKGROUND_VERTEX_COUNT as usize + tile_count * TilemapRenderer::TILE_VERTEX_COUNT);
 
-        // Clear to allow for alpha transparency
+        // Clear to allow for alpha transparency. This has to be the first rectangle, see `draw_background()`
         TilemapRenderer::append_rectangle(&mut vertices, Bounds::new(FVec2::zero(), FVec2::new(tilemap.width as f32, tilemap.height as f32)), Color::WHITE);
 
         for y in 0..tilemap.height() {
             for x in 0..tilemap.width() {
-                let tile = tilemap.get_tile(x, y);
+                vertices.extend(TilemapRenderer::tile_vertices(tilemap, x, y, world_type));
+            }
+        }
 
-                match tile {
-                    Tile::Air | Tile::SpawnPoint => {
-                        // Invisible
-                    },
-                    Tile::Solid => TilemapRenderer::append_vertices_solid(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                    ),
-                    Tile::GoalLeft
-                    | Tile::GoalRight
-                    | Tile::GoalUp
-                    | Tile::GoalDown => TilemapRenderer::append_vertices_direction_gradient(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                    ),
-                    Tile::SpikesLeft => TilemapRenderer::append_vertices_spikes(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                        true,
-                        false,
-                        false,
-                        false,
-                    ),
-                    Tile::SpikesRight => TilemapRenderer::append_vertices_spikes(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                        false,
-                        true,
-                        false,
-                        false,
-                    ),
-                    Tile::SpikesUp => TilemapRenderer::append_vertices_spikes(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                        false,
-                        false,
-                        true,
-                        false,
-                    ),
-                    Tile::SpikesDown => TilemapRenderer::append_vertices_spikes(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                        false,
-                        false,
-                        false,
-                        true,
-                    ),
-                    Tile::SpikeAllSides => TilemapRenderer::append_vertices_spikes(
-                        tile,
-                        &mut vertices,
-                        FVec2::new(x as f32, y as f32),
-                        true,
-                        true,
-                        true,
-                        true,
-                    ),
-                }
+        vertices
+    }
+
+    fn get_emissive_vertices(tilemap: &Tilemap) -> Vec<ColoredVertex> {
+        let mut vertices = Vec::with_capacity((tilemap.width * tilemap.height) as usize * TilemapRenderer::EMISSIVE_TILE_VERTEX_COUNT);
+        for y in 0..tilemap.height() {
+            for x in 0..tilemap.width() {
+                vertices.extend(TilemapRenderer::emissive_tile_vertices(tilemap, x, y));
             }
         }
+        vertices
+    }
 
+    /// Vertices of a single tile, padded to `TILE_VERTEX_COUNT` with degenerate triangles
+    fn tile_vertices(tilemap: &Tilemap, x: i32, y: i32, world_type: WorldType) -> Vec<ColoredVertex> {
+        let mut vertices = Vec::with_capacity(TilemapRenderer::TILE_VERTEX_COUNT);
+        let pos = FVec2::new(x as f32, y as f32);
+        TilemapRenderer::append_tile_vertices(tilemap.get_tile(x, y), &mut vertices, pos, world_type);
+        vertices.resize(TilemapRenderer::TILE_VERTEX_COUNT, ColoredVertex::zeroed());
+        vertices
+    }
+
+    /// Vertices of a single tile in the emissive buffer, padded to `EMISSIVE_TILE_VERTEX_COUNT`
+    fn emissive_tile_vertices(tilemap: &Tilemap, x: i32, y: i32) -> Vec<ColoredVertex> {
+        let mut vertices = Vec::with_capacity(TilemapRenderer::EMISSIVE_TILE_VERTEX_COUNT);
+        let tile = tilemap.get_tile(x, y);
+        if tile.is_emissive() {
+            TilemapRenderer::append_vertices_direction_gradient(tile, &mut vertices, FVec2::new(x as f32, y as f32));
+        }
+        vertices.resize(TilemapRenderer::EMISSIVE_TILE_VERTEX_COUNT, ColoredVertex::zeroed());
         vertices
     }
 
+    fn append_tile_vertices(tile: Tile, vertices: &mut Vec<ColoredVertex>, pos: FVec2, world_type: WorldType) {
+        match tile {
+            Tile::Air | Tile::SpawnPoint => {
+                // Invisible
+            },
+            Tile::Solid | Tile::Ice => TilemapRenderer::append_vertices_solid(
+                tile,
+                vertices,
+                pos,
+            ),
+            Tile::ConveyorLeft | Tile::ConveyorRight => TilemapRenderer::append_vertices_conveyor(
+                tile,
+                vertices,
+                pos,
+            ),
+            Tile::SlopeUpRight | Tile::SlopeUpLeft => TilemapRenderer::append_vertices_slope(
+                tile,
+                vertices,
+                pos,
+            ),
+            Tile::LightOnly | Tile::DarkOnly => TilemapRenderer::append_rectangle(
+                vertices,
+                Bounds::new(pos, pos + FVec2::new(1.0, 1.0)),
+                if tile.world_type() == Some(world_type) {
+                    tile.color()
+                } else {
+                    tile.color().with_alpha(TilemapRenderer::INACTIVE_WORLD_ALPHA)
+                },
+            ),
+            Tile::OneWayUp => TilemapRenderer::append_rectangle(
+                vertices,
+                Bounds::new(pos, pos + FVec2::new(1.0, Tile::ONE_WAY_THICKNESS)),
+                tile.color(),
+            ),
+            Tile::GoalLeft
+            | Tile::GoalRight
+            | Tile::GoalUp
+            | Tile::GoalDown => TilemapRenderer::append_vertices_direction_gradient(
+                tile,
+                vertices,
+                pos,
+            ),
+            Tile::SpikesLeft => TilemapRenderer::append_vertices_spikes(
+                tile,
+                vertices,
+                pos,
+                true,
+                false,
+                false,
+                false,
+            ),
+            Tile::SpikesRight => TilemapRenderer::append_vertices_spikes(
+                tile,
+                vertices,
+                pos,
+                false,
+                true,
+                false,
+                false,
+            ),
+            Tile::SpikesUp => TilemapRenderer::append_vertices_spikes(
+                tile,
+                vertices,
+                pos,
+                false,
+                false,
+                true,
+                false,
+            ),
+            Tile::SpikesDown => TilemapRenderer::append_vertices_spikes(
+                tile,
+                vertices,
+                pos,
+                false,
+                false,
+                false,
+                true,
+            ),
+            Tile::SpikeAllSides => TilemapRenderer::append_vertices_spikes(
+                tile,
+                vertices,
+                pos,
+                true,
+                true,
+                true,
+                true,
+            ),
+        }
+    }
+
+    /// Rebuild the vertices of a single tile after it was changed with `Tilemap::set_tile()`
+    pub fn update_tile(&mut self, queue: &wgpu::Queue, tilemap: &Tilemap, x: i32, y: i32) {
+        let index = (tilemap.width * y + x) as usize;
+        let vertex_size = std::mem::size_of::<ColoredVertex>();
+
+        let offset = (TilemapRenderer::BACKGROUND_VERTEX_COUNT as usize + index * TilemapRenderer::TILE_VERTEX_COUNT) * vertex_size;
+        let vertices = TilemapRenderer::tile_vertices(tilemap, x, y, self.world_type);
+        queue.write_buffer(&self.vertex_buffer, offset as u64, bytemuck::cast_slice(&vertices));
+
+        let offset = index * TilemapRenderer::EMISSIVE_TILE_VERTEX_COUNT * vertex_size;
+        let vertices = TilemapRenderer::emissive_tile_vertices(tilemap, x, y);
+        queue.write_buffer(&self.emissive_vertex_buffer, offset as u64, bytemuck::cast_slice(&vertices));
+        self.has_emissive_tiles = TilemapRenderer::contains_emissive_tiles(tilemap);
+    }
+
+    /// Draw the tiles of `world_type` opaque and the ones of the other world faded, so that the
+    /// player can see what becomes solid after switching
+    pub fn set_world_type(&mut self, queue: &wgpu::Queue, tilemap: &Tilemap, world_type: WorldType) {
+        if self.world_type == world_type {
+            return;
+        }
+        self.world_type = world_type;
+        for y in 0..tilemap.height() {
+            for x in 0..tilemap.width() {
+                if tilemap.get_tile(x, y).world_type().is_some() {
+                    self.update_tile(queue, tilemap, x, y);
+                }
+            }
+        }
+    }
+
+    fn contains_emissive_tiles(tilemap: &Tilemap) -> bool {
+        tilemap.tiles.iter().any(Tile::is_emissive)
+    }
+
     pub fn append_vertices_solid(tile: Tile, vertices: &mut Vec<ColoredVertex>, pos: FVec2) {
         TilemapRenderer::append_rectangle(
             vertices,
@@ -334,6 +709,37 @@ impl TilemapRenderer {
         );
     }
 
+    /// Append the solid half of a slope as a single triangle
+    pub fn append_vertices_slope(tile: Tile, vertices: &mut Vec<ColoredVertex>, pos: FVec2) {
+        let corners = match tile {
+            Tile::SlopeUpRight => [(0.0, 1.0), (1.0, 0.0), (1.0, 1.0)],
+            Tile::SlopeUpLeft => [(0.0, 1.0), (0.0, 0.0), (1.0, 1.0)],
+            _ => panic!("Tile must be a slope"),
+        };
+        for (x, y) in corners {
+            vertices.push(ColoredVertex::new(
+                FVec2::new(pos.x + x, pos.y + y),
+                tile.color(),
+            ));
+        }
+    }
+
+    /// Append a solid block with an arrow in the direction that the conveyor moves things
+    pub fn append_vertices_conveyor(tile: Tile, vertices: &mut Vec<ColoredVertex>, pos: FVec2) {
+        TilemapRenderer::append_vertices_solid(tile, vertices, pos);
+        let (back, tip) = match tile.conveyor_direction() {
+            Some(Direction::Left) => (0.7, 0.3),
+            Some(Direction::Right) => (0.3, 0.7),
+            _ => panic!("Tile must be a conveyor"),
+        };
+        for (x, y) in [(back, 0.3), (tip, 0.5), (back, 0.7)] {
+            vertices.push(ColoredVertex::new(
+                FVec2::new(pos.x + x, pos.y + y),
+                Color::GRAY,
+            ));
+        }
+    }
+
     pub fn append_vertices_direction_gradient(tile: Tile, vertices: &mut Vec<ColoredVertex>, pos: FVec2) {
         let bounds = Bounds::new(pos, pos + FVec2::new(1.0, 1.0));
         let solid = tile.color();
@@ -348,11 +754,12 @@ impl TilemapRenderer {
         TilemapRenderer::append_rectangle_individually_colored(vertices, bounds, colors)
     }
 
-    fn append_rectangle(vertices: &mut Vec<ColoredVertex>, bounds: Bounds, color: Color) {
+    pub fn append_rectangle(vertices: &mut Vec<ColoredVertex>, bounds: Bounds, color: Color) {
         TilemapRenderer::append_rectangle_individually_colored(vertices, bounds, (color, color, color, color))
     }
 
-    fn append_rectangle_individually_colored(vertices: &mut Vec<ColoredVertex>, bounds: Bounds, colors: (Color, Color, Color, Color)) {
+    /// `colors` are in the order top left, top right, bottom left, bottom right
+    pub fn append_rectangle_individually_colored(vertices: &mut Vec<ColoredVertex>, bounds: Bounds, colors: (Color, Color, Color, Color)) {
         vertices.push(ColoredVertex::new(
             FVec2::new(bounds.min.x, bounds.max.y),
             colors.2,
@@ -489,14 +896,14 @@ impl TilemapRenderer {
         }
     }
 
-    pub fn draw(&mut self, context: &mut DrawContext, state: &DrawState, world_type: WorldType) {
-        let uniforms = TilemapUniforms {
-            view_matrix: state.view_matrix,
-            invert_colors: if world_type == WorldType::Dark { 1 } else { 0 },
-            ..bytemuck::Zeroable::zeroed()
-        };
+    /// Draw glowing tiles (goals) into the bloom target
+    pub fn draw_emissive(&mut self, context: &mut DrawContext, state: &DrawState) {
+        if !self.has_emissive_tiles {
+            return;
+        }
+
         self.uniform_buffer
-            .write_with_queue(context.queue, uniforms);
+            .write_with_queue(context.queue, state.clone());
 
         let mut rpass = context
             .encoder
@@ -505,30 +912,70 @@ impl TilemapRenderer {
                     view: &context.output,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(if world_type == WorldType::Dark {
-                            wgpu::Color::WHITE
-                        } else {
-                            wgpu::Color::BLACK
-                        }),
+                        load: wgpu::LoadOp::Load,
                         store: true,
                     },
                 }],
                 depth_stencil_attachment: None,
-                label: Some("tilemap_rpass"),
+                label: Some("tilemap_emissive_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.emissive_vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(0..self.emissive_vertex_count as u32, 0..1);
+    }
+
+
+    /// Draw the tilemap using the colors of the Light world. Clears the target.
+    /// Clear the target and draw the background behind the tiles
+    pub fn draw_background(&mut self, context: &mut DrawContext, state: &DrawState) {
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("tilemap_background_rpass"),
             });
         rpass.set_pipeline(&self.render_pipeline);
         rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
         rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
-        rpass.draw(0..self.vertex_count as u32, 0..1);
+        rpass.draw(0..TilemapRenderer::BACKGROUND_VERTEX_COUNT, 0..1);
     }
-}
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct TilemapUniforms {
-    view_matrix: FMat4,
-    invert_colors: i32,
-    padding: [i8; 12],
+    /// Draw the tiles. `draw_background()` must be called first.
+    pub fn draw(&mut self, context: &mut DrawContext, state: &DrawState) {
+        self.uniform_buffer
+            .write_with_queue(context.queue, state.clone());
+
+        let mut rpass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &context.output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+                label: Some("tilemap_rpass"),
+            });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_bind_group(0, &self.uniform_buffer.bind_group(), &[]);
+        rpass.draw(TilemapRenderer::BACKGROUND_VERTEX_COUNT..self.vertex_count as u32, 0..1);
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -538,3 +985,22 @@ pub enum TilemapLoadError {
     #[error("invalid file magic")]
     InvalidMagic,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(x: f32, y: f32) -> Bounds {
+        Bounds::new(FVec2::new(x, y), FVec2::new(x + 0.5, y + 0.5))
+    }
+
+    #[test]
+    fn bounds_outside_of_the_map_overlap_solid() {
+        let tilemap = Tilemap::new(4, 3);
+        assert!(!tilemap.overlaps_solid(bounds(1.25, 1.25), WorldType::Light));
+        // Past the right edge, which used to wrap into the next row, and past the last row
+        for (x, y) in [(3.75, 1.25), (1.25, 2.75), (3.75, 2.75), (-0.25, 1.25), (1.25, -0.25)] {
+            assert!(tilemap.overlaps_solid(bounds(x, y), WorldType::Light), "({x}, {y})");
+        }
+    }
+}
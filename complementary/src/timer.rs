@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::imgui_helpers::{ImGui, ImGuiSettings};
+
+/// Counter that runs down by one every tick, for cooldowns, input buffers and animations.
+/// It stops at zero, so it can be ticked unconditionally.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickTimer {
+    remaining: i32,
+    /// The `ticks` passed to the last `start()`, see `progress()`
+    duration: i32,
+}
+
+impl TickTimer {
+    /// Run for `ticks`, replacing the remaining time
+    pub fn start(&mut self, ticks: i32) {
+        self.remaining = ticks.max(0);
+        self.duration = self.remaining;
+    }
+
+    pub fn stop(&mut self) {
+        self.remaining = 0;
+    }
+
+    /// Whether the timer hasn't run out yet
+    pub fn active(&self) -> bool {
+        self.remaining > 0
+    }
+
+    pub fn remaining(&self) -> i32 {
+        self.remaining
+    }
+
+    /// Elapsed fraction of the duration, from 0 right after `start()` to 1 when it ran out
+    pub fn progress(&self) -> f32 {
+        if self.duration == 0 {
+            1.0
+        } else {
+            1.0 - self.remaining as f32 / self.duration as f32
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.remaining = 0.max(self.remaining - 1);
+    }
+}
+
+impl ImGui for TickTimer {
+    fn draw_gui_with_settings(&mut self, label: &str, gui: &imgui::Ui, _settings: &ImGuiSettings) {
+        gui.label_text(label, format!("{} / {}", self.remaining, self.duration));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::ui_layout::{anchored_position, Anchor};
+
+lazy_static::lazy_static! {
+    /// Messages posted with `show()` that weren't picked up by the overlay yet
+    static ref PENDING: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+/// Show a short notification in the HUD. Can be called from anywhere, the message is shown
+/// with the next frame.
+pub fn show<S: Into<String>>(message: S) {
+    PENDING
+        .lock()
+        .expect("Poisoned toast lock")
+        .push(message.into());
+}
+
+struct Toast {
+    message: String,
+    shown_at: Instant,
+}
+
+/// Stack of notifications in the bottom left corner which fade in and out
+#[derive(Default)]
+pub struct ToastOverlay {
+    /// Visible toasts, oldest first
+    toasts: VecDeque<Toast>,
+}
+
+impl ToastOverlay {
+    const DURATION: Duration = Duration::from_millis(3000);
+    const FADE_DURATION: Duration = Duration::from_millis(250);
+    /// Older toasts are dropped if there are more than this
+    const MAX_VISIBLE: usize = 5;
+    const MARGIN: f32 = 16.0;
+    const PADDING: f32 = 8.0;
+
+    /// Pick up new messages and remove expired ones
+    fn update(&mut self, now: Instant) {
+        let pending = std::mem::take(&mut *PENDING.lock().expect("Poisoned toast lock"));
+        for message in pending {
+            self.toasts.push_back(Toast {
+                message,
+                shown_at: now,
+            });
+        }
+        while self.toasts.len() > ToastOverlay::MAX_VISIBLE {
+            self.toasts.pop_front();
+        }
+        self.toasts
+            .retain(|toast| now.duration_since(toast.shown_at) < ToastOverlay::DURATION);
+    }
+
+    /// Opacity of a toast that was shown `age` ago
+    fn alpha(age: Duration) -> f32 {
+        let fade = ToastOverlay::FADE_DURATION.as_secs_f32();
+        let fade_in = age.as_secs_f32() / fade;
+        let fade_out = (ToastOverlay::DURATION.as_secs_f32() - age.as_secs_f32()) / fade;
+        fade_in.min(fade_out).clamp(0.0, 1.0)
+    }
+
+    pub fn draw_gui(&mut self, gui: &imgui::Ui) {
+        let now = Instant::now();
+        self.update(now);
+
+        let draw_list = gui.get_foreground_draw_list();
+        // Newest toasts are at the bottom, older ones are pushed upwards
+        let mut offset = ToastOverlay::MARGIN;
+        for toast in self.toasts.iter().rev() {
+            let alpha = ToastOverlay::alpha(now.duration_since(toast.shown_at));
+            let [text_width, text_height] = gui.calc_text_size(&toast.message);
+            let size = [text_width + ToastOverlay::PADDING * 2.0, text_height + ToastOverlay::PADDING * 2.0];
+            let [left, top] = anchored_position(
+                Anchor::BOTTOM_LEFT,
+                size,
+                [ToastOverlay::MARGIN, offset],
+                gui.io().display_size,
+            );
+            // Slide in from the left while fading in
+            let left = left - (1.0 - alpha) * ToastOverlay::MARGIN;
+            let bottom = top + size[1];
+
+            draw_list
+                .add_rect(
+                    [left, top],
+                    [left + size[0], bottom],
+                    [0.0, 0.0, 0.0, 0.7 * alpha],
+                )
+                .filled(true)
+                .rounding(4.0)
+                .build();
+            draw_list.add_text(
+                [left + ToastOverlay::PADDING, top + ToastOverlay::PADDING],
+                [1.0, 1.0, 1.0, alpha],
+                &toast.message,
+            );
+            offset += size[1] + ToastOverlay::PADDING;
+        }
+    }
+}
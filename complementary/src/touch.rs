@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use sdl2::event::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::{ButtonType, Input},
+    math::{Bounds, Color, FVec2},
+};
+
+/// A button on the screen that can be pressed with a finger on touch screens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TouchButton {
+    pub button: ButtonType,
+    pub label: String,
+    /// Bounds in normalized window coordinates (0 to 1 on both axes)
+    pub bounds: Bounds,
+}
+
+impl TouchButton {
+    pub fn new(button: ButtonType, label: &str, min: FVec2, max: FVec2) -> Self {
+        TouchButton {
+            button,
+            label: label.to_owned(),
+            bounds: Bounds::new(min, max),
+        }
+    }
+}
+
+/// Touch input backend which translates SDL finger events into button presses.
+/// The on-screen buttons are only shown after the first touch event was received.
+pub struct TouchControls {
+    buttons: Vec<TouchButton>,
+    /// Button currently held by each finger
+    pressed_by_finger: HashMap<i64, ButtonType>,
+    active: bool,
+}
+
+impl TouchControls {
+    const BUTTON_COLOR: Color = Color::new(0.5, 0.5, 0.5, 0.35);
+    const PRESSED_BUTTON_COLOR: Color = Color::new(0.8, 0.8, 0.8, 0.5);
+
+    pub fn new(buttons: Vec<TouchButton>) -> Self {
+        TouchControls {
+            buttons,
+            pressed_by_finger: HashMap::new(),
+            active: false,
+        }
+    }
+
+    pub fn default_layout() -> Vec<TouchButton> {
+        vec![
+            TouchButton::new(ButtonType::Left, "<", FVec2::new(0.02, 0.75), FVec2::new(0.12, 0.95)),
+            TouchButton::new(ButtonType::Right, ">", FVec2::new(0.14, 0.75), FVec2::new(0.24, 0.95)),
+            TouchButton::new(ButtonType::Switch, "Switch", FVec2::new(0.64, 0.75), FVec2::new(0.74, 0.95)),
+            TouchButton::new(ButtonType::Ability, "Ability", FVec2::new(0.76, 0.75), FVec2::new(0.86, 0.95)),
+            TouchButton::new(ButtonType::Jump, "Jump", FVec2::new(0.88, 0.75), FVec2::new(0.98, 0.95)),
+        ]
+    }
+
+    pub fn set_buttons(&mut self, buttons: Vec<TouchButton>) {
+        self.buttons = buttons;
+        self.pressed_by_finger.clear();
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    fn button_at(&self, x: f32, y: f32) -> Option<ButtonType> {
+        let point = FVec2::new(x, y);
+        self.buttons
+            .iter()
+            .find(|button| button.bounds.contains_point(point))
+            .map(|button| button.button)
+    }
+
+    /// Press or release buttons based on a touch event. Non-touch events are ignored.
+    pub fn handle_event(&mut self, event: &Event, input: &mut Input) {
+        match *event {
+            Event::FingerDown {
+                finger_id, x, y, ..
+            } => {
+                self.active = true;
+                if let Some(button) = self.button_at(x, y) {
+                    input.set_button_pressed(button);
+                    self.pressed_by_finger.insert(finger_id, button);
+                }
+            }
+            Event::FingerMotion {
+                finger_id, x, y, ..
+            } => {
+                // Allow sliding the finger from one button to another
+                let previous = self.pressed_by_finger.get(&finger_id).copied();
+                let current = self.button_at(x, y);
+                if previous != current {
+                    self.release_finger(finger_id, input);
+                    if let Some(button) = current {
+                        input.set_button_pressed(button);
+                        self.pressed_by_finger.insert(finger_id, button);
+                    }
+                }
+            }
+            Event::FingerUp { finger_id, .. } => self.release_finger(finger_id, input),
+            _ => (),
+        }
+    }
+
+    fn release_finger(&mut self, finger_id: i64, input: &mut Input) {
+        if let Some(button) = self.pressed_by_finger.remove(&finger_id) {
+            // Another finger might still hold the same button
+            if !self.pressed_by_finger.values().any(|other| *other == button) {
+                input.set_button_released(button);
+            }
+        }
+    }
+
+    pub fn draw_gui(&self, gui: &imgui::Ui) {
+        if !self.active {
+            return;
+        }
+
+        let [width, height] = gui.io().display_size;
+        let draw_list = gui.get_foreground_draw_list();
+        for button in &self.buttons {
+            let min = [button.bounds.min.x * width, button.bounds.min.y * height];
+            let max = [button.bounds.max.x * width, button.bounds.max.y * height];
+            let pressed = self.pressed_by_finger.values().any(|other| *other == button.button);
+            let color = if pressed {
+                TouchControls::PRESSED_BUTTON_COLOR
+            } else {
+                TouchControls::BUTTON_COLOR
+            };
+
+            draw_list
+                .add_rect(min, max, [color.r, color.g, color.b, color.a])
+                .filled(true)
+                .rounding(8.0)
+                .build();
+
+            let text_size = gui.calc_text_size(&button.label);
+            let text_pos = [
+                (min[0] + max[0] - text_size[0]) / 2.0,
+                (min[1] + max[1] - text_size[1]) / 2.0,
+            ];
+            draw_list.add_text(text_pos, [1.0, 1.0, 1.0, 0.8], &button.label);
+        }
+    }
+}
+
+impl Default for TouchControls {
+    fn default() -> Self {
+        TouchControls::new(TouchControls::default_layout())
+    }
+}
@@ -0,0 +1,71 @@
+use crate::math::FVec2;
+
+/// Alignment on one axis of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// Left or top
+    Start,
+    Center,
+    /// Right or bottom
+    End,
+}
+
+impl Align {
+    /// Position as a fraction of the screen size
+    fn fraction(self) -> f32 {
+        match self {
+            Align::Start => 0.0,
+            Align::Center => 0.5,
+            Align::End => 1.0,
+        }
+    }
+}
+
+/// Point of the screen that a UI element is placed relative to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    pub horizontal: Align,
+    pub vertical: Align,
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Anchor = Anchor { horizontal: Align::Start, vertical: Align::Start };
+    pub const TOP_RIGHT: Anchor = Anchor { horizontal: Align::End, vertical: Align::Start };
+    pub const CENTER: Anchor = Anchor { horizontal: Align::Center, vertical: Align::Center };
+    pub const BOTTOM: Anchor = Anchor { horizontal: Align::Center, vertical: Align::End };
+    pub const BOTTOM_LEFT: Anchor = Anchor { horizontal: Align::Start, vertical: Align::End };
+
+    fn fraction(self) -> [f32; 2] {
+        [self.horizontal.fraction(), self.vertical.fraction()]
+    }
+}
+
+/// Top left corner of an element of `size` placed at `anchor`. The element is aligned to the
+/// anchor, e.g. it's right-aligned on the right edge, and kept `margin` away from the edges it's
+/// anchored to. The margin is ignored on centered axes.
+///
+/// All values are in ImGui's coordinates (`imgui::Io::display_size`), which already account for
+/// the DPI scale of the display.
+pub fn anchored_position(anchor: Anchor, size: [f32; 2], margin: [f32; 2], screen_size: [f32; 2]) -> [f32; 2] {
+    let fraction = anchor.fraction();
+    let axis = |i: usize| {
+        // Moves away from the left/top edge and towards the right/bottom one, not at all when centered
+        let margin_direction = 1.0 - 2.0 * fraction[i];
+        (screen_size[i] - size[i]) * fraction[i] + margin[i] * margin_direction
+    };
+    [axis(0), axis(1)]
+}
+
+/// Top left corner of `text` placed at `anchor`, see `anchored_position()`
+pub fn anchored_text_position(gui: &imgui::Ui, text: &str, anchor: Anchor, margin: [f32; 2]) -> [f32; 2] {
+    anchored_position(anchor, gui.calc_text_size(text), margin, gui.io().display_size)
+}
+
+/// Convert a position on the screen to normalized device coordinates, from -1 to 1 with y
+/// pointing up. See `DrawState::screen_to_world()` for world positions.
+pub fn screen_to_ndc(point: [f32; 2], screen_size: [f32; 2]) -> FVec2 {
+    FVec2::new(
+        point[0] / screen_size[0] * 2.0 - 1.0,
+        1.0 - point[1] / screen_size[1] * 2.0,
+    )
+}
@@ -0,0 +1,139 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{info, warn};
+
+use crate::level::{Level, LevelMetadata};
+
+/// Levels made by players, with the same files as the levels in `assets/maps`
+pub const USER_LEVELS_PATH: &str = "user_levels";
+/// Prepended to the names of user levels so that they can't collide with the main levels
+const LEVEL_NAME_PREFIX: &str = "user/";
+/// How often the directory is checked for added or removed levels
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct UserLevel {
+    /// Name for `Level::load()`, see `level_name()`
+    pub name: String,
+    /// Name of the level's files without the extension
+    pub file_name: String,
+    pub author: Option<String>,
+}
+
+/// Levels found in `USER_LEVELS_PATH`, rescanned when files are added or removed
+pub struct UserLevels {
+    levels: Vec<UserLevel>,
+    /// Modification time of the directory during the last scan
+    directory_modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl UserLevels {
+    pub fn new() -> Self {
+        let mut user_levels = UserLevels {
+            levels: Vec::new(),
+            directory_modified: None,
+            last_poll: Instant::now(),
+        };
+        user_levels.refresh();
+        user_levels
+    }
+
+    pub fn levels(&self) -> &[UserLevel] {
+        &self.levels
+    }
+
+    pub fn refresh(&mut self) {
+        self.directory_modified = directory_modified();
+        self.levels = match scan(Path::new(USER_LEVELS_PATH)) {
+            Ok(levels) => levels,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                warn!("Failed to scan user levels: {err}");
+                Vec::new()
+            }
+        };
+        if !self.levels.is_empty() {
+            info!("Found {} user levels", self.levels.len());
+        }
+    }
+
+    /// Rescan the directory if its contents changed since the last scan. Only checks the
+    /// modification time every `POLL_INTERVAL`, so it can be called every frame.
+    pub fn poll(&mut self) {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return;
+        }
+        self.last_poll = Instant::now();
+        if directory_modified() != self.directory_modified {
+            self.refresh();
+        }
+    }
+
+    /// List the user levels, returning the name of the level that was clicked
+    pub fn draw_gui(&mut self, gui: &imgui::Ui) -> Option<String> {
+        if gui.button("Refresh") {
+            self.refresh();
+        }
+        if self.levels.is_empty() {
+            gui.text(format!("No levels in {USER_LEVELS_PATH}/"));
+        }
+
+        let mut clicked = None;
+        for level in &self.levels {
+            let label = match &level.author {
+                Some(author) => format!("{} by {author}", level.file_name),
+                None => level.file_name.clone(),
+            };
+            if gui.button(label) {
+                clicked = Some(level.name.clone());
+            }
+        }
+        clicked
+    }
+}
+
+fn directory_modified() -> Option<SystemTime> {
+    fs::metadata(USER_LEVELS_PATH).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn scan(path: &Path) -> Result<Vec<UserLevel>, io::Error> {
+    let mut levels = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if !matches!(path.extension().and_then(|ext| ext.to_str()), Some("cmtm")) {
+            continue;
+        }
+        let file_name = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().into_owned(),
+            None => continue,
+        };
+
+        let name = level_name(&file_name);
+        let metadata_path = Level::tilemap_path(&name).with_extension("meta.json");
+        let author = match LevelMetadata::load_from_file(metadata_path) {
+            Ok(metadata) => metadata.author,
+            Err(err) => {
+                warn!("Failed to load metadata of user level \"{file_name}\": {err}");
+                None
+            }
+        };
+        levels.push(UserLevel { name, file_name, author });
+    }
+
+    levels.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+    Ok(levels)
+}
+
+pub fn level_name(file_name: &str) -> String {
+    format!("{LEVEL_NAME_PREFIX}{file_name}")
+}
+
+/// Name of a user level's files, or `None` if `name` doesn't refer to a user level
+pub fn file_name(name: &str) -> Option<&str> {
+    name.strip_prefix(LEVEL_NAME_PREFIX)
+}
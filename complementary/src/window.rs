@@ -1,17 +1,38 @@
-use std::time::{Duration, Instant};
-
-use crate::game::Game;
+use std::{collections::HashMap, thread, time::Duration};
+
+use crate::audio::{self, SoundCategory};
+#[cfg(feature = "automation")]
+use crate::automation::AutomationServer;
+use crate::controls::ControlsPanel;
+use crate::display::{DisplayInfo, DisplayPanel, DisplayRequest};
+use crate::game::{Game, GameState};
 use crate::imgui_sdl2_support::{filter_event, SdlPlatform as ImguiSdlPlatform};
-use crate::input::{ButtonType, Input};
+use crate::input::{Input, KeyBindings};
+use crate::latency::LatencyMeter;
+use crate::level_code::LevelCodePanel;
+use crate::localization;
 use crate::math::{FVec2, FVec3};
+use crate::mods::TickHook;
+use crate::onboarding::Onboarding;
+use crate::pacing::FramePacer;
+use crate::post_processing::PostProcessing;
+use crate::replay::ReplayRecorder;
+use crate::save;
+use crate::settings::{CatchUpPolicy, FullscreenMode, PresentMode, Settings, SettingsPanel, WindowGeometry};
+use crate::tas::TasEditor;
+use crate::thumbnail::{self, ThumbnailCapture, ThumbnailError};
+use crate::toast::ToastOverlay;
+use crate::touch::TouchControls;
 use cgmath::num_traits::ToPrimitive;
 use imgui::FontSource;
 use imgui_wgpu::{Renderer as ImguiRenderer, RendererConfig};
-use log::{debug, info, warn};
+use log::{debug, error, info, warn};
+use sdl2::controller::GameController;
 use sdl2::event::{Event, WindowEvent};
-use sdl2::keyboard::Keycode;
-use sdl2::video::Window as SdlWindow;
-use sdl2::Sdl;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::rect::Point;
+use sdl2::video::{FullscreenType, Window as SdlWindow, WindowPos};
+use sdl2::{GameControllerSubsystem, Sdl, VideoSubsystem};
 
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use wgpu::{include_wgsl, vertex_attr_array, BufferUsages};
@@ -46,6 +67,9 @@ pub struct Window {
     game: Game,
     sdl_context: Sdl,
     sdl_window: SdlWindow,
+    game_controller_subsystem: GameControllerSubsystem,
+    /// Connected controllers by instance ID, they only send events while they're open
+    game_controllers: HashMap<u32, GameController>,
 
     device: wgpu::Device,
     queue: wgpu::Queue,
@@ -55,43 +79,104 @@ pub struct Window {
     imgui: imgui::Context,
     imgui_renderer: ImguiRenderer,
     imgui_platform: ImguiSdlPlatform,
+
+    post_processing: PostProcessing,
+    thumbnail_capture: ThumbnailCapture,
+    /// Texture of the last save's thumbnail while the title screen is shown, `Some(None)` if
+    /// there's none
+    title_thumbnail: Option<Option<imgui::TextureId>>,
+    touch_controls: TouchControls,
+    controls_panel: ControlsPanel,
+    display_panel: DisplayPanel,
+    settings_panel: SettingsPanel,
+    level_code_panel: LevelCodePanel,
+    /// Index of the display the window was on when it was last moved
+    current_display: Option<i32>,
+    /// Set between minimize and restore events, nothing is drawn meanwhile
+    minimized: bool,
+    frame_pacer: FramePacer,
+    latency_meter: LatencyMeter,
+    tas_editor: TasEditor,
+    replay_recorder: ReplayRecorder,
+    toast_overlay: ToastOverlay,
+    /// Shown instead of the game on the first launch
+    onboarding: Option<Onboarding>,
+    settings: Settings,
+
+    #[cfg(feature = "automation")]
+    automation: Option<AutomationServer>,
 }
 
 pub struct DrawContext<'a> {
+    /// Used to create renderers when something is drawn for the first time
+    pub device: &'a wgpu::Device,
     pub encoder: &'a mut wgpu::CommandEncoder,
     pub output: &'a wgpu::TextureView,
     pub queue: &'a wgpu::Queue,
+    /// Format of `output`, which pipelines must be created with. It's derived from the format
+    /// negotiated with the surface, which differs between platforms, see
+    /// `PostProcessing::scene_format()`.
+    pub format: wgpu::TextureFormat,
     pub window_width: u32,
     pub window_height: u32,
 }
 
 impl Window {
-    pub fn new() -> Result<Window, String> {
+    /// Time between two iterations of the main loop while nothing is drawn
+    const HIDDEN_FRAME_DURATION: Duration = Duration::from_millis(10);
+
+    pub fn new(settings: Settings, tick_hooks: Vec<(&str, Box<dyn TickHook>)>) -> Result<Window, String> {
         let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
         let _audio_subsystem = sdl_context.audio()?;
-        let sdl_window = video_subsystem
-            .window("Complementary", 800, 600)
-            .position_centered()
-            .resizable()
-            .allow_highdpi()
-            .build()
-            .map_err(|e| e.to_string())?;
-
-        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
-        let wrapper = WindowWrapper(&sdl_window);
-        let surface = unsafe { instance.create_surface(&wrapper) };
+        // Controllers that are already connected are reported through ControllerDeviceAdded too
+        let game_controller_subsystem = sdl_context.game_controller()?;
+        let restored_position = settings
+            .window
+            .as_ref()
+            .and_then(|geometry| Window::restored_position(&video_subsystem, geometry));
+        let (width, height) = match (&settings.window, restored_position) {
+            (Some(geometry), Some(_)) => (geometry.width, geometry.height),
+            // The display is gone, make sure that the window still fits on the primary one
+            (Some(geometry), None) => match video_subsystem.display_usable_bounds(0) {
+                Ok(bounds) => (geometry.width.min(bounds.width()), geometry.height.min(bounds.height())),
+                Err(_) => (geometry.width, geometry.height),
+            },
+            (None, _) => (800, 600),
+        };
+        let mut window_builder = video_subsystem.window("Complementary", width, height);
+        window_builder.resizable().allow_highdpi();
+        let fullscreen_display = settings
+            .display
+            .as_deref()
+            .filter(|_| settings.fullscreen)
+            .and_then(|name| DisplayInfo::find(&video_subsystem, name));
+        match (fullscreen_display, restored_position) {
+            (Some(display), _) => {
+                let (x, y) = display.centered_position(width, height);
+                window_builder.position(x, y)
+            }
+            (None, Some((x, y))) => window_builder.position(x, y),
+            (None, None) => window_builder.position_centered(),
+        };
+        if settings.fullscreen {
+            match settings.fullscreen_mode {
+                FullscreenMode::Borderless => window_builder.fullscreen_desktop(),
+                FullscreenMode::Exclusive => window_builder.fullscreen(),
+            };
+        }
+        let sdl_window = window_builder.build().map_err(|e| e.to_string())?;
 
-        let adapter_opt =
-            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            }));
-        let adapter = match adapter_opt {
+        // Fall back to any other backend (e.g. GL) if the preferred ones aren't available
+        let (surface, adapter) = match Window::request_adapter(&sdl_window, settings.backends) {
             Some(a) => a,
-            None => return Err(String::from("No adapter found")),
+            None => {
+                warn!("No adapter found for {:?}, trying all backends", settings.backends);
+                Window::request_adapter(&sdl_window, wgpu::Backends::all())
+                    .ok_or_else(|| String::from("No adapter found"))?
+            }
         };
+        info!("Using adapter: {:?}", adapter.get_info());
 
         let (device, queue) = match pollster::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
@@ -105,24 +190,40 @@ impl Window {
             Err(e) => return Err(e.to_string()),
         };
 
-        let game = Game::new(&device).map_err(|e| e.to_string())?;
+        let mut game = Game::new(&settings).map_err(|e| e.to_string())?;
+        for (mod_id, hook) in tick_hooks {
+            if let Err(err) = game.register_tick_hook(mod_id, hook) {
+                warn!("Failed to register a tick hook: {err}");
+            }
+        }
 
+        // The window might start minimized, but the surface can't be configured without area
         let (width, height) = sdl_window.drawable_size();
+        let (width, height) = (width.max(1), height.max(1));
         let surface_config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_preferred_format(&adapter).unwrap(),
             width,
             height,
-            present_mode: wgpu::PresentMode::Mailbox,
+            present_mode: settings.present_mode.to_wgpu(),
         };
         surface.configure(&device, &surface_config);
 
+        let post_processing = PostProcessing::new(
+            &device,
+            surface_config.format,
+            width,
+            height,
+            settings.bloom_intensity,
+        );
+        let thumbnail_capture = ThumbnailCapture::new(&device, surface_config.format);
+
         // Set up dear imgui
         let mut imgui = imgui::Context::create();
         imgui.set_ini_filename(None);
 
         let font_size = 13.0 as f32;
-        imgui.io_mut().font_global_scale = 1.0 as f32;
+        imgui.io_mut().font_global_scale = settings.ui_scale;
 
         imgui.fonts().add_font(&[FontSource::DefaultFontData {
             config: Some(imgui::FontConfig {
@@ -133,18 +234,33 @@ impl Window {
             }),
         }]);
 
+        // ImGui's colors are in sRGB, like the game's, so they must be converted for sRGB surfaces
         let renderer_config = RendererConfig {
             texture_format: surface_config.format,
-            ..Default::default()
+            ..if surface_config.format.describe().srgb {
+                RendererConfig::new_srgb()
+            } else {
+                RendererConfig::new()
+            }
         };
 
         let imgui_platform = ImguiSdlPlatform::init(&mut imgui);
         let imgui_renderer = ImguiRenderer::new(&mut imgui, &device, &queue, renderer_config);
 
+        let current_display = sdl_window.display_index().ok();
+        let refresh_rate = Window::refresh_rate(&video_subsystem, current_display);
+
+        let mut touch_controls = TouchControls::default();
+        if let Some(layout) = &settings.touch_layout {
+            touch_controls.set_buttons(layout.clone());
+        }
+
         Ok(Window {
             game,
             sdl_window,
             sdl_context,
+            game_controller_subsystem,
+            game_controllers: HashMap::new(),
 
             device,
             queue,
@@ -154,90 +270,432 @@ impl Window {
             imgui,
             imgui_platform,
             imgui_renderer,
+
+            post_processing,
+            thumbnail_capture,
+            title_thumbnail: None,
+            touch_controls,
+            controls_panel: ControlsPanel::default(),
+            display_panel: DisplayPanel::default(),
+            settings_panel: SettingsPanel::default(),
+            level_code_panel: LevelCodePanel::default(),
+            current_display,
+            minimized: false,
+            frame_pacer: FramePacer::new(refresh_rate),
+            latency_meter: LatencyMeter::default(),
+            tas_editor: TasEditor::new(),
+            replay_recorder: ReplayRecorder::new(),
+            toast_overlay: ToastOverlay::default(),
+            onboarding: (settings.first_run && !settings.kiosk).then(|| Onboarding::new(&settings)),
+            settings,
+
+            #[cfg(feature = "automation")]
+            automation: match AutomationServer::bind_from_env() {
+                Ok(server) => Some(server),
+                Err(err) => {
+                    warn!("Failed to start automation server: {err}");
+                    None
+                }
+            },
         })
     }
 
+    fn open_game_controller(&mut self, joystick_index: u32) {
+        match self.game_controller_subsystem.open(joystick_index) {
+            Ok(controller) => {
+                info!("Controller connected: {}", controller.name());
+                self.game_controllers.insert(controller.instance_id(), controller);
+            }
+            Err(err) => warn!("Failed to open controller {joystick_index}: {err}"),
+        }
+    }
+
+    /// Load the last save's thumbnail when the title screen is entered and free it when it's left
+    fn update_title_thumbnail(&mut self) {
+        let on_title = self.game.state() == GameState::Title && !self.settings.kiosk;
+        if on_title && self.title_thumbnail.is_none() {
+            let texture = match thumbnail::load(&save::thumbnail_path()) {
+                Ok(thumbnail) => {
+                    let format = if self.surface_config.format.describe().srgb {
+                        wgpu::TextureFormat::Rgba8UnormSrgb
+                    } else {
+                        wgpu::TextureFormat::Rgba8Unorm
+                    };
+                    let texture = imgui_wgpu::Texture::new(&self.device, &self.imgui_renderer, imgui_wgpu::TextureConfig {
+                        size: wgpu::Extent3d {
+                            width: thumbnail.width,
+                            height: thumbnail.height,
+                            depth_or_array_layers: 1,
+                        },
+                        label: Some("title_thumbnail"),
+                        format: Some(format),
+                        ..Default::default()
+                    });
+                    texture.write(&self.queue, &thumbnail.rgba, thumbnail.width, thumbnail.height);
+                    Some(self.imgui_renderer.textures.insert(texture))
+                }
+                Err(ThumbnailError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => None,
+                Err(err) => {
+                    warn!("Failed to load the save thumbnail: {err}");
+                    None
+                }
+            };
+            self.title_thumbnail = Some(texture);
+            self.game.set_title_thumbnail(texture);
+        } else if !on_title {
+            if let Some(Some(texture)) = self.title_thumbnail.take() {
+                self.imgui_renderer.textures.remove(texture);
+                self.game.set_title_thumbnail(None);
+            }
+        }
+    }
+
+    /// Position of the window from the last session. Returns `None` if the window's center
+    /// isn't on any display anymore, e.g. because the display was disconnected.
+    fn restored_position(video_subsystem: &VideoSubsystem, geometry: &WindowGeometry) -> Option<(i32, i32)> {
+        let center = Point::new(
+            geometry.x + geometry.width as i32 / 2,
+            geometry.y + geometry.height as i32 / 2,
+        );
+        let display_count = video_subsystem.num_video_displays().ok()?;
+        let on_screen = (0..display_count).any(|index| {
+            video_subsystem
+                .display_bounds(index)
+                .map(|bounds| bounds.contains_point(center))
+                .unwrap_or(false)
+        });
+        if !on_screen {
+            info!(
+                "Display {} isn't available anymore, centering window",
+                geometry.display.as_deref().unwrap_or("unknown")
+            );
+            return None;
+        }
+        Some((geometry.x, geometry.y))
+    }
+
+    /// Reconfigure the surface and render targets after the window's drawable size changed
+    fn resize_surface(&mut self) {
+        let (width, height) = self.sdl_window.drawable_size();
+        if width == 0 || height == 0 {
+            // Configuring the surface with an empty size panics on some backends. It's
+            // reconfigured once the window has an area again.
+            debug!("Window has no area, not drawing");
+            return;
+        }
+        if width == self.surface_config.width && height == self.surface_config.height {
+            return;
+        }
+        debug!("Changed window dimensions to {width}x{height}");
+        self.surface_config.width = width;
+        self.surface_config.height = height;
+        self.surface.configure(&self.device, &self.surface_config);
+        self.post_processing.resize(&self.device, width, height);
+    }
+
+    /// Configure the surface again after it became outdated or was lost, even if its size
+    /// didn't change
+    fn reconfigure_surface(&mut self) {
+        let (width, height) = self.sdl_window.drawable_size();
+        if width != self.surface_config.width || height != self.surface_config.height {
+            self.resize_surface();
+        } else if width > 0 && height > 0 {
+            self.surface.configure(&self.device, &self.surface_config);
+        }
+    }
+
+    /// Get the texture to draw the next frame to. Returns `None` if the frame should be
+    /// skipped, which happens when the surface can't be recovered right away, e.g. while the
+    /// window is being minimized on Windows. Only running out of memory is fatal.
+    fn acquire_frame(&mut self) -> Result<Option<wgpu::SurfaceTexture>, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(err @ (wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost)) => {
+                warn!("Reconfiguring surface: {err}");
+                self.reconfigure_surface();
+                match self.surface.get_current_texture() {
+                    Ok(frame) => Ok(Some(frame)),
+                    Err(wgpu::SurfaceError::OutOfMemory) => Err(wgpu::SurfaceError::OutOfMemory),
+                    Err(err) => {
+                        warn!("Skipping frame: {err}");
+                        Ok(None)
+                    }
+                }
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                warn!("Timed out getting the next frame, skipping it");
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether the window can't be drawn to, e.g. because it's minimized
+    fn hidden(&self) -> bool {
+        let (width, height) = self.sdl_window.drawable_size();
+        self.minimized || width == 0 || height == 0
+    }
+
+    fn handle_display_request(&mut self, request: DisplayRequest) {
+        let result = match request {
+            DisplayRequest::MoveTo(index) => self.move_to_display(index),
+            DisplayRequest::SetFullscreen(fullscreen) => self.set_fullscreen(fullscreen),
+            DisplayRequest::SetFullscreenMode(fullscreen_mode) => self.set_fullscreen_mode(fullscreen_mode),
+            DisplayRequest::SetPresentMode(present_mode) => {
+                self.set_present_mode(present_mode);
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            warn!("Failed to change display settings: {err}");
+        }
+        self.current_display = self.sdl_window.display_index().ok();
+        self.update_refresh_rate();
+        self.resize_surface();
+    }
+
+    fn refresh_rate(video_subsystem: &VideoSubsystem, display: Option<i32>) -> i32 {
+        display
+            .and_then(|index| DisplayInfo::query(video_subsystem, index).ok())
+            .map(|display| display.refresh_rate)
+            .unwrap_or(0)
+    }
+
+    fn update_refresh_rate(&mut self) {
+        let refresh_rate = Window::refresh_rate(self.sdl_window.subsystem(), self.current_display);
+        if refresh_rate != self.frame_pacer.refresh_rate() {
+            info!("Refresh rate changed to {refresh_rate} Hz");
+            self.frame_pacer.set_refresh_rate(refresh_rate);
+        }
+    }
+
+    /// Center the window on another display, keeping fullscreen if it's enabled
+    fn move_to_display(&mut self, index: i32) -> Result<(), String> {
+        let display = DisplayInfo::query(self.sdl_window.subsystem(), index)?;
+        let fullscreen = self.sdl_window.fullscreen_state();
+        if fullscreen != FullscreenType::Off {
+            // Fullscreen windows can't be moved
+            self.sdl_window.set_fullscreen(FullscreenType::Off)?;
+        }
+        let (width, height) = self.sdl_window.size();
+        let (x, y) = display.centered_position(width, height);
+        self.sdl_window
+            .set_position(WindowPos::Positioned(x), WindowPos::Positioned(y));
+        self.sdl_window.set_fullscreen(fullscreen)?;
+
+        info!("Moved window to display {}", display.name);
+        self.settings.display = Some(display.name);
+        Ok(())
+    }
+
+    fn set_fullscreen(&mut self, fullscreen: bool) -> Result<(), String> {
+        self.sdl_window.set_fullscreen(match (fullscreen, self.settings.fullscreen_mode) {
+            (false, _) => FullscreenType::Off,
+            (true, FullscreenMode::Borderless) => FullscreenType::Desktop,
+            (true, FullscreenMode::Exclusive) => FullscreenType::True,
+        })?;
+        self.settings.fullscreen = fullscreen;
+        Ok(())
+    }
+
+    /// Switch between borderless and exclusive fullscreen, applied immediately if the window
+    /// is already in fullscreen
+    fn set_fullscreen_mode(&mut self, fullscreen_mode: FullscreenMode) -> Result<(), String> {
+        info!("Changing fullscreen mode to {fullscreen_mode:?}");
+        self.settings.fullscreen_mode = fullscreen_mode;
+        if self.sdl_window.fullscreen_state() != FullscreenType::Off {
+            self.set_fullscreen(true)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the settings changed in the `SettingsPanel`. They're saved with the window
+    /// geometry when the game is closed.
+    fn apply_settings(&mut self, input: &Input) {
+        audio::set_volume(SoundCategory::Music, self.settings.music_volume);
+        audio::set_volume(SoundCategory::Effects, self.settings.effects_volume);
+        self.game.apply_settings(&self.settings);
+        self.update_button_prompts(input);
+    }
+
+    /// Prompts depend on the settings and the key bindings, so this is called whenever either
+    /// of them changes
+    fn update_button_prompts(&self, input: &Input) {
+        localization::set_button_prompts(|button| self.settings.button_prompt(button, input));
+    }
+
+    /// Write the initial settings file once the onboarding is done, so it isn't shown again
+    fn finish_onboarding(&mut self) {
+        if !self.onboarding.as_ref().map_or(false, Onboarding::finished) {
+            return;
+        }
+        self.onboarding = None;
+        self.settings.first_run = false;
+        if let Err(err) = self.settings.save() {
+            warn!("Failed to save settings: {err}");
+        }
+    }
+
+    fn set_present_mode(&mut self, present_mode: PresentMode) {
+        info!("Changing present mode to {present_mode:?}");
+        self.settings.present_mode = present_mode;
+        self.surface_config.present_mode = present_mode.to_wgpu();
+        self.reconfigure_surface();
+    }
+
+    /// Remember the window's size and position and save them for the next session
+    fn save_window_geometry(&mut self) {
+        if self.settings.kiosk {
+            // Kiosk mode always runs in fullscreen, don't overwrite the regular settings
+            return;
+        }
+
+        self.settings.fullscreen = self.sdl_window.fullscreen_state() != FullscreenType::Off;
+        if !self.settings.fullscreen {
+            // Keep the last windowed geometry while in fullscreen
+            let (x, y) = self.sdl_window.position();
+            let (width, height) = self.sdl_window.size();
+            let display = self
+                .sdl_window
+                .display_index()
+                .and_then(|index| self.sdl_window.subsystem().display_name(index))
+                .ok();
+            self.settings.window = Some(WindowGeometry {
+                x,
+                y,
+                width,
+                height,
+                display,
+            });
+        }
+        if let Err(err) = self.settings.save() {
+            warn!("Failed to save settings: {err}");
+        }
+    }
+
+    fn request_adapter(
+        sdl_window: &SdlWindow,
+        backends: wgpu::Backends,
+    ) -> Option<(wgpu::Surface, wgpu::Adapter)> {
+        let instance = wgpu::Instance::new(backends);
+        let wrapper = WindowWrapper(sdl_window);
+        let surface = unsafe { instance.create_surface(&wrapper) };
+
+        let adapter =
+            pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            }))?;
+        Some((surface, adapter))
+    }
+
     pub fn run_main_loop(&mut self) -> Result<(), String> {
         let mut input = Input::new();
+        input.set_bindings(KeyBindings::load_or_default());
+        self.update_button_prompts(&input);
 
-        let mut last_frame_time = Instant::now();
         let mut lag = Duration::default();
+        let mut result = Ok(());
 
         let mut event_pump = self.sdl_context.event_pump()?;
+        let timer = self.sdl_context.timer()?;
         'running: loop {
+            let input_locked = self.game.gameplay_input_locked();
+            if input_locked {
+                input.set_pressed_buttons(0);
+            }
+
             for event in event_pump.poll_iter() {
                 self.imgui_platform.handle_event(&mut self.imgui, &event);
+                // Keep typing into ImGui fields or clicking on windows from controlling the player.
+                // Releases are always passed on so that no button stays pressed.
+                let keyboard_captured = self.imgui.io().want_capture_keyboard || input_locked;
+                let mouse_captured = self.imgui.io().want_capture_mouse || input_locked;
+
+                if !mouse_captured || matches!(event, Event::FingerUp { .. }) {
+                    self.touch_controls.handle_event(&event, &mut input);
+                }
+                if self.controls_panel.handle_event(&event, &mut input) {
+                    continue;
+                }
+                if !keyboard_captured {
+                    self.latency_meter.handle_event(&event, timer.ticks());
+                }
 
                 match event {
                     Event::Window {
                         window_id,
                         win_event: WindowEvent::SizeChanged(..),
                         ..
+                    } if window_id == self.sdl_window.id() => self.resize_surface(),
+                    Event::Window {
+                        window_id,
+                        win_event: WindowEvent::Minimized,
+                        ..
+                    } if window_id == self.sdl_window.id() => self.minimized = true,
+                    Event::Window {
+                        window_id,
+                        win_event: WindowEvent::Restored | WindowEvent::Maximized,
+                        ..
                     } if window_id == self.sdl_window.id() => {
-                        let (width, height) = self.sdl_window.drawable_size();
-                        debug!("Changed window dimensions to {width}x{height}");
-                        self.surface_config.width = width;
-                        self.surface_config.height = height;
-                        self.surface.configure(&self.device, &self.surface_config);
+                        self.minimized = false;
+                        self.resize_surface();
+                    }
+                    Event::Window {
+                        window_id,
+                        win_event: WindowEvent::Moved(..),
+                        ..
+                    } if window_id == self.sdl_window.id() => {
+                        let display = self.sdl_window.display_index().ok();
+                        if display != self.current_display {
+                            info!("Window moved to display {display:?}");
+                            self.current_display = display;
+                            self.update_refresh_rate();
+                            // The drawable size changes with the display's scale factor
+                            self.resize_surface();
+                        }
+                    }
+                    Event::Quit { .. } if self.settings.kiosk => {
+                        info!("Ignoring quit request in kiosk mode");
                     }
                     Event::Quit { .. } => {
                         break 'running;
                     }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Return),
+                        keymod,
+                        repeat: false,
+                        ..
+                    } if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) && !self.settings.kiosk => {
+                        let fullscreen = self.sdl_window.fullscreen_state() == FullscreenType::Off;
+                        self.handle_display_request(DisplayRequest::SetFullscreen(fullscreen));
+                    }
                     Event::KeyDown {
                         keycode: Some(keycode),
                         repeat: false,
                         ..
-                    } => match keycode {
-                        Keycode::Space => {
-                            input.set_button_pressed(ButtonType::Jump);
-                            input.set_button_pressed(ButtonType::Confirm);
-                        }
-                        Keycode::Return => {
-                            input.set_button_pressed(ButtonType::Switch);
-                            input.set_button_pressed(ButtonType::Confirm);
-                        }
-                        Keycode::RShift => input.set_button_pressed(ButtonType::SwitchAndAbility),
-                        Keycode::RCtrl | Keycode::RAlt | Keycode::LCtrl => {
-                            input.set_button_pressed(ButtonType::Ability)
-                        }
-                        Keycode::Left | Keycode::A => input.set_button_pressed(ButtonType::Left),
-                        Keycode::Right | Keycode::D => input.set_button_pressed(ButtonType::Right),
-                        Keycode::Up | Keycode::W => {
-                            input.set_button_pressed(ButtonType::Up);
-                            input.set_button_pressed(ButtonType::Jump);
-                        }
-                        Keycode::Down | Keycode::S => input.set_button_pressed(ButtonType::Down),
-                        Keycode::Escape | Keycode::P => input.set_button_pressed(ButtonType::Pause),
-                        _ => (),
-                    },
+                    } if !keyboard_captured => input.set_key_pressed(keycode),
                     Event::KeyUp {
                         keycode: Some(keycode),
                         ..
-                    } => match keycode {
-                        Keycode::Space => {
-                            input.set_button_released(ButtonType::Jump);
-                            input.set_button_released(ButtonType::Confirm);
-                        }
-                        Keycode::Return => {
-                            input.set_button_released(ButtonType::Switch);
-                            input.set_button_released(ButtonType::Confirm);
-                        }
-                        Keycode::RShift => input.set_button_released(ButtonType::SwitchAndAbility),
-                        Keycode::RCtrl | Keycode::RAlt | Keycode::LCtrl => {
-                            input.set_button_released(ButtonType::Ability)
+                    } => input.set_key_released(keycode),
+                    Event::ControllerDeviceAdded { which, .. } => self.open_game_controller(which),
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        if let Some(controller) = self.game_controllers.remove(&which) {
+                            info!("Controller disconnected: {}", controller.name());
+                            // Its buttons won't be released anymore
+                            input.set_pressed_buttons(0);
                         }
-                        Keycode::Left | Keycode::A => input.set_button_released(ButtonType::Left),
-                        Keycode::Right | Keycode::D => input.set_button_released(ButtonType::Right),
-                        Keycode::Up | Keycode::W => {
-                            input.set_button_released(ButtonType::Up);
-                            input.set_button_released(ButtonType::Jump);
-                        }
-                        Keycode::Down | Keycode::S => input.set_button_released(ButtonType::Down),
-                        Keycode::Escape | Keycode::P => {
-                            input.set_button_released(ButtonType::Pause)
-                        }
-                        _ => (),
-                    },
+                    }
+                    Event::ControllerButtonDown { button, .. } if !keyboard_captured => {
+                        input.set_pad_button_pressed(button)
+                    }
+                    Event::ControllerButtonUp { button, .. } => input.set_pad_button_released(button),
+                    // Centering the stick releases its directions, so that always gets through
+                    Event::ControllerAxisMotion { axis, value, .. } => {
+                        input.set_stick_axis(axis, if keyboard_captured { 0 } else { value })
+                    }
 
                     _e => {
                         //dbg!(e);
@@ -245,37 +703,111 @@ impl Window {
                 }
             }
 
-            let elapsed = last_frame_time.elapsed();
-            lag += elapsed;
-            last_frame_time = Instant::now();
+            #[cfg(feature = "automation")]
+            if let Some(automation) = &mut self.automation {
+                automation.poll(&mut self.game, &mut input);
+            }
+
+            let mut elapsed = self.frame_pacer.frame_elapsed();
+            if self.settings.catch_up_policy == CatchUpPolicy::SlowDown {
+                // Frames that took longer than a refresh don't run extra ticks, the game just
+                // falls behind real time
+                elapsed = elapsed.min(self.frame_pacer.frame_interval());
+            }
+            lag += self.game.speed().scale(elapsed);
+            if self.hidden() && self.settings.pause_when_minimized {
+                // Don't catch up on the missed ticks after the window is restored
+                lag = Duration::default();
+            }
+
+            if self.tas_editor.active() {
+                // The TAS editor drives the simulation with its own inputs
+                lag = Duration::default();
+                self.tas_editor.tick(&mut self.game);
+            }
 
             let mut frame_tick_count = 0;
             while lag >= Game::TICK_DURATION {
                 lag -= Game::TICK_DURATION;
 
                 input.tick();
-                self.game.tick(&input, &self.device);
+                if let Some(onboarding) = &mut self.onboarding {
+                    // The game waits until the onboarding is done
+                    let changed = onboarding.tick(&input, &mut self.settings);
+                    if changed {
+                        self.apply_settings(&input);
+                    }
+                    self.finish_onboarding();
+                } else {
+                    self.replay_recorder.tick(&input, &mut self.game);
+                }
+                audio::tick();
+                self.latency_meter.tick_finished();
 
                 frame_tick_count += 1;
 
-                // Only loop ticks up until MAX_TICKS_PER_FRAME to avoid getting stuck forever
-                if frame_tick_count > Game::MAX_TICKS_PER_FRAME {
+                // Only loop ticks up until max_ticks_per_frame to avoid getting stuck forever
+                if frame_tick_count > self.settings.max_ticks_per_frame {
                     let skipped_frame_count = lag.as_nanos() / Game::TICK_DURATION.as_nanos();
                     lag -= Game::TICK_DURATION * (skipped_frame_count.to_u32().unwrap_or(u32::MAX));
                     warn!("Lagging, skipped {skipped_frame_count} ticks");
                 }
             }
 
+            if self.game.quit_requested() {
+                break 'running;
+            }
+
+            if self.hidden() {
+                // Presenting frames doesn't block while hidden, so wait here instead
+                thread::sleep(Window::HIDDEN_FRAME_DURATION);
+                continue;
+            }
+
+            let frame = match self.acquire_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    self.frame_pacer.wait_for_next_frame();
+                    continue;
+                }
+                Err(err) => {
+                    error!("Failed to get the next frame, quitting: {err}");
+                    result = Err(format!("Failed to get the next frame: {err}"));
+                    break 'running;
+                }
+            };
+
+            self.update_title_thumbnail();
             self.imgui_platform
                 .prepare_frame(&mut self.imgui, &self.sdl_window, &event_pump);
             let gui_frame = self.imgui.frame();
-            self.game.draw_gui(&gui_frame, &mut input, &self.device);
+            self.game.draw_gui(&gui_frame, &mut input);
+            self.touch_controls.draw_gui(&gui_frame);
+            self.toast_overlay.draw_gui(&gui_frame);
+            if let Some(onboarding) = &self.onboarding {
+                onboarding.draw_gui(&gui_frame, &self.settings);
+            }
+            let mut display_request = None;
+            let mut settings_changed = false;
+            if !self.settings.kiosk {
+                display_request = self.display_panel.draw_gui(
+                    &gui_frame,
+                    &self.sdl_window,
+                    &self.settings,
+                );
+                settings_changed = self.settings_panel.draw_gui(&gui_frame, &mut self.settings);
+                self.frame_pacer.draw_gui(&gui_frame);
+                self.latency_meter.draw_gui(&gui_frame, self.settings.present_mode);
+                self.controls_panel.draw_gui(&gui_frame, &mut input);
+                self.tas_editor.draw_gui(&gui_frame, &mut self.game);
+                self.replay_recorder.draw_gui(&gui_frame, &mut self.game);
+                self.level_code_panel.draw_gui(
+                    &gui_frame,
+                    &mut self.game,
+                    &self.sdl_window.subsystem().clipboard(),
+                );
+            }
 
-            let frame_res = self.surface.get_current_texture();
-            let frame = match frame_res {
-                Ok(a) => a,
-                Err(e) => return Err(format!("Timeout getting next texture: {}", e)),
-            };
             let output = frame
                 .texture
                 .create_view(&wgpu::TextureViewDescriptor::default());
@@ -286,15 +818,49 @@ impl Window {
                 });
 
             let mut draw_context = DrawContext {
+                device: &self.device,
                 encoder: &mut encoder,
-                output: &output,
+                output: self.post_processing.scene_target(),
                 queue: &self.queue,
+                format: self.post_processing.scene_format(),
                 window_width: self.surface_config.width,
                 window_height: self.surface_config.height,
             };
 
             self.game.draw(&mut draw_context);
 
+            if self.post_processing.bloom_intensity() > 0.0 {
+                self.post_processing.clear_bloom_target(&mut encoder);
+                let mut emissive_context = DrawContext {
+                    device: &self.device,
+                    encoder: &mut encoder,
+                    output: self.post_processing.bloom_target(),
+                    queue: &self.queue,
+                    format: self.post_processing.scene_format(),
+                    window_width: self.surface_config.width,
+                    window_height: self.surface_config.height,
+                };
+                self.game.draw_emissive(&mut emissive_context);
+            }
+            let speed_lines = if self.settings.reduce_motion {
+                None
+            } else {
+                self.game.speed_lines()
+            };
+            self.post_processing.apply(
+                &mut encoder,
+                &self.queue,
+                &output,
+                self.game.inversion(),
+                self.settings.gamma,
+                speed_lines,
+            );
+            let thumbnail_buffer = if self.game.take_thumbnail_request() {
+                self.thumbnail_capture.capture(&self.device, &mut encoder, &self.post_processing)
+            } else {
+                None
+            };
+
             {
                 // Imgui pass
                 let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -315,9 +881,33 @@ impl Window {
             }
 
             self.queue.submit([encoder.finish()]);
+            if let Some(buffer) = thumbnail_buffer {
+                self.thumbnail_capture.save_in_background(buffer, save::thumbnail_path());
+            }
+            if self.thumbnail_capture.in_flight() {
+                // Lets the readback finish without blocking
+                self.device.poll(wgpu::Maintain::Poll);
+            }
+            self.latency_meter.frame_rendered();
             frame.present();
+            self.latency_meter.frame_presented();
+            self.frame_pacer.record_frame(frame_tick_count);
+
+            // Applied after the frame because the GUI borrows the window until it's rendered
+            if settings_changed {
+                self.apply_settings(&input);
+            }
+            if self.controls_panel.take_bindings_changed() {
+                self.update_button_prompts(&input);
+            }
+            if let Some(request) = display_request {
+                self.handle_display_request(request);
+            }
+            self.frame_pacer.wait_for_next_frame();
         }
 
-        Ok(())
+        self.save_window_geometry();
+        self.game.save_progress();
+        result
     }
 }
@@ -0,0 +1,190 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+/// Changed tiles beyond this number are only counted
+const MAX_LISTED_TILES: usize = 20;
+
+struct Tilemap {
+    width: i32,
+    height: i32,
+    tiles: Vec<u8>,
+}
+
+impl Tilemap {
+    /// Read a `.cmtm` file: the magic, width and height as little endian `i32` and one byte per tile
+    fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 12 || &bytes[..4] != b"CMTM" {
+            return Err(format!("'{}' is not a tile map", path.display()).into());
+        }
+        let width = i32::from_le_bytes(bytes[4..8].try_into()?);
+        let height = i32::from_le_bytes(bytes[8..12].try_into()?);
+        Ok(Tilemap {
+            width,
+            height,
+            tiles: bytes[12..].to_vec(),
+        })
+    }
+}
+
+/// Compare two versions of a level and print a summary of the changes. The paths may point
+/// to the `.cmtm` or `.json` file of a level, or omit the extension.
+pub fn diff_levels(old_path: &Path, new_path: &Path) -> Result<(), Box<dyn Error>> {
+    let old_tilemap = Tilemap::load(&old_path.with_extension("cmtm"))?;
+    let new_tilemap = Tilemap::load(&new_path.with_extension("cmtm"))?;
+    diff_tilemaps(&old_tilemap, &new_tilemap);
+
+    let old_objects = load_objects(&old_path.with_extension("json"))?;
+    let new_objects = load_objects(&new_path.with_extension("json"))?;
+    diff_objects(old_objects, new_objects);
+    Ok(())
+}
+
+/// Level paths without the extension, so that both files of a level can be found
+pub fn level_path(arg: &str) -> PathBuf {
+    let path = PathBuf::from(arg);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("cmtm" | "json") => path.with_extension(""),
+        _ => path,
+    }
+}
+
+fn load_objects(path: &Path) -> Result<Vec<Value>, Box<dyn Error>> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn diff_tilemaps(old: &Tilemap, new: &Tilemap) {
+    if (old.width, old.height) != (new.width, new.height) {
+        println!(
+            "Tile map resized from {}x{} to {}x{}",
+            old.width, old.height, new.width, new.height
+        );
+        return;
+    }
+
+    let changes: Vec<(i32, i32, u8, u8)> = old
+        .tiles
+        .iter()
+        .zip(&new.tiles)
+        .enumerate()
+        .filter(|(_, (old_tile, new_tile))| old_tile != new_tile)
+        .map(|(i, (&old_tile, &new_tile))| {
+            (i as i32 % old.width, i as i32 / old.width, old_tile, new_tile)
+        })
+        .collect();
+    if changes.is_empty() {
+        println!("Tile map unchanged");
+        return;
+    }
+
+    println!("{} tiles changed:", changes.len());
+    for (x, y, old_tile, new_tile) in changes.iter().take(MAX_LISTED_TILES) {
+        println!("  ({}, {}): {} -> {}", x, y, old_tile, new_tile);
+    }
+    if changes.len() > MAX_LISTED_TILES {
+        println!("  ... and {} more", changes.len() - MAX_LISTED_TILES);
+    }
+}
+
+/// Objects are matched by their type and position first, so that changed data is reported
+/// as a modification. The remaining objects are matched by their type and data to find
+/// objects that were only moved.
+fn diff_objects(mut old: Vec<Value>, mut new: Vec<Value>) {
+    // Unchanged objects
+    old.retain(|object| match new.iter().position(|other| other == object) {
+        Some(index) => {
+            new.remove(index);
+            false
+        }
+        None => true,
+    });
+
+    let mut changes = Vec::new();
+    old.retain(|object| {
+        let same_place = new.iter().position(|other| {
+            type_name(other) == type_name(object) && other["position"] == object["position"]
+        });
+        let same_data = new
+            .iter()
+            .position(|other| type_name(other) == type_name(object) && other["data"] == object["data"]);
+        match same_place.or(same_data) {
+            Some(index) => {
+                let other = new.remove(index);
+                changes.push(if same_place.is_some() {
+                    format!(
+                        "  ~ {} at {}: {}",
+                        type_name(object),
+                        describe_position(object),
+                        describe_data_changes(&object["data"], &other["data"])
+                    )
+                } else {
+                    format!(
+                        "  > {} moved from {} to {}",
+                        type_name(object),
+                        describe_position(object),
+                        describe_position(&other)
+                    )
+                });
+                false
+            }
+            None => true,
+        }
+    });
+
+    changes.extend(old.iter().map(|object| {
+        format!("  - {} at {}", type_name(object), describe_position(object))
+    }));
+    changes.extend(new.iter().map(|object| {
+        format!("  + {} at {}", type_name(object), describe_position(object))
+    }));
+
+    if changes.is_empty() {
+        println!("Objects unchanged");
+    } else {
+        println!("{} objects changed:", changes.len());
+        for change in changes {
+            println!("{}", change);
+        }
+    }
+}
+
+/// Type of an object, or the prefab it's based on
+fn type_name(object: &Value) -> &str {
+    object["type"]
+        .as_str()
+        .or_else(|| object["prefab"].as_str())
+        .unwrap_or("object")
+}
+
+fn describe_position(object: &Value) -> String {
+    format!("({}, {})", object["position"]["x"], object["position"]["y"])
+}
+
+/// List the fields of an object's data that differ
+fn describe_data_changes(old: &Value, new: &Value) -> String {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            let added_keys = new_fields.keys().filter(|key| !old_fields.contains_key(*key));
+            old_fields
+                .keys()
+                .chain(added_keys)
+                .filter(|key| old_fields.get(*key) != new_fields.get(*key))
+                .map(|key| {
+                    format!(
+                        "{} {} -> {}",
+                        key,
+                        old_fields.get(key).unwrap_or(&Value::Null),
+                        new_fields.get(key).unwrap_or(&Value::Null)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+        _ => format!("{} -> {}", old, new),
+    }
+}
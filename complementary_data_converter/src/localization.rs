@@ -0,0 +1,81 @@
+use std::{collections::BTreeMap, error::Error, fs, path::Path};
+
+/// Collects all display strings referenced by the converted assets, so that the
+/// localization files contain every string the game might look up
+#[derive(Debug, Default)]
+pub struct StringInventory {
+    strings: BTreeMap<String, String>,
+}
+
+impl StringInventory {
+    pub fn new() -> Self {
+        StringInventory::default()
+    }
+
+    /// Add the display name of a level, derived from its file name (e.g. "map003_intro_SPIKES")
+    pub fn add_level(&mut self, file_stem: &str) {
+        self.strings
+            .entry(format!("level.{file_stem}"))
+            .or_insert_with(|| level_display_name(file_stem));
+    }
+
+    /// Add strings referenced by the data of a converted object
+    pub fn add_object(&mut self, r#type: &str, data: &serde_json::Value) {
+        match r#type {
+            "LevelTag" => {
+                if let Some(level_id) = data["level_id"].as_i64() {
+                    self.strings
+                        .entry(format!("level_tag.{level_id}"))
+                        .or_insert_with(|| format!("Level {}", level_id + 1));
+                }
+            }
+            "Tutorial" => {
+                // The original assets only store the type of a tutorial, its text has to be
+                // written in the localization files
+                if let Some(tutorial_type) = data["tutorial_type"].as_str() {
+                    self.strings.entry(format!("tutorial.{tutorial_type}")).or_default();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Write the inventory to a localization file. Strings that already exist in the file are
+    /// kept as-is, so that translations aren't overwritten when assets are converted again.
+    /// Strings without a source text are added empty and count as untranslated.
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut strings: BTreeMap<String, String> = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(_) => BTreeMap::new(),
+        };
+        for (key, value) in &self.strings {
+            strings.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(&strings)?)?;
+        Ok(())
+    }
+}
+
+/// Turn a file name like "map003_intro_SPIKES" into "Intro spikes"
+fn level_display_name(file_stem: &str) -> String {
+    let words: Vec<String> = file_stem.split('_').map(str::to_lowercase).collect();
+
+    // Strip the "mapXXX" prefix of main levels
+    match words[0].strip_prefix("map").and_then(|num| num.parse::<i32>().ok()) {
+        Some(num) if words.len() == 1 => format!("Level {num}"),
+        Some(_) => capitalize(&words[1..].join(" ")),
+        None => capitalize(&words.join(" ")),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
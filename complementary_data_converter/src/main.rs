@@ -1,4 +1,7 @@
+mod diff;
+mod localization;
 mod objects;
+mod prefabs;
 
 use std::{
     env,
@@ -15,7 +18,7 @@ use objects::FVec2;
 use serde::Serialize;
 use walkdir::WalkDir;
 
-use crate::objects::convert_object_data;
+use crate::{localization::StringInventory, objects::convert_object_data, prefabs::ObjectMaps};
 
 enum FileType {
     ObjectMap, // CMOM files
@@ -23,14 +26,38 @@ enum FileType {
 }
 
 pub fn main() {
+    if env::args().nth(1).as_deref() == Some("diff") {
+        let mut levels = env::args().skip(2).map(|arg| diff::level_path(&arg));
+        let (old_path, new_path) = match (levels.next(), levels.next()) {
+            (Some(old_path), Some(new_path)) => (old_path, new_path),
+            _ => panic!("Pass the paths to the old and new version of a level to compare them"),
+        };
+        if let Err(error) = diff::diff_levels(&old_path, &new_path) {
+            eprintln!("Failed to compare levels: {}", error);
+        }
+        return;
+    }
+
     let orig_path = env::args()
         .nth(1)
         .expect("Pass the path to the original assets as the first argument");
+    // Objects shared between maps are only factored out into prefabs on request, since
+    // the maps are harder to read afterwards
+    let extract_prefabs = env::args().skip(2).any(|arg| arg == "--extract-prefabs");
     let target_path = fs::canonicalize("assets/").expect("Assets directory missing");
+    let mut inventory = StringInventory::new();
+    let mut object_maps = ObjectMaps::new();
     for entry in WalkDir::new(&orig_path) {
         let entry = entry.unwrap();
         let ext = entry.path().extension();
 
+        if ext == Some(OsStr::new("cmtm")) {
+            if let Some(stem) = entry.path().file_stem() {
+                inventory.add_level(&stem.to_string_lossy());
+            }
+            continue;
+        }
+
         let file_type = if ext == Some(OsStr::new("cmom")) {
             FileType::ObjectMap
         } else if ext == Some(OsStr::new("cmob")) {
@@ -49,13 +76,31 @@ pub fn main() {
         }
 
         let result = match file_type {
-            FileType::Object => convert_single_object_file(entry.path(), &target_file_path),
-            FileType::ObjectMap => convert_object_map(entry.path(), &target_file_path),
+            FileType::Object => {
+                convert_single_object_file(entry.path(), &target_file_path, &mut inventory)
+            }
+            FileType::ObjectMap => convert_object_map(entry.path(), &mut inventory)
+                .map(|objects| object_maps.add(target_file_path, objects)),
         };
         if let Err(error) = result {
             eprintln!("Failed to convert '{}': {}", relative_path.display(), error);
         }
     }
+
+    if extract_prefabs {
+        match object_maps.extract_prefabs(&target_path.join("prefabs")) {
+            Ok(count) => println!("Extracted {} prefabs", count),
+            Err(error) => eprintln!("Failed to extract prefabs: {}", error),
+        }
+    }
+    if let Err(error) = object_maps.write() {
+        eprintln!("Failed to write object maps: {}", error);
+    }
+
+    let localization_path = target_path.join("localization").join("en.json");
+    if let Err(error) = inventory.write(&localization_path) {
+        eprintln!("Failed to write localization file: {}", error);
+    }
 }
 
 #[derive(Debug, BinRead)]
@@ -69,6 +114,11 @@ struct ObjectBin {
 
 #[derive(Debug, Serialize)]
 struct ObjectJson {
+    /// `<type>_<index in the original object map>`, so that it doesn't change when objects
+    /// are added to the converter or fail to convert. Single object files are used as
+    /// prefabs, which don't have one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
     r#type: &'static str,
     position: FVec2,
     data: serde_json::Value,
@@ -77,6 +127,7 @@ struct ObjectJson {
 pub fn convert_single_object_file(
     source_path: &Path,
     target_path: &Path,
+    inventory: &mut StringInventory,
 ) -> Result<(), Box<dyn Error>> {
     let mut file = BufReader::new(File::open(source_path)?);
     let object = ObjectBin::read(&mut file)?;
@@ -91,8 +142,10 @@ pub fn convert_single_object_file(
 
     let mut data = Cursor::new(data);
     let (r#type, json_data) = convert_object_data(object.prototype_id, &mut data)?;
+    inventory.add_object(r#type, &json_data);
 
     let json_contents = ObjectJson {
+        id: None,
         r#type,
         position: object.position,
         data: json_data,
@@ -124,14 +177,19 @@ struct ObjectMapBinItem {
     data_offset: i32,
 }
 
-pub fn convert_object_map(source_path: &Path, target_path: &Path) -> Result<(), Box<dyn Error>> {
+/// Convert an object map, which is written later by `ObjectMaps::write()`
+pub fn convert_object_map(
+    source_path: &Path,
+    inventory: &mut StringInventory,
+) -> Result<Vec<serde_json::Value>, Box<dyn Error>> {
     let mut file = BufReader::new(File::open(source_path)?);
     let object_map = ObjectMapBin::read(&mut file)?.start_pointer;
 
     let objs: Vec<ObjectJson> = object_map
         .objects
         .iter()
-        .filter_map(|object| {
+        .enumerate()
+        .filter_map(|(index, object)| {
             file.seek(std::io::SeekFrom::Start(object.data_offset as u64))
                 .ok();
             let (r#type, json_data) = convert_object_data(object.prototype_id, &mut file)
@@ -140,8 +198,10 @@ pub fn convert_object_map(source_path: &Path, target_path: &Path) -> Result<(),
                     err
                 })
                 .ok()?;
+            inventory.add_object(r#type, &json_data);
 
             Some(ObjectJson {
+                id: Some(format!("{}_{index}", r#type)),
                 r#type,
                 position: object.position,
                 data: json_data,
@@ -149,8 +209,7 @@ pub fn convert_object_map(source_path: &Path, target_path: &Path) -> Result<(),
         })
         .collect();
 
-    let json_str = serde_json::to_string_pretty(&objs)?;
-    fs::write(target_path, json_str)?;
-
-    Ok(())
+    objs.iter()
+        .map(|object| Ok(serde_json::to_value(object)?))
+        .collect()
 }
@@ -1,6 +1,6 @@
 use std::{
     error::Error,
-    io::{Read, Seek},
+    io::{Read, Seek, SeekFrom},
 };
 
 use binrw::{BinRead, BinReaderExt, BinResult, ReadOptions};
@@ -12,6 +12,8 @@ pub struct FVec2 {
     y: f32,
 }
 
+/// Serialized like `complementary::math::Color`, which reads the converted colors. Both are tested
+/// against `tests/color.json`, since the converter doesn't depend on the game.
 #[derive(Debug, BinRead, Serialize)]
 pub struct Color {
     r: f32,
@@ -197,6 +199,25 @@ struct TutorialData {
     instant: bool,
 }
 
+#[derive(Debug, Serialize, BinRead)]
+#[br(repr = i32)]
+enum Patrol {
+    Points = 0,
+    Ground = 1,
+}
+
+/// Not part of the original game, prototype 16 is the first unused ID
+#[derive(Debug, Serialize, BinRead)]
+#[br(little)]
+struct EnemyData {
+    size: FVec2,
+    goal: FVec2,
+    speed: f32,
+    patrol: Patrol,
+    #[br(parse_with = parse_optional_seen)]
+    world_type: Option<WorldType>,
+}
+
 pub type TypedValue = (&'static str, serde_json::Value);
 
 pub fn convert_object_data<T: Read + Seek>(
@@ -223,12 +244,14 @@ pub fn convert_object_data<T: Read + Seek>(
         13 => convert!("LevelTag", LevelTagData, ()),
         14 => convert!("Door", DoorData, ()),
         15 => convert!("Tutorial", TutorialData, ()),
+        16 => convert!("Enemy", EnemyData, ()),
         _ => panic!("Unknown prototype ID {}", prototype_id),
     };
     Ok(value)
 }
 
-/// Custom parse function to convert a four-byte color to four floats
+/// Custom parse function to convert a four-byte color to four floats from 0 to 1, like the
+/// game's `Color`
 fn parse_color_as_float<R: Read + Seek>(
     reader: &mut R,
     _ro: &ReadOptions,
@@ -236,10 +259,10 @@ fn parse_color_as_float<R: Read + Seek>(
 ) -> BinResult<Color> {
     let (r, g, b, a) = reader.read_le::<(u8, u8, u8, u8)>()?;
     Ok(Color {
-        r: r as f32,
-        g: g as f32,
-        b: b as f32,
-        a: a as f32,
+        r: r as f32 / 255.0,
+        g: g as f32 / 255.0,
+        b: b as f32 / 255.0,
+        a: a as f32 / 255.0,
     })
 }
 
@@ -286,3 +309,34 @@ fn parse_seen<R: Read + Seek>(
         })
     }
 }
+
+/// Like `parse_seen()`, but 2 means that the object exists in both worlds
+fn parse_optional_seen<R: Read + Seek>(
+    reader: &mut R,
+    options: &ReadOptions,
+    _: (),
+) -> BinResult<Option<WorldType>> {
+    let position = reader.stream_position()?;
+    if reader.read_le::<u8>()? == 2 {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(position))?;
+    parse_seen(reader, options, ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_json_matches_the_game() {
+        let color = Color {
+            r: 1.0,
+            g: 0.5,
+            b: 0.25,
+            a: 0.75,
+        };
+        let expected: serde_json::Value = serde_json::from_str(include_str!("../tests/color.json")).unwrap();
+        assert_eq!(serde_json::to_value(&color).unwrap(), expected);
+    }
+}
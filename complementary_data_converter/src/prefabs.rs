@@ -0,0 +1,111 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+/// Objects whose data is identical in at least this many maps are extracted into prefabs
+const MIN_MAP_COUNT: usize = 2;
+
+/// Converted object maps, which are written after all maps were converted so that objects
+/// shared between maps can be factored out into prefabs first
+#[derive(Debug, Default)]
+pub struct ObjectMaps {
+    maps: Vec<(PathBuf, Vec<Value>)>,
+}
+
+impl ObjectMaps {
+    pub fn new() -> Self {
+        ObjectMaps::default()
+    }
+
+    pub fn add(&mut self, target_path: PathBuf, objects: Vec<Value>) {
+        self.maps.push((target_path, objects));
+    }
+
+    /// Replace objects with identical type and data in multiple maps with references to
+    /// prefab files written to `prefabs_path`. The game resolves them when loading a level.
+    /// Returns the number of extracted prefabs.
+    pub fn extract_prefabs(&mut self, prefabs_path: &Path) -> Result<usize, Box<dyn Error>> {
+        // Maps each object's type and data to the maps it appears in, in order of appearance
+        let mut blobs: Vec<(String, Value)> = Vec::new();
+        let mut map_indices: HashMap<String, BTreeSet<usize>> = HashMap::new();
+        for (map_index, (_, objects)) in self.maps.iter().enumerate() {
+            for object in objects {
+                let blob = prefab_contents(object);
+                let key = serde_json::to_string(&blob)?;
+                let entry = map_indices.entry(key.clone()).or_default();
+                if entry.is_empty() {
+                    blobs.push((key, blob));
+                }
+                entry.insert(map_index);
+            }
+        }
+
+        let mut names: HashMap<String, String> = HashMap::new();
+        let mut counts_by_type: HashMap<String, usize> = HashMap::new();
+        fs::create_dir_all(prefabs_path)?;
+        for (key, blob) in blobs {
+            if map_indices[&key].len() < MIN_MAP_COUNT {
+                continue;
+            }
+            let r#type = blob["type"].as_str().unwrap_or("object");
+            let count = counts_by_type.entry(r#type.to_owned()).or_default();
+            *count += 1;
+            let name = format!("{}_{count}", snake_case(r#type));
+
+            fs::write(
+                prefabs_path.join(format!("{name}.json")),
+                serde_json::to_string_pretty(&blob)?,
+            )?;
+            names.insert(key, name);
+        }
+
+        for (_, objects) in &mut self.maps {
+            for object in objects.iter_mut() {
+                let key = serde_json::to_string(&prefab_contents(object))?;
+                if let Some(name) = names.get(&key) {
+                    let mut reference = json!({
+                        "prefab": name,
+                        "position": object["position"],
+                    });
+                    if let Some(id) = object.get("id") {
+                        reference["id"] = id.clone();
+                    }
+                    *object = reference;
+                }
+            }
+        }
+        Ok(names.len())
+    }
+
+    pub fn write(&self) -> Result<(), Box<dyn Error>> {
+        for (path, objects) in &self.maps {
+            fs::write(path, serde_json::to_string_pretty(objects)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fields of an object which are shared by all instances of a prefab
+fn prefab_contents(object: &Value) -> Value {
+    json!({
+        "type": object["type"],
+        "data": object["data"],
+    })
+}
+
+/// Convert type names like "AbilityBlock" to "ability_block"
+fn snake_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            result.push('_');
+        }
+        result.extend(c.to_lowercase());
+    }
+    result
+}
@@ -1,6 +1,6 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{self, spanned::Spanned, Data, DeriveInput, Fields};
+use syn::{self, spanned::Spanned, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
 
 #[proc_macro_derive(ImGui, attributes(gui_ignore))]
 pub fn derive_imgui(input: TokenStream) -> TokenStream {
@@ -14,7 +14,8 @@ fn impl_derive_imgui(ast: syn::DeriveInput) -> syn::Result<TokenStream> {
     let name = &ast.ident;
     let data = match &ast.data {
         Data::Struct(data) => data,
-        _ => return Err(syn::Error::new(ast.span(), "Expected struct")),
+        Data::Enum(data) => return impl_derive_imgui_enum(name, data),
+        _ => return Err(syn::Error::new(ast.span(), "Expected struct or enum")),
     };
 
     let fields = match &data.fields {
@@ -58,6 +59,38 @@ fn impl_derive_imgui(ast: syn::DeriveInput) -> syn::Result<TokenStream> {
     Ok(out.into())
 }
 
+/// Enums are shown as read-only text with the name of the current variant
+fn impl_derive_imgui_enum(name: &syn::Ident, data: &syn::DataEnum) -> syn::Result<TokenStream> {
+    let arms = data
+        .variants
+        .iter()
+        .map(|variant| {
+            if !matches!(variant.fields, Fields::Unit) {
+                return Err(syn::Error::new(
+                    variant.span(),
+                    "Only enums without fields are supported",
+                ));
+            }
+            let ident = &variant.ident;
+            let ident_str = ident.to_string();
+            Ok(quote! { #name::#ident => #ident_str, })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let out = quote! {
+        impl ImGui for #name {
+            fn draw_gui_with_settings(&mut self, label: &str, gui: &imgui::Ui, _settings: &crate::imgui_helpers::ImGuiSettings) {
+                let variant = match self {
+                    #(#arms)*
+                };
+                gui.text(format!("{}: {}", label, variant));
+            }
+        }
+    };
+
+    Ok(out.into())
+}
+
 // Based on https://stackoverflow.com/a/41638362
 #[proc_macro_derive(EnumCount)]
 pub fn derive_enum_count(input: TokenStream) -> TokenStream {
@@ -80,3 +113,312 @@ fn impl_derive_enum_count(ast: syn::DeriveInput) -> syn::Result<TokenStream> {
     };
     Ok(out.into())
 }
+
+/// Generates the WGSL definition of a `#[repr(C)]` struct and asserts at compile time that
+/// its layout matches what shaders expect. Structs are laid out like uniform buffers unless
+/// they have a `#[wgsl(location = <n>)]` attribute, which marks them as vertex or instance
+/// data whose fields are bound to consecutive locations starting at `n`.
+#[proc_macro_derive(WgslStruct, attributes(wgsl))]
+pub fn derive_wgsl_struct(input: TokenStream) -> TokenStream {
+    match syn::parse::<DeriveInput>(input).and_then(impl_derive_wgsl_struct) {
+        Ok(result) => result,
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// First location of a `#[wgsl(location = <n>)]` attribute
+fn parse_wgsl_location(ast: &syn::DeriveInput) -> syn::Result<Option<u32>> {
+    let attr = match ast.attrs.iter().find(|attr| attr.path.is_ident("wgsl")) {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+    if let Meta::List(list) = attr.parse_meta()? {
+        if let Some(NestedMeta::Meta(Meta::NameValue(value))) = list.nested.first() {
+            if let (true, Lit::Int(location)) = (value.path.is_ident("location"), &value.lit) {
+                return Ok(Some(location.base10_parse()?));
+            }
+        }
+    }
+    Err(syn::Error::new(attr.span(), "Expected `#[wgsl(location = <n>)]`"))
+}
+
+fn impl_derive_wgsl_struct(ast: syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let name_str = name.to_string();
+    let location = parse_wgsl_location(&ast)?;
+    let fields = match &ast.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+            _ => return Err(syn::Error::new(ast.span(), "Only structs with named fields are supported")),
+        },
+        _ => return Err(syn::Error::new(ast.span(), "Expected struct")),
+    };
+
+    let definitions = fields.iter().enumerate().map(|(index, field)| {
+        let ty = &field.ty;
+        let prefix = match location {
+            Some(location) => format!("[[location({})]] ", location + index as u32),
+            None => String::new(),
+        };
+        let line = format!("    {}{}: ", prefix, field.ident.as_ref().unwrap());
+        quote! {
+            definition.push_str(#line);
+            definition.push_str(<#ty as crate::shader::WgslType>::NAME);
+            definition.push_str(";\n");
+        }
+    });
+
+    // Fields of `Pod` types are never padded in Rust, so their offsets add up
+    let checks = fields.iter().map(|field| {
+        let ty = &field.ty;
+        let field_str = field.ident.as_ref().unwrap().to_string();
+        match location {
+            Some(_) => {
+                let message = format!("`{}::{}` has a different size in WGSL", name_str, field_str);
+                quote! {
+                    assert!(core::mem::size_of::<#ty>() == <#ty as crate::shader::WgslType>::SIZE, #message);
+                    rust_offset += core::mem::size_of::<#ty>();
+                }
+            }
+            None => {
+                let message = format!("`{}::{}` is at a different offset in WGSL, add padding", name_str, field_str);
+                quote! {
+                    wgsl_offset = crate::shader::wgsl_round_up(wgsl_offset, <#ty as crate::shader::WgslType>::ALIGN);
+                    assert!(wgsl_offset == rust_offset, #message);
+                    wgsl_offset += <#ty as crate::shader::WgslType>::SIZE;
+                    rust_offset += core::mem::size_of::<#ty>();
+                    if <#ty as crate::shader::WgslType>::ALIGN > align {
+                        align = <#ty as crate::shader::WgslType>::ALIGN;
+                    }
+                }
+            }
+        }
+    });
+
+    let size_message = format!("`{}` has a different size in WGSL", name_str);
+    let layout_check = match location {
+        Some(_) => quote! {
+            let mut rust_offset = 0;
+            #(#checks)*
+            assert!(rust_offset == core::mem::size_of::<#name>(), #size_message);
+        },
+        None => quote! {
+            let mut wgsl_offset = 0;
+            let mut rust_offset = 0;
+            let mut align = 1;
+            #(#checks)*
+            assert!(crate::shader::wgsl_round_up(wgsl_offset, align) == core::mem::size_of::<#name>(), #size_message);
+            assert!(rust_offset == core::mem::size_of::<#name>(), #size_message);
+        },
+    };
+
+    let header = format!("struct {} {{\n", name_str);
+    let out = quote! {
+        impl crate::shader::WgslStruct for #name {
+            fn wgsl_definition() -> String {
+                let mut definition = String::from(#header);
+                #(#definitions)*
+                definition.push_str("};\n");
+                definition
+            }
+        }
+
+        #[allow(unused_assignments)]
+        const _: () = {
+            #layout_check
+        };
+    };
+
+    Ok(out.into())
+}
+
+/// Generates a JSON Schema for the serde representation of a struct or an externally tagged
+/// enum, see `crate::schema::JsonSchema`. Fields with `#[serde(default)]` or an `Option` type
+/// aren't required, `#[serde(rename = "...")]` is respected and doc comments become
+/// descriptions. Other serde attributes aren't supported.
+#[proc_macro_derive(JsonSchema, attributes(serde))]
+pub fn derive_json_schema(input: TokenStream) -> TokenStream {
+    match syn::parse::<DeriveInput>(input).and_then(impl_derive_json_schema) {
+        Ok(result) => result,
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// The serde attributes that change the schema
+#[derive(Default)]
+struct SerdeAttributes {
+    default: bool,
+    rename: Option<String>,
+    /// `tag`, `content` or `untagged`, which change how enums are represented
+    tagged: bool,
+}
+
+fn parse_serde_attributes(attrs: &[syn::Attribute]) -> syn::Result<SerdeAttributes> {
+    let mut result = SerdeAttributes::default();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("serde")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            _ => continue,
+        };
+        for nested in &list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("default") => result.default = true,
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("untagged") => result.tagged = true,
+                NestedMeta::Meta(Meta::NameValue(value)) => {
+                    if value.path.is_ident("default") {
+                        result.default = true;
+                    } else if value.path.is_ident("tag") || value.path.is_ident("content") {
+                        result.tagged = true;
+                    } else if let (true, Lit::Str(name)) = (value.path.is_ident("rename"), &value.lit) {
+                        result.rename = Some(name.value());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Doc comment lines joined into one paragraph
+fn doc_comment(attrs: &[syn::Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::NameValue(syn::MetaNameValue { lit: Lit::Str(line), .. })) => Some(line.value().trim().to_owned()),
+            _ => None,
+        })
+        .collect();
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
+
+/// Adds the doc comment in `attrs` to the schema returned by `schema`
+fn describe(schema: proc_macro2::TokenStream, attrs: &[syn::Attribute]) -> proc_macro2::TokenStream {
+    match doc_comment(attrs) {
+        Some(doc) => quote! { crate::schema::describe(#schema, #doc) },
+        None => schema,
+    }
+}
+
+/// Expression that evaluates to the schema of a struct or enum variant with `fields`
+fn fields_schema(fields: &Fields, container_default: bool) -> syn::Result<proc_macro2::TokenStream> {
+    match fields {
+        Fields::Named(fields) => {
+            let properties = fields
+                .named
+                .iter()
+                .map(|field| {
+                    let attributes = parse_serde_attributes(&field.attrs)?;
+                    let ty = &field.ty;
+                    let name = attributes
+                        .rename
+                        .unwrap_or_else(|| field.ident.as_ref().unwrap().to_string());
+                    let schema = describe(quote! { <#ty as crate::schema::JsonSchema>::json_schema() }, &field.attrs);
+                    let require = if attributes.default || container_default {
+                        quote! {}
+                    } else {
+                        quote! {
+                            if <#ty as crate::schema::JsonSchema>::REQUIRED {
+                                required.push(serde_json::Value::from(#name));
+                            }
+                        }
+                    };
+                    Ok(quote! {
+                        properties.insert(String::from(#name), #schema);
+                        #require
+                    })
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            Ok(quote! {{
+                #[allow(unused_mut)]
+                let mut properties = serde_json::Map::new();
+                #[allow(unused_mut)]
+                let mut required: Vec<serde_json::Value> = Vec::new();
+                #(#properties)*
+                serde_json::json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                })
+            }})
+        }
+        // Newtypes are represented like their content
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+            let ty = &fields.unnamed[0].ty;
+            Ok(quote! { <#ty as crate::schema::JsonSchema>::json_schema() })
+        }
+        Fields::Unnamed(fields) => {
+            let items = fields.unnamed.iter().map(|field| {
+                let ty = &field.ty;
+                quote! { <#ty as crate::schema::JsonSchema>::json_schema() }
+            });
+            let len = fields.unnamed.len();
+            Ok(quote! {{
+                let items: Vec<serde_json::Value> = vec![#(#items),*];
+                serde_json::json!({
+                    "type": "array",
+                    "items": items,
+                    "minItems": #len,
+                    "maxItems": #len,
+                })
+            }})
+        }
+        Fields::Unit => Ok(quote! { serde_json::json!({ "type": "null" }) }),
+    }
+}
+
+fn impl_derive_json_schema(ast: syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &ast.ident;
+    let attributes = parse_serde_attributes(&ast.attrs)?;
+    let schema = match &ast.data {
+        Data::Struct(data) => fields_schema(&data.fields, attributes.default)?,
+        Data::Enum(data) => {
+            if attributes.tagged {
+                return Err(syn::Error::new(ast.span(), "Only externally tagged enums are supported"));
+            }
+            let variants = data
+                .variants
+                .iter()
+                .map(|variant| {
+                    let variant_attributes = parse_serde_attributes(&variant.attrs)?;
+                    let variant_name = variant_attributes.rename.unwrap_or_else(|| variant.ident.to_string());
+                    // Unit variants are strings, others objects with the variant's name as the only key
+                    let schema = match &variant.fields {
+                        Fields::Unit => quote! { serde_json::json!({ "const": #variant_name }) },
+                        fields => {
+                            let content = fields_schema(fields, false)?;
+                            quote! {{
+                                let content = #content;
+                                serde_json::json!({
+                                    "type": "object",
+                                    "properties": { #variant_name: content },
+                                    "required": [#variant_name],
+                                    "additionalProperties": false,
+                                })
+                            }}
+                        }
+                    };
+                    Ok(describe(schema, &variant.attrs))
+                })
+                .collect::<syn::Result<Vec<_>>>()?;
+            quote! {{
+                let variants: Vec<serde_json::Value> = vec![#(#variants),*];
+                serde_json::json!({ "oneOf": variants })
+            }}
+        }
+        _ => return Err(syn::Error::new(ast.span(), "Expected struct or enum")),
+    };
+    let schema = describe(schema, &ast.attrs);
+
+    let out = quote! {
+        impl crate::schema::JsonSchema for #name {
+            fn json_schema() -> serde_json::Value {
+                #schema
+            }
+        }
+    };
+
+    Ok(out.into())
+}